@@ -0,0 +1,101 @@
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+use pdf::primitive::{Dictionary, Primitive};
+
+use crate::plotter::BlendMode;
+
+// annotation flags (/F)
+const HIDDEN: u32 = 1 << 1;
+const NO_VIEW: u32 = 1 << 5;
+
+pub fn is_hidden(flags: u32) -> bool {
+    flags & (HIDDEN | NO_VIEW) != 0
+}
+
+// Opacity and blend mode applied to the whole appearance stream of an annotation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnnotationStyle {
+    pub fill_alpha: f32,
+    pub stroke_alpha: f32,
+    pub blend_mode: Option<BlendMode>,
+}
+
+fn number(dict: &Dictionary, key: &str) -> Option<f32> {
+    dict.get(key).and_then(|p| p.as_number().ok())
+}
+
+// /BM is a name or an array of names, the first supported one wins. Normal is
+// no explicit blend mode.
+pub fn blend_mode(p: &Primitive) -> Option<Option<BlendMode>> {
+    match *p {
+        Primitive::Name(ref name) => BlendMode::from_name(name.as_str()).map(|mode| match mode {
+            BlendMode::Normal => None,
+            mode => Some(mode),
+        }),
+        Primitive::Array(ref modes) => modes.iter().find_map(blend_mode),
+        _ => None,
+    }
+}
+
+// /CA is the constant opacity of the annotation, PDF 2.0 adds /ca for fills and /BM.
+// Highlights without a blend mode are multiplied like the common viewers do, so the
+// text below stays readable.
+pub fn annotation_style(subtype: &str, other: &Dictionary) -> AnnotationStyle {
+    let stroke_alpha = number(other, "CA").unwrap_or(1.0).clamp(0.0, 1.0);
+    let fill_alpha = number(other, "ca").map_or(stroke_alpha, |a| a.clamp(0.0, 1.0));
+    let blend_mode = match other.get("BM").and_then(blend_mode) {
+        Some(mode) => mode,
+        None if subtype == "Highlight" => Some(BlendMode::Multiply),
+        None => None,
+    };
+    AnnotationStyle {
+        fill_alpha,
+        stroke_alpha,
+        blend_mode,
+    }
+}
+
+// Maps the (already transformed) appearance bbox onto the annotation rectangle.
+pub fn appearance_transform(bbox: RectF, rect: RectF) -> Transform2F {
+    let scale = |a: f32, b: f32| if a.abs() > f32::EPSILON { b / a } else { 1.0 };
+    let scale = Vector2F::new(scale(bbox.width(), rect.width()), scale(bbox.height(), rect.height()));
+    Transform2F::from_translation(rect.origin())
+        * Transform2F::from_scale(scale)
+        * Transform2F::from_translation(-bbox.origin())
+}
+
+#[cfg(test)]
+mod test {
+    use pathfinder_geometry::{rect::RectF, vector::Vector2F};
+    use pdf::primitive::{Dictionary, Primitive};
+
+    use super::{annotation_style, appearance_transform, is_hidden};
+    use crate::plotter::BlendMode;
+
+    #[test]
+    fn highlight_defaults_to_multiply() {
+        let mut dict = Dictionary::new();
+        dict.insert("CA", Primitive::Number(0.5));
+        let style = annotation_style("Highlight", &dict);
+        assert_eq!(style.blend_mode, Some(BlendMode::Multiply));
+        assert_eq!(style.fill_alpha, 0.5);
+        assert_eq!(style.stroke_alpha, 0.5);
+
+        // an explicit blend mode wins, unknown names are skipped
+        dict.insert(
+            "BM",
+            Primitive::Array(vec![Primitive::Name("Luminosity".into()), Primitive::Name("Normal".into())]),
+        );
+        assert_eq!(annotation_style("Highlight", &dict).blend_mode, None);
+        assert_eq!(annotation_style("Square", &Dictionary::new()).blend_mode, None);
+    }
+
+    #[test]
+    fn appearance_fits_rect() {
+        let bbox = RectF::new(Vector2F::new(-5., -5.), Vector2F::new(10., 20.));
+        let rect = RectF::new(Vector2F::new(100., 200.), Vector2F::new(20., 20.));
+        let t = appearance_transform(bbox, rect);
+        assert_eq!(t * bbox.origin(), rect.origin());
+        assert_eq!(t * bbox.lower_right(), rect.lower_right());
+        assert!(is_hidden(2) && is_hidden(32) && !is_hidden(4));
+    }
+}
@@ -0,0 +1,235 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use pdf::PdfError;
+
+use crate::context::{PageReport, Report};
+use crate::metadata::PageMetadata;
+use crate::options::RenderOptions;
+
+// exit status when some pages were converted and others failed
+pub const PARTIAL_FAILURE: i32 = 3;
+
+// --pages, "all" or page numbers and ranges like "0-3,7"
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageSelection {
+    All,
+    List(Vec<RangeInclusive<u32>>),
+}
+
+pub fn parse_pages(s: &str) -> Result<PageSelection, String> {
+    if s == "all" {
+        return Ok(PageSelection::All);
+    }
+    let number = |n: &str| n.trim().parse::<u32>().map_err(|_| format!("invalid page number {:?}", n));
+    let ranges = s
+        .split(',')
+        .map(|part| match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (number(first)?, number(last)?);
+                if first > last {
+                    return Err(format!("page range {:?} runs backwards", part));
+                }
+                Ok(first..=last)
+            }
+            None => number(part).map(|n| n..=n),
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(PageSelection::List(ranges))
+}
+
+impl PageSelection {
+    // the selected pages of a document with `count` pages, in order and without duplicates
+    pub fn pages(&self, count: u32) -> Result<Vec<u32>, PdfError> {
+        let mut pages: Vec<u32> = match *self {
+            PageSelection::All => return Ok((0..count).collect()),
+            PageSelection::List(ref ranges) => ranges.iter().flat_map(|r| r.clone()).collect(),
+        };
+        if let Some(&page) = pages.iter().find(|&&p| p >= count) {
+            return Err(PdfError::Other {
+                msg: format!("page {} does not exist, the document has {} pages", page, count),
+            });
+        }
+        pages.sort_unstable();
+        pages.dedup();
+        Ok(pages)
+    }
+}
+
+// the output file of one page, {page} in the template is replaced by the page number
+pub fn page_path(template: &Path, page: u32) -> PathBuf {
+    PathBuf::from(template.to_string_lossy().replace("{page}", &page.to_string()))
+}
+
+// Pages are written next to their output and renamed when they are complete.
+// The extension stays, it selects the backend.
+fn part_path(output: &Path) -> PathBuf {
+    let name = output.file_stem().unwrap_or_default().to_string_lossy();
+    let part = match output.extension() {
+        Some(ext) => format!(".{}.part.{}", name, ext.to_string_lossy()),
+        None => format!(".{}.part", name),
+    };
+    output.with_file_name(part)
+}
+
+#[derive(Debug)]
+pub enum PageStatus {
+    Converted(Box<PageMetadata>),
+    Failed(String),
+}
+
+impl fmt::Display for PageStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PageStatus::Converted(ref metadata) => write!(f, "ok, {} warnings", metadata.warnings.len()),
+            PageStatus::Failed(ref msg) => write!(f, "failed: {}", msg),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatchOptions {
+    // stop at the first page that fails
+    pub fail_fast: bool,
+    // pages converted at the same time
+    pub jobs: usize,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions { fail_fast: false, jobs: 1 }
+    }
+}
+
+fn convert_page(
+    input: &Path,
+    template: &Path,
+    page: u32,
+    options: &RenderOptions,
+    convert: &impl Fn(PathBuf, PathBuf, u32, &RenderOptions) -> Result<PageMetadata, PdfError>,
+) -> PageStatus {
+    let output = page_path(template, page);
+    let part = part_path(&output);
+    let result = convert(input.to_owned(), part.clone(), page, options).and_then(|metadata| {
+        std::fs::rename(&part, &output).map_err(|e| PdfError::Other {
+            msg: format!("can not rename {:?} to {:?}: {}", part, output, e),
+        })?;
+        Ok(metadata)
+    });
+    match result {
+        Ok(metadata) => PageStatus::Converted(Box::new(metadata)),
+        Err(e) => {
+            error!("page {} failed: {}", page, e);
+            if part.exists() {
+                if let Err(e) = std::fs::remove_file(&part) {
+                    warn!("can not remove {:?}: {}", part, e);
+                }
+            }
+            PageStatus::Failed(e.to_string())
+        }
+    }
+}
+
+// Convert the pages on batch.jobs threads, each page with its own context. A
+// failed page leaves no output behind. Without fail_fast the other pages are
+// converted anyway, with it no page is started after the first failure. The
+// converted pages are merged into report, the status of every page that was
+// attempted is returned in page order. Every page is written to its own file,
+// so the output does not depend on the number of jobs.
+pub fn convert_pages(
+    input: &Path,
+    template: &Path,
+    pages: &[u32],
+    options: &RenderOptions,
+    batch: BatchOptions,
+    report: &Report,
+    convert: impl Fn(PathBuf, PathBuf, u32, &RenderOptions) -> Result<PageMetadata, PdfError> + Sync,
+) -> Vec<(u32, PageStatus)> {
+    let (next, failed) = (AtomicUsize::new(0), AtomicBool::new(false));
+    let statuses = Mutex::new(vec![]);
+    let worker = || loop {
+        if batch.fail_fast && failed.load(Ordering::SeqCst) {
+            break;
+        }
+        let Some(&page) = pages.get(next.fetch_add(1, Ordering::SeqCst)) else {
+            break;
+        };
+        let status = convert_page(input, template, page, options, &convert);
+        match status {
+            PageStatus::Converted(ref metadata) => report.add(PageReport {
+                page,
+                warnings: metadata.warnings.clone(),
+                stats: metadata.stats.clone(),
+            }),
+            PageStatus::Failed(_) => failed.store(true, Ordering::SeqCst),
+        }
+        statuses.lock().unwrap().push((page, status));
+    };
+    std::thread::scope(|scope| {
+        for _ in 1..batch.jobs {
+            scope.spawn(worker);
+        }
+        worker();
+    });
+    let mut statuses = statuses.into_inner().unwrap();
+    statuses.sort_by_key(|&(page, _)| page);
+    statuses
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{convert_pages, page_path, parse_pages, part_path, BatchOptions, PageSelection, PageStatus};
+    use crate::{context::Report, options::RenderOptions};
+
+    #[test]
+    fn selections() {
+        assert_eq!(parse_pages("all").unwrap(), PageSelection::All);
+        let pages = parse_pages("3,0-1,1").unwrap();
+        assert_eq!(pages.pages(4).unwrap(), [0, 1, 3]);
+        assert!(pages.pages(3).is_err());
+        assert!(parse_pages("2-1").is_err());
+        assert!(parse_pages("one").is_err());
+
+        assert_eq!(page_path(Path::new("out/p{page}.png"), 7), Path::new("out/p7.png"));
+        assert_eq!(part_path(Path::new("out/p7.png")), Path::new("out/.p7.part.png"));
+    }
+
+    #[test]
+    fn corrupt_page() {
+        let dir = std::env::temp_dir().join(format!("pdf2svg-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = Path::new("resources/corrupt_page.pdf");
+        let template = dir.join("page{page}.svg");
+        let options = RenderOptions::new();
+        let converted = |report: &[(u32, PageStatus)]| {
+            report.iter().filter(|(_, s)| matches!(s, PageStatus::Converted(_))).map(|&(p, _)| p).collect::<Vec<_>>()
+        };
+
+        // the second page has a broken content stream, the others are converted
+        let run = Report::new();
+        let report = convert_pages(input, &template, &[0, 1, 2], &options, BatchOptions::default(), &run, crate::convert);
+        assert_eq!(report.len(), 3);
+        assert_eq!(converted(&report), [0, 2]);
+        assert!(page_path(&template, 0).exists() && page_path(&template, 2).exists());
+        assert!(!page_path(&template, 1).exists());
+        let pages: Vec<u32> = run.into_pages().iter().map(|p| p.page).collect();
+        assert_eq!(pages, [0, 2]);
+
+        for page in [0, 2] {
+            std::fs::remove_file(page_path(&template, page)).unwrap();
+        }
+        let fail_fast = BatchOptions { fail_fast: true, ..BatchOptions::default() };
+        let report = convert_pages(input, &template, &[0, 1, 2], &options, fail_fast, &Report::new(), crate::convert);
+        assert_eq!(report.len(), 2);
+        assert_eq!(converted(&report), [0]);
+        assert!(!page_path(&template, 2).exists());
+        // no partial files are left
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+use pathfinder_content::{fill::FillRule, outline::Outline};
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+
+use crate::geometry::is_offpage;
+use crate::plotter::{DrawMode, LinearGradient, Plotter, RgbaImage, Tiling};
+
+// Collects the device space bounds of everything that is painted. Strokes count
+// with their width, caps, joins and dashes, the same outline the other plotters fill.
+#[derive(Default)]
+pub struct BoundsPlotter {
+    bounds: Option<RectF>,
+    // bounds of the clip paths, the index is the clip path id
+    clips: Vec<RectF>,
+    // the page box and how many of its diagonals content may be away from it
+    page: Option<(RectF, f32)>,
+    offpage: usize,
+}
+
+impl BoundsPlotter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // leave out what is drawn far outside the page, see geometry::is_offpage
+    pub fn offpage(mut self, page: RectF, factor: f32) -> Self {
+        self.page = Some((page, factor));
+        self
+    }
+    pub fn bounds(&self) -> Option<RectF> {
+        self.bounds
+    }
+    // number of draws that were left out
+    pub fn offpage_draws(&self) -> usize {
+        self.offpage
+    }
+    fn add(&mut self, rect: RectF, clip: Option<usize>) {
+        let rect = match clip {
+            Some(clip) => match rect.intersection(self.clips[clip]) {
+                Some(rect) => rect,
+                None => return,
+            },
+            None => rect,
+        };
+        if let Some((page, factor)) = self.page {
+            if is_offpage(rect, page, factor) {
+                self.offpage += 1;
+                return;
+            }
+        }
+        self.bounds = Some(self.bounds.map_or(rect, |b| b.union_rect(rect)));
+    }
+}
+
+impl Plotter for BoundsPlotter {
+    type ClipPathId = usize;
+    fn draw_image(&mut self, _: &RgbaImage, transform: Transform2F, clip: Option<Self::ClipPathId>, _: f32, _: bool) {
+        self.add(transform * RectF::new(Vector2F::zero(), Vector2F::splat(1.)), clip);
+    }
+    fn draw_gradient(&mut self, outline: Outline, _: &LinearGradient, clip: Option<Self::ClipPathId>) {
+        self.add(outline.bounds(), clip);
+    }
+    fn draw_tiling(&mut self, outline: Outline, _: FillRule, _: &Tiling, clip: Option<Self::ClipPathId>) {
+        self.add(outline.bounds(), clip);
+    }
+    fn create_clip_path(&mut self, outline: Outline, _: FillRule, parent: Option<Self::ClipPathId>) -> Self::ClipPathId {
+        let bounds = match parent {
+            Some(parent) => outline.bounds().intersection(self.clips[parent]).unwrap_or_default(),
+            None => outline.bounds(),
+        };
+        self.clips.push(bounds);
+        self.clips.len() - 1
+    }
+    fn draw(&mut self, outline: &Outline, mode: &DrawMode, _: FillRule, transform: Transform2F, clip: Option<Self::ClipPathId>) {
+        match mode {
+            DrawMode::Fill { .. } => self.add(outline.clone().transformed(&transform).bounds(), clip),
+            DrawMode::Stroke { stroke_mode, .. } | DrawMode::FillStroke { stroke_mode, .. } => {
+                let stroke = stroke_mode.to_outline(outline).transformed(&transform);
+                self.add(stroke.bounds(), clip);
+                if let DrawMode::FillStroke { .. } = mode {
+                    self.add(outline.clone().transformed(&transform).bounds(), clip);
+                }
+            }
+        }
+    }
+}
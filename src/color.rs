@@ -0,0 +1,54 @@
+// CMYK to RGB without a color profile. Fills and image samples go through the
+// same conversion, so vector and raster content of one color match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CmykToRgb {
+    // 1 - min(1, c + k) for every component, as viewers show unmanaged cmyk
+    Subtractive,
+    // (1 - c) * (1 - k), blending in rgb then gives the same result as blending
+    // the complements in a cmyk group, for the separable blend modes
+    Multiplicative,
+}
+
+impl CmykToRgb {
+    // the multiplicative conversion for colors painted with a blend mode in a cmyk group
+    pub fn new(group_blending: bool) -> Self {
+        match group_blending {
+            true => CmykToRgb::Multiplicative,
+            false => CmykToRgb::Subtractive,
+        }
+    }
+    pub fn rgb(self, [c, m, y, k]: [f32; 4]) -> [f32; 3] {
+        match self {
+            CmykToRgb::Subtractive => [c, m, y].map(|v| 1.0 - (v + k).min(1.0)),
+            CmykToRgb::Multiplicative => [c, m, y].map(|v| (1.0 - v) * (1.0 - k)),
+        }
+    }
+    // the same on 8 bit samples, called for every pixel of an image
+    #[inline]
+    pub fn rgb8(self, [c, m, y, k]: [u8; 4]) -> [u8; 3] {
+        match self {
+            CmykToRgb::Subtractive => [c, m, y].map(|v| 255 - v.saturating_add(k)),
+            CmykToRgb::Multiplicative => [c, m, y].map(|v| (((255 - v) as u32 * (255 - k) as u32 + 127) / 255) as u8),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CmykToRgb;
+
+    #[test]
+    fn samples_match_fills() {
+        // every 8 bit sample comes out as the fill color of the same value would
+        for conversion in [CmykToRgb::Subtractive, CmykToRgb::Multiplicative] {
+            for v in (0..=255u8).step_by(5) {
+                for k in (0..=255u8).step_by(15) {
+                    let cmyk = [v, 255 - v, v / 2, k];
+                    let fill = conversion.rgb(cmyk.map(|c| c as f32 / 255.)).map(|c| (c * 255.).round() as u8);
+                    assert_eq!(conversion.rgb8(cmyk), fill, "{:?} {:?}", conversion, cmyk);
+                }
+            }
+        }
+        assert_eq!(CmykToRgb::new(true), CmykToRgb::Multiplicative);
+    }
+}
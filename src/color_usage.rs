@@ -0,0 +1,75 @@
+use serde::Serialize;
+
+use crate::plotter::{Fill, RgbaImage};
+
+// channels that differ by at most this much, out of 255, are still gray
+pub const GRAY_TOLERANCE: u8 = 2;
+
+pub fn is_gray(r: u8, g: u8, b: u8) -> bool {
+    r.max(g).max(b) - r.min(g).min(b) <= GRAY_TOLERANCE
+}
+
+// a solid color that is not gray, patterns are judged by their cells
+pub fn is_colored(fill: &Fill) -> bool {
+    match *fill {
+        Fill::Solid(r, g, b) => {
+            let [r, g, b] = [r, g, b].map(|c| (c.clamp(0., 1.) * 255.).round() as u8);
+            !is_gray(r, g, b)
+        }
+        Fill::Pattern(_) => false,
+    }
+}
+
+// the share of the pixels of an image that paint in color
+pub fn colored_fraction(image: &RgbaImage) -> f32 {
+    let pixels = image.pixels.chunks_exact(4);
+    let total = pixels.len();
+    let colored = pixels.filter(|p| p[3] > 0 && !is_gray(p[0], p[1], p[2])).count();
+    match total {
+        0 => 0.,
+        total => colored as f32 / total as f32,
+    }
+}
+
+// an opaque image without color can be written as a gray image
+pub fn is_monochrome(image: &RgbaImage) -> bool {
+    image.pixels.chunks_exact(4).all(|p| p[3] == 255 && is_gray(p[0], p[1], p[2]))
+}
+
+// Does the page paint in color, and how much of it. Vector content counts with
+// the bounds of its draw calls, images with their colored pixels, so overlaps
+// count twice and the percentage is an estimate.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct ColorUsage {
+    pub color: bool,
+    pub colored_percent: f32,
+}
+
+impl ColorUsage {
+    // the colored area and the page area in device pixels
+    pub fn new(colored_area: f32, page_area: f32) -> Self {
+        let percent = match page_area > 0. {
+            true => (100. * colored_area / page_area).min(100.),
+            false => 0.,
+        };
+        ColorUsage { color: colored_area > 0., colored_percent: percent }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{colored_fraction, is_colored, is_monochrome, ColorUsage};
+    use crate::plotter::{Fill, RgbaImage};
+
+    #[test]
+    fn gray_and_color() {
+        assert!(!is_colored(&Fill::Solid(0.5, 0.5, 0.501)));
+        assert!(is_colored(&Fill::Solid(1., 0., 0.)));
+        let image = RgbaImage { width: 4, height: 1, pixels: [[0, 0, 0, 255], [200, 10, 10, 255], [9, 8, 10, 255], [255, 0, 0, 0]].concat() };
+        // the transparent red pixel paints nothing
+        assert_eq!(colored_fraction(&image), 0.25);
+        assert!(!is_monochrome(&image));
+        assert_eq!(ColorUsage::new(0., 100.), ColorUsage { color: false, colored_percent: 0. });
+        assert_eq!(ColorUsage::new(300., 100.).colored_percent, 100.);
+    }
+}
@@ -88,7 +88,7 @@ impl Shader {
                     ptr::null_mut(),
                     info_log.as_mut_ptr() as *mut _,
                 );
-                eprintln!(
+                error!(
                     "Failed to compile shader:\n{}",
                     String::from_utf8_lossy(&info_log)
                 );
@@ -157,7 +157,7 @@ pub struct FilesystemResourceLoader;
 impl ResourceLoader for FilesystemResourceLoader {
     fn slurp(&self, dest: &mut Vec<u8>, filename: &str) {
         let path = format!("resources/examples/{}", filename);
-        println!("Loading {}", path);
+        debug!("Loading {}", path);
         File::open(&path)
             .expect("Failed to open file!")
             .read_to_end(dest)
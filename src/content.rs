@@ -0,0 +1,122 @@
+use pdf::{
+    content::{parse_ops, Content, Op},
+    object::Resolve,
+    PdfError,
+};
+
+// Lexer state at the end of a content stream part.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Boundary {
+    Token,
+    // nesting depth of the parentheses
+    Literal(usize),
+    Hex,
+}
+
+fn scan(data: &[u8], mut state: Boundary) -> Boundary {
+    let mut i = 0;
+    while i < data.len() {
+        let c = data[i];
+        state = match state {
+            Boundary::Literal(depth) => match c {
+                b'\\' => {
+                    i += 1;
+                    state
+                }
+                b'(' => Boundary::Literal(depth + 1),
+                b')' if depth == 1 => Boundary::Token,
+                b')' => Boundary::Literal(depth - 1),
+                _ => state,
+            },
+            Boundary::Hex if c == b'>' => Boundary::Token,
+            Boundary::Hex => state,
+            Boundary::Token => match c {
+                b'(' => Boundary::Literal(1),
+                b'<' if data.get(i + 1) == Some(&b'<') => {
+                    i += 1;
+                    state
+                }
+                b'<' => Boundary::Hex,
+                b'%' => {
+                    // a comment runs to the end of the line
+                    while i < data.len() && data[i] != b'\n' && data[i] != b'\r' {
+                        i += 1;
+                    }
+                    state
+                }
+                _ => state,
+            },
+        };
+        i += 1;
+    }
+    state
+}
+
+// The parts of /Contents form one stream. Parts are separated by a newline, so
+// tokens at the boundary and comments at the end of a part stay apart, except
+// when a part ends inside a string: that string continues in the next part.
+pub fn concat_parts<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> Vec<u8> {
+    let mut data = vec![];
+    let mut state = Boundary::Token;
+    for part in parts {
+        if !data.is_empty() && state == Boundary::Token {
+            data.push(b'\n');
+        }
+        state = scan(part, state);
+        data.extend_from_slice(part);
+    }
+    data
+}
+
+pub fn operations(contents: &Content, resolve: &impl Resolve) -> Result<Vec<Op>, PdfError> {
+    let parts = contents
+        .parts
+        .iter()
+        .map(|part| part.data(resolve))
+        .collect::<Result<Vec<_>, _>>()?;
+    let data = concat_parts(parts.iter().map(|p| &**p));
+    parse_ops(&data, resolve)
+}
+
+#[cfg(test)]
+mod test {
+    use pdf::{
+        content::{parse_ops, Op},
+        object::NoResolve,
+    };
+
+    use super::concat_parts;
+
+    fn ops(parts: &[&str]) -> Vec<Op> {
+        let data = concat_parts(parts.iter().map(|p| p.as_bytes()));
+        parse_ops(&data, &NoResolve).unwrap()
+    }
+
+    #[test]
+    fn string_split_across_parts() {
+        let ops = ops(&["BT /F1 12 Tf (Hel", "lo \\) w(orld)) Tj ET"]);
+        let text = ops.iter().find_map(|op| match op {
+            Op::TextDraw { text } => Some(text.as_bytes().to_vec()),
+            _ => None,
+        });
+        assert_eq!(text.as_deref(), Some(&b"Hello ) w(orld)"[..]));
+    }
+
+    #[test]
+    fn state_carries_over() {
+        // the color and the open path of the first part are used in the second
+        let ops = ops(&["1 0 0 RG 0 0 m", "10 10 l S % comment", "0 0 m"]);
+        assert!(matches!(ops[0], Op::StrokeColor { .. }));
+        assert!(matches!(ops[1], Op::MoveTo { .. }));
+        assert!(matches!(ops[2], Op::LineTo { .. }));
+        assert!(matches!(ops[3], Op::Stroke));
+        assert!(matches!(ops[4], Op::MoveTo { .. }));
+    }
+
+    #[test]
+    fn parts_are_separated() {
+        assert_eq!(concat_parts([&b"q"[..], b"Q"]), b"q\nQ");
+        assert_eq!(concat_parts([&b"<< /A 1 >> BDC (a"[..], b"b) Tj"]), b"<< /A 1 >> BDC (ab) Tj");
+        assert_eq!(concat_parts([&b"<41"[..], b"42> Tj"]), b"<4142> Tj");
+    }
+}
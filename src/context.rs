@@ -0,0 +1,65 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use pdf::object::PlainRef;
+
+use crate::extract::PageImage;
+use crate::plotter::Tiling;
+use crate::render::RenderStats;
+
+// Everything collected while rendering one page. Each page owns its context, so
+// pages rendered in parallel can not mix their warnings or counters.
+#[derive(Debug, Default)]
+pub struct PageContext {
+    pub page: u32,
+    pub warnings: Vec<String>,
+    pub unsupported: HashSet<&'static str>,
+    pub stats: RenderStats,
+    // rendered cells of the tiling patterns by pattern space, None if the pattern is not supported
+    pub tilings: HashMap<(PlainRef, [u32; 6]), Option<Arc<Tiling>>>,
+    // the form xobjects that are executing, innermost last
+    pub forms: Vec<PlainRef>,
+    // every drawn image in order, collected for extract-images
+    pub images: Option<Vec<PageImage>>,
+    // the operators for --dump-ops, written out when the page is done
+    pub dump_ops: Option<String>,
+}
+
+impl PageContext {
+    pub fn new(page: u32) -> Self {
+        PageContext {
+            page,
+            ..PageContext::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PageReport {
+    pub page: u32,
+    pub warnings: Vec<String>,
+    pub stats: RenderStats,
+}
+
+// Run level report, the finished pages are merged in under a lock.
+#[derive(Debug, Default)]
+pub struct Report {
+    pages: Mutex<Vec<PageReport>>,
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn add(&self, page: PageReport) {
+        self.pages.lock().unwrap().push(page);
+    }
+    // the pages in page order, independent of the order they finished in
+    pub fn into_pages(self) -> Vec<PageReport> {
+        let mut pages = self.pages.into_inner().unwrap();
+        pages.sort_by_key(|p| p.page);
+        pages
+    }
+}
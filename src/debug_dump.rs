@@ -0,0 +1,136 @@
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use pathfinder_geometry::rect::RectF;
+use pdf::PdfError;
+use serde::Serialize;
+
+use crate::metadata::write_json;
+use crate::options::RenderOptions;
+use crate::output::{DrawCall, DrawStream, OutputFormat, VectorFormat};
+use crate::plotter::{DrawMode, RgbaImage};
+use crate::png;
+
+// What --debug-dump collected and where the conversion stopped.
+#[derive(Serialize, Debug)]
+pub struct Manifest {
+    pub input: String,
+    pub page: u32,
+    pub output: String,
+    // the last stage that was started: open, render or write
+    pub stage: &'static str,
+    pub error: Option<String>,
+    pub warnings: Vec<String>,
+    // relative to the dump directory
+    pub files: Vec<String>,
+}
+
+// Everything a maintainer needs from a failed or wrong conversion in one
+// directory: the operators, the draw calls, the decoded images and, when the
+// output could not be written, the scene as svg.
+pub struct DebugDump {
+    dir: PathBuf,
+    pub manifest: Manifest,
+}
+
+impl DebugDump {
+    pub fn create(dir: &Path, input: &Path, page: u32, output: &Path) -> Result<Self, PdfError> {
+        fs::create_dir_all(dir).map_err(|e| dump_error(dir, e))?;
+        let manifest = Manifest {
+            input: input.display().to_string(),
+            page,
+            output: output.display().to_string(),
+            stage: "open",
+            error: None,
+            warnings: vec![],
+            files: vec![],
+        };
+        Ok(DebugDump { dir: dir.to_path_buf(), manifest })
+    }
+    // a new file in the dump, listed in the manifest
+    pub fn file(&mut self, name: &str) -> Result<BufWriter<File>, PdfError> {
+        let path = self.dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| dump_error(parent, e))?;
+        }
+        let file = File::create(&path).map_err(|e| dump_error(&path, e))?;
+        self.manifest.files.push(name.into());
+        Ok(BufWriter::new(file))
+    }
+    pub fn stage(&mut self, stage: &'static str) {
+        self.manifest.stage = stage;
+    }
+    // one line per draw call, the images go next to it as png
+    pub fn draw_calls(&mut self, stream: &DrawStream) -> Result<(), PdfError> {
+        let mut trace = String::new();
+        let mut images = 0;
+        for call in &stream.calls {
+            let image = match *call {
+                DrawCall::Image { ref image, .. } | DrawCall::Layer { ref image, .. } => {
+                    images += 1;
+                    Some((format!("images/image{}.png", images - 1), image))
+                }
+                _ => None,
+            };
+            let _ = writeln!(trace, "{}", describe(call, image.as_ref().map(|(name, _)| name.as_str())));
+            if let Some((name, image)) = image {
+                self.image(&name, image)?;
+            }
+        }
+        let mut file = self.file("draw_calls.txt")?;
+        file.write_all(trace.as_bytes()).map_err(|e| dump_error(&self.dir, e))
+    }
+    fn image(&mut self, name: &str, image: &RgbaImage) -> Result<(), PdfError> {
+        let file = self.file(name)?;
+        png::write_png_filtered(file, image.width, image.height, &image.pixels, None, None, false)
+    }
+    // the scene as svg, for output that failed to rasterize
+    pub fn scene_svg(&mut self, stream: &DrawStream, options: &RenderOptions) -> Result<(), PdfError> {
+        let mut file = self.file("scene.svg")?;
+        VectorFormat::Svg.write(stream, &mut file, options)?;
+        file.flush().map_err(|e| dump_error(&self.dir, e))
+    }
+    pub fn finish(mut self, error: Option<&PdfError>) -> Result<(), PdfError> {
+        self.manifest.error = error.map(|e| e.to_string());
+        self.manifest.files.push("manifest.json".into());
+        write_json(&self.manifest, &self.dir.join("manifest.json"))
+    }
+}
+
+fn dump_error(path: &Path, e: std::io::Error) -> PdfError {
+    PdfError::Other {
+        msg: format!("can not write the debug dump {:?}: {}", path, e),
+    }
+}
+
+fn rect(r: RectF) -> String {
+    format!("[{:.2} {:.2} {:.2} {:.2}]", r.min_x(), r.min_y(), r.max_x(), r.max_y())
+}
+
+fn clip(clip: Option<usize>) -> String {
+    clip.map_or(String::new(), |id| format!(" clip {}", id))
+}
+
+// a line of the trace, with the device space bounds of what the call draws
+fn describe(call: &DrawCall, image: Option<&str>) -> String {
+    match *call {
+        DrawCall::BeginOp(nr) => format!("op {}", nr),
+        DrawCall::Draw { ref outline, ref mode, transform, clip: id, .. } => {
+            let kind = match *mode {
+                DrawMode::Fill { .. } => "fill",
+                DrawMode::Stroke { .. } => "stroke",
+                DrawMode::FillStroke { .. } => "fill and stroke",
+            };
+            format!("  {} {}{}", kind, rect(transform * outline.bounds()), clip(id))
+        }
+        DrawCall::ClipPath { ref outline, parent, .. } => format!("  clip path {}{}", rect(outline.bounds()), clip(parent)),
+        DrawCall::Image { image: ref data, clip: id, .. } | DrawCall::Layer { image: ref data, clip: id, .. } => {
+            format!("  image {}x{} {}{}", data.width, data.height, image.unwrap_or(""), clip(id))
+        }
+        DrawCall::Gradient { ref outline, clip: id, .. } => format!("  gradient {}{}", rect(outline.bounds()), clip(id)),
+        DrawCall::Tiling { ref outline, clip: id, .. } => format!("  tiling {}{}", rect(outline.bounds()), clip(id)),
+        DrawCall::MarkedContent(context) => format!("  marked content mcid {:?} artifact {}", context.mcid, context.artifact),
+    }
+}
@@ -0,0 +1,95 @@
+use pathfinder_geometry::{rect::RectI, vector::Vector2I};
+
+use crate::plotter::RgbaImage;
+
+pub struct DiffResult {
+    pub changed: usize,
+    // bounds of the changed pixels
+    pub bounds: Option<RectI>,
+    // changed pixels in red over the dimmed new image
+    pub image: RgbaImage,
+}
+
+const WHITE: [u8; 4] = [255; 4];
+const RED: [u8; 4] = [255, 0, 0, 255];
+
+fn pixel(image: &RgbaImage, x: u32, y: u32) -> [u8; 4] {
+    if x >= image.width || y >= image.height {
+        return WHITE;
+    }
+    let i = 4 * (y * image.width + x) as usize;
+    [image.pixels[i], image.pixels[i + 1], image.pixels[i + 2], image.pixels[i + 3]]
+}
+
+fn dim([r, g, b, _]: [u8; 4]) -> [u8; 4] {
+    let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+    let v = (255. - (255. - luma) * 0.3) as u8;
+    [v, v, v, 255]
+}
+
+// Compare two renderings pixel by pixel. Images of different size are compared on
+// the larger canvas, the missing area counts as white. A pixel changed when one
+// channel differs by more than tolerance.
+pub fn diff(a: &RgbaImage, b: &RgbaImage, tolerance: u8) -> DiffResult {
+    let (width, height) = (a.width.max(b.width), a.height.max(b.height));
+    let mut pixels = Vec::with_capacity(4 * (width * height) as usize);
+    let mut changed = 0;
+    let mut bounds: Option<RectI> = None;
+    for y in 0..height {
+        for x in 0..width {
+            let (pa, pb) = (pixel(a, x, y), pixel(b, x, y));
+            if pa.iter().zip(pb.iter()).any(|(&ca, &cb)| ca.abs_diff(cb) > tolerance) {
+                changed += 1;
+                let p = RectI::new(Vector2I::new(x as i32, y as i32), Vector2I::splat(1));
+                bounds = Some(bounds.map_or(p, |r| r.union_rect(p)));
+                pixels.extend_from_slice(&RED);
+            } else {
+                pixels.extend_from_slice(&dim(pb));
+            }
+        }
+    }
+    DiffResult {
+        changed,
+        bounds,
+        image: RgbaImage { width, height, pixels },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pathfinder_geometry::{rect::RectI, vector::Vector2I};
+
+    use crate::plotter::RgbaImage;
+
+    fn white(width: u32, height: u32) -> RgbaImage {
+        RgbaImage { width, height, pixels: vec![255; 4 * (width * height) as usize] }
+    }
+
+    #[test]
+    fn changed_pixels() {
+        let a = white(4, 3);
+        let mut b = white(4, 3);
+        // black pixels at (1, 0) and (2, 2), a slightly gray one at (0, 0)
+        b.pixels[4..8].copy_from_slice(&[0, 0, 0, 255]);
+        b.pixels[40..44].copy_from_slice(&[0, 0, 0, 255]);
+        b.pixels[0..4].copy_from_slice(&[250, 250, 250, 255]);
+
+        let d = super::diff(&a, &b, 8);
+        assert_eq!(d.changed, 2);
+        assert_eq!(d.bounds, Some(RectI::new(Vector2I::new(1, 0), Vector2I::new(2, 3))));
+        assert_eq!(&d.image.pixels[4..8], &[255, 0, 0, 255]);
+
+        let d = super::diff(&a, &a, 0);
+        assert_eq!(d.changed, 0);
+        assert_eq!(d.bounds, None);
+    }
+
+    #[test]
+    fn different_sizes() {
+        let mut b = white(2, 2);
+        b.pixels[12..16].copy_from_slice(&[0, 0, 0, 255]);
+        let d = super::diff(&white(1, 1), &b, 0);
+        assert_eq!((d.image.width, d.image.height), (2, 2));
+        assert_eq!(d.changed, 1);
+    }
+}
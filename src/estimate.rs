@@ -0,0 +1,93 @@
+use std::fmt;
+
+use serde::Serialize;
+
+// A rough class of the png size, from the uncompressed rgba pixels. How well
+// a page compresses depends on what is drawn on it.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeClass {
+    // under 4 MiB of pixels
+    Small,
+    // under 32 MiB
+    Medium,
+    // under 256 MiB
+    Large,
+    Huge,
+}
+
+impl SizeClass {
+    pub fn of(width: u32, height: u32) -> Self {
+        match 4 * width as u64 * height as u64 {
+            b if b < 4 << 20 => SizeClass::Small,
+            b if b < 32 << 20 => SizeClass::Medium,
+            b if b < 256 << 20 => SizeClass::Large,
+            _ => SizeClass::Huge,
+        }
+    }
+}
+
+impl fmt::Display for SizeClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SizeClass::Small => "small",
+            SizeClass::Medium => "medium",
+            SizeClass::Large => "large",
+            SizeClass::Huge => "huge",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PageSize {
+    pub page: u32,
+    // pixels of the png output
+    pub width: u32,
+    pub height: u32,
+    pub size_class: SizeClass,
+    // the media box as left bottom right top, in pdf units
+    pub media_box: [f32; 4],
+    pub rotate: i32,
+}
+
+impl PageSize {
+    pub fn new(page: u32, (width, height): (u32, u32), media_box: [f32; 4], rotate: i32) -> Self {
+        PageSize { page, width, height, size_class: SizeClass::of(width, height), media_box, rotate }
+    }
+}
+
+impl fmt::Display for PageSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [left, bottom, right, top] = self.media_box;
+        write!(
+            f,
+            "page {}: {}x{} px, {}, media box {} {} {} {}",
+            self.page, self.width, self.height, self.size_class, left, bottom, right, top
+        )?;
+        if self.rotate != 0 {
+            write!(f, ", rotate {}", self.rotate)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PageSize, SizeClass};
+
+    #[test]
+    fn size_classes() {
+        assert_eq!(SizeClass::of(612, 792), SizeClass::Small);
+        // letter at 300 dpi
+        assert_eq!(SizeClass::of(2550, 3300), SizeClass::Medium);
+        assert_eq!(SizeClass::of(10200, 13200), SizeClass::Huge);
+        assert_eq!(SizeClass::of(0, 0), SizeClass::Small);
+    }
+
+    #[test]
+    fn display() {
+        let size = PageSize::new(2, (200, 100), [0., 0., 100., 200.], 90);
+        assert_eq!(size.to_string(), "page 2: 200x100 px, small, media box 0 0 100 200, rotate 90");
+    }
+}
@@ -0,0 +1,66 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use pdf::{
+    enc::StreamFilter,
+    object::{PlainRef, Ref, Resolve, XObject},
+    PdfError,
+};
+
+use crate::{plotter::RgbaImage, png};
+
+// an image as the page drew it, with the reference of its xobject, inline images have none
+pub type PageImage = (Option<PlainRef>, Arc<RgbaImage>);
+
+// the stream of a jpeg image xobject as it is stored in the file, None for other images
+pub fn jpeg_data(resolve: &impl Resolve, r: PlainRef) -> Result<Option<Arc<[u8]>>, PdfError> {
+    let xobject = resolve.get(Ref::<XObject>::new(r))?;
+    let XObject::Image(ref image) = *xobject else {
+        return Ok(None);
+    };
+    let (data, filter) = image.raw_image_data(resolve)?;
+    Ok(matches!(filter, Some(StreamFilter::DCTDecode(_))).then_some(data))
+}
+
+// Writes the images of a page as img-p{page}-{n}.png into dir, numbered in
+// drawing order. Xobjects in seen were written before and are skipped. With
+// raw, jpeg xobjects are copied as img-p{page}-{n}.jpg.
+pub fn write_images(
+    images: &[PageImage],
+    page: u32,
+    dir: &Path,
+    seen: &mut HashSet<PlainRef>,
+    raw: Option<&impl Resolve>,
+) -> Result<Vec<PathBuf>, PdfError> {
+    let mut written = vec![];
+    for (r, image) in images {
+        if let Some(r) = *r {
+            if !seen.insert(r) {
+                continue;
+            }
+        }
+        let jpeg = match (raw, *r) {
+            (Some(resolve), Some(r)) => jpeg_data(resolve, r)?,
+            _ => None,
+        };
+        let name = format!("img-p{}-{}", page, written.len());
+        let path = dir.join(name + if jpeg.is_some() { ".jpg" } else { ".png" });
+        let io_error = |e: std::io::Error| PdfError::Other {
+            msg: format!("can not write {:?}: {}", path, e),
+        };
+        match jpeg {
+            Some(data) => std::fs::write(&path, data).map_err(io_error)?,
+            None => {
+                let file = File::create(&path).map_err(io_error)?;
+                png::write_png(BufWriter::new(file), image.width, image.height, &image.pixels, None);
+            }
+        }
+        written.push(path);
+    }
+    Ok(written)
+}
@@ -0,0 +1,333 @@
+use pdf::{enc::CCITTFaxDecodeParams, PdfError};
+
+// (code length, code, run) of the terminating and makeup codes of T.4
+const WHITE_CODES: &[(u8, u16, u32)] = &[
+    (8, 0b00110101, 0), (6, 0b000111, 1), (4, 0b0111, 2), (4, 0b1000, 3), (4, 0b1011, 4), (4, 0b1100, 5),
+    (4, 0b1110, 6), (4, 0b1111, 7), (5, 0b10011, 8), (5, 0b10100, 9), (5, 0b00111, 10), (5, 0b01000, 11),
+    (6, 0b001000, 12), (6, 0b000011, 13), (6, 0b110100, 14), (6, 0b110101, 15), (6, 0b101010, 16),
+    (6, 0b101011, 17), (7, 0b0100111, 18), (7, 0b0001100, 19), (7, 0b0001000, 20), (7, 0b0010111, 21),
+    (7, 0b0000011, 22), (7, 0b0000100, 23), (7, 0b0101000, 24), (7, 0b0101011, 25), (7, 0b0010011, 26),
+    (7, 0b0100100, 27), (7, 0b0011000, 28), (8, 0b00000010, 29), (8, 0b00000011, 30), (8, 0b00011010, 31),
+    (8, 0b00011011, 32), (8, 0b00010010, 33), (8, 0b00010011, 34), (8, 0b00010100, 35), (8, 0b00010101, 36),
+    (8, 0b00010110, 37), (8, 0b00010111, 38), (8, 0b00101000, 39), (8, 0b00101001, 40), (8, 0b00101010, 41),
+    (8, 0b00101011, 42), (8, 0b00101100, 43), (8, 0b00101101, 44), (8, 0b00000100, 45), (8, 0b00000101, 46),
+    (8, 0b00001010, 47), (8, 0b00001011, 48), (8, 0b01010010, 49), (8, 0b01010011, 50), (8, 0b01010100, 51),
+    (8, 0b01010101, 52), (8, 0b00100100, 53), (8, 0b00100101, 54), (8, 0b01011000, 55), (8, 0b01011001, 56),
+    (8, 0b01011010, 57), (8, 0b01011011, 58), (8, 0b01001010, 59), (8, 0b01001011, 60), (8, 0b00110010, 61),
+    (8, 0b00110011, 62), (8, 0b00110100, 63), (5, 0b11011, 64), (5, 0b10010, 128), (6, 0b010111, 192),
+    (7, 0b0110111, 256), (8, 0b00110110, 320), (8, 0b00110111, 384), (8, 0b01100100, 448),
+    (8, 0b01100101, 512), (8, 0b01101000, 576), (8, 0b01100111, 640), (9, 0b011001100, 704),
+    (9, 0b011001101, 768), (9, 0b011010010, 832), (9, 0b011010011, 896), (9, 0b011010100, 960),
+    (9, 0b011010101, 1024), (9, 0b011010110, 1088), (9, 0b011010111, 1152), (9, 0b011011000, 1216),
+    (9, 0b011011001, 1280), (9, 0b011011010, 1344), (9, 0b011011011, 1408), (9, 0b010011000, 1472),
+    (9, 0b010011001, 1536), (9, 0b010011010, 1600), (6, 0b011000, 1664), (9, 0b010011011, 1728),
+];
+const BLACK_CODES: &[(u8, u16, u32)] = &[
+    (10, 0b0000110111, 0), (3, 0b010, 1), (2, 0b11, 2), (2, 0b10, 3), (3, 0b011, 4), (4, 0b0011, 5),
+    (4, 0b0010, 6), (5, 0b00011, 7), (6, 0b000101, 8), (6, 0b000100, 9), (7, 0b0000100, 10),
+    (7, 0b0000101, 11), (7, 0b0000111, 12), (8, 0b00000100, 13), (8, 0b00000111, 14), (9, 0b000011000, 15),
+    (10, 0b0000010111, 16), (10, 0b0000011000, 17), (10, 0b0000001000, 18), (11, 0b00001100111, 19),
+    (11, 0b00001101000, 20), (11, 0b00001101100, 21), (11, 0b00000110111, 22), (11, 0b00000101000, 23),
+    (11, 0b00000010111, 24), (11, 0b00000011000, 25), (12, 0b000011001010, 26), (12, 0b000011001011, 27),
+    (12, 0b000011001100, 28), (12, 0b000011001101, 29), (12, 0b000001101000, 30), (12, 0b000001101001, 31),
+    (12, 0b000001101010, 32), (12, 0b000001101011, 33), (12, 0b000011010010, 34), (12, 0b000011010011, 35),
+    (12, 0b000011010100, 36), (12, 0b000011010101, 37), (12, 0b000011010110, 38), (12, 0b000011010111, 39),
+    (12, 0b000001101100, 40), (12, 0b000001101101, 41), (12, 0b000011011010, 42), (12, 0b000011011011, 43),
+    (12, 0b000001010100, 44), (12, 0b000001010101, 45), (12, 0b000001010110, 46), (12, 0b000001010111, 47),
+    (12, 0b000001100100, 48), (12, 0b000001100101, 49), (12, 0b000001010010, 50), (12, 0b000001010011, 51),
+    (12, 0b000000100100, 52), (12, 0b000000110111, 53), (12, 0b000000111000, 54), (12, 0b000000100111, 55),
+    (12, 0b000000101000, 56), (12, 0b000001011000, 57), (12, 0b000001011001, 58), (12, 0b000000101011, 59),
+    (12, 0b000000101100, 60), (12, 0b000001011010, 61), (12, 0b000001100110, 62), (12, 0b000001100111, 63),
+    (10, 0b0000001111, 64), (12, 0b000011001000, 128), (12, 0b000011001001, 192), (12, 0b000001011011, 256),
+    (12, 0b000000110011, 320), (12, 0b000000110100, 384), (12, 0b000000110101, 448),
+    (13, 0b0000001101100, 512), (13, 0b0000001101101, 576), (13, 0b0000001001010, 640),
+    (13, 0b0000001001011, 704), (13, 0b0000001001100, 768), (13, 0b0000001001101, 832),
+    (13, 0b0000001110010, 896), (13, 0b0000001110011, 960), (13, 0b0000001110100, 1024),
+    (13, 0b0000001110101, 1088), (13, 0b0000001110110, 1152), (13, 0b0000001110111, 1216),
+    (13, 0b0000001010010, 1280), (13, 0b0000001010011, 1344), (13, 0b0000001010100, 1408),
+    (13, 0b0000001010101, 1472), (13, 0b0000001011010, 1536), (13, 0b0000001011011, 1600),
+    (13, 0b0000001100100, 1664), (13, 0b0000001100101, 1728),
+];
+// makeup codes for runs from 1792 on, the same for both colors
+const EXTENDED_CODES: &[(u8, u16, u32)] = &[
+    (11, 0b00000001000, 1792), (11, 0b00000001100, 1856), (11, 0b00000001101, 1920),
+    (12, 0b000000010010, 1984), (12, 0b000000010011, 2048), (12, 0b000000010100, 2112),
+    (12, 0b000000010101, 2176), (12, 0b000000010110, 2240), (12, 0b000000010111, 2304),
+    (12, 0b000000011100, 2368), (12, 0b000000011101, 2432), (12, 0b000000011110, 2496),
+    (12, 0b000000011111, 2560),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Pass,
+    Horizontal,
+    // a1 relative to b1
+    Vertical(i32),
+}
+
+const MODE_CODES: &[(u8, u16, Mode)] = &[
+    (1, 0b1, Mode::Vertical(0)),
+    (3, 0b011, Mode::Vertical(1)),
+    (3, 0b010, Mode::Vertical(-1)),
+    (3, 0b001, Mode::Horizontal),
+    (4, 0b0001, Mode::Pass),
+    (6, 0b000011, Mode::Vertical(2)),
+    (6, 0b000010, Mode::Vertical(-2)),
+    (7, 0b0000011, Mode::Vertical(3)),
+    (7, 0b0000010, Mode::Vertical(-3)),
+];
+
+// the end of a group 4 block, two EOLs
+const EOFB: u32 = 0x001001;
+
+fn invalid(what: &str, pos: usize) -> PdfError {
+    PdfError::Other {
+        msg: format!("invalid {} in fax data at bit {}", what, pos),
+    }
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Bits<'_> {
+    fn peek(&self, n: usize) -> Option<u32> {
+        if self.pos + n > 8 * self.data.len() {
+            return None;
+        }
+        Some((self.pos..self.pos + n).fold(0, |v, i| v << 1 | (self.data[i / 8] >> (7 - i % 8) & 1) as u32))
+    }
+    fn consume(&mut self, n: usize) {
+        self.pos += n;
+    }
+    fn align(&mut self) {
+        self.pos = (self.pos + 7) / 8 * 8;
+    }
+    // the value of the next code of the tables, None at the end of the data
+    fn code<T: Copy>(&mut self, tables: &[&[(u8, u16, T)]], what: &str) -> Result<Option<T>, PdfError> {
+        for len in 1..=13 {
+            let Some(bits) = self.peek(len) else { return Ok(None) };
+            let code = tables.iter().flat_map(|t| t.iter()).find(|&&(l, c, _)| l as usize == len && c as u32 == bits);
+            if let Some(&(_, _, value)) = code {
+                self.consume(len);
+                return Ok(Some(value));
+            }
+        }
+        Err(invalid(what, self.pos))
+    }
+    // makeup codes up to the terminating code
+    fn run(&mut self, black: bool) -> Result<Option<u32>, PdfError> {
+        let codes = match black {
+            true => BLACK_CODES,
+            false => WHITE_CODES,
+        };
+        let mut total = 0;
+        loop {
+            let Some(run) = self.code(&[codes, EXTENDED_CODES], "run")? else { return Ok(None) };
+            total += run;
+            if run < 64 {
+                return Ok(Some(total));
+            }
+        }
+    }
+    // an EOL, any number of fill zeros before it
+    fn eol(&mut self) -> bool {
+        let mut zeros = 0;
+        while self.peek(zeros + 1) == Some(0) {
+            zeros += 1;
+        }
+        if zeros >= 11 && self.peek(zeros + 1) == Some(1) {
+            self.consume(zeros + 1);
+            return true;
+        }
+        false
+    }
+}
+
+// The first changing element of the reference line right of a0 that changes
+// to the other color than a0 has, and the one after it. Changes at even indices
+// are to black.
+fn b1_b2(reference: &[u32], a0: i64, black: bool, width: u32) -> (u32, u32) {
+    let i = (0..reference.len())
+        .find(|&i| reference[i] as i64 > a0 && (i % 2 == 1) == black)
+        .unwrap_or(reference.len());
+    let at = |i: usize| reference.get(i).copied().unwrap_or(width);
+    (at(i), at(i + 1))
+}
+
+// A two dimensional line as the positions where the color changes, starting
+// with white. None if the data ends first.
+fn line_2d(bits: &mut Bits, reference: &[u32], width: u32) -> Result<Option<Vec<u32>>, PdfError> {
+    let mut changes = vec![];
+    let (mut a0, mut black) = (-1i64, false);
+    while a0 < width as i64 {
+        let (b1, b2) = b1_b2(reference, a0, black, width);
+        let Some(mode) = bits.code(&[MODE_CODES], "mode")? else { return Ok(None) };
+        match mode {
+            Mode::Pass => a0 = b2 as i64,
+            Mode::Horizontal => {
+                let (Some(r1), Some(r2)) = (bits.run(black)?, bits.run(!black)?) else { return Ok(None) };
+                let a1 = a0.max(0) as u32 + r1;
+                changes.extend([a1, a1 + r2]);
+                a0 = (a1 + r2) as i64;
+            }
+            Mode::Vertical(d) => {
+                let a1 = (b1 as i64 + d as i64).clamp(0, width as i64);
+                changes.push(a1 as u32);
+                a0 = a1;
+                black = !black;
+            }
+        }
+    }
+    changes.retain(|&c| c < width);
+    Ok(Some(changes))
+}
+
+// a line of alternating white and black runs
+fn line_1d(bits: &mut Bits, width: u32) -> Result<Option<Vec<u32>>, PdfError> {
+    let mut changes = vec![];
+    let (mut pos, mut black) = (0, false);
+    while pos < width {
+        let Some(run) = bits.run(black)? else { return Ok(None) };
+        pos += run;
+        changes.push(pos);
+        black = !black;
+    }
+    changes.retain(|&c| c < width);
+    Ok(Some(changes))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Coding {
+    // < 0 group 4, 0 group 3 one dimensional, > 0 group 3 mixed
+    k: i32,
+    columns: u32,
+    byte_align: bool,
+    end_of_line: bool,
+}
+
+// the changes of each line, up to rows lines or the end of the data
+fn lines(data: &[u8], coding: Coding, rows: u32) -> Result<Vec<Vec<u32>>, PdfError> {
+    let mut bits = Bits { data, pos: 0 };
+    let mut lines: Vec<Vec<u32>> = vec![];
+    while lines.len() < rows as usize {
+        let reference = lines.last().map(|l| l.as_slice()).unwrap_or(&[]);
+        let line = match coding.k {
+            k if k < 0 => {
+                if coding.byte_align {
+                    bits.align();
+                }
+                if bits.peek(24) == Some(EOFB) {
+                    break;
+                }
+                line_2d(&mut bits, reference, coding.columns)?
+            }
+            k => {
+                // byte aligned lines without EOLs can start like one
+                let eol = (k > 0 || coding.end_of_line || !coding.byte_align) && bits.eol();
+                if !eol && coding.byte_align {
+                    bits.align();
+                }
+                // after the EOL of mixed data a bit tells 1d (1) from 2d (0)
+                let two_d = k > 0 && bits.peek(1) == Some(0);
+                if k > 0 {
+                    bits.consume(1);
+                }
+                match two_d {
+                    true => line_2d(&mut bits, reference, coding.columns)?,
+                    false => line_1d(&mut bits, coding.columns)?,
+                }
+            }
+        };
+        let Some(line) = line else { break };
+        lines.push(line);
+    }
+    Ok(lines)
+}
+
+// The lines as 1 bit rows of width pixels that start at a byte, black is 0
+// unless black_is_1. Rows after the last line are white.
+fn pack(lines: &[Vec<u32>], width: u32, height: u32, black_is_1: bool) -> Vec<u8> {
+    let stride = (width as usize + 7) / 8;
+    let white = if black_is_1 { 0x00 } else { 0xFF };
+    let mut out = vec![white; stride * height as usize];
+    for (row, line) in out.chunks_exact_mut(stride).zip(lines) {
+        for span in line.chunks(2) {
+            let start = span[0].min(width) as usize;
+            let end = span.get(1).copied().unwrap_or(width).min(width) as usize;
+            for x in start..end {
+                row[x / 8] ^= 0x80 >> (x % 8);
+            }
+        }
+    }
+    out
+}
+
+// Decodes CCITTFaxDecode data to 1 bit rows for the image and returns them
+// with the number of rows that were in the data.
+pub fn decode(data: &[u8], params: &CCITTFaxDecodeParams, width: u32, height: u32) -> Result<(Vec<u8>, u32), PdfError> {
+    let coding = Coding {
+        k: params.k,
+        columns: params.columns,
+        byte_align: params.encoded_byte_align,
+        end_of_line: params.end_of_line,
+    };
+    let rows = match params.rows {
+        0 => height,
+        rows => rows.min(height),
+    };
+    let lines = lines(data, coding, rows)?;
+    Ok((pack(&lines, width, height, params.black_is_1), lines.len() as u32))
+}
+
+// Group 4 data without filter parameters, as in the MMR coded regions of
+// JBIG2. 1 bit rows, black is 1.
+pub fn decode_g4(data: &[u8], width: u32, height: u32) -> Result<Vec<u8>, PdfError> {
+    let coding = Coding { k: -1, columns: width, byte_align: false, end_of_line: false };
+    Ok(pack(&lines(data, coding, height)?, width, height, true))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lines, pack, Coding};
+
+    const PICTURE: [&str; 5] = ["..##....#.", "..###...##", "##########", "..........", "#........#"];
+
+    fn check(data: &[u8], k: i32, byte_align: bool, end_of_line: bool) {
+        let coding = Coding { k, columns: 10, byte_align, end_of_line };
+        let lines = lines(data, coding, 5).unwrap();
+        let rows = pack(&lines, 10, 5, true);
+        for (y, expected) in PICTURE.iter().enumerate() {
+            let row: String = (0..10).map(|x| if rows[2 * y + x / 8] >> (7 - x % 8) & 1 == 1 { '#' } else { '.' }).collect();
+            assert_eq!(&row, expected, "k {} line {}", k, y);
+        }
+    }
+
+    #[test]
+    fn group_4() {
+        check(&[0x2f, 0x84, 0xb7, 0x61, 0x0c, 0x9c, 0x37, 0x26, 0xa9, 0x40, 0x04, 0x00, 0x40], -1, false, false);
+        let aligned = [0x2f, 0x84, 0xa0, 0xbb, 0x08, 0x60, 0x27, 0x0d, 0xc0, 0x26, 0xa9, 0x40, 0x04, 0x00, 0x40];
+        check(&aligned, -1, true, false);
+    }
+
+    #[test]
+    fn group_3() {
+        let one_d = [0x00, 0x17, 0xed, 0x0e, 0x00, 0x2f, 0x46, 0x00, 0x26, 0xa1, 0x00, 0x04, 0xe0, 0x02, 0x6a, 0xa6, 0x80];
+        check(&one_d, 0, false, true);
+        // every second line two dimensional, EOLs end on a byte
+        let mixed = [
+            0x00, 0x01, 0xbf, 0x68, 0x70, 0x01, 0x5d, 0x80, 0x01, 0x9a, 0x84, 0x00, 0x01, 0x13, 0x86, 0xe0, 0x01, 0x9a, 0xa9, 0xa0,
+        ];
+        check(&mixed, 2, true, true);
+    }
+
+    #[test]
+    fn short_data() {
+        let coding = Coding { k: -1, columns: 10, byte_align: false, end_of_line: false };
+        // the first two lines of the group 4 data
+        assert_eq!(lines(&[0x2f, 0x84, 0xb7, 0x61], coding, 5).unwrap(), vec![vec![2, 4, 8, 9], vec![2, 5, 8]]);
+        let rows = pack(&[vec![2, 4]], 10, 2, false);
+        assert_eq!(rows, vec![0b1100_1111, 0xFF, 0xFF, 0xFF]);
+    }
+}
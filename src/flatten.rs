@@ -0,0 +1,283 @@
+use pathfinder_content::{outline::{ContourIterFlags, Outline}, segment::Segment};
+use pathfinder_geometry::vector::Vector2F;
+use serde::Serialize;
+
+// subdivision stops at this depth even if the curve is not flat yet
+const MAX_DEPTH: u32 = 16;
+// an arc replaces at least this many points, 3 segments
+const MIN_ARC_POINTS: usize = 4;
+// longer arc segments are not a good fit for a circle
+const MAX_ARC_STEP: f32 = std::f32::consts::FRAC_PI_2;
+// larger circles are written as lines, plotters handle them badly
+const MAX_RADIUS: f32 = 1e5;
+
+// Size and fidelity of line output.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct LineStats {
+    pub lines: usize,
+    pub arcs: usize,
+    // upper bound of the distance between a curve and its flattened polyline
+    pub max_deviation: f32,
+    // largest distance between a flattened run and the arc that replaces it
+    pub max_arc_deviation: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Piece {
+    Line(Vector2F),
+    // sweep in radians, positive from the x to the y axis
+    Arc { center: Vector2F, sweep: f32, end: Vector2F },
+}
+
+fn distance_to_segment(p: Vector2F, a: Vector2F, b: Vector2F) -> f32 {
+    let d = b - a;
+    let len2 = d.square_length();
+    if len2 == 0. {
+        return (p - a).length();
+    }
+    let t = ((p - a).dot(d) / len2).clamp(0., 1.);
+    (p - (a + d * t)).length()
+}
+
+fn flatten_cubic(p: [Vector2F; 4], tolerance: f32, depth: u32, out: &mut Vec<Vector2F>, stats: &mut LineStats) {
+    // the curve stays within 3/4 of the control point distance from the chord
+    let flatness = 0.75 * distance_to_segment(p[1], p[0], p[3]).max(distance_to_segment(p[2], p[0], p[3]));
+    if flatness <= tolerance || depth == MAX_DEPTH {
+        stats.max_deviation = stats.max_deviation.max(flatness);
+        out.push(p[3]);
+        return;
+    }
+    let mid = |a: Vector2F, b: Vector2F| (a + b) * 0.5;
+    let (p01, p12, p23) = (mid(p[0], p[1]), mid(p[1], p[2]), mid(p[2], p[3]));
+    let (p012, p123) = (mid(p01, p12), mid(p12, p23));
+    let m = mid(p012, p123);
+    flatten_cubic([p[0], p01, p012, m], tolerance, depth + 1, out, stats);
+    flatten_cubic([m, p123, p23, p[3]], tolerance, depth + 1, out, stats);
+}
+
+// One polyline per contour, the curves split until they are within tolerance.
+// Closed contours end with their first point.
+pub fn flatten(outline: &Outline, tolerance: f32, stats: &mut LineStats) -> Vec<Vec<Vector2F>> {
+    let mut polylines = vec![];
+    for contour in outline.contours() {
+        if contour.is_empty() {
+            continue;
+        }
+        let mut points = vec![contour.position_of(0)];
+        for segment in contour.iter(ContourIterFlags::empty()) {
+            flatten_segment(&segment, tolerance, &mut points, stats);
+        }
+        if contour.is_closed() && points.last() != Some(&points[0]) {
+            points.push(points[0]);
+        }
+        polylines.push(points);
+    }
+    polylines
+}
+
+fn flatten_segment(segment: &Segment, tolerance: f32, out: &mut Vec<Vector2F>, stats: &mut LineStats) {
+    if segment.is_none() {
+        return;
+    }
+    if segment.is_line() {
+        out.push(segment.baseline.to());
+        return;
+    }
+    let cubic = segment.to_cubic();
+    let p = [cubic.baseline.from(), cubic.ctrl.from(), cubic.ctrl.to(), cubic.baseline.to()];
+    flatten_cubic(p, tolerance, 0, out, stats);
+}
+
+// Least squares circle through the points, center and radius. None if the
+// points are (nearly) on a line.
+pub fn fit_circle(points: &[Vector2F]) -> Option<(Vector2F, f32)> {
+    let n = points.len() as f64;
+    let (mx, my) = points
+        .iter()
+        .fold((0., 0.), |(x, y), p| (x + p.x() as f64, y + p.y() as f64));
+    let (mx, my) = (mx / n, my / n);
+    let (mut suu, mut svv, mut suv, mut suuu, mut svvv, mut suvv, mut svuu) = (0., 0., 0., 0., 0., 0., 0.);
+    for p in points {
+        let (u, v) = (p.x() as f64 - mx, p.y() as f64 - my);
+        suu += u * u;
+        svv += v * v;
+        suv += u * v;
+        suuu += u * u * u;
+        svvv += v * v * v;
+        suvv += u * v * v;
+        svuu += v * u * u;
+    }
+    let det = suu * svv - suv * suv;
+    if det.abs() <= 1e-9 * (suu + svv) * (suu + svv) {
+        return None;
+    }
+    let (bu, bv) = (0.5 * (suuu + suvv), 0.5 * (svvv + svuu));
+    let uc = (bu * svv - bv * suv) / det;
+    let vc = (bv * suu - bu * suv) / det;
+    let radius = (uc * uc + vc * vc + (suu + svv) / n).sqrt();
+    Some((Vector2F::new((mx + uc) as f32, (my + vc) as f32), radius as f32))
+}
+
+// The arc through the points, with its sweep and deviation, if the points turn
+// in one direction around the fitted center and both the points and the chords
+// between them stay within tolerance.
+fn arc(points: &[Vector2F], tolerance: f32) -> Option<(Vector2F, f32, f32)> {
+    let (center, radius) = fit_circle(points)?;
+    if radius > MAX_RADIUS {
+        return None;
+    }
+    let mut deviation = points
+        .iter()
+        .map(|&p| ((p - center).length() - radius).abs())
+        .fold(0., f32::max);
+    if deviation > tolerance {
+        return None;
+    }
+    let angle = |p: Vector2F| (p.y() - center.y()).atan2(p.x() - center.x());
+    let mut sweep = 0.;
+    for pair in points.windows(2) {
+        let mut step = angle(pair[1]) - angle(pair[0]);
+        if step > std::f32::consts::PI {
+            step -= 2. * std::f32::consts::PI;
+        } else if step < -std::f32::consts::PI {
+            step += 2. * std::f32::consts::PI;
+        }
+        if step == 0. || step.abs() > MAX_ARC_STEP || (sweep != 0. && step.signum() != f32::signum(sweep)) {
+            return None;
+        }
+        sweep += step;
+        // the arc bulges out of the chord by the sagitta
+        deviation = deviation.max(radius * (1. - (0.5 * step).cos()));
+    }
+    if deviation > tolerance || sweep.abs() > 2. * std::f32::consts::PI {
+        return None;
+    }
+    Some((center, sweep, deviation))
+}
+
+// Replace runs of the polyline by circular arcs where they fit within tolerance.
+// The first point is the start and not part of the result.
+pub fn fit_arcs(points: &[Vector2F], tolerance: f32, stats: &mut LineStats) -> Vec<Piece> {
+    let mut pieces = vec![];
+    let mut i = 0;
+    while i + 1 < points.len() {
+        let mut best = None;
+        let mut j = i + MIN_ARC_POINTS - 1;
+        while j < points.len() {
+            match arc(&points[i..=j], tolerance) {
+                Some(found) => best = Some((j, found)),
+                None => break,
+            }
+            j += 1;
+        }
+        match best {
+            Some((j, (center, sweep, deviation))) => {
+                pieces.push(Piece::Arc { center, sweep, end: points[j] });
+                stats.arcs += 1;
+                stats.max_arc_deviation = stats.max_arc_deviation.max(deviation);
+                i = j;
+            }
+            None => {
+                pieces.push(Piece::Line(points[i + 1]));
+                stats.lines += 1;
+                i += 1;
+            }
+        }
+    }
+    pieces
+}
+
+// the polyline as lines only
+pub fn lines(points: &[Vector2F], stats: &mut LineStats) -> Vec<Piece> {
+    stats.lines += points.len().saturating_sub(1);
+    points.iter().skip(1).map(|&p| Piece::Line(p)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use pathfinder_content::outline::{Contour, Outline};
+    use pathfinder_geometry::vector::Vector2F;
+
+    use super::{fit_arcs, fit_circle, flatten, LineStats, Piece};
+
+    // circle of four cubic curves, within 0.03% of the radius
+    fn circle(center: Vector2F, r: f32) -> Outline {
+        let k = 0.552_284_8 * r;
+        let p = |x: f32, y: f32| center + Vector2F::new(x, y);
+        let mut contour = Contour::new();
+        contour.push_endpoint(p(r, 0.));
+        contour.push_cubic(p(r, k), p(k, r), p(0., r));
+        contour.push_cubic(p(-k, r), p(-r, k), p(-r, 0.));
+        contour.push_cubic(p(-r, -k), p(-k, -r), p(0., -r));
+        contour.push_cubic(p(k, -r), p(r, -k), p(r, 0.));
+        contour.close();
+        let mut outline = Outline::new();
+        outline.push_contour(contour);
+        outline
+    }
+
+    #[test]
+    fn flatten_circle() {
+        let center = Vector2F::new(100., 100.);
+        let r = 50.;
+        let mut previous = 0;
+        for tolerance in [1., 0.1, 0.01] {
+            let mut stats = LineStats::default();
+            let polylines = flatten(&circle(center, r), tolerance, &mut stats);
+            assert_eq!(polylines.len(), 1);
+            let points = &polylines[0];
+            assert_eq!(points.first(), points.last());
+            assert!(stats.max_deviation <= tolerance);
+            for pair in points.windows(2) {
+                // the middle of each chord is at most tolerance inside the circle
+                let mid = (pair[0] + pair[1]) * 0.5;
+                assert!(r - (mid - center).length() <= tolerance + 0.02, "{:?}", pair);
+            }
+            assert!(points.len() > previous);
+            previous = points.len();
+        }
+    }
+
+    #[test]
+    fn circle_fit() {
+        let points: Vec<_> = (0..8)
+            .map(|i| {
+                let a = i as f32 * 0.3;
+                Vector2F::new(10. + 20. * a.cos(), -5. + 20. * a.sin())
+            })
+            .collect();
+        let (center, radius) = fit_circle(&points).unwrap();
+        assert!((center - Vector2F::new(10., -5.)).length() < 1e-3);
+        assert!((radius - 20.).abs() < 1e-3);
+
+        let line: Vec<_> = (0..5).map(|i| Vector2F::new(i as f32, 2. * i as f32)).collect();
+        assert!(fit_circle(&line).is_none());
+    }
+
+    #[test]
+    fn arcs_for_circles() {
+        let center = Vector2F::new(100., 100.);
+        let mut stats = LineStats::default();
+        let points = &flatten(&circle(center, 50.), 0.01, &mut stats)[0];
+        let pieces = fit_arcs(points, 0.05, &mut stats);
+        assert!(stats.arcs >= 1 && stats.arcs <= 4, "{:?}", stats);
+        assert!(stats.max_arc_deviation <= 0.05);
+        assert!(pieces.len() < points.len() / 4);
+        let mut sweep = 0.;
+        for piece in &pieces {
+            if let Piece::Arc { center: c, sweep: s, .. } = *piece {
+                assert!((c - center).length() < 0.1);
+                sweep += s;
+            }
+        }
+        // counterclockwise, all the way around less the line pieces
+        assert!(sweep > 5. && sweep <= 2. * std::f32::consts::PI + 1e-3);
+
+        // a square has no arcs
+        let square = [(0., 0.), (10., 0.), (10., 10.), (0., 10.), (0., 0.)].map(|(x, y)| Vector2F::new(x, y));
+        let mut stats = LineStats::default();
+        let pieces = fit_arcs(&square, 0.05, &mut stats);
+        assert_eq!(pieces.len(), 4);
+        assert_eq!((stats.lines, stats.arcs), (4, 0));
+    }
+}
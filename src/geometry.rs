@@ -0,0 +1,618 @@
+use pathfinder_content::{
+    fill::FillRule,
+    outline::{Contour, Outline},
+    stroke::LineCap,
+};
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+
+use crate::flatten::{flatten, LineStats};
+
+// Transformations are applied in this order:
+// 1. page space to output units: y flip and scale (dpi)
+// 2. page /Rotate, then moved back to the origin
+// 3. the user transform (--transform), in output units
+#[derive(Debug, Clone, Copy)]
+pub struct PageGeometry {
+    pub transform: Transform2F,
+    pub view_box: RectF,
+}
+
+impl PageGeometry {
+    // bounds: the page box in page space, already scaled to output units
+    pub fn new(bounds: RectF, scale: f32, rotate: i32, user: Transform2F, transform_view_box: bool) -> Self {
+        let rotate = Transform2F::from_rotation(rotate as f32 * std::f32::consts::PI / 180.);
+        let br = rotate * RectF::new(Vector2F::zero(), bounds.size());
+        let translate = Transform2F::from_translation(Vector2F::new(
+            -br.min_x().min(br.max_x()),
+            -br.min_y().min(br.max_y()),
+        ));
+        let view_box = match transform_view_box {
+            true => user * translate * br,
+            false => translate * br,
+        };
+        let transform = user
+            * translate
+            * rotate
+            * Transform2F::row_major(scale, 0.0, -bounds.min_x(), 0.0, -scale, bounds.max_y());
+        PageGeometry { transform, view_box }
+    }
+}
+
+// the pixels of a raster image of the view box, partial pixels are kept
+pub fn pixel_size(view_box: RectF) -> (u32, u32) {
+    let size = view_box.size().ceil().to_i32();
+    (size.x() as u32, size.y() as u32)
+}
+
+// Parse a pdf style matrix "a b c d e f", separated by spaces or commas.
+pub fn parse_transform(s: &str) -> Result<Transform2F, String> {
+    let values = s
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse::<f32>().map_err(|e| format!("invalid number {:?}: {}", v, e)))
+        .collect::<Result<Vec<f32>, String>>()?;
+    let &[a, b, c, d, e, f] = values.as_slice() else {
+        return Err(format!("expected 6 numbers \"a b c d e f\", got {}", values.len()));
+    };
+    if values.iter().any(|v| !v.is_finite()) {
+        return Err("matrix values must be finite".into());
+    }
+    let det = a * d - b * c;
+    if det.abs() < 1e-6 {
+        return Err(format!(
+            "matrix [{}] is singular (determinant {}), it would collapse the page to a line or point",
+            s, det
+        ));
+    }
+    Ok(Transform2F::row_major(a, c, e, b, d, f))
+}
+
+// A point "x,y", separated by a comma or spaces.
+pub fn parse_point(s: &str) -> Result<Vector2F, String> {
+    let values = s
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse::<f32>().map_err(|e| format!("invalid number {:?}: {}", v, e)))
+        .collect::<Result<Vec<f32>, String>>()?;
+    match values.as_slice() {
+        &[x, y] if x.is_finite() && y.is_finite() => Ok(Vector2F::new(x, y)),
+        &[_, _] => Err("point values must be finite".into()),
+        _ => Err(format!("expected 2 numbers \"x,y\", got {}", values.len())),
+    }
+}
+
+// A rectangle "x0,y0,x1,y1" from two opposite corners, as --bbox prints them.
+pub fn parse_rect(s: &str) -> Result<RectF, String> {
+    let values = s
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse::<f32>().map_err(|e| format!("invalid number {:?}: {}", v, e)))
+        .collect::<Result<Vec<f32>, String>>()?;
+    match values.as_slice() {
+        &[x0, y0, x1, y1] if values.iter().all(|v| v.is_finite()) => {
+            Ok(RectF::from_points(Vector2F::new(x0.min(x1), y0.min(y1)), Vector2F::new(x0.max(x1), y0.max(y1))))
+        }
+        &[_, _, _, _] => Err("rectangle values must be finite".into()),
+        _ => Err(format!("expected 4 numbers \"x0,y0,x1,y1\", got {}", values.len())),
+    }
+}
+
+// A length with unit, "20mm", "1in", "2.5cm" or "12pt", in points. Plain numbers are points.
+pub fn parse_length(s: &str) -> Result<f32, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let value = number
+        .trim()
+        .parse::<f32>()
+        .map_err(|e| format!("invalid length {:?}: {}", s, e))?;
+    let per_unit = match unit {
+        "" | "pt" => 1.,
+        "mm" => 72. / 25.4,
+        "cm" => 72. / 2.54,
+        "in" => 72.,
+        unit => return Err(format!("unknown unit {:?}, expected pt, mm, cm or in", unit)),
+    };
+    Ok(value * per_unit)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Anchor {
+    #[default]
+    Center,
+    Tl,
+    Tr,
+    Bl,
+    Br,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StampScale {
+    // as large as fits inside the margins
+    Fit,
+    // relative to the size of the stamp page
+    Percent(f32),
+}
+
+pub fn parse_stamp_scale(s: &str) -> Result<StampScale, String> {
+    if s == "fit" {
+        return Ok(StampScale::Fit);
+    }
+    let percent = s
+        .trim_end_matches('%')
+        .parse::<f32>()
+        .map_err(|_| format!("expected fit or a percentage, got {:?}", s))?;
+    if !(percent > 0.) {
+        return Err(format!("scale must be positive, got {:?}", s));
+    }
+    Ok(StampScale::Percent(percent))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Placement {
+    pub anchor: Anchor,
+    pub scale: StampScale,
+    // degrees, counterclockwise
+    pub rotate: f32,
+    // points
+    pub margin: f32,
+}
+
+impl Placement {
+    // Maps the stamp box onto the target box, both y up. The stamp is rotated
+    // around its center, scaled, and its rotated bounds are placed at the anchor.
+    pub fn transform(&self, target: RectF, stamp: RectF) -> Transform2F {
+        let rotate = Transform2F::from_rotation(self.rotate.to_radians());
+        let bounds = rotate * RectF::new(-stamp.size() * 0.5, stamp.size());
+        let scale = match self.scale {
+            StampScale::Fit => {
+                let room = target.size() - Vector2F::splat(2. * self.margin);
+                (room.x() / bounds.width()).min(room.y() / bounds.height()).max(0.)
+            }
+            StampScale::Percent(percent) => percent / 100.,
+        };
+        let half = bounds.size() * (0.5 * scale) + Vector2F::splat(self.margin);
+        let (min, max) = (target.origin() + half, target.lower_right() - half);
+        let center = match self.anchor {
+            Anchor::Center => target.center(),
+            Anchor::Tl => Vector2F::new(min.x(), max.y()),
+            Anchor::Tr => max,
+            Anchor::Bl => min,
+            Anchor::Br => Vector2F::new(max.x(), min.y()),
+        };
+        Transform2F::from_translation(center)
+            * Transform2F::from_scale(Vector2F::splat(scale))
+            * rotate
+            * Transform2F::from_translation(-stamp.center())
+    }
+}
+
+// How rectangles are given on the command line and written to json.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Coords {
+    // points from the lower left of the page box, y up
+    #[default]
+    Pdf,
+    // output pixels from the top left of the image, y down
+    Image,
+}
+
+// Converts between device space, where the plotters draw, and the coordinate
+// convention of the user. Everything that reads or writes rectangles goes
+// through here, so they all agree with the rendered image.
+#[derive(Debug, Clone, Copy)]
+pub struct CoordSystem {
+    // page space to device space
+    pub transform: Transform2F,
+    pub view_box: RectF,
+    // the page box in page space
+    pub page_box: RectF,
+    pub coords: Coords,
+}
+
+impl CoordSystem {
+    pub fn new(geometry: &PageGeometry, page_box: RectF, coords: Coords) -> Self {
+        CoordSystem { transform: geometry.transform, view_box: geometry.view_box, page_box, coords }
+    }
+    fn to_user(&self) -> Transform2F {
+        match self.coords {
+            Coords::Pdf => Transform2F::from_translation(-self.page_box.origin()) * self.transform.inverse(),
+            Coords::Image => Transform2F::from_translation(-self.view_box.origin()),
+        }
+    }
+    pub fn from_device(&self, rect: RectF) -> RectF {
+        self.to_user() * rect
+    }
+    pub fn to_device(&self, rect: RectF) -> RectF {
+        self.to_user().inverse() * rect
+    }
+    pub fn point_to_device(&self, point: Vector2F) -> Vector2F {
+        self.to_user().inverse() * point
+    }
+}
+
+// below this determinant a matrix squashes everything to a line or a point
+const SINGULAR_DET: f32 = 1e-12;
+
+pub fn is_singular(transform: Transform2F) -> bool {
+    !(transform.matrix.det().abs() >= SINGULAR_DET)
+}
+
+// the exact bits of a transform, to key caches by it
+pub fn transform_bits(transform: Transform2F) -> [u32; 6] {
+    [transform.m11(), transform.m12(), transform.m13(), transform.m21(), transform.m22(), transform.m23()].map(f32::to_bits)
+}
+
+// The smallest factor by which the transform scales a length, for a stroke
+// that is the width in its thinnest direction.
+pub fn min_scale(transform: Transform2F) -> f32 {
+    let m = transform.matrix;
+    let squares = m.m11().powi(2) + m.m12().powi(2) + m.m21().powi(2) + m.m22().powi(2);
+    let det = m.det().abs();
+    // the singular values multiply to det, the larger one squared is this
+    let max_squared = 0.5 * (squares + (squares * squares - 4. * det * det).max(0.).sqrt());
+    match max_squared > 0. {
+        true => det / max_squared.sqrt(),
+        false => 0.,
+    }
+}
+
+// Content parked far away from the page box, like mail merge leftovers at
+// -10000 -10000: its bounds do not reach the box grown by factor page diagonals.
+pub fn is_offpage(bounds: RectF, page: RectF, factor: f32) -> bool {
+    let margin = factor * page.size().length();
+    !bounds.intersects(page.dilate(Vector2F::splat(margin)))
+}
+
+// Page space to the page as it is displayed, after /Rotate, y up with the
+// origin at the bottom left. The size of the displayed page is the second value.
+pub fn display_space(bounds: RectF, rotate: i32) -> (Transform2F, Vector2F) {
+    let g = PageGeometry::new(bounds, 1.0, rotate, Transform2F::default(), false);
+    let size = g.view_box.size();
+    (Transform2F::row_major(1., 0., 0., 0., -1., size.y()) * g.transform, size)
+}
+
+// The bounds of a closed four corner contour with axis aligned edges, like the
+// ones re draws.
+fn contour_rect(contour: &Contour) -> Option<RectF> {
+    if contour.len() != 4 || !contour.is_closed() || !(0..4).all(|i| contour.point_is_endpoint(i)) {
+        return None;
+    }
+    let p: Vec<Vector2F> = (0..4).map(|i| contour.position_of(i)).collect();
+    let vertical = |i: usize| p[i].x() == p[(i + 1) % 4].x();
+    let horizontal = |i: usize| p[i].y() == p[(i + 1) % 4].y();
+    // every edge is either vertical or horizontal, and they alternate
+    if !(0..4).all(|i| vertical(i) != horizontal(i) && vertical(i) != vertical((i + 1) % 4)) {
+        return None;
+    }
+    Some(RectF::from_points(p[0].min(p[2]), p[0].max(p[2])))
+}
+
+// Edges on whole pixels, a line thinner than a pixel keeps one pixel.
+fn snap_span(lo: f32, hi: f32) -> (f32, f32) {
+    let (a, b) = (lo.round(), hi.round());
+    match a == b && hi > lo {
+        true => (lo.floor(), lo.floor() + 1.),
+        false => (a, b),
+    }
+}
+
+// The rectangles of the outline in device space with their edges on the pixel
+// grid, which starts at origin. None unless the outline consists of rectangles
+// and the transform keeps them axis aligned. The corners keep their order, so
+// the winding of each rectangle is the same.
+pub fn snap_rects(outline: &Outline, transform: Transform2F, origin: Vector2F) -> Option<Outline> {
+    let m = transform.matrix;
+    let aligned = (m.m12() == 0. && m.m21() == 0.) || (m.m11() == 0. && m.m22() == 0.);
+    if !aligned || outline.contours().is_empty() {
+        return None;
+    }
+    let mut snapped = Outline::new();
+    for contour in outline.contours() {
+        let rect = transform * contour_rect(contour)?;
+        let (x0, x1) = snap_span(rect.min_x() - origin.x(), rect.max_x() - origin.x());
+        let (y0, y1) = snap_span(rect.min_y() - origin.y(), rect.max_y() - origin.y());
+        let mut corners = Contour::new();
+        for i in 0..4 {
+            let p = transform * contour.position_of(i);
+            let x = if p.x() - rect.min_x() < rect.max_x() - p.x() { x0 } else { x1 };
+            let y = if p.y() - rect.min_y() < rect.max_y() - p.y() { y0 } else { y1 };
+            corners.push_endpoint(origin + Vector2F::new(x, y));
+        }
+        corners.close();
+        snapped.push_contour(corners);
+    }
+    Some(snapped)
+}
+
+// The point moved into limit, which is in device space. None when it is inside
+// already or the transform can not be inverted. The inverse is taken in f64,
+// the determinant of an absurd scale overflows f32.
+pub fn clamp_point(p: Vector2F, transform: Transform2F, limit: RectF) -> Option<Vector2F> {
+    let q = transform * p;
+    if limit.contains_point(q) {
+        return None;
+    }
+    let q = q.max(limit.origin()).min(limit.lower_right()) - transform.vector;
+    let m = transform.matrix;
+    let (a, b, c, d) = (m.m11() as f64, m.m12() as f64, m.m21() as f64, m.m22() as f64);
+    let det = a * d - b * c;
+    let (x, y) = (q.x() as f64, q.y() as f64);
+    let clamped = Vector2F::new(((d * x - b * y) / det) as f32, ((a * y - c * x) / det) as f32);
+    (clamped.x().is_finite() && clamped.y().is_finite()).then_some(clamped)
+}
+
+// A closed subpath of a single point, or one whose points all lie on the same
+// spot, has no length and the stroker drops it. With round and square caps it
+// is painted as a dot of the line width instead, squares are aligned with user
+// space. None when there are no such subpaths.
+pub fn zero_length_dots(outline: &Outline, cap: LineCap, width: f32) -> Option<Outline> {
+    if !matches!(cap, LineCap::Round | LineCap::Square) || !(width > 0.) {
+        return None;
+    }
+    let mut dots = Outline::new();
+    for contour in outline.contours() {
+        let size = contour.bounds().size();
+        let degenerate = match contour.len() {
+            0 => false,
+            1 => contour.is_closed(),
+            _ => size.x().max(size.y()) < width * 1e-3,
+        };
+        if !degenerate {
+            continue;
+        }
+        let center = contour.position_of(0);
+        let dot = match cap {
+            LineCap::Square => Contour::from_rect(RectF::new(center - Vector2F::splat(width / 2.), Vector2F::splat(width))),
+            _ => {
+                let mut circle = Contour::new();
+                circle.push_ellipse(&(Transform2F::from_translation(center) * Transform2F::from_scale(width / 2.)));
+                circle.close();
+                circle
+            }
+        };
+        dots.push_contour(dot);
+    }
+    (!dots.contours().is_empty()).then_some(dots)
+}
+
+// Is the point inside the filled outline. Curves are flattened to within
+// tolerance, open contours are closed like a fill does.
+pub fn contains(outline: &Outline, fill_rule: FillRule, point: Vector2F, tolerance: f32) -> bool {
+    if !outline.bounds().contains_point(point) {
+        return false;
+    }
+    let mut winding = 0i32;
+    for polyline in flatten(outline, tolerance, &mut LineStats::default()) {
+        for (&a, &b) in polyline.iter().zip(polyline.iter().cycle().skip(1)) {
+            // the edge crosses the horizontal line through the point, left to right of it
+            let side = (b - a).det(point - a);
+            if a.y() <= point.y() && b.y() > point.y() && side > 0. {
+                winding += 1;
+            } else if a.y() > point.y() && b.y() <= point.y() && side < 0. {
+                winding -= 1;
+            }
+        }
+    }
+    match fill_rule {
+        FillRule::Winding => winding != 0,
+        FillRule::EvenOdd => winding % 2 != 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pathfinder_content::{
+        fill::FillRule,
+        outline::{Contour, Outline},
+    };
+    use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+
+    use super::{
+        contains, display_space, is_offpage, is_singular, min_scale, parse_length, parse_point, parse_rect, parse_stamp_scale, parse_transform, snap_rects, Anchor, CoordSystem, Coords,
+        PageGeometry, Placement, StampScale,
+    };
+
+    fn assert_near(a: Vector2F, b: Vector2F) {
+        assert!((a - b).length() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn parse() {
+        let t = parse_transform("-1 0 0 1 100 0").unwrap();
+        assert_near(t * Vector2F::new(10., 20.), Vector2F::new(90., 20.));
+        assert!(parse_transform("1,0,0,1,0,0").is_ok());
+        assert!(parse_transform("1 0 0 1 0").is_err());
+        assert!(parse_transform("1 2 2 4 0 0").unwrap_err().contains("singular"));
+        assert!(parse_transform("0 0 0 0 0 0").is_err());
+    }
+
+    #[test]
+    fn order_of_application() {
+        let bounds = RectF::new(Vector2F::zero(), Vector2F::new(100., 200.));
+
+        let g = PageGeometry::new(bounds, 1.0, 0, Transform2F::from_translation(Vector2F::new(10., 0.)), false);
+        // top left corner of the page
+        assert_near(g.transform * Vector2F::new(0., 200.), Vector2F::new(10., 0.));
+        assert_near(g.view_box.size(), Vector2F::new(100., 200.));
+        assert_near(g.view_box.origin(), Vector2F::zero());
+
+        // the user transform mirrors the already rotated page
+        let mirror = Transform2F::row_major(-1., 0., 200., 0., 1., 0.);
+        let g = PageGeometry::new(bounds, 1.0, 90, Transform2F::default(), false);
+        assert_near(g.transform * Vector2F::new(0., 200.), Vector2F::new(200., 0.));
+        let g = PageGeometry::new(bounds, 1.0, 90, mirror, true);
+        assert_near(g.transform * Vector2F::new(0., 200.), Vector2F::new(0., 0.));
+        assert_near(g.view_box.size(), Vector2F::new(200., 100.));
+    }
+
+    #[test]
+    fn offpage() {
+        let page = RectF::new(Vector2F::zero(), Vector2F::new(30., 40.));
+        let at = |x: f32, y: f32| RectF::new(Vector2F::new(x, y), Vector2F::splat(10.));
+        assert!(is_offpage(at(-10000., -10000.), page, 2.));
+        // 2 diagonals are 100 units around the page
+        assert!(!is_offpage(at(-105., 0.), page, 2.));
+        assert!(is_offpage(at(-111., 0.), page, 2.));
+        assert!(!is_offpage(at(-111., 0.), page, 3.));
+        assert!(!is_offpage(at(10., 10.), page, 0.));
+    }
+
+    #[test]
+    fn points() {
+        assert_eq!(parse_point("3,4.5").unwrap(), Vector2F::new(3., 4.5));
+        assert_eq!(parse_point(" 3 4 ").unwrap(), Vector2F::new(3., 4.));
+        assert!(parse_point("3").is_err());
+        assert!(parse_point("3,x").is_err());
+    }
+
+    #[test]
+    fn rects() {
+        let rect = parse_rect("60 50, 10 20").unwrap();
+        assert_eq!((rect.min_x(), rect.min_y(), rect.max_x(), rect.max_y()), (10., 20., 60., 50.));
+        assert!(parse_rect("1,2,3").is_err());
+        assert!(parse_rect("1,2,3,inf").is_err());
+    }
+
+    #[test]
+    fn lengths() {
+        assert_eq!(parse_length("72").unwrap(), 72.);
+        assert!((parse_length("25.4mm").unwrap() - 72.).abs() < 1e-3);
+        assert!((parse_length("1in").unwrap() - 72.).abs() < 1e-3);
+        assert!(parse_length("3furlong").is_err());
+        assert_eq!(parse_stamp_scale("fit").unwrap(), StampScale::Fit);
+        assert_eq!(parse_stamp_scale("50%").unwrap(), StampScale::Percent(50.));
+        assert!(parse_stamp_scale("-5").is_err());
+    }
+
+    #[test]
+    fn stamp_bounds() {
+        let target = RectF::new(Vector2F::zero(), Vector2F::new(600., 800.));
+        let stamp = RectF::new(Vector2F::new(10., 10.), Vector2F::new(100., 50.));
+        let bounds = |anchor, scale, rotate, margin| {
+            Placement { anchor, scale, rotate, margin }.transform(target, stamp) * stamp
+        };
+        let b = bounds(Anchor::Br, StampScale::Percent(100.), 0., 20.);
+        assert_near(b.origin(), Vector2F::new(480., 20.));
+        assert_near(b.size(), Vector2F::new(100., 50.));
+        let b = bounds(Anchor::Tl, StampScale::Percent(50.), 0., 0.);
+        assert_near(b.origin(), Vector2F::new(0., 775.));
+        let b = bounds(Anchor::Tr, StampScale::Percent(100.), 0., 10.);
+        assert_near(b.lower_right(), Vector2F::new(590., 790.));
+        let b = bounds(Anchor::Bl, StampScale::Percent(200.), 0., 0.);
+        assert_near(b.origin(), Vector2F::zero());
+        assert_near(b.size(), Vector2F::new(200., 100.));
+        // fit uses the full width, then centers
+        let b = bounds(Anchor::Center, StampScale::Fit, 0., 0.);
+        assert_near(b.size(), Vector2F::new(600., 300.));
+        assert_near(b.center(), Vector2F::new(300., 400.));
+        // a diagonal stamp stays inside the page
+        let b = bounds(Anchor::Center, StampScale::Fit, 45., 0.);
+        assert!(b.min_x() > -1e-3 && b.max_x() < 600. + 1e-3);
+        assert_near(b.center(), Vector2F::new(300., 400.));
+    }
+
+    #[test]
+    fn display_space_rotation() {
+        let bounds = RectF::new(Vector2F::zero(), Vector2F::new(100., 200.));
+        let (t, size) = display_space(bounds, 90);
+        assert_near(size, Vector2F::new(200., 100.));
+        // rotated clockwise, the top left corner of the page ends up top right
+        assert_near(t * Vector2F::new(0., 200.), Vector2F::new(200., 100.));
+    }
+
+    #[test]
+    fn snapped_rects() {
+        let cell = |x: f32| Contour::from_rect(RectF::new(Vector2F::new(x, 2.), Vector2F::new(6.7, 6.7)));
+        let mut outline = Outline::new();
+        outline.push_contour(cell(5.));
+        outline.push_contour(cell(11.7));
+        let scale = Transform2F::from_scale(Vector2F::splat(1.37));
+        let snapped = snap_rects(&outline, scale, Vector2F::zero()).unwrap();
+        let [a, b] = [0, 1].map(|i| snapped.contours()[i].bounds());
+        // neighbouring cells share the edge on a whole pixel
+        assert_eq!(a.max_x(), b.min_x());
+        assert_eq!((a.min_x(), a.max_x(), b.max_x()), (7., 16., 25.));
+        // a hairline keeps one pixel
+        let mut line = Outline::new();
+        line.push_contour(Contour::from_rect(RectF::new(Vector2F::new(10.1, 0.), Vector2F::new(0.2, 5.))));
+        assert_eq!(snap_rects(&line, Transform2F::default(), Vector2F::zero()).unwrap().bounds().width(), 1.);
+        // rotated by 45 degrees and other paths are left alone
+        assert!(snap_rects(&outline, Transform2F::from_rotation(0.785), Vector2F::zero()).is_none());
+        let mut triangle = Contour::new();
+        for p in [Vector2F::zero(), Vector2F::new(4., 0.), Vector2F::new(0., 4.)] {
+            triangle.push_endpoint(p);
+        }
+        triangle.close();
+        let mut path = Outline::new();
+        path.push_contour(triangle);
+        assert!(snap_rects(&path, scale, Vector2F::zero()).is_none());
+    }
+
+    #[test]
+    fn containment() {
+        // a square with a square hole in the same direction, and a circle
+        let mut outline = Outline::new();
+        outline.push_contour(Contour::from_rect(RectF::new(Vector2F::zero(), Vector2F::splat(30.))));
+        outline.push_contour(Contour::from_rect(RectF::new(Vector2F::splat(10.), Vector2F::splat(10.))));
+        let hole = Vector2F::splat(15.);
+        assert!(contains(&outline, FillRule::Winding, hole, 0.1));
+        assert!(!contains(&outline, FillRule::EvenOdd, hole, 0.1));
+        assert!(contains(&outline, FillRule::EvenOdd, Vector2F::splat(5.), 0.1));
+        assert!(!contains(&outline, FillRule::Winding, Vector2F::new(35., 15.), 0.1));
+
+        let mut circle = Contour::new();
+        circle.push_ellipse(&(Transform2F::from_translation(Vector2F::splat(50.)) * Transform2F::from_scale(10.)));
+        circle.close();
+        let mut round = Outline::new();
+        round.push_contour(circle);
+        assert!(contains(&round, FillRule::Winding, Vector2F::new(50., 41.), 0.01));
+        // inside the bounds, outside the curve
+        assert!(!contains(&round, FillRule::Winding, Vector2F::splat(42.), 0.01));
+
+        // an open triangle is closed like a fill
+        let mut open = Contour::new();
+        for p in [Vector2F::zero(), Vector2F::new(20., 0.), Vector2F::new(0., 20.)] {
+            open.push_endpoint(p);
+        }
+        let mut triangle = Outline::new();
+        triangle.push_contour(open);
+        assert!(contains(&triangle, FillRule::Winding, Vector2F::splat(5.), 0.1));
+        assert!(!contains(&triangle, FillRule::Winding, Vector2F::splat(15.), 0.1));
+    }
+
+    #[test]
+    fn singular() {
+        assert!(is_singular(Transform2F::row_major(0., 0., 0., 0., 0., 0.)));
+        assert!(is_singular(Transform2F::row_major(1., 2., 5., 2., 4., 7.)));
+        assert!(is_singular(Transform2F::row_major(f32::NAN, 0., 0., 0., 1., 0.)));
+        assert!(!is_singular(Transform2F::from_scale(Vector2F::splat(1e-3))));
+        assert!(!is_singular(Transform2F::from_rotation(0.3)));
+    }
+
+    #[test]
+    fn min_scales() {
+        let near = |a: f32, b: f32| assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+        near(min_scale(Transform2F::default()), 1.);
+        near(min_scale(Transform2F::from_scale(Vector2F::new(2., 0.5))), 0.5);
+        near(min_scale(Transform2F::from_rotation(0.7) * Transform2F::from_scale(Vector2F::new(3., 4.))), 3.);
+        near(min_scale(Transform2F::row_major(1., 2., 0., 2., 4., 0.)), 0.);
+    }
+
+    #[test]
+    fn coord_systems() {
+        let page_box = RectF::new(Vector2F::new(100., 200.), Vector2F::new(200., 100.));
+        let geometry = PageGeometry::new(page_box * 2., 2., 90, Transform2F::default(), false);
+        let device = RectF::new(Vector2F::new(20., 40.), Vector2F::new(60., 100.));
+        for coords in [Coords::Pdf, Coords::Image] {
+            let system = CoordSystem::new(&geometry, page_box, coords);
+            let user = system.from_device(device);
+            assert_near(system.to_device(user).origin(), device.origin());
+            assert_near(system.to_device(user).size(), device.size());
+        }
+        // rotated by 90 degrees and at twice the size, the lower left corner of
+        // the page box is at the top left of the image
+        let pdf = CoordSystem::new(&geometry, page_box, Coords::Pdf);
+        assert_near(pdf.from_device(RectF::new(Vector2F::zero(), Vector2F::splat(2.))).origin(), Vector2F::zero());
+    }
+}
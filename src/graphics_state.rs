@@ -1,46 +1,91 @@
-use pathfinder_content::stroke::StrokeStyle;
-use pathfinder_geometry::transform2d::Transform2F;
+use std::sync::Arc;
+
+use pathfinder_content::stroke::{LineJoin, StrokeStyle};
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F};
 use pdf::object::ColorSpace;
 
-use crate::plotter::{Fill, Plotter, Stroke};
+use crate::plotter::{BlendMode, Fill, Plotter, Stroke};
 
-#[derive(Copy, Debug)]
-pub struct GraphicsState<'a, P: Plotter> {
+#[derive(Debug)]
+pub struct GraphicsState<P: Plotter> {
     //pub transform: Transform2F,
     pub transform: Transform2F,
     pub stroke_style: StrokeStyle,
+    // kept separately, the line join only carries it while it is a miter join
+    pub miter_limit: f32,
 
     pub fill_color: Fill,
-    pub fill_color_alpha: f32,
     pub fill_paint: Option<u16>, //Option<PaintId>,
     pub stroke_color: Fill,
-    pub stroke_color_alpha: f32,
     pub stroke_paint: Option<u16>, //Option<PaintId>,
     pub clip_path_id: Option<P::ClipPathId>,
+    // device space bounds of the current clip path
+    pub clip_bounds: Option<RectF>,
     //pub clip_path: Option<ClipPath>,
     //pub clip_path_rect: Option<RectF>,
-    pub fill_color_space: &'a ColorSpace,
-    pub stroke_color_space: &'a ColorSpace,
-    pub dash_pattern: Option<(&'a [f32], f32)>,
+    // owned, a state saved in a form and restored outside of it does not borrow
+    // from the resources of the form
+    pub fill_color_space: Arc<ColorSpace>,
+    pub stroke_color_space: Arc<ColorSpace>,
+    // dash lengths and phase in user space, None for solid lines
+    pub dash_pattern: Option<(Vec<f32>, f32)>,
 
+    // constant alpha, /CA and /ca of the ExtGState
     pub stroke_alpha: f32,
     pub fill_alpha: f32,
+    // alpha of an enclosing annotation, multiplied with the constant alpha
+    pub group_stroke_alpha: f32,
+    pub group_fill_alpha: f32,
 
     pub overprint_fill: bool,
     pub overprint_stroke: bool,
     pub overprint_mode: i32,
+    // blend mode set explicitly, e.g. by an annotation
+    pub blend_mode: Option<BlendMode>,
 }
 
-impl<'a, P: Plotter> Clone for GraphicsState<'a, P> {
+impl<P: Plotter> Clone for GraphicsState<P> {
     fn clone(&self) -> Self {
         GraphicsState {
             //clip_path: self.clip_path.clone(),
+            dash_pattern: self.dash_pattern.clone(),
+            fill_color_space: self.fill_color_space.clone(),
+            stroke_color_space: self.stroke_color_space.clone(),
             .. *self
         }
     }
 }
 
-impl<'a, P: Plotter> GraphicsState<'a, P> {
+impl<P: Plotter> GraphicsState<P> {
+    pub fn new(transform: Transform2F) -> Self {
+        GraphicsState {
+            transform,
+            stroke_style: StrokeStyle {
+                line_join: LineJoin::Miter(10.),
+                ..StrokeStyle::default()
+            },
+            miter_limit: 10.,
+            fill_color: Fill::black(),
+            fill_paint: None,
+            stroke_color: Fill::black(),
+            stroke_paint: None,
+            clip_path_id: None,
+            clip_bounds: None,
+            //clip_path: None,
+            //clip_path_rect: None,
+            fill_color_space: Arc::new(ColorSpace::DeviceRGB),
+            stroke_color_space: Arc::new(ColorSpace::DeviceRGB),
+            dash_pattern: None,
+            stroke_alpha: 1.0,
+            fill_alpha: 1.0,
+            group_stroke_alpha: 1.0,
+            group_fill_alpha: 1.0,
+            overprint_fill: false,
+            overprint_stroke: false,
+            overprint_mode: 0,
+            blend_mode: None,
+        }
+    }
     pub fn set_fill_color(&mut self, fill: Fill) {
         if fill != self.fill_color {
             self.fill_color = fill;
@@ -48,9 +93,8 @@ impl<'a, P: Plotter> GraphicsState<'a, P> {
         }
     }
     pub fn set_fill_alpha(&mut self, alpha: f32) {
-        let a = self.fill_alpha * alpha;
-        if a != self.fill_color_alpha {
-            self.fill_color_alpha = a;
+        if alpha != self.fill_alpha {
+            self.fill_alpha = alpha;
             self.fill_paint = None;
         }
     }
@@ -61,16 +105,142 @@ impl<'a, P: Plotter> GraphicsState<'a, P> {
         }
     }
     pub fn set_stroke_alpha(&mut self, alpha: f32) {
-        let a = self.stroke_alpha * alpha;
-        if a != self.stroke_color_alpha {
-            self.stroke_alpha = a;
+        if alpha != self.stroke_alpha {
+            self.stroke_alpha = alpha;
             self.stroke_paint = None;
         }
     }
+    // the alpha fills and strokes are drawn with
+    pub fn effective_fill_alpha(&self) -> f32 {
+        self.fill_alpha * self.group_fill_alpha
+    }
+    pub fn effective_stroke_alpha(&self) -> f32 {
+        self.stroke_alpha * self.group_stroke_alpha
+    }
+    // An empty array, or one without any length, draws solid lines.
+    pub fn set_dash(&mut self, pattern: &[f32], phase: f32) {
+        self.dash_pattern = dash_pattern(pattern, phase);
+    }
+    // Take over the parameters of a state drawn with another plotter. Paints and
+    // clip paths belong to their plotter.
+    pub fn copy_from<Q: Plotter>(&mut self, other: &GraphicsState<Q>) {
+        self.transform = other.transform;
+        self.stroke_style = other.stroke_style;
+        self.miter_limit = other.miter_limit;
+        self.fill_color = other.fill_color;
+        self.fill_paint = None;
+        self.stroke_color = other.stroke_color;
+        self.stroke_paint = None;
+        self.fill_color_space = other.fill_color_space.clone();
+        self.stroke_color_space = other.stroke_color_space.clone();
+        self.dash_pattern = other.dash_pattern.clone();
+        self.stroke_alpha = other.stroke_alpha;
+        self.fill_alpha = other.fill_alpha;
+        self.group_stroke_alpha = other.group_stroke_alpha;
+        self.group_fill_alpha = other.group_fill_alpha;
+        self.overprint_fill = other.overprint_fill;
+        self.overprint_stroke = other.overprint_stroke;
+        self.overprint_mode = other.overprint_mode;
+        self.blend_mode = other.blend_mode;
+    }
     pub fn stroke(&self) -> Stroke {
         Stroke {
             style: self.stroke_style,
-            dash_pattern: self.dash_pattern.map(|(a, p)| (a.into(), p))
+            dash_pattern: self.dash_pattern.clone(),
         }
     }
 }
+
+// The dasher needs an even number of lengths and a phase within one period.
+fn dash_pattern(pattern: &[f32], phase: f32) -> Option<(Vec<f32>, f32)> {
+    if pattern.iter().any(|&l| !(l >= 0.) || !l.is_finite()) {
+        return None;
+    }
+    let mut pattern = pattern.to_vec();
+    if pattern.len() % 2 == 1 {
+        // an odd array repeats with on and off swapped
+        pattern.extend_from_within(..);
+    }
+    let period: f32 = pattern.iter().sum();
+    if !(period > 0.) {
+        return None;
+    }
+    let phase = match phase.is_finite() {
+        true => phase.rem_euclid(period),
+        false => 0.,
+    };
+    Some((pattern, phase))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use pathfinder_geometry::transform2d::Transform2F;
+    use pdf::object::ColorSpace;
+
+    use super::{dash_pattern, GraphicsState};
+    use crate::bounds_plotter::BoundsPlotter;
+
+    #[test]
+    fn dash_patterns() {
+        assert_eq!(dash_pattern(&[6., 4.], -3.), Some((vec![6., 4.], 7.)));
+        assert_eq!(dash_pattern(&[6., 4.], 23.), Some((vec![6., 4.], 3.)));
+        assert_eq!(dash_pattern(&[3.], 0.), Some((vec![3., 3.], 0.)));
+        // solid
+        assert_eq!(dash_pattern(&[], 0.), None);
+        assert_eq!(dash_pattern(&[0., 0.], 0.), None);
+        assert_eq!(dash_pattern(&[2., -1.], 0.), None);
+    }
+
+    #[test]
+    fn alpha() {
+        let mut gs = GraphicsState::<BoundsPlotter>::new(Transform2F::default());
+        gs.set_fill_alpha(0.5);
+        assert_eq!(gs.effective_fill_alpha(), 0.5);
+        // q
+        let saved = gs.clone();
+        gs.set_fill_alpha(0.25);
+        gs.set_stroke_alpha(0.25);
+        assert_eq!((gs.effective_fill_alpha(), gs.effective_stroke_alpha()), (0.25, 0.25));
+        // Q
+        gs = saved;
+        assert_eq!((gs.effective_fill_alpha(), gs.effective_stroke_alpha()), (0.5, 1.0));
+
+        // alternating gs operators set the constant alpha, they do not multiply
+        for _ in 0..3 {
+            gs.set_fill_alpha(0.5);
+            gs.set_fill_alpha(1.0);
+        }
+        assert_eq!(gs.effective_fill_alpha(), 1.0);
+
+        // an annotation fades its appearance, gs inside it multiply with that
+        gs.group_fill_alpha = 0.5;
+        gs.set_fill_alpha(0.5);
+        assert_eq!((gs.fill_alpha, gs.effective_fill_alpha()), (0.5, 0.25));
+    }
+
+    #[test]
+    fn color_spaces_outlive_their_resources() {
+        let mut gs = GraphicsState::<BoundsPlotter>::new(Transform2F::default());
+        let saved = {
+            // the resources of a form, gone when the form is done
+            let form_spaces = vec![ColorSpace::DeviceCMYK];
+            gs.fill_color_space = Arc::new(form_spaces[0].clone());
+            // q inside the form
+            let saved = gs.clone();
+            gs.stroke_color_space = Arc::new(ColorSpace::DeviceGray);
+            saved
+        };
+        assert!(matches!(*saved.fill_color_space, ColorSpace::DeviceCMYK));
+        assert!(matches!(*saved.stroke_color_space, ColorSpace::DeviceRGB));
+
+        // a layer takes them over with the rest of the state, and hands them back
+        let mut layer = GraphicsState::<BoundsPlotter>::new(Transform2F::default());
+        layer.copy_from(&gs);
+        assert!(Arc::ptr_eq(&layer.stroke_color_space, &gs.stroke_color_space));
+        layer.fill_color_space = Arc::new(ColorSpace::DeviceGray);
+        gs.copy_from(&layer);
+        assert!(matches!(*gs.fill_color_space, ColorSpace::DeviceGray));
+    }
+}
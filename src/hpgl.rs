@@ -0,0 +1,161 @@
+use std::{fmt::Write as _, io::Write, time::Instant};
+
+use pathfinder_content::{fill::FillRule, outline::Outline};
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+use pdf::PdfError;
+
+use crate::flatten::{self, LineStats, Piece};
+use crate::plotter::{DrawMode, LinearGradient, Plotter, RgbaImage, Tiling};
+use crate::timings::Timings;
+
+// plotter units per inch, 0.025 mm
+const PLU_PER_INCH: f32 = 1016.;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LineOptions {
+    // maximum distance between a curve and its line segments, in points
+    pub tolerance: f32,
+    // write runs of segments that fit a circle as AA arcs
+    pub fit_arcs: bool,
+}
+
+impl Default for LineOptions {
+    fn default() -> Self {
+        // 0.05 mm
+        LineOptions { tolerance: 0.05 * 72. / 25.4, fit_arcs: false }
+    }
+}
+
+// Pen plotter output: filled areas are drawn as their outline, strokes along
+// their center line. Images, shadings and patterns are left out.
+pub struct HpglPlotter {
+    view_box: RectF,
+    // device space outlines in drawing order
+    outlines: Vec<Outline>,
+    skipped: usize,
+}
+
+impl HpglPlotter {
+    pub fn new(view_box: RectF) -> Self {
+        HpglPlotter { view_box, outlines: vec![], skipped: 0 }
+    }
+    // dpi: output units per inch
+    pub fn write(&self, out: &mut dyn Write, dpi: f32, options: LineOptions) -> Result<(Timings, LineStats), PdfError> {
+        let start = Instant::now();
+        if self.skipped > 0 {
+            warn!("hpgl: {} images, shadings or pattern fills left out", self.skipped);
+        }
+        let (hpgl, stats) = self.encode(dpi, options);
+        out.write_all(hpgl.as_bytes()).map_err(|e| PdfError::Other {
+            msg: format!("can not write the hpgl output: {}", e),
+        })?;
+        info!(
+            "hpgl: {} lines, {} arcs, max deviation {:.4} (flattening) {:.4} (arcs)",
+            stats.lines, stats.arcs, stats.max_deviation, stats.max_arc_deviation
+        );
+        let timings = Timings {
+            encode: start.elapsed(),
+            ..Timings::default()
+        };
+        Ok((timings, stats))
+    }
+    fn encode(&self, dpi: f32, options: LineOptions) -> (String, LineStats) {
+        let scale = PLU_PER_INCH / dpi;
+        // y up with the origin in the lower left corner
+        let to_plotter = Transform2F::row_major(
+            scale,
+            0.,
+            -self.view_box.min_x() * scale,
+            0.,
+            -scale,
+            self.view_box.max_y() * scale,
+        );
+        let tolerance = options.tolerance * dpi / 72.;
+        let mut stats = LineStats::default();
+        // one pen and the outlines in drawing order, nothing depends on the run
+        let mut out = String::from("IN;SP1;\n");
+        for outline in &self.outlines {
+            for points in flatten::flatten(outline, tolerance, &mut stats) {
+                let pieces = match options.fit_arcs {
+                    true => flatten::fit_arcs(&points, tolerance, &mut stats),
+                    false => flatten::lines(&points, &mut stats),
+                };
+                write_polyline(&mut out, to_plotter * points[0], &pieces, to_plotter);
+            }
+        }
+        out.push_str("PU;SP0;\n");
+        // deviations in points
+        stats.max_deviation *= 72. / dpi;
+        stats.max_arc_deviation *= 72. / dpi;
+        (out, stats)
+    }
+}
+
+fn write_polyline(out: &mut String, start: Vector2F, pieces: &[Piece], to_plotter: Transform2F) {
+    let p = |v: Vector2F| format!("{},{}", v.x().round() as i32, v.y().round() as i32);
+    let _ = write!(out, "PU{};", p(start));
+    let mut pen_down = false;
+    for piece in pieces {
+        match *piece {
+            Piece::Line(to) => {
+                out.push_str(if pen_down { "," } else { "PD" });
+                out.push_str(&p(to_plotter * to));
+                pen_down = true;
+            }
+            Piece::Arc { center, sweep, .. } => {
+                if pen_down {
+                    out.push(';');
+                } else {
+                    out.push_str("PD;");
+                }
+                // the y flip turns the direction of the arc around
+                let _ = write!(out, "AA{},{:.2};", p(to_plotter * center), -sweep.to_degrees());
+                pen_down = false;
+            }
+        }
+    }
+    if pen_down {
+        out.push(';');
+    }
+    out.push('\n');
+}
+
+impl Plotter for HpglPlotter {
+    type ClipPathId = ();
+    fn draw(&mut self, outline: &Outline, _: &DrawMode, _: FillRule, transform: Transform2F, _: Option<()>) {
+        self.outlines.push(outline.clone().transformed(&transform));
+    }
+    fn create_clip_path(&mut self, _: Outline, _: FillRule, _: Option<()>) {}
+    fn draw_image(&mut self, _: &RgbaImage, _: Transform2F, _: Option<()>, _: f32, _: bool) {
+        self.skipped += 1;
+    }
+    fn draw_gradient(&mut self, _: Outline, _: &LinearGradient, _: Option<()>) {
+        self.skipped += 1;
+    }
+    fn draw_tiling(&mut self, _: Outline, _: FillRule, _: &Tiling, _: Option<()>) {
+        self.skipped += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pathfinder_content::outline::{Contour, Outline};
+    use pathfinder_geometry::{rect::RectF, vector::Vector2F};
+
+    use super::{HpglPlotter, LineOptions};
+
+    #[test]
+    fn plotter_units() {
+        let mut plotter = HpglPlotter::new(RectF::new(Vector2F::zero(), Vector2F::splat(72.)));
+        let mut contour = Contour::new();
+        contour.push_endpoint(Vector2F::new(0., 0.));
+        contour.push_endpoint(Vector2F::new(72., 72.));
+        let mut outline = Outline::new();
+        outline.push_contour(contour);
+        plotter.outlines.push(outline);
+        let (out, stats) = plotter.encode(72., LineOptions::default());
+        // one inch, the device y axis points down
+        assert_eq!(out, "IN;SP1;\nPU0,1016;PD1016,0;\nPU;SP0;\n");
+        assert_eq!((stats.lines, stats.arcs), (1, 0));
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    fmt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use pdf::object::PlainRef;
+
+use crate::{
+    color::CmykToRgb,
+    page_cache::{CacheStats, PageCache, Weight},
+    plotter::RgbaImage,
+};
+
+// default budget for decoded images, shared by all pages of a run
+pub const IMAGE_CACHE_BYTES: usize = 256 << 20;
+
+// An image xobject decodes to the same pixels wherever it is drawn, as long as
+// cmyk samples are converted the same way.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ImageKey {
+    pub document: PathBuf,
+    pub image: PlainRef,
+    pub cmyk: CmykToRgb,
+}
+
+impl Weight for Arc<RgbaImage> {
+    fn weight(&self) -> usize {
+        self.pixels.len()
+    }
+}
+
+// how often pages rendered in parallel waited for the cache of another page
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Contention {
+    pub waits: usize,
+    pub waited: Duration,
+}
+
+impl fmt::Display for Contention {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} waits, {:.1} ms", self.waits, self.waited.as_secs_f64() * 1000.)
+    }
+}
+
+// Decoded images with their soft mask applied, least recently used ones are
+// dropped over the budget. Images larger than the whole budget are not kept.
+// All pages of a run share the cache, the time spent waiting for its lock is
+// measured.
+pub struct ImageCache {
+    budget: usize,
+    images: Mutex<PageCache<Arc<RgbaImage>, ImageKey>>,
+    waits: AtomicUsize,
+    waited_ns: AtomicU64,
+}
+
+impl ImageCache {
+    pub fn new(budget: usize) -> Self {
+        ImageCache {
+            budget,
+            images: Mutex::new(PageCache::new(budget)),
+            waits: AtomicUsize::new(0),
+            waited_ns: AtomicU64::new(0),
+        }
+    }
+    fn lock(&self) -> MutexGuard<PageCache<Arc<RgbaImage>, ImageKey>> {
+        if let Ok(images) = self.images.try_lock() {
+            return images;
+        }
+        let start = Instant::now();
+        let images = self.images.lock().unwrap();
+        self.waits.fetch_add(1, Ordering::Relaxed);
+        self.waited_ns.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        images
+    }
+    pub fn get(&self, key: &ImageKey) -> Option<Arc<RgbaImage>> {
+        self.lock().get(key.clone()).cloned()
+    }
+    pub fn insert(&self, key: ImageKey, image: Arc<RgbaImage>) {
+        if image.weight() <= self.budget {
+            self.lock().insert(key, image);
+        }
+    }
+    pub fn stats(&self) -> CacheStats {
+        self.lock().stats()
+    }
+    pub fn contention(&self) -> Contention {
+        Contention {
+            waits: self.waits.load(Ordering::Relaxed),
+            waited: Duration::from_nanos(self.waited_ns.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, sync::Arc};
+
+    use pdf::object::PlainRef;
+
+    use super::{ImageCache, ImageKey};
+    use crate::{color::CmykToRgb, plotter::RgbaImage};
+
+    fn key(document: &str, id: u64) -> ImageKey {
+        ImageKey { document: PathBuf::from(document), image: PlainRef { id, gen: 0 }, cmyk: CmykToRgb::Subtractive }
+    }
+    fn image(pixels: usize) -> Arc<RgbaImage> {
+        Arc::new(RgbaImage { width: pixels as u32, height: 1, pixels: vec![0; 4 * pixels] })
+    }
+
+    #[test]
+    fn bounded_by_bytes() {
+        let cache = ImageCache::new(1000);
+        cache.insert(key("a.pdf", 5), image(100));
+        // the same object number in another document is another image
+        assert!(cache.get(&key("b.pdf", 5)).is_none());
+        assert!(cache.get(&key("a.pdf", 5)).is_some());
+        assert!(cache.get(&ImageKey { cmyk: CmykToRgb::Multiplicative, ..key("a.pdf", 5) }).is_none());
+
+        cache.insert(key("a.pdf", 6), image(200));
+        assert!(cache.get(&key("a.pdf", 5)).is_none());
+        // over the whole budget
+        cache.insert(key("a.pdf", 7), image(300));
+        assert!(cache.get(&key("a.pdf", 7)).is_none());
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.evictions, stats.bytes), (1, 1, 800));
+    }
+}
@@ -0,0 +1,559 @@
+use std::sync::Arc;
+
+use pathfinder_color::ColorU;
+use pathfinder_content::pattern::{Image, Pattern};
+use pathfinder_geometry::{
+    transform2d::Transform2F,
+    vector::{Vector2F, Vector2I},
+};
+use pathfinder_renderer::paint::Paint;
+use pdf::{object::ColorSpace, PdfError};
+
+use crate::color::CmykToRgb;
+use crate::plotter::{RgbaImage, Tiling};
+
+// Maps image pixels to the unit square: the first row is at the top (y = 1).
+pub fn pixels_to_unit(width: u32, height: u32) -> Transform2F {
+    Transform2F::row_major(1. / width as f32, 0., 0., 0., -1. / height as f32, 1.)
+}
+
+fn image_pattern(image: &RgbaImage) -> Pattern {
+    let pixels = image
+        .pixels
+        .chunks_exact(4)
+        .map(|p| ColorU::new(p[0], p[1], p[2], p[3]))
+        .collect();
+    let size = Vector2I::new(image.width as i32, image.height as i32);
+    Pattern::from_image(Image::new(size, Arc::new(pixels)))
+}
+
+// A paint that shows the image on the unit square of transform, faded by alpha
+// and sampled bilinearly with smooth, else at the nearest pixel.
+pub fn image_paint(image: &RgbaImage, transform: Transform2F, alpha: f32, smooth: bool) -> Paint {
+    let mut pattern = image_pattern(image);
+    pattern.set_smoothing_enabled(smooth);
+    pattern.apply_transform(transform * pixels_to_unit(image.width, image.height));
+    let mut paint = Paint::from_pattern(pattern);
+    paint.apply_opacity(alpha.clamp(0., 1.));
+    paint
+}
+
+// Box filter the image down to width x height, the colors are averaged
+// weighted by alpha so that transparent pixels do not darken the edges.
+pub fn downsample(image: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let (src_width, src_height) = (image.width as usize, image.height as usize);
+    let (width, height) = (width.clamp(1, image.width.max(1)) as usize, height.clamp(1, image.height.max(1)) as usize);
+    let mut pixels = Vec::with_capacity(4 * width * height);
+    for y in 0..height {
+        let (y0, y1) = (y * src_height / height, ((y + 1) * src_height / height).max(y * src_height / height + 1));
+        for x in 0..width {
+            let (x0, x1) = (x * src_width / width, ((x + 1) * src_width / width).max(x * src_width / width + 1));
+            let mut sum = [0u64; 4];
+            for row in y0..y1 {
+                for p in image.pixels[4 * (row * src_width + x0)..4 * (row * src_width + x1)].chunks_exact(4) {
+                    let a = p[3] as u64;
+                    for (s, &v) in sum.iter_mut().zip(&p[..3]) {
+                        *s += v as u64 * a;
+                    }
+                    sum[3] += a;
+                }
+            }
+            let n = ((x1 - x0) * (y1 - y0)) as u64;
+            let rgb = [0, 1, 2].map(|c| match sum[3] {
+                0 => 0,
+                a => ((sum[c] + a / 2) / a) as u8,
+            });
+            pixels.extend_from_slice(&[rgb[0], rgb[1], rgb[2], ((sum[3] + n / 2) / n) as u8]);
+        }
+    }
+    RgbaImage { width: width as u32, height: height as u32, pixels }
+}
+
+// A copy of the image at the size it covers in device pixels, when it has more
+// than factor pixels per device pixel along a side. None keeps the image, as
+// does a factor of 0.
+pub fn downsample_to_device(image: &RgbaImage, transform: Transform2F, factor: f32) -> Option<RgbaImage> {
+    if factor <= 0. {
+        return None;
+    }
+    // the device length of the unit square sides along the rows and the columns
+    let width = Vector2F::new(transform.m11(), transform.m21()).length().ceil().max(1.);
+    let height = Vector2F::new(transform.m12(), transform.m22()).length().ceil().max(1.);
+    if image.width as f32 <= factor * width && image.height as f32 <= factor * height {
+        return None;
+    }
+    Some(downsample(image, (width as u32).min(image.width), (height as u32).min(image.height)))
+}
+
+// The cell image repeated over the plane, one copy on each unit square of the
+// cell transform.
+pub fn tiling_paint(tiling: &Tiling) -> Paint {
+    let image = &tiling.image;
+    let mut pattern = image_pattern(image);
+    pattern.set_repeat_x(true);
+    pattern.set_repeat_y(true);
+    pattern.apply_transform(tiling.transform * pixels_to_unit(image.width, image.height));
+    Paint::from_pattern(pattern)
+}
+
+pub fn components(color_space: &ColorSpace) -> Option<usize> {
+    match *color_space {
+        ColorSpace::DeviceGray => Some(1),
+        ColorSpace::DeviceRGB => Some(3),
+        ColorSpace::DeviceCMYK => Some(4),
+        _ => None,
+    }
+}
+
+// Expand 8 bit gray, rgb or cmyk samples to rgba, cmyk as the fill colors.
+pub fn decode_samples(data: &[u8], width: u32, height: u32, components: usize, cmyk: CmykToRgb) -> Result<RgbaImage, PdfError> {
+    let pixels = width as usize * height as usize;
+    if data.len() < pixels * components {
+        return Err(PdfError::Other {
+            msg: format!(
+                "image data too short: {} bytes for {}x{} with {} components",
+                data.len(),
+                width,
+                height,
+                components
+            ),
+        });
+    }
+    let mut rgba = Vec::with_capacity(4 * pixels);
+    for sample in data.chunks_exact(components).take(pixels) {
+        match *sample {
+            [g] => rgba.extend_from_slice(&[g, g, g, 255]),
+            [r, g, b] => rgba.extend_from_slice(&[r, g, b, 255]),
+            [c, m, y, k] => {
+                let [r, g, b] = cmyk.rgb8([c, m, y, k]);
+                rgba.extend_from_slice(&[r, g, b, 255]);
+            }
+            _ => unreachable!("{} components", components),
+        }
+    }
+    Ok(RgbaImage { width, height, pixels: rgba })
+}
+
+// /Decode maps every component from [0, 1] to [dmin, dmax], [1 0] inverts it
+pub fn apply_decode(samples: &mut [u8], components: usize, decode: &[f32]) {
+    if decode.len() < 2 * components || decode.chunks_exact(2).all(|d| d == [0., 1.]) {
+        return;
+    }
+    for sample in samples.chunks_exact_mut(components) {
+        for (v, d) in sample.iter_mut().zip(decode.chunks_exact(2)) {
+            let f = d[0] + *v as f32 / 255. * (d[1] - d[0]);
+            *v = (f.clamp(0., 1.) * 255.).round() as u8;
+        }
+    }
+}
+
+// The color transform of an Adobe APP14 marker, if the jpeg has one. Adobe
+// writes cmyk inverted and says so with this marker.
+pub fn adobe_transform(jpeg: &[u8]) -> Option<u8> {
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF {
+        let marker = jpeg[pos + 1];
+        // the entropy coded data starts after SOS
+        if marker == 0xDA {
+            return None;
+        }
+        let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        let payload = jpeg.get(pos + 4..pos + 2 + len)?;
+        if marker == 0xEE && payload.len() >= 12 && payload.starts_with(b"Adobe") {
+            return Some(payload[11]);
+        }
+        pos += 2 + len;
+    }
+    None
+}
+
+// A DCTDecode stream to 8 bit gray, rgb or cmyk samples with the number of
+// components, the width and the height.
+pub fn decode_jpeg(jpeg: &[u8]) -> Result<(Vec<u8>, usize, u32, u32), PdfError> {
+    let mut decoder = jpeg_decoder::Decoder::new(jpeg);
+    let mut samples = decoder.decode().map_err(|e| PdfError::Other { msg: format!("jpeg: {}", e) })?;
+    let Some(info) = decoder.info() else {
+        return Err(PdfError::Other { msg: "jpeg without a frame".into() });
+    };
+    let components = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => 1,
+        jpeg_decoder::PixelFormat::RGB24 => 3,
+        jpeg_decoder::PixelFormat::CMYK32 => 4,
+        format => {
+            return Err(PdfError::Other {
+                msg: format!("jpeg pixel format {:?}", format),
+            })
+        }
+    };
+    // the decoder inverts four components, as they are stored with the Adobe
+    // marker. Without the marker they were not inverted in the first place.
+    if components == 4 && adobe_transform(jpeg).is_none() {
+        samples.iter_mut().for_each(|v| *v = 255 - *v);
+    }
+    Ok((samples, components, info.width as u32, info.height as u32))
+}
+
+// Samples of 1, 2, 4 or 8 bits to a byte each, without scaling them, as the
+// palette indices of an indexed image. Every row starts at a byte.
+pub fn unpack_samples(data: &[u8], width: u32, height: u32, components: usize, bits: u32) -> Result<Vec<u8>, PdfError> {
+    if !matches!(bits, 1 | 2 | 4 | 8) {
+        return Err(PdfError::Other {
+            msg: format!("{} bits per component", bits),
+        });
+    }
+    let row_samples = width as usize * components;
+    let stride = (row_samples * bits as usize + 7) / 8;
+    if data.len() < stride * height as usize {
+        return Err(PdfError::Other {
+            msg: format!("image data too short: {} bytes for {}x{} with {} bits", data.len(), width, height, bits),
+        });
+    }
+    let (per_byte, mask) = (8 / bits as usize, ((1u16 << bits) - 1) as u8);
+    let mut samples = Vec::with_capacity(row_samples * height as usize);
+    for row in data.chunks_exact(stride).take(height as usize) {
+        samples.extend((0..row_samples).map(|x| {
+            let shift = 8 - bits as usize * (x % per_byte + 1);
+            row[x / per_byte] >> shift & mask
+        }));
+    }
+    Ok(samples)
+}
+
+// Samples of 1, 2, 4, 8 or 16 bits scaled to 8 bit, 16 bit samples are
+// rounded to the nearest 8 bit value.
+pub fn expand_bits(data: &[u8], width: u32, height: u32, components: usize, bits: u32) -> Result<Vec<u8>, PdfError> {
+    if bits == 16 {
+        let stride = 2 * width as usize * components;
+        if data.len() < stride * height as usize {
+            return Err(PdfError::Other {
+                msg: format!("image data too short: {} bytes for {}x{} with 16 bits", data.len(), width, height),
+            });
+        }
+        let samples = data[..stride * height as usize].chunks_exact(2);
+        return Ok(samples.map(|v| ((u16::from_be_bytes([v[0], v[1]]) as u32 * 255 + 32767) / 65535) as u8).collect());
+    }
+    let mut samples = unpack_samples(data, width, height, components, bits)?;
+    let max = (1u32 << bits) - 1;
+    if max != 255 {
+        for v in samples.iter_mut() {
+            *v = (*v as u32 * 255 / max) as u8;
+        }
+    }
+    Ok(samples)
+}
+
+// /Decode on an indexed image maps the samples from [0, 2^bits - 1] to
+// [dmin, dmax] of the palette, [hival 0] reverses the palette.
+pub fn decode_indices(indices: &mut [u8], bits: u32, hival: usize, decode: &[f32]) {
+    let max = ((1u32 << bits) - 1) as f32;
+    let &[d0, d1, ..] = decode else {
+        return;
+    };
+    if d0 == 0. && d1 == max {
+        return;
+    }
+    for i in indices {
+        let v = d0 + *i as f32 * (d1 - d0) / max;
+        *i = v.round().clamp(0., hival as f32) as u8;
+    }
+}
+
+// The entry of a palette index in the base color space. Indices above hival
+// or the end of the palette take the last entry, None for an empty palette.
+pub fn palette_entry(palette: &[u8], components: usize, hival: usize, index: usize) -> Option<&[u8]> {
+    let entries = palette.len() / components;
+    let i = index.min(hival).min(entries.checked_sub(1)?);
+    Some(&palette[i * components..(i + 1) * components])
+}
+
+// The samples of the base color space for palette indices.
+pub fn lookup_palette(indices: &[u8], components: usize, hival: usize, palette: &[u8]) -> Result<Vec<u8>, PdfError> {
+    if palette_entry(palette, components, hival, 0).is_none() {
+        return Err(PdfError::Other {
+            msg: "empty palette".into(),
+        });
+    }
+    let mut samples = Vec::with_capacity(indices.len() * components);
+    for &i in indices {
+        samples.extend_from_slice(palette_entry(palette, components, hival, i as usize).unwrap_or_default());
+    }
+    Ok(samples)
+}
+
+// A 1 bit stencil mask: samples that are 0 (1 with an inverting /Decode [1 0])
+// are painted in the rgba color, the others stay transparent. Rows start at a byte.
+pub fn decode_stencil(data: &[u8], width: u32, height: u32, invert: bool, color: [u8; 4]) -> Result<RgbaImage, PdfError> {
+    let stride = (width as usize + 7) / 8;
+    if data.len() < stride * height as usize {
+        return Err(PdfError::Other {
+            msg: format!("image mask too short: {} bytes for {}x{}", data.len(), width, height),
+        });
+    }
+    let mut rgba = Vec::with_capacity(4 * width as usize * height as usize);
+    for row in data.chunks_exact(stride).take(height as usize) {
+        for x in 0..width as usize {
+            let bit = row[x / 8] >> (7 - x % 8) & 1 == 1;
+            match bit == invert {
+                true => rgba.extend_from_slice(&color),
+                false => rgba.extend_from_slice(&[0, 0, 0, 0]),
+            }
+        }
+    }
+    Ok(RgbaImage { width, height, pixels: rgba })
+}
+
+// Color key masking: a pixel whose components all lie in their (min, max)
+// range is left out.
+pub fn color_key(samples: &[u8], components: usize, ranges: &[(u8, u8)]) -> Vec<bool> {
+    samples
+        .chunks_exact(components)
+        .map(|pixel| pixel.iter().zip(ranges).all(|(v, (min, max))| (min..=max).contains(&v)))
+        .collect()
+}
+
+// pixels whose samples matched the color key become transparent
+pub fn clear_keyed(rgba: &mut RgbaImage, keyed: &[bool]) {
+    for (pixel, _) in rgba.pixels.chunks_exact_mut(4).zip(keyed).filter(|&(_, &keyed)| keyed) {
+        pixel[3] = 0;
+    }
+}
+
+// Multiply the gray of an /SMask image into the alpha, a mask of another size
+// is sampled at the nearest pixel. With a /Matte color the samples were blended
+// with it by the mask, that is taken out again.
+pub fn apply_soft_mask(rgba: &mut RgbaImage, mask: &RgbaImage, matte: Option<&[u8]>) {
+    let (width, height) = (rgba.width as usize, rgba.height as usize);
+    let (mask_width, mask_height) = (mask.width as usize, mask.height as usize);
+    if mask_width == 0 || mask_height == 0 {
+        return;
+    }
+    for (i, pixel) in rgba.pixels.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i % width, i / width);
+        let m = mask.pixels[4 * (y * mask_height / height * mask_width + x * mask_width / width)];
+        if let (Some(matte), 1..) = (matte, m) {
+            for (c, &mc) in pixel[..3].iter_mut().zip(matte) {
+                let v = mc as f32 + (*c as f32 - mc as f32) * 255. / m as f32;
+                *c = v.round().clamp(0., 255.) as u8;
+            }
+        }
+        pixel[3] = ((pixel[3] as u32 * m as u32 + 127) / 255) as u8;
+    }
+}
+
+// /Decode [1 0] on a mask swaps painted and transparent samples
+pub fn is_inverted(decode: Option<&[f32]>) -> bool {
+    matches!(decode, Some(&[d0, d1, ..]) if d0 > d1)
+}
+
+#[cfg(test)]
+mod test {
+    use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+
+    use crate::color::CmykToRgb;
+    use crate::plotter::RgbaImage;
+
+    use super::{
+        adobe_transform, apply_decode, apply_soft_mask, color_key, decode_indices, decode_samples, decode_stencil, downsample, downsample_to_device, expand_bits, is_inverted, lookup_palette,
+        palette_entry, pixels_to_unit, unpack_samples,
+    };
+
+    #[test]
+    fn gray_and_rgb() {
+        let gray = decode_samples(&[0, 128, 255, 7], 2, 2, 1, CmykToRgb::Subtractive).unwrap();
+        assert_eq!(&gray.pixels[4..8], &[128, 128, 128, 255]);
+        let rgb = decode_samples(&[1, 2, 3, 4, 5, 6], 2, 1, 3, CmykToRgb::Subtractive).unwrap();
+        assert_eq!(rgb.pixels, vec![1, 2, 3, 255, 4, 5, 6, 255]);
+        assert!(decode_samples(&[0; 5], 2, 1, 3, CmykToRgb::Subtractive).is_err());
+        let cmyk = decode_samples(&[255, 0, 255, 0, 0, 0, 0, 128], 2, 1, 4, CmykToRgb::Subtractive).unwrap();
+        assert_eq!(cmyk.pixels, vec![0, 255, 0, 255, 127, 127, 127, 255]);
+    }
+
+    #[test]
+    fn one_bit_gray() {
+        // 3x2, rows padded to a byte
+        let samples = expand_bits(&[0b1010_0000, 0b0110_0000], 3, 2, 1, 1).unwrap();
+        assert_eq!(samples, vec![255, 0, 255, 0, 255, 255]);
+        assert!(expand_bits(&[0], 3, 2, 1, 1).is_err());
+    }
+
+    // packs the samples of each row from the most significant bit on, rows
+    // padded to a byte
+    fn pack(samples: &[u32], row_samples: usize, bits: u32) -> Vec<u8> {
+        let mut data = vec![];
+        for row in samples.chunks(row_samples) {
+            let mut bytes = vec![0u8; (row_samples * bits as usize + 7) / 8];
+            for (x, &v) in row.iter().enumerate() {
+                for bit in 0..bits as usize {
+                    let pos = x * bits as usize + bit;
+                    if v >> (bits as usize - 1 - bit) & 1 == 1 {
+                        bytes[pos / 8] |= 0x80 >> (pos % 8);
+                    }
+                }
+            }
+            data.extend(bytes);
+        }
+        data
+    }
+
+    #[test]
+    fn sub_byte_rows() {
+        for bits in [1, 2, 4, 8] {
+            let max = (1u32 << bits) - 1;
+            for components in [1, 3, 4] {
+                for width in 1..=17u32 {
+                    let height = 3;
+                    let row_samples = width as usize * components;
+                    // a different value in every sample, the rows do not repeat
+                    let values: Vec<u32> = (0..row_samples * height).map(|i| (i as u32 * 7 + i as u32 / row_samples as u32) % (max + 1)).collect();
+                    let data = pack(&values, row_samples, bits);
+                    let raw = unpack_samples(&data, width, height as u32, components, bits).unwrap();
+                    assert_eq!(raw, values.iter().map(|&v| v as u8).collect::<Vec<_>>(), "{} bits, {} components, width {}", bits, components, width);
+                    let scaled = expand_bits(&data, width, height as u32, components, bits).unwrap();
+                    assert_eq!(scaled, values.iter().map(|&v| (v * 255 / max) as u8).collect::<Vec<_>>());
+                    // one byte short of the last row
+                    assert!(unpack_samples(&data[..data.len() - 1], width, height as u32, components, bits).is_err());
+                }
+            }
+        }
+        assert!(unpack_samples(&[0; 8], 3, 2, 1, 3).is_err());
+    }
+
+    #[test]
+    fn sixteen_bits() {
+        let data = [0x00, 0x00, 0xFF, 0xFF, 0x80, 0x00, 0x01, 0x00, 0x00, 0x7F];
+        assert_eq!(expand_bits(&data, 5, 1, 1, 16).unwrap(), vec![0, 255, 128, 1, 0]);
+        assert_eq!(expand_bits(&data[..8], 2, 1, 2, 16).unwrap(), vec![0, 255, 128, 1]);
+        assert!(expand_bits(&data[..9], 5, 1, 1, 16).is_err());
+    }
+
+    #[test]
+    fn decode_array() {
+        let mut samples = [0, 255, 51, 0];
+        apply_decode(&mut samples, 2, &[1., 0., 0., 0.5]);
+        assert_eq!(samples, [255, 128, 204, 0]);
+        // identity and short arrays leave the samples alone
+        apply_decode(&mut samples, 2, &[0., 1., 0., 1.]);
+        apply_decode(&mut samples, 2, &[1., 0.]);
+        assert_eq!(samples, [255, 128, 204, 0]);
+    }
+
+    #[test]
+    fn indexed_samples() {
+        // 3x2 with 2 bits, rows padded to a byte
+        let mut indices = unpack_samples(&[0b0001_1000, 0b1110_0100], 3, 2, 1, 2).unwrap();
+        assert_eq!(indices, vec![0, 1, 2, 3, 2, 1]);
+        assert!(unpack_samples(&[0], 3, 2, 1, 2).is_err());
+        assert_eq!(unpack_samples(&[0b1010_0000], 3, 1, 1, 1).unwrap(), vec![1, 0, 1]);
+
+        // the default [0 3] leaves the indices alone, [3 0] reverses them
+        decode_indices(&mut indices, 2, 3, &[0., 3.]);
+        assert_eq!(indices, vec![0, 1, 2, 3, 2, 1]);
+        decode_indices(&mut indices, 2, 3, &[3., 0.]);
+        assert_eq!(indices, vec![3, 2, 1, 0, 1, 2]);
+        // a partial range is interpolated and kept inside the palette
+        let mut indices = vec![0, 255, 128];
+        decode_indices(&mut indices, 8, 1, &[0., 2.]);
+        assert_eq!(indices, vec![0, 1, 1]);
+
+        let palette = [255, 0, 0, 0, 0, 255, 0, 255, 0];
+        assert_eq!(lookup_palette(&[1, 0, 7], 3, 2, &palette).unwrap(), vec![0, 0, 255, 255, 0, 0, 0, 255, 0]);
+        // hival bounds the palette even when the string is longer
+        assert_eq!(lookup_palette(&[2, 9], 3, 1, &palette).unwrap(), vec![0, 0, 255, 0, 0, 255]);
+        assert!(lookup_palette(&[0], 3, 1, &[1, 2]).is_err());
+        assert_eq!(palette_entry(&palette[..7], 3, 255, 200), Some(&[0, 0, 255][..]));
+    }
+
+    #[test]
+    fn box_filter() {
+        // 4x2 to 2x1: a black and white pair, and red next to a transparent pixel
+        let image = RgbaImage {
+            width: 4,
+            height: 2,
+            pixels: [[0, 0, 0, 255], [255, 255, 255, 255], [255, 0, 0, 255], [0, 0, 0, 0]].repeat(2).concat(),
+        };
+        let small = downsample(&image, 2, 1);
+        assert_eq!((small.width, small.height), (2, 1));
+        // the transparent pixel halves the alpha but does not darken the red
+        assert_eq!(small.pixels, vec![128, 128, 128, 255, 255, 0, 0, 128]);
+        // 3 to 2 columns, every source pixel is in a box
+        let small = downsample(&image, 3, 2);
+        assert_eq!(&small.pixels[..8], &[0, 0, 0, 255, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn device_resolution() {
+        let image = RgbaImage { width: 100, height: 40, pixels: vec![255; 4 * 100 * 40] };
+        // 100 pixels on 40 device pixels is more than twice, 40 on 20 is not
+        let small = downsample_to_device(&image, Transform2F::row_major(40., 0., 0., 0., 20., 0.), 2.).unwrap();
+        assert_eq!((small.width, small.height), (40, 20));
+        assert!(downsample_to_device(&image, Transform2F::row_major(50., 0., 0., 0., 20., 0.), 2.).is_none());
+        assert!(downsample_to_device(&image, Transform2F::row_major(40., 0., 0., 0., 20., 0.), 0.).is_none());
+        // rotated by 90 degrees the rows run along y
+        let small = downsample_to_device(&image, Transform2F::row_major(0., -10., 0., 10., 0., 0.), 2.).unwrap();
+        assert_eq!((small.width, small.height), (10, 10));
+    }
+
+    #[test]
+    fn adobe_marker() {
+        let app14 = [&[0xFF, 0xEE, 0, 14][..], b"Adobe", &[0, 100, 0, 0, 0, 0, 2]].concat();
+        let jfif = [&[0xFF, 0xE0, 0, 6][..], b"JFIF"].concat();
+        let sos = [0xFF, 0xDA, 0, 2];
+        let jpeg = [&[0xFF, 0xD8][..], &jfif, &app14, &sos].concat();
+        assert_eq!(adobe_transform(&jpeg), Some(2));
+        // markers after the scan are not looked at
+        let late = [&[0xFF, 0xD8][..], &jfif, &sos, &app14].concat();
+        assert_eq!(adobe_transform(&late), None);
+        assert_eq!(adobe_transform(&[0xFF, 0xD8, 0xFF]), None);
+    }
+
+    #[test]
+    fn first_row_on_top() {
+        let t = pixels_to_unit(4, 2);
+        assert_eq!(t * Vector2F::zero(), Vector2F::new(0., 1.));
+        assert_eq!(t * Vector2F::new(4., 2.), Vector2F::new(1., 0.));
+    }
+
+    #[test]
+    fn stencil_mask() {
+        // 3x2, rows padded to a byte: 010 / 100
+        let data = [0b0100_0000, 0b1000_0000];
+        let mask = decode_stencil(&data, 3, 2, false, [255, 0, 0, 255]).unwrap();
+        let alpha: Vec<u8> = mask.pixels.chunks(4).map(|p| p[3]).collect();
+        assert_eq!(alpha, vec![255, 0, 255, 0, 255, 255]);
+        assert_eq!(&mask.pixels[0..4], &[255, 0, 0, 255]);
+
+        let inverted = decode_stencil(&data, 3, 2, true, [0, 0, 0, 128]).unwrap();
+        let alpha: Vec<u8> = inverted.pixels.chunks(4).map(|p| p[3]).collect();
+        assert_eq!(alpha, vec![0, 128, 0, 128, 0, 0]);
+
+        assert!(is_inverted(Some(&[1., 0.])));
+        assert!(!is_inverted(Some(&[0., 1.])));
+        assert!(!is_inverted(None));
+    }
+
+    #[test]
+    fn soft_masks() {
+        // a 2x2 image and a 1x2 mask, opaque at the top and half at the bottom
+        let mut image = decode_samples(&[255, 0, 0, 0, 0, 255, 0, 255, 0, 200, 200, 200], 2, 2, 3, CmykToRgb::Subtractive).unwrap();
+        let mask = decode_samples(&[255, 128], 1, 2, 1, CmykToRgb::Subtractive).unwrap();
+        apply_soft_mask(&mut image, &mask, None);
+        let alpha: Vec<u8> = image.pixels.chunks(4).map(|p| p[3]).collect();
+        assert_eq!(alpha, vec![255, 255, 128, 128]);
+        assert_eq!(&image.pixels[8..11], &[0, 255, 0]);
+
+        // white matte: 200 is 145 blended with white at half alpha
+        let mut image = decode_samples(&[200], 1, 1, 1, CmykToRgb::Subtractive).unwrap();
+        let mask = decode_samples(&[128], 1, 1, 1, CmykToRgb::Subtractive).unwrap();
+        apply_soft_mask(&mut image, &mask, Some(&[255, 255, 255, 255]));
+        assert_eq!(image.pixels, vec![145, 145, 145, 128]);
+    }
+
+    #[test]
+    fn color_keys() {
+        let samples = [255, 250, 252, 255, 0, 0, 249, 255, 255];
+        assert_eq!(color_key(&samples, 3, &[(250, 255); 3]), vec![true, false, false]);
+        // 1 bit gray, white expanded to 255
+        let samples = expand_bits(&[0b1000_0000], 2, 1, 1, 1).unwrap();
+        assert_eq!(color_key(&samples, 1, &[(255, 255)]), vec![true, false]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::{fmt, fs::File, ops::Deref, path::Path, sync::Arc};
+
+use pdf::{
+    file::{CachedFile, FileOptions},
+    PdfError,
+};
+
+// The bytes of an input document. Files are mapped with the mmap feature, so
+// only the parts that are parsed are read; buffers of the caller are shared.
+pub enum InputData {
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+    Read(Vec<u8>),
+    Shared(Arc<[u8]>),
+    // the document after leading garbage
+    Tail(Box<InputData>, usize),
+}
+
+impl Deref for InputData {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match *self {
+            #[cfg(feature = "mmap")]
+            InputData::Mapped(ref map) => map,
+            InputData::Read(ref data) => data,
+            InputData::Shared(ref data) => data,
+            InputData::Tail(ref data, start) => &data[start..],
+        }
+    }
+}
+
+impl From<Vec<u8>> for InputData {
+    fn from(data: Vec<u8>) -> Self {
+        InputData::Read(data)
+    }
+}
+
+impl From<Arc<[u8]>> for InputData {
+    fn from(data: Arc<[u8]>) -> Self {
+        InputData::Shared(data)
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> PdfError {
+    PdfError::Other {
+        msg: format!("can not read {:?}: {}", path, e),
+    }
+}
+
+#[cfg(feature = "mmap")]
+pub fn read_input(path: &Path) -> Result<InputData, PdfError> {
+    let file = File::open(path).map_err(|e| io_error(path, e))?;
+    // Safety: the file must not be truncated while it is mapped. Where mapping
+    // fails (pipes, some network file systems) the file is read instead.
+    match unsafe { memmap2::Mmap::map(&file) } {
+        Ok(map) => Ok(InputData::Mapped(map)),
+        Err(e) => {
+            debug!("can not map {:?}, reading it: {}", path, e);
+            read_file(path, file)
+        }
+    }
+}
+
+#[cfg(not(feature = "mmap"))]
+pub fn read_input(path: &Path) -> Result<InputData, PdfError> {
+    let file = File::open(path).map_err(|e| io_error(path, e))?;
+    read_file(path, file)
+}
+
+fn read_file(path: &Path, mut file: File) -> Result<InputData, PdfError> {
+    use std::io::Read;
+    let mut data = vec![];
+    file.read_to_end(&mut data).map_err(|e| io_error(path, e))?;
+    Ok(InputData::Read(data))
+}
+
+// the %PDF header may come this late, after garbage the producer wrote first
+const MAX_HEADER_OFFSET: usize = 1024;
+
+// what an input is when it is not a pdf
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotPdf {
+    Empty,
+    Html,
+    Zip,
+    Unknown,
+}
+
+impl fmt::Display for NotPdf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NotPdf::Empty => write!(f, "an empty file"),
+            NotPdf::Html => write!(f, "an HTML page"),
+            NotPdf::Zip => write!(f, "a zip archive"),
+            NotPdf::Unknown => write!(f, "data without a %PDF header in the first {} bytes", MAX_HEADER_OFFSET),
+        }
+    }
+}
+
+// the offset of the %PDF header
+pub fn sniff(data: &[u8]) -> Result<usize, NotPdf> {
+    if data.is_empty() {
+        return Err(NotPdf::Empty);
+    }
+    // a zip may well contain a pdf header
+    if data.starts_with(b"PK\x03\x04") {
+        return Err(NotPdf::Zip);
+    }
+    let head = &data[..data.len().min(MAX_HEADER_OFFSET + 5)];
+    if let Some(start) = head.windows(5).position(|w| w == b"%PDF-") {
+        return Ok(start);
+    }
+    let text = head.strip_prefix(b"\xef\xbb\xbf").unwrap_or(head);
+    let text = &text[text.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(text.len())..];
+    let starts_with = |prefix: &[u8]| text.get(..prefix.len()).is_some_and(|t| t.eq_ignore_ascii_case(prefix));
+    match starts_with(b"<!doctype html") || starts_with(b"<html") {
+        true => Err(NotPdf::Html),
+        false => Err(NotPdf::Unknown),
+    }
+}
+
+fn checked(data: InputData) -> Result<InputData, NotPdf> {
+    match sniff(&data)? {
+        0 => Ok(data),
+        start => {
+            warn!("skipping {} bytes before the %PDF header", start);
+            Ok(InputData::Tail(Box::new(data), start))
+        }
+    }
+}
+
+pub fn open(path: &Path) -> Result<CachedFile<InputData>, PdfError> {
+    let data = checked(read_input(path)?).map_err(|e| PdfError::Other {
+        msg: format!("{:?} is not a pdf, it is {}", path, e),
+    })?;
+    FileOptions::cached().load(data)
+}
+
+// a document already in memory, Arc<[u8]> buffers are not copied
+pub fn open_data(data: impl Into<InputData>) -> Result<CachedFile<InputData>, PdfError> {
+    let data = checked(data.into()).map_err(|e| PdfError::Other {
+        msg: format!("not a pdf, it is {}", e),
+    })?;
+    FileOptions::cached().load(data)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, sync::Arc};
+
+    use super::{open, open_data, read_input, sniff, InputData, NotPdf};
+
+    #[test]
+    fn input_sources() {
+        let path = Path::new("resources/fill_alpha.pdf");
+        let data = read_input(path).unwrap();
+        #[cfg(feature = "mmap")]
+        assert!(matches!(data, InputData::Mapped(_)));
+        let shared: Arc<[u8]> = data.to_vec().into();
+        let file = open_data(shared.clone()).unwrap();
+        assert_eq!(file.num_pages(), 1);
+        assert_eq!(open(path).unwrap().num_pages(), 1);
+        // the document keeps the buffer of the caller
+        assert_eq!(Arc::strong_count(&shared), 2);
+        drop(file);
+        assert_eq!(Arc::strong_count(&shared), 1);
+    }
+
+    #[test]
+    fn junk_inputs() {
+        let sniffed = |name: &str| sniff(&std::fs::read(Path::new("resources").join(name)).unwrap());
+        assert_eq!(sniffed("junk_empty.pdf"), Err(NotPdf::Empty));
+        assert_eq!(sniffed("junk_html.pdf"), Err(NotPdf::Html));
+        assert_eq!(sniffed("junk_zip.pdf"), Err(NotPdf::Zip));
+        assert_eq!(sniffed("junk_text.pdf"), Err(NotPdf::Unknown));
+        let error = open(Path::new("resources/junk_html.pdf")).err().unwrap();
+        assert!(error.to_string().contains("is not a pdf, it is an HTML page"), "{}", error);
+
+        // garbage before the header is skipped, the offsets count from the header
+        assert!(sniffed("leading_garbage.pdf").unwrap() > 0);
+        let file = open(Path::new("resources/leading_garbage.pdf")).unwrap();
+        assert_eq!(file.num_pages(), 1);
+        assert!(file.get_page(0).is_ok());
+    }
+}
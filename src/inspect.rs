@@ -0,0 +1,180 @@
+use std::fmt;
+
+use pathfinder_content::{fill::FillRule, outline::{Contour, Outline}};
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+
+use crate::geometry::contains;
+use crate::plotter::{DrawMode, Fill, LinearGradient, Plotter, RgbaImage, Tiling};
+
+// curves are flattened to this many device units for the hit test
+const TOLERANCE: f32 = 0.1;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DrawKind {
+    Fill,
+    Stroke,
+    Image,
+    Gradient,
+    Tiling,
+}
+
+impl fmt::Display for DrawKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DrawKind::Fill => "fill",
+            DrawKind::Stroke => "stroke",
+            DrawKind::Image => "image",
+            DrawKind::Gradient => "gradient",
+            DrawKind::Tiling => "tiling",
+        };
+        f.write_str(name)
+    }
+}
+
+// one draw call and the operator it came from, everything in device space
+#[derive(Debug, Clone)]
+pub struct DrawRecord {
+    pub op: usize,
+    pub kind: DrawKind,
+    // None for images and shadings
+    pub color: Option<Fill>,
+    pub bounds: RectF,
+    // the painted area, strokes with their width, caps and dashes
+    outline: Outline,
+    fill_rule: FillRule,
+    clip: Option<usize>,
+}
+
+impl fmt::Display for DrawRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "op {}: {}", self.op, self.kind)?;
+        match self.color {
+            Some(Fill::Solid(r, g, b)) => write!(f, " rgb {} {} {}", r, g, b),
+            Some(Fill::Pattern(pattern)) => write!(f, " pattern {}", pattern.get_inner().id),
+            None => Ok(()),
+        }
+    }
+}
+
+struct ClipRecord {
+    outline: Outline,
+    fill_rule: FillRule,
+    parent: Option<usize>,
+}
+
+// Keeps the geometry of every draw call with the operator it came from, to
+// find what was painted at a point of the page.
+#[derive(Default)]
+pub struct InspectPlotter {
+    op: usize,
+    draws: Vec<DrawRecord>,
+    // the index is the clip path id
+    clips: Vec<ClipRecord>,
+}
+
+impl InspectPlotter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // the topmost draw that paints the point, bounds first, then the outline and the clip
+    pub fn hit_test(&self, point: Vector2F) -> Option<&DrawRecord> {
+        self.draws.iter().rev().find(|draw| {
+            draw.bounds.contains_point(point) && contains(&draw.outline, draw.fill_rule, point, TOLERANCE) && self.clipped_in(draw.clip, point)
+        })
+    }
+    fn clipped_in(&self, mut clip: Option<usize>, point: Vector2F) -> bool {
+        while let Some(id) = clip {
+            let record = &self.clips[id];
+            if !contains(&record.outline, record.fill_rule, point, TOLERANCE) {
+                return false;
+            }
+            clip = record.parent;
+        }
+        true
+    }
+    fn add(&mut self, kind: DrawKind, color: Option<Fill>, outline: Outline, fill_rule: FillRule, clip: Option<usize>) {
+        self.draws.push(DrawRecord { op: self.op, kind, color, bounds: outline.bounds(), outline, fill_rule, clip });
+    }
+}
+
+impl Plotter for InspectPlotter {
+    type ClipPathId = usize;
+    fn draw(&mut self, outline: &Outline, mode: &DrawMode, fill_rule: FillRule, transform: Transform2F, clip: Option<Self::ClipPathId>) {
+        match mode {
+            DrawMode::Fill { fill } => self.add(DrawKind::Fill, Some(fill.color), outline.clone().transformed(&transform), fill_rule, clip),
+            DrawMode::Stroke { stroke, stroke_mode } => {
+                let area = stroke_mode.to_outline(outline).transformed(&transform);
+                self.add(DrawKind::Stroke, Some(stroke.color), area, FillRule::Winding, clip);
+            }
+            DrawMode::FillStroke { fill, stroke, stroke_mode } => {
+                self.add(DrawKind::Fill, Some(fill.color), outline.clone().transformed(&transform), fill_rule, clip);
+                let area = stroke_mode.to_outline(outline).transformed(&transform);
+                self.add(DrawKind::Stroke, Some(stroke.color), area, FillRule::Winding, clip);
+            }
+        }
+    }
+    fn create_clip_path(&mut self, outline: Outline, fill_rule: FillRule, parent: Option<Self::ClipPathId>) -> Self::ClipPathId {
+        self.clips.push(ClipRecord { outline, fill_rule, parent });
+        self.clips.len() - 1
+    }
+    fn draw_image(&mut self, _: &RgbaImage, transform: Transform2F, clip: Option<Self::ClipPathId>, _: f32, _: bool) {
+        let mut outline = Outline::new();
+        outline.push_contour(Contour::from_rect(RectF::new(Vector2F::zero(), Vector2F::splat(1.))));
+        self.add(DrawKind::Image, None, outline.transformed(&transform), FillRule::Winding, clip);
+    }
+    fn draw_gradient(&mut self, outline: Outline, _: &LinearGradient, clip: Option<Self::ClipPathId>) {
+        self.add(DrawKind::Gradient, None, outline, FillRule::Winding, clip);
+    }
+    fn draw_tiling(&mut self, outline: Outline, fill_rule: FillRule, _: &Tiling, clip: Option<Self::ClipPathId>) {
+        self.add(DrawKind::Tiling, None, outline, fill_rule, clip);
+    }
+    fn begin_op(&mut self, op: usize) {
+        self.op = op;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pathfinder_content::{
+        fill::FillRule,
+        outline::{Contour, Outline},
+        stroke::StrokeStyle,
+    };
+    use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+
+    use super::{DrawKind, InspectPlotter};
+    use crate::plotter::{BlendMode, DrawMode, Fill, FillMode, Plotter, Stroke};
+
+    fn rect(origin: f32, size: f32) -> Outline {
+        let mut outline = Outline::new();
+        outline.push_contour(Contour::from_rect(RectF::new(Vector2F::splat(origin), Vector2F::splat(size))));
+        outline
+    }
+
+    fn fill(r: f32) -> FillMode {
+        FillMode { color: Fill::Solid(r, 0., 0.), alpha: 1., mode: BlendMode::Normal }
+    }
+
+    #[test]
+    fn topmost_draw() {
+        let mut plotter = InspectPlotter::new();
+        plotter.begin_op(3);
+        plotter.draw(&rect(0., 100.), &DrawMode::Fill { fill: fill(1.) }, FillRule::Winding, Transform2F::default(), None);
+        plotter.begin_op(8);
+        let stroke_mode = Stroke { dash_pattern: None, style: StrokeStyle { line_width: 4., ..Default::default() } };
+        plotter.draw(&rect(20., 40.), &DrawMode::Stroke { stroke: fill(0.5), stroke_mode }, FillRule::Winding, Transform2F::default(), None);
+        plotter.begin_op(12);
+        let clip = plotter.create_clip_path(rect(70., 10.), FillRule::Winding, None);
+        plotter.draw(&rect(0., 100.), &DrawMode::Fill { fill: fill(0.) }, FillRule::Winding, Transform2F::default(), Some(clip));
+
+        let hit = |x: f32, y: f32| plotter.hit_test(Vector2F::new(x, y)).map(|draw| (draw.op, draw.kind));
+        // on the stroke, inside the stroked square only the fill below
+        assert_eq!(hit(21., 40.), Some((8, DrawKind::Stroke)));
+        assert_eq!(hit(40., 40.), Some((3, DrawKind::Fill)));
+        // the last fill covers the page but is clipped to its small square
+        assert_eq!(hit(75., 75.), Some((12, DrawKind::Fill)));
+        assert_eq!(hit(90., 90.), Some((3, DrawKind::Fill)));
+        assert_eq!(hit(150., 50.), None);
+        assert_eq!(plotter.hit_test(Vector2F::new(21., 40.)).unwrap().to_string(), "op 8: stroke rgb 0.5 0 0");
+    }
+}
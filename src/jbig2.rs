@@ -0,0 +1,731 @@
+use std::collections::HashMap;
+
+use pdf::PdfError;
+
+use crate::fax;
+
+// larger regions are refused instead of allocated
+const MAX_PIXELS: u64 = 1 << 28;
+
+fn error(msg: impl Into<String>) -> PdfError {
+    PdfError::Other { msg: format!("jbig2: {}", msg.into()) }
+}
+
+// (Qe, next index after an MPS, next index after an LPS, switch the MPS) of T.88 table E.1
+const QE: [(u32, u8, u8, bool); 47] = [
+    (0x5601, 1, 1, true), (0x3401, 2, 6, false), (0x1801, 3, 9, false), (0x0AC1, 4, 12, false),
+    (0x0521, 5, 29, false), (0x0221, 38, 33, false), (0x5601, 7, 6, true), (0x5401, 8, 14, false),
+    (0x4801, 9, 14, false), (0x3801, 10, 14, false), (0x3001, 11, 17, false), (0x2401, 12, 18, false),
+    (0x1C01, 13, 20, false), (0x1601, 29, 21, false), (0x5601, 15, 14, true), (0x5401, 16, 14, false),
+    (0x5101, 17, 15, false), (0x4801, 18, 16, false), (0x3801, 19, 17, false), (0x3401, 20, 18, false),
+    (0x3001, 21, 19, false), (0x2801, 22, 19, false), (0x2401, 23, 20, false), (0x2201, 24, 21, false),
+    (0x1C01, 25, 22, false), (0x1801, 26, 23, false), (0x1601, 27, 24, false), (0x1401, 28, 25, false),
+    (0x1201, 29, 26, false), (0x1101, 30, 27, false), (0x0AC1, 31, 28, false), (0x09C1, 32, 29, false),
+    (0x08A1, 33, 30, false), (0x0521, 34, 31, false), (0x0441, 35, 32, false), (0x02A1, 36, 33, false),
+    (0x0221, 37, 34, false), (0x0141, 38, 35, false), (0x0111, 39, 36, false), (0x0085, 40, 37, false),
+    (0x0049, 41, 38, false), (0x0025, 42, 39, false), (0x0015, 43, 40, false), (0x0009, 44, 41, false),
+    (0x0005, 45, 42, false), (0x0001, 45, 43, false), (0x5601, 46, 46, false),
+];
+
+// the fill bytes read after the end of the data before it counts as cut off
+const MAX_FILLS: u32 = 16;
+
+// The arithmetic decoder of T.88 annex E. A context is the index into QE
+// shifted left by one and the MPS in the lowest bit. Past the end of the data
+// it reads 0xFF bytes, like after a marker.
+struct MqDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    c: u32,
+    a: u32,
+    ct: u32,
+    fills: u32,
+}
+
+impl<'a> MqDecoder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        let mut mq = MqDecoder { data, pos: 0, c: 0, a: 0x8000, ct: 0, fills: 0 };
+        mq.c = (mq.byte(0) as u32) << 16;
+        mq.byte_in();
+        mq.c <<= 7;
+        mq.ct -= 7;
+        mq
+    }
+    fn byte(&self, pos: usize) -> u8 {
+        self.data.get(pos).copied().unwrap_or(0xFF)
+    }
+    fn byte_in(&mut self) {
+        if self.byte(self.pos) == 0xFF {
+            if self.byte(self.pos + 1) > 0x8F {
+                self.c = self.c.wrapping_add(0xFF00);
+                self.ct = 8;
+                self.fills += 1;
+            } else {
+                self.pos += 1;
+                self.c = self.c.wrapping_add((self.byte(self.pos) as u32) << 9);
+                self.ct = 7;
+            }
+        } else {
+            self.pos += 1;
+            self.c = self.c.wrapping_add((self.byte(self.pos) as u32) << 8);
+            self.ct = 8;
+        }
+    }
+    fn check(&self) -> Result<(), PdfError> {
+        match self.fills > MAX_FILLS {
+            true => Err(error("data ends early")),
+            false => Ok(()),
+        }
+    }
+    fn decode(&mut self, cx: &mut u8) -> u8 {
+        let (index, mut mps) = ((*cx >> 1) as usize, *cx & 1);
+        let (qe, nmps, nlps, switch) = QE[index];
+        self.a -= qe;
+        let (d, next) = if self.c >> 16 < qe {
+            // the exchanged lps path
+            let lps = self.a >= qe;
+            self.a = qe;
+            match lps {
+                true => (1 - mps, nlps),
+                false => (mps, nmps),
+            }
+        } else {
+            self.c -= qe << 16;
+            if self.a & 0x8000 != 0 {
+                return mps;
+            }
+            match self.a < qe {
+                true => (1 - mps, nlps),
+                false => (mps, nmps),
+            }
+        };
+        if d != mps && switch {
+            mps = d;
+        }
+        while self.a & 0x8000 == 0 {
+            if self.ct == 0 {
+                self.byte_in();
+            }
+            self.a <<= 1;
+            self.c <<= 1;
+            self.ct -= 1;
+        }
+        *cx = next << 1 | mps;
+        d
+    }
+}
+
+// the integer decoding procedure of annex A.2, None is OOB
+struct IntDecoder {
+    contexts: Vec<u8>,
+}
+
+impl IntDecoder {
+    fn new() -> Self {
+        IntDecoder { contexts: vec![0; 512] }
+    }
+    fn decode(&mut self, mq: &mut MqDecoder) -> Option<i64> {
+        let mut prev = 1usize;
+        let mut bits = |n: u32| {
+            let mut value = 0i64;
+            for _ in 0..n {
+                let bit = mq.decode(&mut self.contexts[prev]) as usize;
+                prev = match prev < 256 {
+                    true => prev << 1 | bit,
+                    false => (prev << 1 | bit) & 511 | 256,
+                };
+                value = value << 1 | bit as i64;
+            }
+            value
+        };
+        let negative = bits(1) == 1;
+        let value = if bits(1) == 0 {
+            bits(2)
+        } else if bits(1) == 0 {
+            bits(4) + 4
+        } else if bits(1) == 0 {
+            bits(6) + 20
+        } else if bits(1) == 0 {
+            bits(8) + 84
+        } else if bits(1) == 0 {
+            bits(12) + 340
+        } else {
+            bits(32) + 4436
+        };
+        match (negative, value) {
+            (true, 0) => None,
+            (true, v) => Some(-v),
+            (false, v) => Some(v),
+        }
+    }
+}
+
+// the symbol id decoding procedure of annex A.3
+struct IdDecoder {
+    len: u32,
+    contexts: Vec<u8>,
+}
+
+impl IdDecoder {
+    fn new(len: u32) -> Self {
+        IdDecoder { len, contexts: vec![0; 2 << len] }
+    }
+    fn decode(&mut self, mq: &mut MqDecoder) -> usize {
+        let mut prev = 1usize;
+        for _ in 0..self.len {
+            prev = prev << 1 | mq.decode(&mut self.contexts[prev]) as usize;
+        }
+        prev - (1 << self.len)
+    }
+}
+
+// one byte per pixel, 1 is black as in JBIG2
+#[derive(Debug, Clone, PartialEq)]
+struct Bitmap {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Bitmap {
+    fn new(width: u32, height: u32, value: u8) -> Result<Self, PdfError> {
+        if width as u64 * height as u64 > MAX_PIXELS {
+            return Err(error(format!("region of {}x{} pixels is too large", width, height)));
+        }
+        Ok(Bitmap { width, height, pixels: vec![value; width as usize * height as usize] })
+    }
+    // pixels outside the bitmap are white
+    fn get(&self, x: i64, y: i64) -> u8 {
+        match x >= 0 && y >= 0 && x < self.width as i64 && y < self.height as i64 {
+            true => self.pixels[y as usize * self.width as usize + x as usize],
+            false => 0,
+        }
+    }
+    fn row(&self, y: u32) -> &[u8] {
+        let w = self.width as usize;
+        &self.pixels[y as usize * w..(y as usize + 1) * w]
+    }
+    // draws other with its top left corner at x, y, the parts outside are dropped
+    fn combine(&mut self, other: &Bitmap, x: i64, y: i64, op: Combine) {
+        for oy in 0..other.height as i64 {
+            for ox in 0..other.width as i64 {
+                let (px, py) = (x + ox, y + oy);
+                if px < 0 || py < 0 || px >= self.width as i64 || py >= self.height as i64 {
+                    continue;
+                }
+                let pixel = &mut self.pixels[py as usize * self.width as usize + px as usize];
+                let src = other.pixels[oy as usize * other.width as usize + ox as usize];
+                *pixel = match op {
+                    Combine::Or => *pixel | src,
+                    Combine::And => *pixel & src,
+                    Combine::Xor => *pixel ^ src,
+                    Combine::Xnor => 1 - (*pixel ^ src),
+                    Combine::Replace => src,
+                };
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Combine {
+    Or,
+    And,
+    Xor,
+    Xnor,
+    Replace,
+}
+
+impl Combine {
+    fn from_bits(bits: u8) -> Result<Self, PdfError> {
+        Ok(match bits {
+            0 => Combine::Or,
+            1 => Combine::And,
+            2 => Combine::Xor,
+            3 => Combine::Xnor,
+            4 => Combine::Replace,
+            bits => return Err(error(format!("invalid combination operator {}", bits))),
+        })
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], PdfError> {
+        let bytes = self.data.get(self.pos..self.pos + n).ok_or_else(|| error("data ends early"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+    fn u8(&mut self) -> Result<u8, PdfError> {
+        Ok(self.take(1)?[0])
+    }
+    fn i8(&mut self) -> Result<i8, PdfError> {
+        Ok(self.u8()? as i8)
+    }
+    fn u16(&mut self) -> Result<u16, PdfError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+    fn u32(&mut self) -> Result<u32, PdfError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        self.pos = self.data.len();
+        rest
+    }
+}
+
+struct Segment<'a> {
+    number: u32,
+    kind: u8,
+    refs: Vec<u32>,
+    data: &'a [u8],
+}
+
+// the segment headers of 7.2, in the sequential organisation of embedded streams
+fn segments(data: &[u8]) -> Result<Vec<Segment<'_>>, PdfError> {
+    let mut reader = Reader { data, pos: 0 };
+    let mut segments = vec![];
+    while reader.pos < data.len() {
+        let number = reader.u32()?;
+        let flags = reader.u8()?;
+        let first = reader.u8()?;
+        let count = match first >> 5 {
+            7 => {
+                reader.pos -= 1;
+                let count = reader.u32()? & 0x1FFF_FFFF;
+                // the retain bits of the referred segments and this one
+                reader.take(count as usize / 8 + 1)?;
+                count
+            }
+            count => count as u32,
+        };
+        let size = match number {
+            0..=256 => 1,
+            257..=65536 => 2,
+            _ => 4,
+        };
+        let refs = (0..count)
+            .map(|_| Ok(reader.take(size)?.iter().fold(0, |v, &b| v << 8 | b as u32)))
+            .collect::<Result<Vec<u32>, PdfError>>()?;
+        reader.take(if flags & 0x40 != 0 { 4 } else { 1 })?;
+        let len = reader.u32()?;
+        if len == u32::MAX {
+            return Err(error(format!("segment {} of unknown length", number)));
+        }
+        segments.push(Segment { number, kind: flags & 0x3F, refs, data: reader.take(len as usize)? });
+    }
+    Ok(segments)
+}
+
+// the region segment information field of 7.4.1
+struct RegionInfo {
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    combine: Combine,
+}
+
+impl RegionInfo {
+    fn read(reader: &mut Reader) -> Result<Self, PdfError> {
+        Ok(RegionInfo {
+            width: reader.u32()?,
+            height: reader.u32()?,
+            x: reader.u32()?,
+            y: reader.u32()?,
+            combine: Combine::from_bits(reader.u8()? & 7)?,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Pixel {
+    At(usize),
+    Fixed(i64, i64),
+}
+use Pixel::{At, Fixed};
+
+// The pixels of the generic templates, from the most to the least significant
+// bit of the context. At are the adaptive pixels in the order of the segment.
+const TEMPLATES: [&[Pixel]; 4] = [
+    &[
+        At(3), Fixed(-1, -2), Fixed(0, -2), Fixed(1, -2), At(2), At(1), Fixed(-2, -1), Fixed(-1, -1), Fixed(0, -1), Fixed(1, -1), Fixed(2, -1),
+        At(0), Fixed(-4, 0), Fixed(-3, 0), Fixed(-2, 0), Fixed(-1, 0),
+    ],
+    &[
+        Fixed(-1, -2), Fixed(0, -2), Fixed(1, -2), Fixed(2, -2), Fixed(-2, -1), Fixed(-1, -1), Fixed(0, -1), Fixed(1, -1), Fixed(2, -1), At(0),
+        Fixed(-3, 0), Fixed(-2, 0), Fixed(-1, 0),
+    ],
+    &[Fixed(-1, -2), Fixed(0, -2), Fixed(1, -2), Fixed(-2, -1), Fixed(-1, -1), Fixed(0, -1), Fixed(1, -1), At(0), Fixed(-2, 0), Fixed(-1, 0)],
+    &[Fixed(-3, -1), Fixed(-2, -1), Fixed(-1, -1), Fixed(0, -1), Fixed(1, -1), At(0), Fixed(-4, 0), Fixed(-3, 0), Fixed(-2, 0), Fixed(-1, 0)],
+];
+
+// the context of the typical prediction bit of each template, 6.2.5.7
+const SLTP_CONTEXTS: [usize; 4] = [0x9B25, 0x0795, 0x00E5, 0x0195];
+
+#[derive(Debug, Clone)]
+struct Generic {
+    template: usize,
+    typical_prediction: bool,
+    at: Vec<(i8, i8)>,
+}
+
+impl Generic {
+    // the template number is read by the caller, it is packed with other flags
+    fn read_at(reader: &mut Reader, template: usize) -> Result<Vec<(i8, i8)>, PdfError> {
+        let count = if template == 0 { 4 } else { 1 };
+        (0..count).map(|_| Ok((reader.i8()?, reader.i8()?))).collect()
+    }
+    fn contexts(&self) -> Vec<u8> {
+        vec![0; 1 << TEMPLATES[self.template].len()]
+    }
+    // the arithmetic generic region decoding procedure of 6.2.5
+    fn decode(&self, mq: &mut MqDecoder, contexts: &mut [u8], width: u32, height: u32) -> Result<Bitmap, PdfError> {
+        let mut bitmap = Bitmap::new(width, height, 0)?;
+        let pixels: Vec<(i64, i64)> = TEMPLATES[self.template]
+            .iter()
+            .map(|&p| match p {
+                At(i) => (self.at[i].0 as i64, self.at[i].1 as i64),
+                Fixed(dx, dy) => (dx, dy),
+            })
+            .collect();
+        let mut typical = false;
+        for y in 0..height {
+            mq.check()?;
+            if self.typical_prediction {
+                typical ^= mq.decode(&mut contexts[SLTP_CONTEXTS[self.template]]) == 1;
+                if typical {
+                    if y > 0 {
+                        let w = width as usize;
+                        let (above, row) = bitmap.pixels.split_at_mut(y as usize * w);
+                        row[..w].copy_from_slice(&above[(y as usize - 1) * w..]);
+                    }
+                    continue;
+                }
+            }
+            for x in 0..width {
+                let cx = pixels.iter().fold(0, |cx, &(dx, dy)| cx << 1 | bitmap.get(x as i64 + dx, y as i64 + dy) as usize);
+                bitmap.pixels[(y * width + x) as usize] = mq.decode(&mut contexts[cx]);
+            }
+        }
+        Ok(bitmap)
+    }
+}
+
+// the generic region segment of 7.4.6
+fn generic_region(data: &[u8]) -> Result<(RegionInfo, Bitmap), PdfError> {
+    let mut reader = Reader { data, pos: 0 };
+    let info = RegionInfo::read(&mut reader)?;
+    let flags = reader.u8()?;
+    let bitmap = match flags & 1 == 1 {
+        true => {
+            let rows = fax::decode_g4(reader.rest(), info.width, info.height)?;
+            let stride = (info.width as usize).div_ceil(8);
+            let mut bitmap = Bitmap::new(info.width, info.height, 0)?;
+            for (i, pixel) in bitmap.pixels.iter_mut().enumerate() {
+                let (y, x) = (i / info.width as usize, i % info.width as usize);
+                *pixel = rows[y * stride + x / 8] >> (7 - x % 8) & 1;
+            }
+            bitmap
+        }
+        false => {
+            let template = (flags >> 1 & 3) as usize;
+            let generic = Generic { template, typical_prediction: flags & 8 != 0, at: Generic::read_at(&mut reader, template)? };
+            let mut mq = MqDecoder::new(reader.rest());
+            generic.decode(&mut mq, &mut generic.contexts(), info.width, info.height)?
+        }
+    };
+    Ok((info, bitmap))
+}
+
+// the symbol dictionary segment of 7.4.2, returns the exported symbols
+fn symbol_dictionary(data: &[u8], inputs: Vec<&Bitmap>) -> Result<Vec<Bitmap>, PdfError> {
+    let mut reader = Reader { data, pos: 0 };
+    let flags = reader.u16()?;
+    if flags & 1 != 0 {
+        return Err(error("huffman coded symbol dictionaries are not supported"));
+    }
+    if flags & 2 != 0 {
+        return Err(error("refinement coding is not supported"));
+    }
+    if flags & 0x100 != 0 {
+        return Err(error("retained coding contexts are not supported"));
+    }
+    let template = (flags >> 10 & 3) as usize;
+    let generic = Generic { template, typical_prediction: false, at: Generic::read_at(&mut reader, template)? };
+    let exported = reader.u32()? as usize;
+    let new = reader.u32()? as usize;
+    let mut mq = MqDecoder::new(reader.rest());
+    let mut contexts = generic.contexts();
+    let (mut height_delta, mut width_delta, mut export_runs) = (IntDecoder::new(), IntDecoder::new(), IntDecoder::new());
+
+    // height classes of symbols that are decoded one after the other, 6.5.5
+    let mut symbols = Vec::with_capacity(new.min(1 << 16));
+    let mut height = 0i64;
+    while symbols.len() < new {
+        mq.check()?;
+        height += height_delta.decode(&mut mq).ok_or_else(|| error("missing height class"))?;
+        let mut width = 0i64;
+        while let Some(delta) = width_delta.decode(&mut mq) {
+            mq.check()?;
+            if symbols.len() == new {
+                return Err(error("more symbols than announced"));
+            }
+            width += delta;
+            let (Ok(w), Ok(h)) = (u32::try_from(width), u32::try_from(height)) else {
+                return Err(error(format!("invalid symbol size {}x{}", width, height)));
+            };
+            symbols.push(generic.decode(&mut mq, &mut contexts, w, h)?);
+        }
+    }
+
+    // runs of flags over the input and the new symbols, starting with not exported, 6.5.10
+    let total = inputs.len() + symbols.len();
+    let mut flags = Vec::with_capacity(total);
+    let mut export = false;
+    while flags.len() < total {
+        mq.check()?;
+        let run = export_runs.decode(&mut mq).ok_or_else(|| error("missing export flags"))?;
+        if run < 0 || flags.len() + run as usize > total {
+            return Err(error("invalid export flags"));
+        }
+        flags.resize(flags.len() + run as usize, export);
+        export = !export;
+    }
+    let all = inputs.into_iter().cloned().chain(symbols);
+    let symbols: Vec<Bitmap> = all.zip(flags).filter(|&(_, export)| export).map(|(symbol, _)| symbol).collect();
+    if symbols.len() != exported {
+        warn!("jbig2 symbol dictionary exports {} of {} symbols", symbols.len(), exported);
+    }
+    Ok(symbols)
+}
+
+// the text region segment of 7.4.3, symbols placed along strips
+fn text_region(data: &[u8], symbols: &[&Bitmap]) -> Result<(RegionInfo, Bitmap), PdfError> {
+    let mut reader = Reader { data, pos: 0 };
+    let info = RegionInfo::read(&mut reader)?;
+    let flags = reader.u16()?;
+    if flags & 1 != 0 {
+        return Err(error("huffman coded text regions are not supported"));
+    }
+    let refine = flags & 2 != 0;
+    let strips = 1i64 << (flags >> 2 & 3);
+    let corner = flags >> 4 & 3;
+    let transposed = flags & 0x40 != 0;
+    let op = Combine::from_bits((flags >> 7 & 3) as u8)?;
+    let default_pixel = (flags >> 9 & 1) as u8;
+    // five bit signed
+    let ds_offset = ((flags >> 10 & 31) as i64 ^ 16) - 16;
+    if refine && flags & 0x8000 == 0 {
+        // the adaptive pixels of the refinement template
+        reader.take(4)?;
+    }
+    let instances = reader.u32()?;
+    let mut mq = MqDecoder::new(reader.rest());
+    let mut region = Bitmap::new(info.width, info.height, default_pixel)?;
+
+    let [mut dt, mut fs, mut ds, mut it, mut ri] = [(); 5].map(|_| IntDecoder::new());
+    let id_len = (0..32).find(|&len| 1usize << len >= symbols.len()).unwrap_or(32);
+    let mut ids = IdDecoder::new(id_len);
+    let missing = || error("text region ends early");
+
+    // 6.4.5, strip positions in units of the strip size
+    let mut strip = -dt.decode(&mut mq).ok_or_else(missing)?;
+    let mut first_s = 0i64;
+    let mut placed = 0;
+    while placed < instances {
+        mq.check()?;
+        strip += dt.decode(&mut mq).ok_or_else(missing)?;
+        first_s += fs.decode(&mut mq).ok_or_else(missing)?;
+        let mut s = first_s;
+        loop {
+            mq.check()?;
+            let t = strips * strip + if strips > 1 { it.decode(&mut mq).ok_or_else(missing)? } else { 0 };
+            let id = ids.decode(&mut mq);
+            let symbol = *symbols.get(id).ok_or_else(|| error(format!("symbol {} of {}", id, symbols.len())))?;
+            if refine && ri.decode(&mut mq) == Some(1) {
+                return Err(error("refinement coding is not supported"));
+            }
+            let (w, h) = (symbol.width as i64, symbol.height as i64);
+            // the size along the strip
+            let size = if transposed { h } else { w };
+            let (right, bottom) = (corner & 2 != 0, corner & 1 == 0);
+            let ahead = match transposed {
+                false => right,
+                true => bottom,
+            };
+            if ahead {
+                s += size - 1;
+            }
+            let (x, y) = if transposed { (t, s) } else { (s, t) };
+            let left = x - if right { w - 1 } else { 0 };
+            let top = y - if bottom { h - 1 } else { 0 };
+            region.combine(symbol, left, top, op);
+            if !ahead {
+                s += size - 1;
+            }
+            placed += 1;
+            let Some(delta) = ds.decode(&mut mq) else { break };
+            s += delta + ds_offset;
+        }
+    }
+    Ok((info, region))
+}
+
+// Decodes JBIG2Decode data, with the segments of the JBIG2Globals stream
+// before it, to 1 bit rows of the image size. Black is 0 like in DeviceGray,
+// where the JBIG2 page has 1.
+pub fn decode(data: &[u8], globals: Option<&[u8]>, width: u32, height: u32) -> Result<Vec<u8>, PdfError> {
+    let mut dictionaries: HashMap<u32, Vec<Bitmap>> = HashMap::new();
+    let mut page: Option<Bitmap> = None;
+    let all = segments(globals.unwrap_or_default())?.into_iter().chain(segments(data)?);
+    for segment in all {
+        let referred = || segment.refs.iter().filter_map(|r| dictionaries.get(r)).flatten().collect::<Vec<&Bitmap>>();
+        let region = match segment.kind {
+            0 => {
+                let symbols = symbol_dictionary(segment.data, referred())?;
+                dictionaries.insert(segment.number, symbols);
+                None
+            }
+            6 | 7 => Some(text_region(segment.data, &referred())?),
+            38 | 39 => Some(generic_region(segment.data)?),
+            48 => {
+                let mut reader = Reader { data: segment.data, pos: 0 };
+                let (page_width, page_height) = (reader.u32()?, reader.u32()?);
+                reader.take(8)?;
+                let default_pixel = reader.u8()? >> 2 & 1;
+                // striped pages of unknown height end where the image does
+                let page_height = if page_height == u32::MAX { height } else { page_height };
+                page = Some(Bitmap::new(page_width, page_height, default_pixel)?);
+                None
+            }
+            4 | 36 => return Err(error("intermediate regions are not supported")),
+            40 | 42 | 43 => return Err(error("refinement coding is not supported")),
+            16 | 20 | 22 | 23 => return Err(error("halftone regions are not supported")),
+            // end of page, stripe or file, profiles, tables and extensions
+            _ => None,
+        };
+        if let Some((info, bitmap)) = region {
+            let page = page.as_mut().ok_or_else(|| error("region before the page information"))?;
+            page.combine(&bitmap, info.x as i64, info.y as i64, info.combine);
+        }
+    }
+    let page = page.ok_or_else(|| error("no page information"))?;
+
+    let stride = (width as usize).div_ceil(8);
+    let mut rows = vec![0xFF; stride * height as usize];
+    for y in 0..height.min(page.height) {
+        for (x, &pixel) in page.row(y).iter().take(width as usize).enumerate() {
+            rows[y as usize * stride + x / 8] ^= pixel << (7 - x % 8);
+        }
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, IntDecoder, MqDecoder};
+
+    // black is 0 in the decoded rows
+    fn picture(rows: &[u8], width: usize, height: usize) -> Vec<String> {
+        let stride = width.div_ceil(8);
+        let pixel = |x: usize, y: usize| rows[y * stride + x / 8] >> (7 - x % 8) & 1;
+        (0..height).map(|y| (0..width).map(|x| if pixel(x, y) == 0 { '#' } else { '.' }).collect()).collect()
+    }
+
+    #[test]
+    fn integers() {
+        let data = [
+            0xa0, 0x64, 0x95, 0x7f, 0xc8, 0x18, 0xa1, 0xa8, 0xf4, 0x70, 0x70, 0xa8, 0xfe, 0x06, 0xc7, 0x06,
+            0xf8, 0x38, 0x8a, 0xd0, 0xf0, 0xaa, 0xbf, 0xff, 0xac,
+        ];
+        let mut mq = MqDecoder::new(&data);
+        let mut decoder = IntDecoder::new();
+        let values: Vec<Option<i64>> = (0..16).map(|_| decoder.decode(&mut mq)).collect();
+        let expected = [0, 3, -1, 4, 19, 20, 83, 84, 339, 340, 4435, 4436, 70000, -5000];
+        assert_eq!(values[..14], expected.map(Some));
+        // OOB, then the decoder goes on
+        assert_eq!(values[14..], [None, Some(2)]);
+    }
+
+    #[test]
+    fn generic_regions() {
+        // the left half with template 0 and typical prediction, the right half
+        // with template 2 and a moved adaptive pixel
+        let data = [
+            0x00, 0x00, 0x00, 0x00, 0x30, 0x00, 0x01, 0x00, 0x00, 0x00, 0x13, 0x00, 0x00, 0x00, 0x1a, 0x00,
+            0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x01, 0x26, 0x00, 0x01, 0x00, 0x00, 0x00, 0x29, 0x00, 0x00, 0x00, 0x0d, 0x00, 0x00, 0x00,
+            0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x03, 0xff, 0xfd, 0xff, 0x02,
+            0xfe, 0xfe, 0xfe, 0xf1, 0xb8, 0xe9, 0xc8, 0x8c, 0x0b, 0x22, 0x5c, 0xa3, 0x55, 0x99, 0x64, 0x7f,
+            0xff, 0xac, 0x00, 0x00, 0x00, 0x02, 0x26, 0x00, 0x01, 0x00, 0x00, 0x00, 0x23, 0x00, 0x00, 0x00,
+            0x0d, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x0d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0xfd,
+            0xff, 0x81, 0x2c, 0x62, 0x9e, 0xa8, 0x80, 0xf2, 0x53, 0x51, 0x59, 0x15, 0x78, 0x5f, 0xff, 0xac,
+        ];
+        let rows = decode(&data, None, 26, 9).unwrap();
+        let expected = [
+            "..........................",
+            ".####.####..###..###..###.",
+            "....#.#...#..#..#....#...#",
+            "....#.#...#..#..#........#",
+            "....#.####...#..#..##...#.",
+            "....#.#...#..#..#...#..#..",
+            "#...#.#...#..#..#...#.#...",
+            ".###..####..###..###..####",
+            "..........................",
+        ];
+        assert_eq!(picture(&rows, 26, 9), expected);
+    }
+
+    #[test]
+    fn symbols_from_globals() {
+        // F, A and X in the globals, placed by a text region of the page
+        let globals = [
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x22, 0x00, 0x00, 0x03, 0xff, 0xfd,
+            0xff, 0x02, 0xfe, 0xfe, 0xfe, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0x52, 0x9f, 0xbc,
+            0x0f, 0xfb, 0x7f, 0x98, 0xaf, 0x03, 0x2b, 0xd5, 0x71, 0x7a, 0x6f, 0xff, 0xac,
+        ];
+        let data = [
+            0x00, 0x00, 0x00, 0x01, 0x30, 0x00, 0x01, 0x00, 0x00, 0x00, 0x13, 0x00, 0x00, 0x00, 0x10, 0x00,
+            0x00, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x02, 0x06, 0x20, 0x00, 0x01, 0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00,
+            0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00,
+            0x05, 0xa5, 0xd9, 0xe6, 0xca, 0xcc, 0x8e, 0xff, 0xac,
+        ];
+        let rows = decode(&data, Some(&globals), 16, 12).unwrap();
+        let expected = [
+            "................",
+            ".####.##.#...#..",
+            ".#...#..#.#.#...",
+            ".###.####..#....",
+            ".#...#..#.#.#...",
+            ".#...#..##...#..",
+            "................",
+            "...#...#.####...",
+            "....#.#..#......",
+            ".....#...###....",
+            "....#.#..#......",
+            "...#...#.#......",
+        ];
+        assert_eq!(picture(&rows, 16, 12), expected);
+        // without the globals the text region has no symbols
+        assert!(decode(&data, None, 16, 12).is_err());
+    }
+
+    #[test]
+    fn unsupported_segments() {
+        // a page, then an immediate refinement region
+        let mut data = vec![0, 0, 0, 0, 48, 0, 1, 0, 0, 0, 19];
+        data.extend([0, 0, 0, 8, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend([0, 0, 0, 1, 42, 0, 1, 0, 0, 0, 0]);
+        let error = decode(&data, None, 8, 8).unwrap_err();
+        assert!(error.to_string().contains("refinement"), "{}", error);
+        // the rows of a page without regions are white
+        assert_eq!(decode(&data[..30], None, 8, 8).unwrap(), vec![0xFF; 8]);
+    }
+}
@@ -0,0 +1,1379 @@
+// The renderer behind the pdf2svg command line tool. Pages are converted with a
+// Converter, which picks the output format by the extension of the output file,
+// or rendered into a caller's buffer with render_page_into.
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+extern crate pathfinder_geometry as g;
+#[macro_use]
+extern crate log;
+
+
+//mod common;
+pub mod plotter;
+//mod fontentry;
+mod graphics_state;
+mod text_state;
+mod render;
+//mod screen_plotter;
+mod vector_plotter;
+pub mod png;
+pub mod metadata;
+pub mod op_hook;
+pub mod options;
+pub mod svg;
+pub mod geometry;
+mod images;
+mod optional_content;
+mod timings;
+mod annotations;
+pub mod bounds_plotter;
+pub mod content;
+pub mod context;
+pub mod diff;
+mod page_cache;
+mod image_cache;
+mod extract;
+mod thumbnail;
+pub mod permissions;
+pub mod transparency;
+mod shading;
+mod mesh;
+mod xobject;
+mod pattern;
+mod flatten;
+pub mod hpgl;
+pub mod structure;
+pub mod validate;
+mod soft_mask;
+pub mod input;
+pub mod batch;
+mod color;
+mod color_usage;
+pub mod estimate;
+mod fax;
+#[cfg(feature = "jbig2")]
+mod jbig2;
+pub mod inspect;
+pub mod output;
+mod debug_dump;
+
+use g::transform2d::Transform2F;
+use pathfinder_content::{fill::FillRule, outline::Outline};
+use pdf::file::CachedFile;
+use pdf::object::{Page, Rect};
+use pdf::PdfError;
+
+use crate::batch::PageSelection;
+use crate::bounds_plotter::BoundsPlotter;
+use crate::context::PageContext;
+use crate::debug_dump::DebugDump;
+use crate::diff::DiffResult;
+use crate::inspect::InspectPlotter;
+use crate::geometry::{display_space, pixel_size, CoordSystem, Coords};
+use crate::metadata::OutputMetadata;
+use crate::optional_content::OptionalContent;
+use crate::options::Stamp;
+use crate::output::{DrawStream, PngFormat};
+use crate::plotter::{BlendMode, DrawMode, Fill, FillMode, Plotter, RgbaImage};
+use crate::render::RenderState;
+use crate::timings::Timings;
+
+pub use crate::geometry::PageGeometry;
+pub use crate::input::{open_data, InputData};
+pub use crate::metadata::PageMetadata;
+pub use crate::options::RenderOptions;
+pub use crate::output::OutputFormat;
+
+//const SCALE: f32 = 25.4 / 72.;
+pub const SCALE: f32 = 1.0;
+
+// US Letter, for media boxes without area like other viewers do
+const FALLBACK_MEDIA_BOX: Rect = Rect { left: 0., bottom: 0., right: 612., top: 792. };
+
+pub fn page_bounds(page: &Page) -> g::rect::RectF {
+    let Rect { left, right, top, bottom } = match page.media_box().expect("no media box") {
+        r if (r.right - r.left).abs() > 0. && (r.top - r.bottom).abs() > 0. => r,
+        r => {
+            warn!("media box {:?} has no area, using US Letter", r);
+            FALLBACK_MEDIA_BOX
+        }
+    };
+    g::rect::RectF::from_points(g::vector::Vector2F::new(left, bottom), g::vector::Vector2F::new(right, top)) * SCALE
+}
+
+// the page in output units, the one place convert and the estimates get it from
+fn page_geometry(page: &Page, options: &RenderOptions) -> PageGeometry {
+    PageGeometry::new(page_bounds(page), SCALE, page.rotate, options.transform, options.transform_view_box)
+}
+
+// The width and height in pixels of the png convert writes for the page,
+// from the page dictionary alone.
+pub fn estimate_output_size(options: &RenderOptions, page: &Page) -> (u32, u32) {
+    pixel_size(page_geometry(page, options).view_box)
+}
+
+// the coordinate system of the page as convert renders it
+fn coord_system(page: &Page, options: &RenderOptions, coords: Coords) -> CoordSystem {
+    let geometry = page_geometry(page, options);
+    CoordSystem::new(&geometry, page_bounds(page) * (1. / SCALE), coords)
+}
+
+// the layers of the document, shown as --layers and --exclude-layers select
+pub fn optional_content(file: &CachedFile<InputData>, options: &RenderOptions) -> Result<OptionalContent, PdfError> {
+    let resolve = file.resolver();
+    let catalog = resolve.resolve(file.trailer.root.get_ref().get_inner())?.into_dictionary()?;
+    let mut content = OptionalContent::from_catalog(&catalog, &resolve)?;
+    for name in content.select(options.layers.as_deref(), &options.exclude_layers) {
+        warn!("no layer named {:?}", name);
+    }
+    Ok(content)
+}
+
+// the bounds of everything painted on the page, None if nothing is. Content
+// further than offpage_factor page diagonals from the page box is not counted.
+pub fn content_bounds(input: &Path, page_nr: u32, options: &RenderOptions, coords: Coords, offpage_factor: f32) -> Result<Option<g::rect::RectF>, PdfError> {
+    let file = input::open(input)?;
+    let mut resolve = file.resolver();
+    let page = file.get_page(page_nr)?;
+    let system = coord_system(&page, options, coords);
+    let resources = pdf::t!(page.resources());
+    let layers = optional_content(&file, options)?;
+
+    let mut plotter = BoundsPlotter::new().offpage(system.view_box, offpage_factor);
+    let mut render = RenderState::new(&mut plotter, &mut resolve, resources, system.transform, system.view_box, options, page_nr, file.num_pages());
+    render.set_optional_content(&layers);
+    render.set_image_cache(&options.image_cache, input);
+    render.render(&page)?;
+    drop(render);
+    if plotter.offpage_draws() > 0 {
+        info!("{} draws far outside the page left out", plotter.offpage_draws());
+    }
+    Ok(plotter.bounds().map(|bounds| system.from_device(bounds)))
+}
+
+// Render a page into the plotter made for its view box, at the same scale as convert.
+pub fn render_with<P: Plotter>(
+    input: &Path,
+    page_nr: u32,
+    options: &RenderOptions,
+    plotter: impl FnOnce(&PageGeometry) -> P,
+) -> Result<(P, PageContext), PdfError> {
+    info!("render {:?} page {}", input, page_nr);
+    let file = input::open(input)?;
+    let mut resolve = file.resolver();
+    let page = file.get_page(page_nr)?;
+    let geometry = page_geometry(&page, options);
+    let PageGeometry { transform, view_box } = geometry;
+    let resources = pdf::t!(page.resources());
+    let layers = optional_content(&file, options)?;
+
+    let mut plotter = plotter(&geometry);
+    let mut render = RenderState::new(&mut plotter, &mut resolve, resources, transform, view_box, options, page_nr, file.num_pages());
+    render.set_optional_content(&layers);
+    render.set_image_cache(&options.image_cache, input);
+    render.render(&page)?;
+    let ctx = render.into_context();
+    Ok((plotter, ctx))
+}
+
+// render a page into memory, at the same scale as convert
+pub fn rasterize(input: &Path, page_nr: u32, options: &RenderOptions) -> Result<RgbaImage, PdfError> {
+    Ok(render_with(input, page_nr, options, |g| png::PngPlotter::new(g.view_box).limits(options.scene_limits).downsample(options.image_downsample))?.0.rasterize())
+}
+
+// Render a page straight into the caller's RGBA buffer, rows top down and
+// stride bytes apart, at the same scale as convert. The bytes after each row
+// are not touched.
+pub fn render_page_into(input: &Path, page_nr: u32, options: &RenderOptions, buf: &mut [u8], stride: usize) -> Result<PageGeometry, PdfError> {
+    let mut geometry = None;
+    let (mut plotter, _) = render_with(input, page_nr, options, |g| {
+        geometry = Some(*g);
+        png::PngPlotter::new(g.view_box).limits(options.scene_limits).downsample(options.image_downsample)
+    })?;
+    plotter.rasterize_into(buf, stride)?;
+    Ok(geometry.expect("the plotter is made for the page geometry"))
+}
+
+// the page rasterized by the png backend and by the scene of the vector backend
+pub fn selftest_page(input: &Path, page_nr: u32, options: &RenderOptions, tolerance: u8) -> Result<(RgbaImage, RgbaImage, DiffResult), PdfError> {
+    let raster = rasterize(input, page_nr, options)?;
+    let vector = render_with(input, page_nr, options, |g| vector_plotter::VectorPlotter::new(g.view_box))?.0.rasterize();
+    let result = diff::diff(&raster, &vector, tolerance);
+    Ok((raster, vector, result))
+}
+
+// Writes the images of the pages into dir, an xobject drawn several times or
+// on several pages only once.
+pub fn extract_images(input: &Path, pages: &PageSelection, dir: &Path, raw: bool) -> Result<Vec<PathBuf>, PdfError> {
+    let file = input::open(input)?;
+    let resolve = file.resolver();
+    let options = RenderOptions::new();
+    let mut seen = HashSet::new();
+    let mut written = vec![];
+    for nr in pages.pages(file.num_pages())? {
+        let images = page_images(input, nr, &options)?;
+        written.extend(extract::write_images(&images, nr, dir, &mut seen, raw.then_some(&resolve))?);
+    }
+    Ok(written)
+}
+
+// the images drawn by the page and its forms, patterns and annotations, decoded as for rendering
+fn page_images(input: &Path, page_nr: u32, options: &RenderOptions) -> Result<Vec<extract::PageImage>, PdfError> {
+    let file = input::open(input)?;
+    let mut resolve = file.resolver();
+    let page = file.get_page(page_nr)?;
+    let PageGeometry { transform, view_box } = page_geometry(&page, options);
+    let resources = pdf::t!(page.resources());
+    let layers = optional_content(&file, options)?;
+
+    let mut plotter = BoundsPlotter::new();
+    let mut render = RenderState::new(&mut plotter, &mut resolve, resources, transform, view_box, options, page_nr, file.num_pages());
+    render.set_optional_content(&layers);
+    render.set_image_cache(&options.image_cache, input);
+    render.collect_images();
+    render.render(&page)?;
+    Ok(render.into_context().images.unwrap_or_default())
+}
+
+// The /Thumb image of the page scaled to fit size x size, without one the page
+// rendered at the scale that fits.
+pub fn thumbnail(input: &Path, page_nr: u32, size: u32) -> Result<RgbaImage, PdfError> {
+    let file = input::open(input)?;
+    let mut resolve = file.resolver();
+    let page = file.get_page(page_nr)?;
+    let options = RenderOptions::new();
+    if let Some(thumb) = thumbnail::page_thumb(page.get_ref().get_inner(), &resolve)? {
+        let PageGeometry { transform, view_box } = page_geometry(&page, &options);
+        let resources = pdf::t!(page.resources());
+        let mut plotter = BoundsPlotter::new();
+        let mut render = RenderState::new(&mut plotter, &mut resolve, resources, transform, view_box, &options, page_nr, file.num_pages());
+        if let Some(image) = render.decode(&thumb)? {
+            let (width, height) = thumbnail::fit(image.width as f32, image.height as f32, size);
+            return Ok(thumbnail::scale(&image, width, height));
+        }
+    }
+    info!("page {} has no thumbnail, rendering it", page_nr);
+    let bounds = page_bounds(&page) * (1. / SCALE);
+    let scale = size as f32 / bounds.width().max(bounds.height());
+    let options = options.transform(Transform2F::from_scale(g::vector::Vector2F::splat(scale)), true);
+    rasterize(input, page_nr, &options)
+}
+
+// the topmost draw at the point and its bounds, both in the coordinates of the point
+pub fn inspect_point(
+    input: &Path,
+    page_nr: u32,
+    options: &RenderOptions,
+    point: g::vector::Vector2F,
+    coords: Coords,
+) -> Result<Option<(inspect::DrawRecord, g::rect::RectF)>, PdfError> {
+    let file = input::open(input)?;
+    let page = file.get_page(page_nr)?;
+    let system = coord_system(&page, options, coords);
+    let (plotter, _) = render_with(input, page_nr, options, |_| InspectPlotter::new())?;
+    Ok(plotter.hit_test(system.point_to_device(point)).map(|draw| (draw.clone(), system.from_device(draw.bounds))))
+}
+
+// The stamp is placed on the page as it is displayed, so the anchors follow /Rotate.
+// Returns the warnings of the stamp page.
+fn draw_stamp<P: Plotter>(plotter: &mut P, stamp: &Stamp, page: &Page, root_transformation: Transform2F, view_box: g::rect::RectF) -> Result<Vec<String>, PdfError> {
+    let file = input::open(&stamp.file)?;
+    let mut resolve = file.resolver();
+    let stamp_page = file.get_page(0)?;
+    let resources = pdf::t!(stamp_page.resources());
+
+    let (display, size) = display_space(page_bounds(page) * (1. / SCALE), page.rotate);
+    let target = g::rect::RectF::new(g::vector::Vector2F::zero(), size);
+    let placement = stamp.placement.transform(target, page_bounds(&stamp_page) * (1. / SCALE));
+    let transform = root_transformation * display.inverse() * placement;
+
+    let options = RenderOptions::new();
+    let layers = optional_content(&file, &options)?;
+    let mut render = RenderState::new(plotter, &mut resolve, resources, transform, view_box, &options, 0, file.num_pages());
+    render.set_optional_content(&layers);
+    render.render(&stamp_page)?;
+    Ok(render.into_context().warnings)
+}
+
+// The highlights are rectangles in the coordinates --bbox prints, drawn in
+// yellow over the page and multiplied, so what they mark stays readable.
+fn draw_highlights<P: Plotter>(plotter: &mut P, highlights: &[g::rect::RectF], system: &CoordSystem) {
+    let mode = DrawMode::Fill {
+        fill: FillMode { color: Fill::Solid(1., 1., 0.), alpha: 1.0, mode: BlendMode::Multiply },
+    };
+    for &rect in highlights {
+        let outline = Outline::from_rect(system.to_device(rect));
+        plotter.draw(&outline, &mode, FillRule::Winding, Transform2F::default(), None);
+    }
+}
+
+// Converts pages to files in the format registered for the extension of the
+// output. Registered formats are consulted before the built-in ones, unknown
+// extensions are written as png.
+pub struct Converter {
+    formats: Vec<Box<dyn OutputFormat>>,
+}
+
+impl Converter {
+    pub fn new() -> Self {
+        Converter { formats: output::builtin() }
+    }
+    pub fn register(&mut self, format: Box<dyn OutputFormat>) -> &mut Self {
+        self.formats.insert(0, format);
+        self
+    }
+    pub fn format(&self, output: &Path) -> &dyn OutputFormat {
+        let extension = output.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+        self.formats
+            .iter()
+            .find(|format| extension.as_deref().is_some_and(|e| format.extensions().contains(&e)))
+            .map(|format| &**format)
+            .unwrap_or(&PngFormat)
+    }
+    pub fn convert(&self, input: PathBuf, output: PathBuf, page_nr: u32, options: &RenderOptions) -> Result<PageMetadata, PdfError> {
+        info!("convert {:?} page {} to {:?}", input, page_nr, output);
+        let Some(ref dir) = options.debug_dump else {
+            return self.convert_page(&input, &output, page_nr, options, None);
+        };
+        let mut dump = DebugDump::create(dir, &input, page_nr, &output)?;
+        options.set_dump_ops(Some(Box::new(dump.file("ops.txt")?)));
+        let result = self.convert_page(&input, &output, page_nr, options, Some(&mut dump));
+        // dropping the writer flushes the operators
+        options.set_dump_ops(None);
+        dump.finish(result.as_ref().err())?;
+        result
+    }
+
+    fn convert_page(&self, input: &Path, output: &Path, page_nr: u32, options: &RenderOptions, mut dump: Option<&mut DebugDump>) -> Result<PageMetadata, PdfError> {
+        let format = self.format(output);
+        let output_metadata = options.embed_metadata.then(|| OutputMetadata {
+            source: input.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default(),
+            page: page_nr,
+            dpi: 72. * SCALE,
+            options: format!("scale={}", SCALE),
+        });
+        let start = Instant::now();
+        let file = input::open(input)?;
+        let mut resolve = file.resolver();
+        let page = file.get_page(page_nr)?;
+        let open = start.elapsed();
+        if let Some(dump) = dump.as_mut() {
+            dump.stage("render");
+        }
+
+        let PageGeometry { transform: root_transformation, view_box } = page_geometry(&page, options);
+        let resources = pdf::t!(page.resources());
+        let group_color_space = transparency::page_group_color_space(&page, &resolve)?;
+        let layers = optional_content(&file, options)?;
+
+        let mut stream = DrawStream::new(view_box, 72. * SCALE, format.hairline_width(), output_metadata);
+        let mut render = RenderState::new(&mut stream, &mut resolve, resources, root_transformation, view_box, options, page_nr, file.num_pages());
+        render.set_optional_content(&layers);
+        render.set_image_cache(&options.image_cache, input);
+        let rendered = render.render(&page);
+        let (render_timings, mut ctx) = (render.timings(), render.into_context());
+        // the draw calls up to a failing operator are part of the dump
+        if let Some(dump) = dump.as_mut() {
+            dump.manifest.warnings = ctx.warnings.clone();
+            dump.draw_calls(&stream)?;
+        }
+        rendered?;
+        if let Some(ref stamp) = options.stamp {
+            ctx.warnings.extend(draw_stamp(&mut stream, stamp, &page, root_transformation, view_box)?);
+        }
+        if !options.highlights.is_empty() {
+            let system = coord_system(&page, options, options.highlight_coords);
+            draw_highlights(&mut stream, &options.highlights, &system);
+        }
+
+        if let Some(dump) = dump.as_mut() {
+            dump.stage("write");
+        }
+        let io_error = |e: std::io::Error| PdfError::Other {
+            msg: format!("can not write {:?}: {}", output, e),
+        };
+        let write = || -> Result<_, PdfError> {
+            let mut out = BufWriter::new(File::create(output).map_err(io_error)?);
+            let written = format.write(&stream, &mut out, options)?;
+            out.flush().map_err(io_error)?;
+            Ok(written)
+        };
+        let written = write();
+        if let Some(dump) = dump {
+            dump.manifest.warnings = ctx.warnings.iter().chain(written.iter().flat_map(|w| &w.warnings)).cloned().collect();
+            if written.is_err() {
+                if let Err(e) = dump.scene_svg(&stream, options) {
+                    warn!("failed to dump the scene: {}", e);
+                }
+            }
+        }
+        let written = written?;
+        ctx.warnings.extend(written.warnings);
+        // Cache hits depend on the pages converted before with the same options,
+        // and with --jobs on which thread got to a shared image first.
+        if options.reproducible {
+            ctx.stats.images_cached = 0;
+        }
+        let timings = match options.reproducible {
+            true => Timings::default(),
+            false => Timings {
+                open,
+                decode: render_timings.decode,
+                render: render_timings.render,
+                ..written.timings
+            },
+        };
+
+        let size = view_box.size().ceil().to_i32();
+        Ok(PageMetadata {
+            page: page_nr,
+            width: size.x() as u32,
+            height: size.y() as u32,
+            dpi: 72. * SCALE,
+            page_box: "MediaBox",
+            rotation: page.rotate,
+            group_color_space: group_color_space.map(|cs| cs.to_string()),
+            warnings: ctx.warnings,
+            stats: ctx.stats,
+            lines: written.lines,
+            timings,
+        })
+    }
+}
+
+impl Default for Converter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn convert(input: PathBuf, output: PathBuf, page_nr: u32, options: &RenderOptions) -> Result<PageMetadata, PdfError> {
+    Converter::new().convert(input, output, page_nr, options)
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use crate::plotter::RgbaImage;
+
+    // the rgb of a pixel, x to the right and y down from the top left corner
+    fn pixel(image: &RgbaImage, x: usize, y: usize) -> [u8; 3] {
+        let i = 4 * (y * image.width as usize + x);
+        [image.pixels[i], image.pixels[i + 1], image.pixels[i + 2]]
+    }
+
+    //test convert sample pdf file to svg
+    #[test]
+    fn test_pdf_to_svg() {
+        let options = super::RenderOptions::new();
+        super::convert(Path::new("rack.pdf").to_path_buf(), Path::new("rack.png").to_path_buf(), 0, &options).unwrap();
+    }
+
+    #[test]
+    fn backends_agree() {
+        let options = super::RenderOptions::new();
+        for fixture in ["rack.pdf", "resources/axial_shading.pdf", "resources/hatch_pattern.pdf", "resources/dashed_border.pdf", "resources/soft_mask.pdf", "resources/transparency_group.pdf", "resources/image_orientation.pdf"] {
+            let (_, _, result) = super::selftest_page(Path::new(fixture), 0, &options, 8).unwrap();
+            assert_eq!(result.changed, 0, "{}", fixture);
+        }
+    }
+
+    #[test]
+    fn hatch_pattern() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/hatch_pattern.pdf"), 0, &options).unwrap();
+        let (mut red, mut white, mut black) = (0, 0, 0);
+        for y in 10..90 {
+            for x in 10..90 {
+                match pixel(&image, x, y) {
+                    [r, g, b] if r > 200 && g < 100 && b < 100 => red += 1,
+                    [r, g, b] if r > 240 && g > 240 && b > 240 => white += 1,
+                    [r, g, b] if r < 50 && g < 50 && b < 50 => black += 1,
+                    _ => {}
+                }
+            }
+        }
+        // the cross hatch shows with the page between the lines
+        assert!(red > 500, "{} red pixels", red);
+        assert!(white > 2000, "{} white pixels", white);
+        assert_eq!(black, 0);
+    }
+
+    #[test]
+    fn hatch_inside_scaled_form() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/form_hatch.pdf"), 0, &options).unwrap();
+        let (red, white) = ([255, 0, 0], [255, 255, 255]);
+        // the form doubles the 10pt cell, its red quarter covers 0..10
+        assert_eq!(pixel(&image, 7, 93), red);
+        assert_eq!(pixel(&image, 13, 93), white);
+        assert_eq!(pixel(&image, 27, 73), red);
+        assert_eq!(pixel(&image, 33, 73), white);
+        // the same pattern on the page keeps its own cell of 10pt
+        assert_eq!(pixel(&image, 53, 47), red);
+        assert_eq!(pixel(&image, 57, 47), white);
+        assert_eq!(pixel(&image, 63, 43), white);
+    }
+
+    #[test]
+    fn hatched_fill_with_border() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/hatch_border.pdf"), 0, &options).unwrap();
+        let red = |[r, g, b]: [u8; 3]| r > 200 && g < 60 && b < 60;
+        let blue = |[r, g, b]: [u8; 3]| r < 60 && g < 60 && b > 200;
+        // the 8pt border covers 16..24 around the edge at 20, on top of the hatching
+        for x in 17..24 {
+            assert!(red(pixel(&image, x, 50)), "{} {:?}", x, pixel(&image, x, 50));
+            assert!(red(pixel(&image, 50, x)), "{} {:?}", x, pixel(&image, 50, x));
+        }
+        let inside: Vec<[u8; 3]> = (28..72).flat_map(|y| (28..72).map(move |x| (x, y))).map(|(x, y)| pixel(&image, x, y)).collect();
+        assert!(inside.iter().filter(|&&p| blue(p)).count() > 300);
+        assert!(inside.iter().filter(|&&p| p == [255, 255, 255]).count() > 500);
+        assert!(!inside.iter().any(|&p| red(p)));
+    }
+
+    #[test]
+    fn extgstate_fill_alpha() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/fill_alpha.pdf"), 0, &options).unwrap();
+        // red at /ca 0.3 over the white page
+        let [r, g, b] = pixel(&image, 50, 50);
+        assert_eq!(r, 255);
+        assert!((g as i32 - 179).abs() <= 3 && (b as i32 - 179).abs() <= 3, "{:?}", (r, g, b));
+    }
+
+    #[test]
+    fn blend_modes() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/blend_modes.pdf"), 0, &options).unwrap();
+        // red over blue: multiply, screen and normal
+        assert_eq!(pixel(&image, 16, 50), [0, 0, 0]);
+        assert_eq!(pixel(&image, 50, 50), [255, 0, 255]);
+        assert_eq!(pixel(&image, 84, 50), [255, 0, 0]);
+        // the same modes over the white page
+        assert_eq!(pixel(&image, 16, 10), [255, 0, 0]);
+        assert_eq!(pixel(&image, 50, 10), [255, 255, 255]);
+    }
+
+    #[test]
+    fn luminosity_soft_mask() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/soft_mask.pdf"), 0, &options).unwrap();
+        // the red fill fades in from left to right with the gray ramp of the mask
+        let [r, g, b] = pixel(&image, 2, 50);
+        assert!(r > 240 && g > 240 && b > 240, "{:?}", (r, g, b));
+        let [r, g, b] = pixel(&image, 50, 50);
+        assert!(r > 240 && (g as i32 - 128).abs() < 20 && g == b, "{:?}", (r, g, b));
+        let [r, g, b] = pixel(&image, 97, 50);
+        assert!(r > 240 && g < 20 && b < 20, "{:?}", (r, g, b));
+        // Q ends the mask
+        assert_eq!(pixel(&image, 5, 95), [0, 0, 255]);
+    }
+
+    #[test]
+    fn color_space_scopes() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/color_space_scopes.pdf"), 0, &options).unwrap();
+        // the form inherits the green separation of the page, sets its own blue
+        // one inside q and is back on the page space after Q
+        assert_eq!(pixel(&image, 10, 90), [0, 255, 0]);
+        assert_eq!(pixel(&image, 30, 90), [0, 0, 255]);
+        assert_eq!(pixel(&image, 50, 90), [0, 255, 0]);
+        // the space set in the form does not leak out of it
+        assert_eq!(pixel(&image, 70, 90), [0, 255, 0]);
+        // a space set under a soft mask carries on after the next gs
+        let [r, g, b] = pixel(&image, 10, 30);
+        assert!((r as i32 - 128).abs() <= 1 && r == g && g == b, "{:?}", (r, g, b));
+        let [r, g, b] = pixel(&image, 30, 30);
+        assert!((r as i32 - 64).abs() <= 1 && r == g && g == b, "{:?}", (r, g, b));
+    }
+
+    #[test]
+    fn stencil_masks() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/stencil_mask.pdf"), 0, &options).unwrap();
+        // 4x4 mask of 2x2 blocks scaled to 40pt, the 0 samples are painted blue
+        assert_eq!(pixel(&image, 35, 15), [0, 0, 255]);
+        assert_eq!(pixel(&image, 15, 15), [255, 255, 255]);
+        assert_eq!(pixel(&image, 15, 45), [0, 0, 255]);
+        assert_eq!(pixel(&image, 45, 45), [255, 255, 255]);
+        // the inverted mask in red at half alpha, clipped to x 60..80
+        let pink = |[r, g, b]: [u8; 3]| r == 255 && (g as i32 - 128).abs() <= 1 && g == b;
+        assert!(pink(pixel(&image, 65, 15)), "{:?}", pixel(&image, 65, 15));
+        assert!(pink(pixel(&image, 75, 45)), "{:?}", pixel(&image, 75, 45));
+        assert_eq!(pixel(&image, 55, 15), [255, 255, 255]);
+        assert_eq!(pixel(&image, 75, 15), [255, 255, 255]);
+        assert_eq!(pixel(&image, 85, 45), [255, 255, 255]);
+    }
+
+    #[test]
+    fn image_soft_masks() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/image_soft_mask.pdf"), 0, &options).unwrap();
+        // red images over blue, the 1x2 masks are stretched over the 2x2 images
+        assert_eq!(pixel(&image, 25, 25), [255, 0, 0]);
+        assert_eq!(pixel(&image, 25, 75), [0, 0, 255]);
+        // the second mask has /Decode [1 0]
+        assert_eq!(pixel(&image, 75, 25), [0, 0, 255]);
+        assert_eq!(pixel(&image, 75, 75), [255, 0, 0]);
+    }
+
+    #[test]
+    fn image_decode_arrays() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/image_decode.pdf"), 0, &options).unwrap();
+        // the gray scan is stored as a negative, /Decode [1 0] turns it back
+        assert_eq!(pixel(&image, 12, 50), [255, 255, 255]);
+        assert_eq!(pixel(&image, 37, 50), [0, 0, 0]);
+        // on an indexed image the indices are reversed: red, green becomes green, red
+        assert_eq!(pixel(&image, 62, 50), [0, 255, 0]);
+        assert_eq!(pixel(&image, 87, 50), [255, 0, 0]);
+    }
+
+    #[test]
+    fn image_bit_depths() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/image_bits.pdf"), 0, &options).unwrap();
+        // 2 bit gray 0 and 3
+        assert_eq!(pixel(&image, 12, 50), [0, 0, 0]);
+        assert_eq!(pixel(&image, 37, 50), [255, 255, 255]);
+        // 16 bit rgb ffff 0000 8000
+        assert_eq!(pixel(&image, 75, 50), [255, 0, 128]);
+    }
+
+    #[test]
+    fn downsampled_images() {
+        // a 200x200 checkerboard of single pixels drawn 50 pixels wide
+        let gray = |options: &super::RenderOptions| {
+            let image = super::rasterize(Path::new("resources/fine_checker.pdf"), 0, options).unwrap();
+            pixel(&image, 50, 50)[0]
+        };
+        assert!((120..=136).contains(&gray(&super::RenderOptions::new())));
+        assert!([0, 255].contains(&gray(&super::RenderOptions::new().image_downsample(0.))));
+    }
+
+    #[test]
+    fn image_fill_alpha() {
+        // a red image drawn opaque and with /ca 0.5 on white
+        let image = super::rasterize(Path::new("resources/image_alpha.pdf"), 0, &super::RenderOptions::new()).unwrap();
+        assert_eq!(pixel(&image, 25, 50), [255, 0, 0]);
+        let [r, g, b] = pixel(&image, 75, 50);
+        assert_eq!(r, 255);
+        assert!((126..=129).contains(&g) && g == b, "{:?}", [r, g, b]);
+    }
+
+    #[test]
+    fn inline_color_spaces() {
+        let options = super::RenderOptions::new();
+        let (mut plotter, ctx) =
+            super::render_with(Path::new("resources/inline_color_spaces.pdf"), 0, &options, |g| super::png::PngPlotter::new(g.view_box)).unwrap();
+        let image = plotter.rasterize();
+        // /CS0 from the resources, /RGB, /G and [/I /RGB 1 <...>]
+        assert_eq!(pixel(&image, 12, 25), [0, 0, 255]);
+        assert_eq!(pixel(&image, 37, 25), [0, 255, 0]);
+        assert_eq!(pixel(&image, 62, 25), [128, 128, 128]);
+        assert_eq!(pixel(&image, 87, 25), [0, 255, 255]);
+        // an unknown name, one sample per pixel is gray
+        assert_eq!(pixel(&image, 50, 75), [64, 64, 64]);
+        assert!(ctx.warnings.iter().any(|w| w.contains("Missing not found, drawn as DeviceGray")), "{:?}", ctx.warnings);
+    }
+
+    #[test]
+    fn image_orientation() {
+        // a 2x2 image, red green on the first row and blue white on the second
+        let image = super::rasterize(Path::new("resources/image_orientation.pdf"), 0, &super::RenderOptions::new()).unwrap();
+        let (red, green, blue, white) = ([255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]);
+        // upright, the first row on top
+        assert_eq!([pixel(&image, 15, 15), pixel(&image, 35, 15), pixel(&image, 15, 35), pixel(&image, 35, 35)], [red, green, blue, white]);
+        // turned a quarter counterclockwise, the first row on the left
+        assert_eq!([pixel(&image, 65, 15), pixel(&image, 85, 15), pixel(&image, 65, 35), pixel(&image, 85, 35)], [green, white, red, blue]);
+        // flipped by the matrix, the first row at the bottom
+        assert_eq!([pixel(&image, 15, 65), pixel(&image, 35, 65), pixel(&image, 15, 85), pixel(&image, 35, 85)], [blue, white, red, green]);
+    }
+
+    #[test]
+    fn indexed_images() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/indexed_image.pdf"), 0, &options).unwrap();
+        // a fill with index 1 of the same palette
+        assert_eq!(pixel(&image, 50, 25), [0, 255, 0]);
+        // 4 bit indices 0 1 2 15 of a palette in an icc profile, 15 is above hival 3
+        assert_eq!(pixel(&image, 12, 75), [255, 0, 0]);
+        assert_eq!(pixel(&image, 37, 75), [0, 255, 0]);
+        assert_eq!(pixel(&image, 62, 75), [0, 0, 255]);
+        assert_eq!(pixel(&image, 87, 75), [255, 255, 255]);
+    }
+
+    #[test]
+    fn interpolated_images() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/interpolate.pdf"), 0, &options).unwrap();
+        // the gray values along the middle row of each 16x16 ramp scaled to 512 px
+        let grays = |x0: usize| {
+            let mut grays: Vec<u8> = (x0..x0 + 512).map(|x| pixel(&image, x, 256)[0]).collect();
+            grays.dedup();
+            grays.len()
+        };
+        // without /Interpolate the 16 samples show as blocks
+        assert_eq!(grays(0), 16);
+        assert!(grays(512) > 100, "{} grays", grays(512));
+    }
+
+    #[test]
+    fn color_key_masks() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/color_key_mask.pdf"), 0, &options).unwrap();
+        // white and near white are keyed out of the rgb image, the blue page shows
+        assert_eq!(pixel(&image, 12, 25), [0, 0, 255]);
+        assert_eq!(pixel(&image, 37, 25), [255, 0, 0]);
+        assert_eq!(pixel(&image, 12, 75), [255, 0, 0]);
+        assert_eq!(pixel(&image, 37, 75), [0, 0, 255]);
+        // the 1 bit gray image keys out its white samples, /Mask [1 1]
+        assert_eq!(pixel(&image, 62, 25), [0, 0, 255]);
+        assert_eq!(pixel(&image, 87, 25), [0, 0, 0]);
+        assert_eq!(pixel(&image, 62, 75), [0, 0, 0]);
+        assert_eq!(pixel(&image, 87, 75), [0, 0, 255]);
+    }
+
+    #[test]
+    fn debug_dump() {
+        let dir = std::env::temp_dir().join(format!("pdf2svg-dump-{}", std::process::id()));
+        let options = super::RenderOptions::new().debug_dump(Some(dir.join("dump")));
+        let output = dir.join("page.png");
+        // the Q has no q, strict parsing fails at operator 3
+        assert!(super::convert(Path::new("resources/unbalanced_restore.pdf").into(), output.clone(), 0, &options).is_err());
+        assert!(!output.exists());
+
+        let ops = std::fs::read_to_string(dir.join("dump/ops.txt")).unwrap();
+        assert!(ops.starts_with("page 0\n"), "{}", ops);
+        assert!(ops.lines().last().unwrap().starts_with("3: Restore"), "{}", ops);
+        assert!(!ops.lines().any(|line| line.starts_with("4:")));
+        let calls = std::fs::read_to_string(dir.join("dump/draw_calls.txt")).unwrap();
+        assert!(calls.contains("fill [0.00 90.00 10.00 100.00]"), "{}", calls);
+        let manifest: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(dir.join("dump/manifest.json")).unwrap()).unwrap();
+        assert_eq!(manifest["stage"], "render");
+        assert!(manifest["error"].as_str().unwrap().contains("graphics stack is empty"));
+        assert_eq!(manifest["files"], serde_json::json!(["ops.txt", "draw_calls.txt", "manifest.json"]));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn transparency_group() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/transparency_group.pdf"), 0, &options).unwrap();
+        // the group is faded as a whole, the overlap of its two rectangles has no seam
+        let [r, g, b] = pixel(&image, 20, 25);
+        assert!(r == 255 && (g as i32 - 128).abs() <= 3 && g == b, "{:?}", (r, g, b));
+        assert_eq!(pixel(&image, 50, 25), pixel(&image, 20, 25));
+        // the same form without a group fades each rectangle
+        assert_eq!(pixel(&image, 20, 75), pixel(&image, 20, 25));
+        let [_, g, _] = pixel(&image, 50, 75);
+        assert!((g as i32 - 64).abs() <= 3, "{:?}", g);
+    }
+
+    #[test]
+    fn layers() {
+        let path = Path::new("resources/layers.pdf");
+        let file = super::input::open(path).unwrap();
+        let layers: Vec<(String, bool)> =
+            super::optional_content(&file, &super::RenderOptions::new()).unwrap().layers().map(|(n, on)| (n.to_owned(), on)).collect();
+        assert_eq!(layers, [("Top".to_owned(), true), ("Hidden".to_owned(), false)]);
+
+        // red is in Top, blue and the yellow form in Hidden, green shows when either is on
+        let quadrants = |options: &super::RenderOptions| {
+            let image = super::rasterize(path, 0, options).unwrap();
+            let (w, h) = (image.width as usize, image.height as usize);
+            [pixel(&image, w / 4, 3 * h / 4), pixel(&image, 3 * w / 4, 3 * h / 4), pixel(&image, w / 4, h / 4), pixel(&image, 3 * w / 4, h / 4)]
+        };
+        let (white, red, blue, green, yellow) = ([255; 3], [255, 0, 0], [0, 0, 255], [0, 255, 0], [255, 255, 0]);
+        let layers = |show: Option<&[&str]>, hide: &[&str]| {
+            let names = |n: &[&str]| n.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+            super::RenderOptions::new().layers(show.map(names), names(hide))
+        };
+        assert_eq!(quadrants(&super::RenderOptions::new()), [red, white, green, white]);
+        assert_eq!(quadrants(&layers(Some(&["Hidden"]), &[])), [white, blue, green, yellow]);
+        assert_eq!(quadrants(&layers(None, &["Top"])), [white, white, white, white]);
+        assert_eq!(quadrants(&layers(Some(&["Top", "Hidden"]), &["Top"])), [white, blue, green, yellow]);
+    }
+
+    #[test]
+    fn snapped_grid() {
+        use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+        let path = Path::new("resources/spreadsheet_grid.pdf");
+        let zoom = Transform2F::from_scale(Vector2F::splat(1.37));
+        // the cells cover x 7..126 and y 11..130 at this zoom
+        let seams = |options: &super::RenderOptions| {
+            let image = super::rasterize(path, 0, options).unwrap();
+            let mut seams = 0;
+            for y in 12..129 {
+                for x in 8..125 {
+                    seams += (pixel(&image, x, y) != [0, 0, 255]) as usize;
+                }
+            }
+            seams
+        };
+        assert!(seams(&super::RenderOptions::new().transform(zoom, true)) > 0);
+        assert_eq!(seams(&super::RenderOptions::new().transform(zoom, true).snap_rects(true)), 0);
+    }
+
+    #[test]
+    fn color_usage() {
+        let usage = |name: &str| {
+            let (_, ctx) = super::render_with(Path::new(name), 0, &super::RenderOptions::new(), |_| super::BoundsPlotter::new()).unwrap();
+            ctx.stats.color
+        };
+        assert!(!usage("resources/black_text.pdf").color);
+        // 20 x 6 of 200 x 100
+        let red = usage("resources/red_word.pdf");
+        assert!(red.color);
+        assert!((red.colored_percent - 0.6).abs() < 0.01, "{:?}", red);
+
+        let dir = std::env::temp_dir().join(format!("pdf2svg-gray-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = super::RenderOptions::new().grayscale_if_monochrome(true);
+        let color_type = |name: &str| {
+            let output = dir.join("page.png");
+            super::convert(Path::new("resources").join(name), output.clone(), 0, &options).unwrap();
+            let decoder = ::png::Decoder::new(std::fs::File::open(&output).unwrap());
+            let reader = decoder.read_info().unwrap();
+            reader.info().color_type
+        };
+        assert_eq!(color_type("black_text.pdf"), ::png::ColorType::Grayscale);
+        assert_eq!(color_type("red_word.pdf"), ::png::ColorType::Rgba);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn highlight_bbox() {
+        use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+        // a blue rectangle in a media box that starts at 100 200, at 1.5 times the size
+        let input = Path::new("resources/offset_box.pdf");
+        let scale = Transform2F::from_scale(Vector2F::splat(1.5));
+        let plain = super::rasterize(input, 0, &super::RenderOptions::new().transform(scale, true)).unwrap();
+        let dir = std::env::temp_dir().join(format!("pdf2svg-highlight-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for coords in [super::Coords::Pdf, super::Coords::Image] {
+            let options = super::RenderOptions::new().transform(scale, true);
+            let bbox = super::content_bounds(input, 0, &options, coords, 2.).unwrap().unwrap();
+            let output = dir.join("page.png");
+            super::convert(input.to_path_buf(), output.clone(), 0, &options.highlights(vec![bbox], coords)).unwrap();
+            let mut reader = ::png::Decoder::new(std::fs::File::open(&output).unwrap()).read_info().unwrap();
+            let mut pixels = vec![0; reader.output_buffer_size()];
+            reader.next_frame(&mut pixels).unwrap();
+            let highlighted = RgbaImage { width: plain.width, height: plain.height, pixels };
+            // the yellow turns the blue black and covers nothing else
+            for y in 0..plain.height as usize {
+                for x in 0..plain.width as usize {
+                    let expected = match pixel(&plain, x, y) {
+                        [0, 0, 255] => [0, 0, 0],
+                        other => other,
+                    };
+                    assert_eq!(pixel(&highlighted, x, y), expected, "{:?} at {},{}", coords, x, y);
+                }
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn form_bbox_clips() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/form_bbox_clip.pdf"), 0, &options).unwrap();
+        let black = |x: usize, y: usize| pixel(&image, x, 100 - y) == [0, 0, 0];
+        // the form paints 30..70, its bbox moved by /Matrix covers 30..50
+        assert!(black(40, 40));
+        assert!(!black(60, 60));
+        assert!(!black(40, 60));
+        assert!(!black(60, 40));
+    }
+
+    #[test]
+    fn render_into_buffer() {
+        let path = Path::new("resources/hatch_pattern.pdf");
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(path, 0, &options).unwrap();
+        let (width, height) = (image.width as usize, image.height as usize);
+        // 9 padding pixels per row and two spare rows at the end
+        let stride = 4 * (width + 9);
+        let mut buf = vec![0xab; stride * (height + 2)];
+        let geometry = super::render_page_into(path, 0, &options, &mut buf, stride).unwrap();
+        assert_eq!(geometry.view_box.size().ceil().to_i32().x() as usize, width);
+        for y in 0..height {
+            let row = &buf[y * stride..(y + 1) * stride];
+            assert_eq!(&row[..4 * width], &image.pixels[4 * width * y..4 * width * (y + 1)], "row {}", y);
+            assert!(row[4 * width..].iter().all(|&b| b == 0xab), "padding of row {}", y);
+        }
+        assert!(buf[stride * height..].iter().all(|&b| b == 0xab));
+
+        // the last row needs no padding, one byte less is too small
+        let mut small = vec![0; stride * (height - 1) + 4 * width - 1];
+        assert!(super::render_page_into(path, 0, &options, &mut small, stride).is_err());
+    }
+
+    #[test]
+    fn culled_forms() {
+        // one form drawn ten times, eight of them outside the page
+        let options = super::RenderOptions::new();
+        let ctx = super::render_with(Path::new("resources/culled_forms.pdf"), 0, &options, |_| super::BoundsPlotter::new()).unwrap().1;
+        assert_eq!((ctx.stats.forms, ctx.stats.forms_culled), (10, 8));
+    }
+
+    #[test]
+    fn recursive_forms() {
+        let run = |name: &str, options: &super::RenderOptions| {
+            super::render_with(Path::new(name), 0, options, |_| super::BoundsPlotter::new()).unwrap().1
+        };
+        let options = super::RenderOptions::new();
+        // the form draws its square once and refuses to draw itself
+        let ctx = run("resources/self_form.pdf", &options);
+        assert_eq!(ctx.stats.forms, 1);
+        assert!(ctx.warnings.iter().any(|w| w.contains("draws itself")), "{:?}", ctx.warnings);
+        assert!(ctx.forms.is_empty());
+        // a chain of ten forms
+        assert_eq!(run("resources/nested_forms.pdf", &options).stats.forms, 10);
+        let ctx = run("resources/nested_forms.pdf", &super::RenderOptions::new().max_depth(4));
+        assert_eq!(ctx.stats.forms, 4);
+        assert!(ctx.warnings.iter().any(|w| w.contains("deeper than 4")), "{:?}", ctx.warnings);
+    }
+
+    #[test]
+    fn cached_images() {
+        // one logo stamped 200 times on each of two pages
+        let input = Path::new("resources/repeated_logo.pdf");
+        let run = |page: u32, options: &super::RenderOptions| {
+            super::render_with(input, page, options, |_| super::BoundsPlotter::new()).unwrap().1.stats
+        };
+        let options = super::RenderOptions::new();
+        let stats = run(0, &options);
+        assert_eq!((stats.images, stats.images_cached), (200, 199));
+        // the cache outlives the page
+        assert_eq!(run(1, &options).images_cached, 200);
+        assert_eq!(options.image_cache.stats().misses, 1);
+        let uncached = super::RenderOptions::new().image_cache_bytes(0);
+        assert_eq!(run(0, &uncached).images_cached, 0);
+        assert_eq!(super::rasterize(input, 0, &options).unwrap().pixels, super::rasterize(input, 0, &uncached).unwrap().pixels);
+    }
+
+    #[test]
+    fn extracted_images() {
+        // page 0 draws an rgb image twice and a jpeg, page 1 the rgb image in a
+        // form and an inline image
+        let input = Path::new("resources/image_extract.pdf");
+        let dir = std::env::temp_dir().join(format!("pdf2svg-extract-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let names = |raw: bool| {
+            let written = super::extract_images(input, &super::PageSelection::All, &dir, raw).unwrap();
+            written.iter().map(|path| path.file_name().unwrap().to_string_lossy().into_owned()).collect::<Vec<_>>()
+        };
+        assert_eq!(names(false), ["img-p0-0.png", "img-p0-1.png", "img-p1-0.png"]);
+        let decoder = ::png::Decoder::new(std::fs::File::open(dir.join("img-p0-0.png")).unwrap());
+        let reader = decoder.read_info().unwrap();
+        assert_eq!((reader.info().width, reader.info().height), (2, 2));
+
+        assert_eq!(names(true), ["img-p0-0.png", "img-p0-1.jpg", "img-p1-0.png"]);
+        let jpeg = std::fs::read(dir.join("img-p0-1.jpg")).unwrap();
+        assert_eq!((jpeg.len(), &jpeg[..2]), (186, &[0xff, 0xd8][..]));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn thumbnails() {
+        // an 8x4 /Thumb with a red and a blue half on a green page
+        let image = super::thumbnail(Path::new("resources/page_thumb.pdf"), 0, 32).unwrap();
+        assert_eq!((image.width, image.height), (32, 16));
+        assert_eq!(pixel(&image, 3, 8), [255, 0, 0]);
+        assert_eq!(pixel(&image, 28, 8), [0, 0, 255]);
+        // no /Thumb, the page is rendered at the size
+        let image = super::thumbnail(Path::new("resources/fine_checker.pdf"), 0, 50).unwrap();
+        assert_eq!((image.width, image.height), (50, 50));
+    }
+
+    #[test]
+    fn widened_hairlines() {
+        use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+        let path = Path::new("resources/schematic.pdf");
+        let thumbnail = Transform2F::from_scale(Vector2F::splat(0.5));
+        // lengths of the inked runs across the lines, in columns and rows clear of the crossing lines
+        let runs = |options: &super::RenderOptions| {
+            let image = super::rasterize(path, 0, options).unwrap();
+            let ink = |x: usize, y: usize| pixel(&image, x, y) != [255, 255, 255];
+            let (w, h) = (image.width as usize, image.height as usize);
+            let mut lines: Vec<Vec<bool>> = [30, 75].iter().map(|&x| (0..h).map(|y| ink(x, y)).collect()).collect();
+            lines.extend([17, 30].iter().map(|&y| (0..w).map(|x| ink(x, y)).collect()));
+            let mut runs = vec![];
+            for line in lines {
+                let mut run = 0;
+                for inked in line.into_iter().chain([false]) {
+                    if inked {
+                        run += 1;
+                    } else if run > 0 {
+                        runs.push(run);
+                        run = 0;
+                    }
+                }
+            }
+            runs
+        };
+        let thin = runs(&super::RenderOptions::new().transform(thumbnail, true));
+        // three hairlines and a 6pt line in each column, two hairlines in each row
+        assert_eq!(thin.len(), 12);
+        assert!(thin.contains(&1));
+        let widened = runs(&super::RenderOptions::new().transform(thumbnail, true).min_stroke_px(Some(1.5)));
+        assert_eq!(widened.len(), 12);
+        assert!(widened.iter().all(|&run| run >= 2), "{:?}", widened);
+        // the 6pt line is 3 pixels wide and stays as it is
+        assert_eq!((thin[3], thin[7]), (3, 3));
+        assert_eq!((widened[3], widened[7]), (3, 3));
+    }
+
+    #[test]
+    fn bounds_cover_pixels() {
+        use g::{rect::RectF, vector::Vector2F};
+        let path = Path::new("resources/offset_box.pdf");
+        let options = super::RenderOptions::new();
+        let pdf = super::content_bounds(path, 0, &options, super::Coords::Pdf, 2.).unwrap().unwrap();
+        // the page box starts at 100 200
+        assert_eq!(pdf, RectF::from_points(Vector2F::new(20., 10.), Vector2F::new(70., 50.)));
+
+        // in image coordinates the box covers exactly the painted pixels
+        let bounds = super::content_bounds(path, 0, &options, super::Coords::Image, 2.).unwrap().unwrap();
+        assert_eq!(bounds, RectF::from_points(Vector2F::new(20., 50.), Vector2F::new(70., 90.)));
+        let image = super::rasterize(path, 0, &options).unwrap();
+        let mut painted: Option<RectF> = None;
+        for y in 0..image.height as usize {
+            for x in 0..image.width as usize {
+                if pixel(&image, x, y) != [255, 255, 255] {
+                    let cell = RectF::new(Vector2F::new(x as f32, y as f32), Vector2F::splat(1.));
+                    painted = Some(painted.map_or(cell, |p| p.union_rect(cell)));
+                }
+            }
+        }
+        assert_eq!(painted, Some(bounds));
+    }
+
+    #[test]
+    fn parked_content() {
+        use g::{rect::RectF, vector::Vector2F};
+        let path = Path::new("resources/parked_content.pdf");
+        let options = super::RenderOptions::new();
+        // the rectangle at -10000 -10000 is not part of the content
+        let bounds = super::content_bounds(path, 0, &options, super::Coords::Pdf, 2.).unwrap().unwrap();
+        assert_eq!(bounds, RectF::from_points(Vector2F::new(20., 30.), Vector2F::new(60., 50.)));
+        let all = super::content_bounds(path, 0, &options, super::Coords::Pdf, 1000.).unwrap().unwrap();
+        assert!(all.min_x() < -9000., "{:?}", all);
+    }
+
+    #[test]
+    fn reproducible_output() {
+        let dir = std::env::temp_dir().join(format!("pdf2svg-reproducible-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = super::RenderOptions::new().reproducible(true);
+        let fixtures = ["rack.pdf", "resources/axial_shading.pdf", "resources/hatch_pattern.pdf", "resources/image_soft_mask.pdf", "resources/transparency_group.pdf"];
+        let run = |fixture: &str, output: std::path::PathBuf, options: &super::RenderOptions| {
+            let metadata = super::convert(Path::new(fixture).into(), output.clone(), 0, options).unwrap();
+            (std::fs::read(&output).unwrap(), serde_json::to_string(&metadata).unwrap())
+        };
+        for fixture in fixtures {
+            for extension in ["png", "svg", "pdf", "ps", "hpgl"] {
+                let output = |nr: u32| dir.join(format!("run{}.{}", nr, extension));
+                assert!(run(fixture, output(0), &options) == run(fixture, output(1), &options), "{} to {}", fixture, extension);
+            }
+        }
+        // hpgl arcs, and png at 600 dpi rendered in tiles with downsampled images
+        let arcs = super::RenderOptions::new().reproducible(true).line_options(super::hpgl::LineOptions { fit_arcs: true, ..Default::default() });
+        let scale = g::transform2d::Transform2F::from_scale(g::vector::Vector2F::splat(600. / 72.));
+        let tiled = super::RenderOptions::new()
+            .reproducible(true)
+            .transform(scale, true)
+            .scene_limits(super::png::SceneLimits { max_paths: 100, max_segments: 100_000 })
+            .image_downsample(1.);
+        for (fixture, extension, options) in [("rack.pdf", "hpgl", &arcs), ("rack.pdf", "png", &tiled), ("resources/image_soft_mask.pdf", "png", &tiled)] {
+            let output = |nr: u32| dir.join(format!("run{}.{}", nr, extension));
+            assert!(run(fixture, output(0), options) == run(fixture, output(1), options), "{} to {}", fixture, extension);
+        }
+
+        // a batch writes the same pages and metadata twice
+        let run = |nr: u32| {
+            let template = dir.join(format!("batch{}-{{page}}.png", nr));
+            let batch = super::batch::BatchOptions::default();
+            let report = super::batch::convert_pages(Path::new("resources/corrupt_page.pdf"), &template, &[0, 2], &options, batch, &super::context::Report::new(), super::convert);
+            let pages: Vec<_> = report.iter().map(|&(page, _)| std::fs::read(super::batch::page_path(&template, page)).unwrap()).collect();
+            let json: Vec<_> = report
+                .iter()
+                .map(|(_, status)| match status {
+                    super::batch::PageStatus::Converted(metadata) => serde_json::to_string(metadata).unwrap(),
+                    super::batch::PageStatus::Failed(e) => panic!("{}", e),
+                })
+                .collect();
+            (pages, json)
+        };
+        assert!(run(0) == run(1));
+
+        // pages on four threads share the decoded image, the archive is the same as one thread writes
+        let archive = |nr: u32, jobs: usize| {
+            let template = dir.join(format!("jobs{}-{{page}}.png", nr));
+            let pages: Vec<u32> = (0..16).collect();
+            let batch = super::batch::BatchOptions { jobs, ..super::batch::BatchOptions::default() };
+            let options = super::RenderOptions::new().reproducible(true);
+            let report = super::batch::convert_pages(Path::new("resources/sixteen_pages.pdf"), &template, &pages, &options, batch, &super::context::Report::new(), super::convert);
+            let metadata: Vec<_> = report
+                .iter()
+                .map(|(_, status)| match status {
+                    super::batch::PageStatus::Converted(metadata) => &**metadata,
+                    super::batch::PageStatus::Failed(e) => panic!("{}", e),
+                })
+                .collect();
+            let json = dir.join(format!("jobs{}.json", nr));
+            super::metadata::write_pages(&metadata, &json).unwrap();
+            let pages: Vec<_> = pages.iter().map(|&page| std::fs::read(super::batch::page_path(&template, page)).unwrap()).collect();
+            (pages, std::fs::read(&json).unwrap())
+        };
+        let serial = archive(0, 1);
+        assert!(archive(1, 4) == serial);
+        assert!(archive(2, 4) == serial);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parallel_pages_match_serial() {
+        let dir = std::env::temp_dir().join(format!("pdf2svg-jobs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = Path::new("resources/sixteen_pages.pdf");
+        let pages: Vec<u32> = (0..16).collect();
+        let options = super::RenderOptions::new();
+        let run = |jobs: usize| {
+            let template = dir.join(format!("jobs{}-{{page}}.png", jobs));
+            let batch = super::batch::BatchOptions { jobs, ..super::batch::BatchOptions::default() };
+            let report = super::context::Report::new();
+            let statuses = super::batch::convert_pages(input, &template, &pages, &options, batch, &report, super::convert);
+            assert!(statuses.iter().all(|(_, status)| matches!(status, super::batch::PageStatus::Converted(_))));
+            let images: Vec<Vec<u8>> = pages.iter().map(|&p| std::fs::read(super::batch::page_path(&template, p)).unwrap()).collect();
+            (report.into_pages(), images)
+        };
+        let (serial, serial_images) = run(1);
+        let (parallel, parallel_images) = run(8);
+        assert_eq!(parallel.len(), pages.len());
+        for (serial, parallel) in serial.iter().zip(&parallel) {
+            assert_eq!(serial.page, parallel.page);
+            assert_eq!(serial.stats.ops, parallel.stats.ops, "page {}", serial.page);
+            assert_eq!(serial.warnings, parallel.warnings);
+        }
+        // every page strokes one more line (m l S) than the one before
+        assert!(serial.windows(2).all(|w| w[1].stats.ops == w[0].stats.ops + 3));
+        assert!(serial_images == parallel_images);
+        // the image of all pages was decoded once, by the first page
+        assert_eq!(options.image_cache.stats().misses, 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // peak resident memory of the process in bytes
+    #[cfg(all(feature = "mmap", target_os = "linux"))]
+    fn peak_rss() -> u64 {
+        let status = std::fs::read_to_string("/proc/self/status").unwrap();
+        let line = status.lines().find(|l| l.starts_with("VmHWM:")).unwrap();
+        line.split_whitespace().nth(1).unwrap().parse::<u64>().unwrap() * 1024
+    }
+
+    // a 2 GB file that is mostly an unused stream, run with --ignored
+    #[test]
+    #[ignore]
+    #[cfg(all(feature = "mmap", target_os = "linux"))]
+    fn large_input_is_mapped() {
+        use std::io::{Seek, Write};
+        const SIZE: u64 = 2 << 30;
+        let path = std::env::temp_dir().join("pdf2svg-large-input.pdf");
+        let mut file = std::fs::File::create(&path).unwrap();
+        let mut offsets = vec![];
+        file.write_all(b"%PDF-1.4\n").unwrap();
+        let content = b"0 0 1 rg 10 10 80 80 re f";
+        let objects = [
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+            b"<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] /Resources << >> /Contents 4 0 R >>".to_vec(),
+            [format!("<< /Length {} >>\nstream\n", content.len()).as_bytes(), content, b"\nendstream"].concat(),
+        ];
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(file.stream_position().unwrap());
+            write!(file, "{} 0 obj\n", i + 1).unwrap();
+            file.write_all(object).unwrap();
+            file.write_all(b"\nendobj\n").unwrap();
+        }
+        // the stream data is a hole in the file
+        offsets.push(file.stream_position().unwrap());
+        write!(file, "5 0 obj\n<< /Length {} >>\nstream\n", SIZE).unwrap();
+        let end = file.stream_position().unwrap() + SIZE;
+        file.set_len(end).unwrap();
+        file.seek(std::io::SeekFrom::Start(end)).unwrap();
+        file.write_all(b"\nendstream\nendobj\n").unwrap();
+        let xref = file.stream_position().unwrap();
+        write!(file, "xref\n0 6\n0000000000 65535 f \n").unwrap();
+        for offset in &offsets {
+            write!(file, "{:010} 00000 n \n", offset).unwrap();
+        }
+        write!(file, "trailer\n<< /Size 6 /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", xref).unwrap();
+        drop(file);
+
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(&path, 0, &options);
+        let peak = peak_rss();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(image.unwrap().width, 100);
+        assert!(peak < SIZE / 8, "peak rss {} MB", peak >> 20);
+    }
+
+    #[test]
+    fn dashed_corner_at_600_dpi() {
+        use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+        let scale = 600. / 72.;
+        let options = super::RenderOptions::new().transform(Transform2F::from_scale(Vector2F::splat(scale)), true);
+        let image = super::rasterize(Path::new("resources/dashed_corner.pdf"), 0, &options).unwrap();
+        // page coordinates, y up
+        let dark = |x: f32, y: f32| {
+            let (x, y) = ((x * scale) as usize, ((100. - y) * scale) as usize);
+            pixel(&image, x, y)[0] < 128
+        };
+        // a dash runs through the start of the rectangle at 20,20, the outer
+        // corner of its miter join is inked and not notched
+        assert!(dark(18.5, 18.5));
+        assert!(dark(19.5, 18.5) && dark(18.5, 19.5));
+        // the gap from 5 to 15 along the bottom edge
+        assert!(!dark(30., 20.));
+    }
+
+    #[test]
+    fn estimate_matches_render() {
+        use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+        let cases = [
+            ("resources/schematic.pdf", 72.),
+            ("resources/offset_box.pdf", 150.),
+            ("resources/rotated_page.pdf", 72.),
+            ("resources/rotated_page.pdf", 300.),
+        ];
+        for (path, dpi) in cases {
+            let options = super::RenderOptions::new().transform(Transform2F::from_scale(Vector2F::splat(dpi / 72.)), true);
+            let file = super::input::open(Path::new(path)).unwrap();
+            let estimate = super::estimate_output_size(&options, &file.get_page(0).unwrap());
+            let image = super::rasterize(Path::new(path), 0, &options).unwrap();
+            assert_eq!(estimate, (image.width, image.height), "{} at {} dpi", path, dpi);
+        }
+        // the rotated page is taller than wide, partial pixels count
+        let file = super::input::open(Path::new("resources/rotated_page.pdf")).unwrap();
+        assert_eq!(super::estimate_output_size(&super::RenderOptions::new(), &file.get_page(0).unwrap()), (51, 101));
+    }
+
+    #[test]
+    fn inspect_points() {
+        use super::Coords;
+        use pathfinder_geometry::vector::Vector2F;
+        let path = Path::new("resources/inspect.pdf");
+        let options = super::RenderOptions::new();
+        let at = |x: f32, y: f32, coords: Coords| {
+            super::inspect_point(path, 0, &options, Vector2F::new(x, y), coords).unwrap().map(|(draw, _)| draw.to_string())
+        };
+        // image pixels, y down from the top of the 100pt page
+        assert_eq!(at(20., 70., Coords::Image).as_deref(), Some("op 2: fill rgb 1 0 0"));
+        assert_eq!(at(50., 50., Coords::Image).as_deref(), Some("op 7: stroke rgb 0 0 1"));
+        // the black fill covers the page but is clipped to a small square
+        assert_eq!(at(80., 85., Coords::Image).as_deref(), Some("op 14: fill rgb 0 0 0"));
+        assert_eq!(at(50., 5., Coords::Image), None);
+        // the same points in pdf units
+        assert_eq!(at(20., 30., Coords::Pdf).as_deref(), Some("op 2: fill rgb 1 0 0"));
+        assert_eq!(at(80., 15., Coords::Pdf).as_deref(), Some("op 14: fill rgb 0 0 0"));
+
+        let (_, bounds) = super::inspect_point(path, 0, &options, Vector2F::new(20., 30.), Coords::Pdf).unwrap().unwrap();
+        assert_eq!((bounds.min_x(), bounds.min_y(), bounds.max_x(), bounds.max_y()), (10., 10., 60., 60.));
+    }
+
+    #[test]
+    fn tiled_fallback() {
+        let limits = super::png::SceneLimits { max_paths: 100, max_segments: 100_000 };
+        let options = super::RenderOptions::new().scene_limits(limits);
+        // 1600 small squares, over the limit they are rendered in 5x5 tiles
+        let path = Path::new("resources/many_paths.pdf");
+        let whole = super::rasterize(path, 0, &super::RenderOptions::new()).unwrap();
+        let tiled = super::rasterize(path, 0, &options).unwrap();
+        assert_eq!(super::diff::diff(&whole, &tiled, 2).changed, 0);
+
+        let dir = std::env::temp_dir().join(format!("pdf2svg-tiles-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let metadata = super::convert(path.to_path_buf(), dir.join("page.png"), 0, &options).unwrap();
+        assert!(metadata.warnings.iter().any(|w| w.contains("5x5 tiles")), "{:?}", metadata.warnings);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fax_images() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/fax_g4.pdf"), 0, &options).unwrap();
+        // "FAX" in group 4 as a gray image on top, the stem of the F and the bar of the A
+        assert_eq!(pixel(&image, 16, 25), [0, 0, 0]);
+        assert_eq!(pixel(&image, 48, 24), [0, 0, 0]);
+        assert_eq!(pixel(&image, 35, 25), [255, 255, 255]);
+        // the same data as an image mask painted in red below
+        assert_eq!(pixel(&image, 16, 75), [255, 0, 0]);
+        assert_eq!(pixel(&image, 35, 75), [255, 255, 255]);
+    }
+
+    #[cfg(feature = "jbig2")]
+    #[test]
+    fn jbig2_images() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/jbig2.pdf"), 0, &options).unwrap();
+        // symbols of the globals stream placed by the page, 6 points per pixel:
+        // the top of the F and the corner left of it
+        assert_eq!(pixel(&image, 9, 23), [0, 0, 0]);
+        assert_eq!(pixel(&image, 3, 17), [255, 255, 255]);
+        assert_eq!(pixel(&image, 21, 59), [0, 0, 0]);
+        // the same data as a red image mask on the right
+        assert_eq!(pixel(&image, 109, 23), [255, 0, 0]);
+        assert_eq!(pixel(&image, 103, 17), [255, 255, 255]);
+    }
+
+    #[test]
+    fn jpeg_images() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/jpeg_images.pdf"), 0, &options).unwrap();
+        let near = |p: [u8; 3], q: [u8; 3]| p.iter().zip(q).all(|(a, b)| a.abs_diff(b) < 8);
+        // a red rgb jpeg on the left and a green cmyk one with the Adobe marker
+        assert!(near(pixel(&image, 25, 50), [255, 0, 0]), "{:?}", pixel(&image, 25, 50));
+        assert!(near(pixel(&image, 75, 50), [0, 255, 0]), "{:?}", pixel(&image, 75, 50));
+    }
+
+    #[test]
+    fn cmyk_images_match_fills() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/cmyk_match.pdf"), 0, &options).unwrap();
+        // the fill above and the cmyk image below have the same color
+        assert_eq!(pixel(&image, 25, 25), [153, 102, 51]);
+        assert_eq!(pixel(&image, 25, 75), pixel(&image, 25, 25));
+        // green as a fill and as the inverted cmyk jpeg with the Adobe marker
+        let near = |p: [u8; 3], q: [u8; 3]| p.iter().zip(q).all(|(a, b)| a.abs_diff(b) < 8);
+        assert_eq!(pixel(&image, 75, 25), [0, 255, 0]);
+        assert!(near(pixel(&image, 75, 75), pixel(&image, 75, 25)), "{:?}", pixel(&image, 75, 75));
+    }
+
+    #[test]
+    fn dashed_border() {
+        let options = super::RenderOptions::new();
+        let image = super::rasterize(Path::new("resources/dashed_border.pdf"), 0, &options).unwrap();
+        let dark = |x: usize, y: usize| pixel(&image, x, y)[0] < 128;
+        // dark runs along a row
+        let dashes = |y: usize, xs: std::ops::Range<usize>| {
+            let row: Vec<bool> = xs.map(|x| dark(x, y)).collect();
+            row.windows(2).filter(|w| w[1] && !w[0]).count() + row[0] as usize
+        };
+        // [6 4] along the top of the border
+        let n = dashes(20, 24..76);
+        assert!((4..=6).contains(&n), "{} dashes", n);
+        // [0 0] is solid
+        assert!((12..88).all(|x| dark(x, 90)));
+        // [2 2] from the ExtGState
+        assert!(dashes(5, 12..88) >= 15);
+    }
+}
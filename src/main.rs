@@ -1,30 +1,282 @@
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
 
 extern crate pathfinder_geometry as g;
 
-//mod common;
-mod plotter;
-//mod fontentry;
-mod graphics_state;
-mod text_state;
-mod render;
-//mod screen_plotter;
-mod vector_plotter;
-mod png;
-
-use clap::Parser;
-use g::rect::RectF;
+use clap::{Parser, Subcommand};
 use g::transform2d::Transform2F;
-use g::vector::Vector2F;
-use pdf::file::FileOptions;
-use pdf::object::{Page, Rect};
 use pdf::PdfError;
 
-use crate::render::RenderState;
+use pdf2svg::batch::{self, BatchOptions, PageSelection};
+use pdf2svg::bounds_plotter::BoundsPlotter;
+use pdf2svg::context::Report;
+use pdf2svg::hpgl::LineOptions;
+use pdf2svg::geometry::{parse_length, parse_point, parse_rect, parse_stamp_scale, parse_transform, Anchor, Coords, Placement, StampScale};
+use pdf2svg::metadata::{self, PageMetadata};
+use pdf2svg::options::{Progress, RenderOptions};
+use pdf2svg::permissions::Permissions;
+use pdf2svg::png::{self, PngFilter, SceneLimits};
+use pdf2svg::svg::{SvgSize, SvgUnits};
+use pdf2svg::{content, diff, estimate, input, structure, transparency, validate};
+use pdf2svg::{
+    content_bounds, convert, estimate_output_size, extract_images, inspect_point, optional_content, page_bounds, rasterize, render_with, selftest_page, thumbnail, SCALE,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Input file
+    #[arg(short, long, required = true)]
+    input: Option<PathBuf>,
+
+    /// Page number
+    #[arg(short, long, default_value_t = 0)]
+    page: u32,
+
+    /// Pages to convert, "all" or a list like "0-3,7"; {page} in the output name is replaced by the page number
+    #[arg(long, value_parser = batch::parse_pages, conflicts_with = "page")]
+    pages: Option<PageSelection>,
+
+    /// With --pages, stop at the first page that fails
+    #[arg(long, requires = "pages")]
+    fail_fast: bool,
+
+    /// With --pages, convert this many pages at the same time
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..), requires = "pages")]
+    jobs: u32,
+
+    /// Output file
+    #[arg(short, long, required_unless_present = "info")]
+    output: Option<PathBuf>,
+
+    /// Print page count and permissions of the input file
+    #[arg(long)]
+    info: bool,
+
+    /// Extract content even if the document does not permit it
+    #[arg(long)]
+    ignore_permissions: bool,
+
+    /// Convert CMYK colors for blending in a CMYK page group
+    #[arg(long)]
+    group_aware_blending: bool,
+
+    /// Snap the edges of axis aligned rectangle fills to the pixel grid
+    #[arg(long)]
+    snap_rects: bool,
+
+    /// Widen strokes that are thinner than this many pixels, for legible thumbnails
+    #[arg(long, value_name = "PX")]
+    min_stroke_px: Option<f32>,
+
+    /// Width of strokes with a line width of 0 [default: 1 pixel, 0.1 for vector output]
+    #[arg(long, value_name = "PX")]
+    hairline_width: Option<f32>,
+
+    /// Clamp path points to this many page sizes around the page
+    #[arg(long, value_name = "PAGES", default_value_t = 100.)]
+    coord_limit: f32,
+
+    /// Skip forms nested deeper than this
+    #[arg(long, value_name = "DEPTH", default_value_t = 32)]
+    max_depth: usize,
+
+    /// Write pages without color as grayscale PNG
+    #[arg(long)]
+    grayscale_if_monochrome: bool,
+
+    /// Show only these layers (optional content groups), by name
+    #[arg(long, value_delimiter = ',')]
+    layers: Option<Vec<String>>,
+
+    /// Hide these layers, also when --layers names them
+    #[arg(long, value_delimiter = ',')]
+    exclude_layers: Vec<String>,
+
+    /// Write page metadata as json
+    #[arg(long)]
+    metadata: Option<PathBuf>,
+
+    /// More log output, repeat for debug and trace
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only log errors
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Show a progress bar on stderr
+    #[arg(long)]
+    progress: bool,
+
+    /// Do not embed source and settings into the output file
+    #[arg(long)]
+    no_metadata: bool,
+
+    /// Overwrite existing output files
+    #[arg(long)]
+    force: bool,
+
+    /// Transform "a b c d e f" applied after the page rotation, in output units
+    #[arg(long, value_parser = parse_transform, allow_hyphen_values = true)]
+    transform: Option<Transform2F>,
+
+    /// Transform the view box with --transform instead of keeping the page size
+    #[arg(long, requires = "transform")]
+    transform_viewbox: bool,
+
+    /// Print the time spent in each conversion stage
+    #[arg(long)]
+    timings: bool,
+
+    /// Write the same bytes on every run, without the timings in --metadata
+    #[arg(long, conflicts_with = "timings")]
+    reproducible: bool,
+
+    /// Write every decoded operator with its index to this file
+    #[arg(long)]
+    dump_ops: Option<PathBuf>,
+
+    /// Collect operators, draw calls, images and warnings into this directory for a bug report
+    #[arg(long, conflicts_with_all = ["dump_ops", "pages"])]
+    debug_dump: Option<PathBuf>,
+
+    /// Write a png of at most PX pixels on the longer side from the /Thumb image of the page, or a quick render without one
+    #[arg(long, value_name = "PX", num_args = 0..=1, default_missing_value = "128", conflicts_with_all = ["pages", "debug_dump"])]
+    thumbnail: Option<u32>,
+
+    /// Stop rendering after this operator, numbered like in --dump-ops
+    #[arg(long)]
+    stop_at_op: Option<usize>,
+
+    /// Units of the svg width and height
+    #[arg(long, value_enum, default_value_t = SvgUnits::Pt)]
+    svg_units: SvgUnits,
+
+    /// Omit the svg width and height so it scales to its container
+    #[arg(long)]
+    svg_responsive: bool,
+
+    /// Row filter of png output, none also selects fast compression
+    #[arg(long, value_enum)]
+    png_filter: Option<PngFilter>,
+
+    /// Downsample images with more pixels than this per png pixel, 0 keeps them at full size
+    #[arg(long, value_name = "FACTOR", default_value_t = png::IMAGE_DOWNSAMPLE)]
+    image_downsample: f32,
+
+    /// Keep up to this many bytes of decoded images for reuse across Do operators and pages, 0 disables the cache
+    #[arg(long, value_name = "BYTES", default_value_t = image_cache::IMAGE_CACHE_BYTES)]
+    image_cache_bytes: usize,
+
+    /// Render png pages with more draw paths than this in tiles
+    #[arg(long, value_name = "PATHS", default_value_t = SceneLimits::default().max_paths)]
+    max_scene_paths: usize,
+
+    /// Render png pages with more path segments than this in tiles
+    #[arg(long, value_name = "SEGMENTS", default_value_t = SceneLimits::default().max_segments)]
+    max_scene_segments: usize,
+
+    /// Maximum distance between curves and their segments in hpgl output, in pt, mm, cm or in
+    #[arg(long, value_parser = parse_length, default_value = "0.05mm")]
+    flatten_tolerance: f32,
+
+    /// Write circular runs of segments as hpgl arcs
+    #[arg(long)]
+    fit_arcs: bool,
+
+    /// Draw the first page of this pdf on top of the page
+    #[arg(long)]
+    stamp: Option<PathBuf>,
+
+    /// Corner or center of the displayed page the stamp is placed at
+    #[arg(long, value_enum, default_value_t = Anchor::Center, requires = "stamp")]
+    stamp_anchor: Anchor,
+
+    /// Stamp size, "fit" or a percentage of the stamp page size
+    #[arg(long, value_parser = parse_stamp_scale, default_value = "fit", requires = "stamp")]
+    stamp_scale: StampScale,
+
+    /// Counterclockwise rotation of the stamp in degrees
+    #[arg(long, default_value_t = 0., allow_hyphen_values = true, requires = "stamp")]
+    stamp_rotate: f32,
+
+    /// Distance to the page edges, in pt, mm, cm or in
+    #[arg(long, value_parser = parse_length, default_value = "0", requires = "stamp")]
+    stamp_margin: f32,
+
+    /// Mark the rectangle "x0,y0,x1,y1" in yellow, can be given several times
+    #[arg(long, value_parser = parse_rect, allow_hyphen_values = true)]
+    highlight: Vec<g::rect::RectF>,
+
+    /// Points from the lower left of the page box, or pixels from the top left of the image
+    #[arg(long, value_enum, default_value_t = Coords::Pdf)]
+    coords: Coords,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Highlight the pixels that differ between two pages
+    Diff(DiffArgs),
+    /// Print the bounds of the painted content in points
+    Bbox(BboxArgs),
+    /// Compare the png and the vector backend on every page
+    Selftest(SelftestArgs),
+    /// Parse the content of every page and report problems
+    Check(CheckArgs),
+    /// Print the pixel size of the png output of each page without rendering
+    Size(SizeArgs),
+    /// Print the operator that painted a point of the page
+    Inspect(InspectArgs),
+    /// Write the images drawn on the pages as png files
+    ExtractImages(ExtractImagesArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ExtractImagesArgs {
+    /// Input file
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Pages, "all" or a list like "0-3,7"
+    #[arg(long, value_parser = batch::parse_pages, default_value = "all")]
+    pages: PageSelection,
+
+    /// Directory for the images
+    #[arg(short, long, default_value = ".")]
+    output: PathBuf,
+
+    /// Copy jpeg images as they are stored instead of decoding them
+    #[arg(long)]
+    raw: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct SizeArgs {
+    /// Input file
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Pages, "all" or a list like "0-3,7"
+    #[arg(long, value_parser = batch::parse_pages, default_value = "all")]
+    pages: PageSelection,
+
+    /// Resolution of the output
+    #[arg(long, default_value_t = 72.)]
+    dpi: f32,
+
+    /// Print the sizes as json
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct InspectArgs {
     /// Input file
     #[arg(short, long)]
     input: PathBuf,
@@ -33,55 +285,460 @@ struct Args {
     #[arg(short, long, default_value_t = 0)]
     page: u32,
 
-    /// Output file
+    /// The point "x,y" to look at
+    #[arg(long, value_parser = parse_point)]
+    at: g::vector::Vector2F,
+
+    /// Points from the lower left of the page box, or pixels from the top left of the image
+    #[arg(long, value_enum, default_value_t = Coords::Image)]
+    coords: Coords,
+}
+
+#[derive(clap::Args, Debug)]
+struct CheckArgs {
+    /// Input file
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Only check this page
+    #[arg(short, long)]
+    page: Option<u32>,
+
+    /// Compare the resources of each page with the operators that use them
+    #[arg(long)]
+    pedantic_check: bool,
+
+    /// Write the findings as json
+    #[arg(long)]
+    json: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct SelftestArgs {
+    /// Input file
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Only check this page
+    #[arg(short, long)]
+    page: Option<u32>,
+
+    /// Number of changed pixels that is still accepted
+    #[arg(long, default_value_t = 0)]
+    threshold: usize,
+
+    /// Largest difference of a color channel that counts as unchanged
+    #[arg(long, default_value_t = 8)]
+    tolerance: u8,
+
+    /// Directory for the images of the pages that differ
+    #[arg(long, default_value = "selftest")]
+    artifacts: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct BboxArgs {
+    /// Input file
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Page number
+    #[arg(short, long, default_value_t = 0)]
+    page: u32,
+
+    /// Points from the lower left of the page box, or pixels from the top left of the image
+    #[arg(long, value_enum, default_value_t = Coords::Pdf)]
+    coords: Coords,
+
+    /// Leave out content farther from the page box than this many page diagonals
+    #[arg(long, default_value_t = 2.0)]
+    offpage_factor: f32,
+}
+
+#[derive(clap::Args, Debug)]
+struct DiffArgs {
+    /// Old file
+    #[arg(short = 'a')]
+    old: PathBuf,
+
+    /// New file
+    #[arg(short = 'b')]
+    new: PathBuf,
+
+    /// Page number
+    #[arg(short, long, default_value_t = 0)]
+    page: u32,
+
+    /// Page number in the new file, defaults to --page
+    #[arg(long)]
+    page_b: Option<u32>,
+
+    /// Output image
     #[arg(short, long)]
     output: PathBuf,
+
+    /// Number of changed pixels that is still accepted
+    #[arg(long, default_value_t = 0)]
+    threshold: usize,
+
+    /// Largest difference of a color channel that counts as unchanged
+    #[arg(long, default_value_t = 0)]
+    tolerance: u8,
+
+    /// Overwrite an existing output file
+    #[arg(long)]
+    force: bool,
+}
+
+// RUST_LOG takes precedence over the command line flags
+fn init_logger(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => "error",
+        (false, 0) => "warn",
+        (false, 1) => "info",
+        (false, 2) => "debug",
+        (false, _) => "trace",
+    };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level))
+        .format_timestamp(None)
+        .init();
 }
 
-//const SCALE: f32 = 25.4 / 72.;
-const SCALE: f32 = 1.0;
+fn print_progress(progress: Progress) {
+    const WIDTH: usize = 40;
+    let fraction = match progress.total_ops {
+        0 => 1.0,
+        total => progress.ops as f32 / total as f32,
+    };
+    let filled = (fraction * WIDTH as f32) as usize;
+    eprint!(
+        "\rpage {}/{} [{}{}] {:3.0}%",
+        progress.page + 1,
+        progress.pages,
+        "#".repeat(filled),
+        " ".repeat(WIDTH - filled),
+        fraction * 100.
+    );
+    if progress.ops == progress.total_ops {
+        eprintln!();
+    }
+}
 
-pub fn page_bounds(page: &Page) -> g::rect::RectF {
-    let Rect { left, right, top, bottom } = page.media_box().expect("no media box");
-    g::rect::RectF::from_points(g::vector::Vector2F::new(left, bottom), g::vector::Vector2F::new(right, top)) * SCALE
+// Check all outputs before anything is rendered, so a failing run does not leave
+// a partly overwritten set of files behind.
+fn check_outputs(outputs: &[&Path], force: bool) -> Result<(), PdfError> {
+    if force {
+        return Ok(());
+    }
+    match outputs.iter().find(|p| p.exists()) {
+        Some(path) => Err(PdfError::Other {
+            msg: format!("output file {:?} exists, use --force to overwrite", path),
+        }),
+        None => Ok(()),
+    }
 }
 
 fn main() -> Result<(), PdfError>{
     let args = Args::parse();
-    convert(args.input, args.output, args.page)
+    init_logger(args.verbose, args.quiet);
+    match args.command {
+        Some(Command::Diff(diff)) => return run_diff(diff),
+        Some(Command::Bbox(bbox)) => return run_bbox(bbox),
+        Some(Command::Selftest(selftest)) => return run_selftest(selftest),
+        Some(Command::Check(check)) => return run_check(check),
+        Some(Command::Size(size)) => return run_size(size),
+        Some(Command::Inspect(inspect)) => return run_inspect(inspect),
+        Some(Command::ExtractImages(extract)) => return run_extract_images(extract),
+        None => {}
+    }
+    if args.info {
+        let options = RenderOptions::new().ignore_permissions(args.ignore_permissions);
+        return print_info(args.input.as_deref().expect("input is required"), &options);
+    }
+    let (Some(input), Some(output)) = (args.input, args.output) else {
+        unreachable!("input and output are required without a subcommand");
+    };
+    // every output file is checked before the first page is rendered
+    let pages = match args.pages {
+        Some(ref selection) => Some(selection.pages(input::open(&input)?.num_pages())?),
+        None => None,
+    };
+    let page_outputs: Vec<PathBuf> = match pages {
+        Some(ref pages) => pages.iter().map(|&p| batch::page_path(&output, p)).collect(),
+        None => vec![output.clone()],
+    };
+    let mut outputs: Vec<&Path> = page_outputs.iter().map(|p| p.as_path()).collect();
+    outputs.extend(args.metadata.as_deref());
+    outputs.extend(args.dump_ops.as_deref());
+    check_outputs(&outputs, args.force)?;
+    if let Some(size) = args.thumbnail {
+        let image = thumbnail(&input, args.page, size)?;
+        png::write_png(BufWriter::new(File::create(&output)?), image.width, image.height, &image.pixels, None);
+        return Ok(());
+    }
+
+    let mut options = RenderOptions::new()
+        .embed_metadata(!args.no_metadata)
+        .ignore_permissions(args.ignore_permissions)
+        .group_aware_blending(args.group_aware_blending)
+        .snap_rects(args.snap_rects)
+        .min_stroke_px(args.min_stroke_px)
+        .hairline_width(args.hairline_width)
+        .coord_limit(args.coord_limit)
+        .max_depth(args.max_depth)
+        .layers(args.layers, args.exclude_layers)
+        .svg_size(SvgSize { units: args.svg_units, responsive: args.svg_responsive })
+        .png_filter(args.png_filter)
+        .scene_limits(SceneLimits { max_paths: args.max_scene_paths, max_segments: args.max_scene_segments })
+        .image_downsample(args.image_downsample)
+        .image_cache_bytes(args.image_cache_bytes)
+        .grayscale_if_monochrome(args.grayscale_if_monochrome)
+        .reproducible(args.reproducible)
+        .line_options(LineOptions { tolerance: args.flatten_tolerance, fit_arcs: args.fit_arcs })
+        .highlights(args.highlight, args.coords)
+        .debug_dump(args.debug_dump);
+    if let Some(transform) = args.transform {
+        options = options.transform(transform, args.transform_viewbox);
+    }
+    if let Some(stamp) = args.stamp {
+        let placement = Placement {
+            anchor: args.stamp_anchor,
+            scale: args.stamp_scale,
+            rotate: args.stamp_rotate,
+            margin: args.stamp_margin,
+        };
+        options = options.stamp(stamp, placement);
+    }
+    if args.progress {
+        options = options.on_progress(print_progress);
+    }
+    if let Some(op) = args.stop_at_op {
+        options = options.stop_at_op(op);
+    }
+    if let Some(ref file) = args.dump_ops {
+        options = options.dump_ops(BufWriter::new(File::create(file)?));
+    }
+    if let Some(pages) = pages {
+        if pages.len() > 1 && !output.to_string_lossy().contains("{page}") {
+            return Err(PdfError::Other {
+                msg: format!("the output {:?} needs {{page}} to convert more than one page", output),
+            });
+        }
+        let batch = BatchOptions { fail_fast: args.fail_fast, jobs: args.jobs as usize };
+        let run = Report::new();
+        let report = batch::convert_pages(&input, &output, &pages, &options, batch, &run, convert);
+        for (page, status) in &report {
+            eprintln!("page {}: {}", page, status);
+        }
+        let converted_pages = run.into_pages();
+        let ops: usize = converted_pages.iter().map(|p| p.stats.ops).sum();
+        let warnings: usize = converted_pages.iter().map(|p| p.warnings.len()).sum();
+        eprintln!(
+            "{} pages, {} operators, {} warnings, image cache contention: {}",
+            converted_pages.len(),
+            ops,
+            warnings,
+            options.image_cache.contention()
+        );
+        let converted: Vec<&PageMetadata> = report
+            .iter()
+            .filter_map(|(_, status)| match *status {
+                batch::PageStatus::Converted(ref metadata) => Some(&**metadata),
+                batch::PageStatus::Failed(_) => None,
+            })
+            .collect();
+        if let Some(file) = args.metadata {
+            metadata::write_pages(&converted, &file)?;
+        }
+        if converted.len() < report.len() {
+            match args.fail_fast {
+                true => std::process::exit(1),
+                false => std::process::exit(batch::PARTIAL_FAILURE),
+            }
+        }
+        return Ok(());
+    }
+    let metadata = convert(input, output, args.page, &options)?;
+    if args.timings {
+        eprintln!("{}", metadata.timings);
+    }
+    if let Some(file) = args.metadata {
+        metadata.write(&file)?;
+    }
+    Ok(())
 }
 
-pub fn convert(input: PathBuf, output: PathBuf, page_nr: u32) -> Result<(), PdfError>{
+fn print_info(input: &Path, options: &RenderOptions) -> Result<(), PdfError> {
+    let file = input::open(input)?;
+    let permissions = Permissions::from_crypt(file.trailer.encrypt_dict.as_deref());
+    println!("pages: {}", file.num_pages());
+    println!("permissions: {}", permissions);
+    let resolve = file.resolver();
+    for (name, on) in optional_content(&file, options)?.layers() {
+        println!("layer {}: {}", name, if on { "on" } else { "off" });
+    }
+    let catalog = resolve.resolve(file.trailer.root.get_ref().get_inner())?.into_dictionary()?;
+    // the reading order is structure, which needs the extract permission
+    if catalog.get("StructTreeRoot").is_some() {
+        permissions.check_extract(options.ignore_permissions)?;
+    }
+    for nr in 0..file.num_pages() {
+        let page = file.get_page(nr)?;
+        let color = render_with(input, nr, options, |_| BoundsPlotter::new())?.1.stats.color;
+        match color.color {
+            true => println!("page {}: color, {:.1}% of the area", nr, color.colored_percent),
+            false => println!("page {}: no color", nr),
+        }
+        if let Some(cs) = transparency::page_group_color_space(&page, &resolve)? {
+            println!("page {}: transparency group {}", nr, cs);
+        }
+        if let Some(order) = structure::reading_order(&catalog, page.get_ref().get_inner(), &resolve)? {
+            let items: Vec<String> = order.iter().map(|item| item.to_string()).collect();
+            println!("page {}: structure order {}", nr, items.join(", "));
+        }
+    }
+    Ok(())
+}
 
-    let file = FileOptions::cached().open(input).unwrap();
-    let mut resolve = file.resolver();
-    let page = file.get_page(page_nr).expect("no such page");
+// Exits with status 1 when more than --threshold pixels changed.
+fn run_diff(args: DiffArgs) -> Result<(), PdfError> {
+    check_outputs(&[args.output.as_path()], args.force)?;
+    let options = RenderOptions::new();
+    let old = rasterize(&args.old, args.page, &options)?;
+    let new = rasterize(&args.new, args.page_b.unwrap_or(args.page), &options)?;
+    let result = diff::diff(&old, &new, args.tolerance);
 
-        let transform = Transform2F::default();
+    let file = BufWriter::new(File::create(&args.output)?);
+    png::write_png(file, result.image.width, result.image.height, &result.image.pixels, None);
+    match result.bounds {
+        Some(bounds) => println!(
+            "{} changed pixels in {},{} - {},{}",
+            result.changed,
+            bounds.min_x(),
+            bounds.min_y(),
+            bounds.max_x(),
+            bounds.max_y()
+        ),
+        None => println!("no changed pixels"),
+    }
+    if result.changed > args.threshold {
+        std::process::exit(1);
+    }
+    Ok(())
+}
 
-        let bounds = page_bounds(&page);
-        let rotate = Transform2F::from_rotation(page.rotate as f32 * std::f32::consts::PI / 180.);
-        let br = rotate * RectF::new(Vector2F::zero(), bounds.size());
-        let translate = Transform2F::from_translation(Vector2F::new(
-            -br.min_x().min(br.max_x()),
-            -br.min_y().min(br.max_y()),
-        ));
-        let view_box = transform * translate * br;
+// Prints "left bottom right top" in pdf user space of the page.
+fn run_bbox(args: BboxArgs) -> Result<(), PdfError> {
+    match content_bounds(&args.input, args.page, &RenderOptions::new(), args.coords, args.offpage_factor)? {
+        Some(bounds) => println!("{:.2} {:.2} {:.2} {:.2}", bounds.min_x(), bounds.min_y(), bounds.max_x(), bounds.max_y()),
+        None => println!("empty page"),
+    }
+    Ok(())
+}
+
+// Exits with status 1 when a page differs by more than --threshold pixels, the
+// images of those pages are written to --artifacts.
+fn run_selftest(args: SelftestArgs) -> Result<(), PdfError> {
+    // the same hairlines in both outputs
+    let options = RenderOptions::new().hairline_width(Some(1.));
+    let pages = match args.page {
+        Some(page) => vec![page],
+        None => (0..input::open(&args.input)?.num_pages()).collect(),
+    };
+    let mut failed = 0;
+    for page in pages {
+        let (raster, vector, result) = selftest_page(&args.input, page, &options, args.tolerance)?;
+        if result.changed <= args.threshold {
+            println!("page {}: ok", page);
+            continue;
+        }
+        failed += 1;
+        println!("page {}: {} pixels differ", page, result.changed);
+        std::fs::create_dir_all(&args.artifacts)?;
+        for (name, image) in [("png", &raster), ("vector", &vector), ("diff", &result.image)] {
+            let file = BufWriter::new(File::create(args.artifacts.join(format!("page{}-{}.png", page, name)))?);
+            png::write_png(file, image.width, image.height, &image.pixels, None);
+        }
+    }
+    if failed > 0 {
+        println!("{} pages differ, see {:?}", failed, args.artifacts);
+        std::process::exit(1);
+    }
+    Ok(())
+}
 
-        let root_transformation = transform
-            * translate
-            * rotate
-            * Transform2F::row_major(SCALE, 0.0, -bounds.min_x(), 0.0, -SCALE, bounds.max_y());
+// Exits with status 1 when there are findings.
+fn run_check(args: CheckArgs) -> Result<(), PdfError> {
+    let file = input::open(&args.input)?;
+    let resolve = file.resolver();
+    let pages = match args.page {
+        Some(page) => vec![page],
+        None => (0..file.num_pages()).collect(),
+    };
+    let mut findings = vec![];
+    for nr in pages {
+        let page = file.get_page(nr)?;
+        let Some(contents) = page.contents.as_ref() else { continue };
+        let ops = content::operations(contents, &resolve)?;
+        if args.pedantic_check {
+            findings.extend(validate::check_page(nr, &ops, page.resources()?, &resolve)?);
+        }
+    }
+    for finding in &findings {
+        println!("{}", finding);
+    }
+    if let Some(ref json) = args.json {
+        validate::write_report(&findings, json)?;
+    }
+    if !findings.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
 
-        let resources = pdf::t!(page.resources());
+fn run_size(args: SizeArgs) -> Result<(), PdfError> {
+    let file = input::open(&args.input)?;
+    let scale = args.dpi / 72.;
+    let options = RenderOptions::new().transform(Transform2F::from_scale(g::vector::Vector2F::splat(scale)), true);
+    let mut sizes = vec![];
+    for nr in args.pages.pages(file.num_pages())? {
+        let page = file.get_page(nr)?;
+        let bounds = page_bounds(&page) * (1. / SCALE);
+        let media_box = [bounds.min_x(), bounds.min_y(), bounds.max_x(), bounds.max_y()];
+        sizes.push(estimate::PageSize::new(nr, estimate_output_size(&options, &page), media_box, page.rotate));
+    }
+    match args.json {
+        true => {
+            let json = serde_json::to_string_pretty(&sizes).map_err(|e| PdfError::Other {
+                msg: format!("failed to write json: {}", e),
+            })?;
+            println!("{}", json);
+        }
+        false => sizes.iter().for_each(|size| println!("{}", size)),
+    }
+    Ok(())
+}
 
-    let mut plotter = vector_plotter::VectorPlotter::new(view_box);
-    let mut plotter = png::PngPlotter::new(view_box);
-    //let mut plotter = screen_plotter::ScreenPlotter::new(view_box);
-    let mut render = RenderState::new(&mut plotter, &mut resolve, resources, root_transformation);
-    render.render(&page)?;
-    plotter.write(output);
+fn run_extract_images(args: ExtractImagesArgs) -> Result<(), PdfError> {
+    for path in extract_images(&args.input, &args.pages, &args.output, args.raw)? {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
 
+fn run_inspect(args: InspectArgs) -> Result<(), PdfError> {
+    let options = RenderOptions::new();
+    match inspect_point(&args.input, args.page, &options, args.at, args.coords)? {
+        Some((draw, bounds)) => println!(
+            "{}, bounds {:.2} {:.2} {:.2} {:.2}",
+            draw,
+            bounds.min_x(),
+            bounds.min_y(),
+            bounds.max_x(),
+            bounds.max_y()
+        ),
+        None => println!("nothing painted at {},{}", args.at.x(), args.at.y()),
+    }
     Ok(())
 }
 
@@ -89,9 +746,14 @@ pub fn convert(input: PathBuf, output: PathBuf, page_nr: u32) -> Result<(), PdfE
 mod test {
     use std::path::Path;
 
-    //test convert sample pdf file to svg
     #[test]
-    fn test_pdf_to_svg() {
-        super::convert(Path::new("rack.pdf").to_path_buf(), Path::new("rack.png").to_path_buf(), 0).unwrap();
+    fn locked_structure() {
+        // a tagged document that permits everything but extraction
+        let path = Path::new("resources/locked_tagged.pdf");
+        let err = super::print_info(path, &super::RenderOptions::new()).unwrap_err();
+        assert!(err.to_string().contains("does not permit to extract content"), "{}", err);
+        super::print_info(path, &super::RenderOptions::new().ignore_permissions(true)).unwrap();
+        // rendering is always allowed
+        assert!(super::rasterize(path, 0, &super::RenderOptions::new()).is_ok());
     }
 }
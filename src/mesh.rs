@@ -0,0 +1,410 @@
+use pathfinder_geometry::vector::Vector2F;
+use pdf::{
+    primitive::{Dictionary, Primitive},
+    PdfError,
+};
+
+// largest color difference, in decoded component units, of a flat piece
+pub const COLOR_THRESHOLD: f32 = 0.02;
+// triangles are split at most this often
+const MAX_DEPTH: u32 = 6;
+// a patch is cut into at most this many rows and columns
+const MAX_SEGMENTS: usize = 32;
+
+// Reads big endian bit fields of up to 32 bits.
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader { data, pos: 0 }
+    }
+    pub fn read(&mut self, bits: u32) -> Option<u32> {
+        if self.pos + bits as usize > self.data.len() * 8 {
+            return None;
+        }
+        let mut value = 0u64;
+        for _ in 0..bits {
+            let bit = self.data[self.pos / 8] >> (7 - self.pos % 8) & 1;
+            value = value << 1 | bit as u64;
+            self.pos += 1;
+        }
+        Some(value as u32)
+    }
+    // skip the padding up to the next byte
+    pub fn align(&mut self) {
+        self.pos = (self.pos + 7) / 8 * 8;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Vertex {
+    pub point: Vector2F,
+    pub color: Vec<f32>,
+}
+
+impl Vertex {
+    fn mix(&self, other: &Vertex, t: f32) -> Vertex {
+        Vertex {
+            point: self.point.lerp(other.point, t),
+            color: mix(&self.color, &other.color, t),
+        }
+    }
+}
+
+fn mix(a: &[f32], b: &[f32], t: f32) -> Vec<f32> {
+    a.iter().zip(b).map(|(a, b)| a + (b - a) * t).collect()
+}
+
+// largest difference of a color component between the colors
+fn color_delta<'c>(mut colors: impl Iterator<Item = &'c [f32]> + Clone) -> f32 {
+    let Some(first) = colors.next() else {
+        return 0.;
+    };
+    (0..first.len())
+        .map(|i| {
+            let values = std::iter::once(first[i]).chain(colors.clone().map(|c| c[i]));
+            let (min, max) = values.fold((f32::MAX, f32::MIN), |(min, max), v| (min.min(v), max.max(v)));
+            max - min
+        })
+        .fold(0., f32::max)
+}
+
+// /BitsPerCoordinate, /BitsPerComponent, /BitsPerFlag and /Decode of a mesh shading
+#[derive(Debug, Clone)]
+pub struct MeshFormat {
+    pub bits_per_coordinate: u32,
+    pub bits_per_component: u32,
+    pub bits_per_flag: u32,
+    pub decode: Vec<f32>,
+}
+
+impl MeshFormat {
+    pub fn from_dict(dict: &Dictionary) -> Result<Self, PdfError> {
+        let bits = |key: &str| -> Result<u32, PdfError> {
+            match dict.get(key) {
+                Some(p) => Ok(p.as_integer()? as u32),
+                None => Ok(0),
+            }
+        };
+        let decode = match dict.get("Decode") {
+            Some(Primitive::Array(ref parts)) => parts.iter().map(|p| p.as_number()).collect::<Result<Vec<_>, _>>()?,
+            _ => {
+                return Err(PdfError::Other {
+                    msg: "mesh shading without /Decode".into(),
+                })
+            }
+        };
+        let format = MeshFormat {
+            bits_per_coordinate: bits("BitsPerCoordinate")?,
+            bits_per_component: bits("BitsPerComponent")?,
+            bits_per_flag: bits("BitsPerFlag")?,
+            decode,
+        };
+        if format.bits_per_coordinate > 32 || format.bits_per_component > 16 || format.bits_per_flag > 8 {
+            return Err(PdfError::Other {
+                msg: format!("invalid mesh bit sizes {:?}", format),
+            });
+        }
+        Ok(format)
+    }
+    // color values per vertex: one with a /Function, else the decode pairs after x and y
+    fn components(&self) -> usize {
+        self.decode.len().saturating_sub(4) / 2
+    }
+    fn value(&self, raw: u32, bits: u32, range: usize) -> f32 {
+        let (min, max) = match self.decode.get(2 * range..2 * range + 2) {
+            Some(&[min, max]) => (min, max),
+            _ => (0., 1.),
+        };
+        let scale = ((1u64 << bits) - 1).max(1) as f64;
+        (min as f64 + raw as f64 * (max - min) as f64 / scale) as f32
+    }
+    fn read_point(&self, reader: &mut BitReader) -> Option<Vector2F> {
+        let bits = self.bits_per_coordinate;
+        let x = self.value(reader.read(bits)?, bits, 0);
+        let y = self.value(reader.read(bits)?, bits, 1);
+        Some(Vector2F::new(x, y))
+    }
+    fn read_color(&self, reader: &mut BitReader) -> Option<Vec<f32>> {
+        let bits = self.bits_per_component;
+        (0..self.components())
+            .map(|i| Some(self.value(reader.read(bits)?, bits, 2 + i)))
+            .collect()
+    }
+    fn read_vertex(&self, reader: &mut BitReader) -> Option<Vertex> {
+        let point = self.read_point(reader)?;
+        let color = self.read_color(reader)?;
+        Some(Vertex { point, color })
+    }
+}
+
+// /ShadingType 4, every vertex starts with a flag that tells how it continues
+// the previous triangle
+pub fn free_form_triangles(data: &[u8], format: &MeshFormat) -> Vec<[Vertex; 3]> {
+    let mut reader = BitReader::new(data);
+    let mut next = || {
+        let flag = reader.read(format.bits_per_flag)?;
+        let vertex = format.read_vertex(&mut reader)?;
+        reader.align();
+        Some((flag, vertex))
+    };
+    let mut triangles: Vec<[Vertex; 3]> = vec![];
+    while let Some((flag, d)) = next() {
+        let triangle = match (flag, triangles.last()) {
+            (1, Some([_, b, c])) => [b.clone(), c.clone(), d],
+            (2, Some([a, _, c])) => [a.clone(), c.clone(), d],
+            _ => {
+                // the flags of the other two vertices are ignored
+                let (Some((_, e)), Some((_, f))) = (next(), next()) else {
+                    break;
+                };
+                [d, e, f]
+            }
+        };
+        triangles.push(triangle);
+    }
+    triangles
+}
+
+// /ShadingType 5, rows of vertices_per_row vertices, every cell is split into
+// two triangles
+pub fn lattice_triangles(data: &[u8], format: &MeshFormat, vertices_per_row: usize) -> Result<Vec<[Vertex; 3]>, PdfError> {
+    if vertices_per_row < 2 {
+        return Err(PdfError::Other {
+            msg: format!("/VerticesPerRow {} in lattice shading", vertices_per_row),
+        });
+    }
+    let mut reader = BitReader::new(data);
+    let mut vertices = vec![];
+    while let Some(vertex) = format.read_vertex(&mut reader) {
+        vertices.push(vertex);
+        reader.align();
+    }
+    let rows: Vec<_> = vertices.chunks_exact(vertices_per_row).collect();
+    let mut triangles = vec![];
+    for pair in rows.windows(2) {
+        let (top, bottom) = (pair[0], pair[1]);
+        for i in 0..vertices_per_row - 1 {
+            triangles.push([top[i].clone(), top[i + 1].clone(), bottom[i].clone()]);
+            triangles.push([top[i + 1].clone(), bottom[i + 1].clone(), bottom[i].clone()]);
+        }
+    }
+    Ok(triangles)
+}
+
+// Coons patch. The boundary points run around the patch in stream order, the
+// colors belong to points 0, 3, 6 and 9.
+#[derive(Debug, Clone)]
+pub struct Patch {
+    pub points: [Vector2F; 12],
+    pub colors: [Vec<f32>; 4],
+}
+
+// /ShadingType 6 and 7. The inner control points of tensor patches are
+// skipped, so those are drawn as Coons patches.
+pub fn patches(data: &[u8], format: &MeshFormat, tensor: bool) -> Vec<Patch> {
+    let mut reader = BitReader::new(data);
+    let mut patches: Vec<Patch> = vec![];
+    let inner = if tensor { 4 } else { 0 };
+    loop {
+        let Some(flag) = reader.read(format.bits_per_flag) else {
+            break;
+        };
+        // the first edge and its two colors are shared with the previous patch
+        let shared = match (flag, patches.last()) {
+            (0, _) | (_, None) => None,
+            (1, Some(p)) => Some(([p.points[3], p.points[4], p.points[5], p.points[6]], [&p.colors[1], &p.colors[2]])),
+            (2, Some(p)) => Some(([p.points[6], p.points[7], p.points[8], p.points[9]], [&p.colors[2], &p.colors[3]])),
+            (_, Some(p)) => Some(([p.points[9], p.points[10], p.points[11], p.points[0]], [&p.colors[3], &p.colors[0]])),
+        };
+        let mut points = [Vector2F::zero(); 12];
+        let (first, colors) = match shared {
+            Some((edge, [c0, c1])) => {
+                points[..4].copy_from_slice(&edge);
+                (4, vec![c0.clone(), c1.clone()])
+            }
+            None => (0, vec![]),
+        };
+        let patch = (|| {
+            for p in &mut points[first..] {
+                *p = format.read_point(&mut reader)?;
+            }
+            for _ in 0..inner {
+                format.read_point(&mut reader)?;
+            }
+            let mut colors = colors;
+            while colors.len() < 4 {
+                colors.push(format.read_color(&mut reader)?);
+            }
+            let colors: [Vec<f32>; 4] = colors.try_into().ok()?;
+            Some(Patch { points, colors })
+        })();
+        let Some(patch) = patch else {
+            break;
+        };
+        reader.align();
+        patches.push(patch);
+    }
+    patches
+}
+
+fn cubic(p: [Vector2F; 4], t: f32) -> Vector2F {
+    let s = 1. - t;
+    p[0] * (s * s * s) + p[1] * (3. * s * s * t) + p[2] * (3. * s * t * t) + p[3] * (t * t * t)
+}
+
+impl Patch {
+    // the point at (u, v), u runs from point 0 to 3 and v from point 0 to 9
+    fn point(&self, u: f32, v: f32) -> Vector2F {
+        let p = &self.points;
+        let bottom = cubic([p[0], p[1], p[2], p[3]], u);
+        let top = cubic([p[9], p[8], p[7], p[6]], u);
+        let left = cubic([p[0], p[11], p[10], p[9]], v);
+        let right = cubic([p[3], p[4], p[5], p[6]], v);
+        let corners = p[0] * ((1. - u) * (1. - v)) + p[3] * (u * (1. - v)) + p[9] * ((1. - u) * v) + p[6] * (u * v);
+        bottom * (1. - v) + top * v + left * (1. - u) + right * u - corners
+    }
+    fn color(&self, u: f32, v: f32) -> Vec<f32> {
+        let c = &self.colors;
+        mix(&mix(&c[0], &c[1], u), &mix(&c[3], &c[2], u), v)
+    }
+}
+
+// A flat shaded piece of a mesh.
+pub struct Piece {
+    pub points: Vec<Vector2F>,
+    pub color: Vec<f32>,
+}
+
+// Split the triangle until the colors of its corners are closer than the threshold.
+pub fn tessellate_triangle(triangle: &[Vertex; 3], threshold: f32, out: &mut Vec<Piece>) {
+    subdivide(triangle.clone(), threshold, 0, out);
+}
+
+fn subdivide(t: [Vertex; 3], threshold: f32, depth: u32, out: &mut Vec<Piece>) {
+    let delta = color_delta(t.iter().map(|v| v.color.as_slice()));
+    if delta <= threshold || depth == MAX_DEPTH {
+        let color = mix(&mix(&t[0].color, &t[1].color, 0.5), &t[2].color, 1. / 3.);
+        out.push(Piece {
+            points: t.iter().map(|v| v.point).collect(),
+            color,
+        });
+        return;
+    }
+    let [a, b, c] = t;
+    let (ab, bc, ca) = (a.mix(&b, 0.5), b.mix(&c, 0.5), c.mix(&a, 0.5));
+    subdivide([a, ab.clone(), ca.clone()], threshold, depth + 1, out);
+    subdivide([ab.clone(), b, bc.clone()], threshold, depth + 1, out);
+    subdivide([ca.clone(), bc.clone(), c], threshold, depth + 1, out);
+    subdivide([ab, bc, ca], threshold, depth + 1, out);
+}
+
+// Cut the patch into a grid of cells fine enough for the color change.
+pub fn tessellate_patch(patch: &Patch, threshold: f32, out: &mut Vec<Piece>) {
+    let delta = color_delta(patch.colors.iter().map(|c| c.as_slice()));
+    let n = ((delta / threshold).ceil() as usize).clamp(4, MAX_SEGMENTS);
+    let step = 1. / n as f32;
+    for j in 0..n {
+        for i in 0..n {
+            let (u, v) = (i as f32 * step, j as f32 * step);
+            out.push(Piece {
+                points: vec![
+                    patch.point(u, v),
+                    patch.point(u + step, v),
+                    patch.point(u + step, v + step),
+                    patch.point(u, v + step),
+                ],
+                color: patch.color(u + 0.5 * step, v + 0.5 * step),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pathfinder_geometry::vector::Vector2F;
+
+    use super::{free_form_triangles, patches, tessellate_triangle, BitReader, MeshFormat, Vertex};
+
+    fn format(bits_per_coordinate: u32, bits_per_component: u32) -> MeshFormat {
+        MeshFormat {
+            bits_per_coordinate,
+            bits_per_component,
+            bits_per_flag: 8,
+            decode: vec![0., 100., 0., 100., 0., 1.],
+        }
+    }
+
+    fn push(data: &mut Vec<u8>, value: u32, bits: u32) {
+        data.extend_from_slice(&value.to_be_bytes()[4 - bits as usize / 8..]);
+    }
+
+    #[test]
+    fn bit_fields() {
+        let mut reader = BitReader::new(&[0b1010_0000, 0xff, 0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(reader.read(3), Some(0b101));
+        reader.align();
+        assert_eq!(reader.read(8), Some(0xff));
+        assert_eq!(reader.read(32), Some(0x1234_5678));
+        assert_eq!(reader.read(1), None);
+    }
+
+    #[test]
+    fn coordinate_sizes() {
+        for bits in [16, 24, 32] {
+            let mut data = vec![];
+            let max = ((1u64 << bits) - 1) as u32;
+            for (flag, x, y) in [(0, 0, 0), (0, max, 0), (0, max, max), (1, 0, max)] {
+                data.push(flag);
+                push(&mut data, x, bits);
+                push(&mut data, y, bits);
+                push(&mut data, 0x7fff, 16);
+            }
+            let triangles = free_form_triangles(&data, &format(bits, 16));
+            assert_eq!(triangles.len(), 2, "{} bits", bits);
+            assert_eq!(triangles[0][1].point, Vector2F::new(100., 0.));
+            assert_eq!(triangles[1][2].point, Vector2F::new(0., 100.));
+            assert!((triangles[0][0].color[0] - 0.5).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn shared_patch_edge() {
+        let mut data = vec![0];
+        for i in 0..12 {
+            push(&mut data, i * 1000, 16);
+            push(&mut data, i * 2000, 16);
+        }
+        for c in [0, 100, 200, 255] {
+            data.push(c);
+        }
+        data.push(2);
+        for i in 0..8 {
+            push(&mut data, i, 16);
+            push(&mut data, i, 16);
+        }
+        data.extend_from_slice(&[10, 20]);
+        let patches = patches(&data, &format(16, 8), false);
+        assert_eq!(patches.len(), 2);
+        // flag 2 continues on the edge from point 6 to 9
+        assert_eq!(patches[1].points[0], patches[0].points[6]);
+        assert_eq!(patches[1].points[3], patches[0].points[9]);
+        assert_eq!(patches[1].colors[0], patches[0].colors[2]);
+        assert_eq!(patches[1].colors[1], patches[0].colors[3]);
+    }
+
+    #[test]
+    fn subdivision() {
+        let vertex = |x, y, c| Vertex { point: Vector2F::new(x, y), color: vec![c] };
+        let triangle = [vertex(0., 0., 0.), vertex(10., 0., 1.), vertex(0., 10., 0.)];
+        let mut pieces = vec![];
+        tessellate_triangle(&triangle, 0.3, &mut pieces);
+        assert_eq!(pieces.len(), 16);
+        let mut flat = vec![];
+        tessellate_triangle(&[vertex(0., 0., 0.5), vertex(1., 0., 0.5), vertex(0., 1., 0.5)], 0.3, &mut flat);
+        assert_eq!(flat.len(), 1);
+    }
+}
@@ -0,0 +1,66 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use pdf::PdfError;
+use serde::Serialize;
+
+use crate::flatten::LineStats;
+use crate::render::RenderStats;
+use crate::timings::Timings;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PageMetadata {
+    pub page: u32,
+    pub width: u32,
+    pub height: u32,
+    pub dpi: f32,
+    pub page_box: &'static str,
+    pub rotation: i32,
+    pub group_color_space: Option<String>,
+    pub warnings: Vec<String>,
+    pub stats: RenderStats,
+    // segment counts and deviations of hpgl output
+    pub lines: Option<LineStats>,
+    pub timings: Timings,
+}
+
+impl PageMetadata {
+    pub fn write(&self, file: &Path) -> Result<(), PdfError> {
+        write_json(self, file)
+    }
+}
+
+// the metadata of the converted pages of --pages as one array
+pub fn write_pages(pages: &[&PageMetadata], file: &Path) -> Result<(), PdfError> {
+    write_json(&pages, file)
+}
+
+pub fn write_json(value: &impl Serialize, file: &Path) -> Result<(), PdfError> {
+    let writer = BufWriter::new(File::create(file).map_err(|e| PdfError::Other {
+        msg: format!("can not create metadata file {:?}: {}", file, e),
+    })?);
+    serde_json::to_writer_pretty(writer, value).map_err(|e| PdfError::Other {
+        msg: format!("can not write metadata file {:?}: {}", file, e),
+    })
+}
+
+// Provenance embedded into the written image, kept free of timestamps and
+// absolute paths so the output stays reproducible.
+#[derive(Debug, Clone)]
+pub struct OutputMetadata {
+    pub source: String,
+    pub page: u32,
+    pub dpi: f32,
+    pub options: String,
+}
+
+impl OutputMetadata {
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("Software", format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))),
+            ("Source", self.source.clone()),
+            ("Page", self.page.to_string()),
+            ("DPI", self.dpi.to_string()),
+            ("Options", self.options.clone()),
+        ]
+    }
+}
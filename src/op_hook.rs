@@ -0,0 +1,39 @@
+use pathfinder_geometry::transform2d::Transform2F;
+use pdf::content::Op;
+
+use crate::plotter::Fill;
+
+// what happens to the operator a hook has seen
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HookAction {
+    Continue,
+    // the operator is not executed, a skipped painting operator ends the path like n
+    Skip,
+}
+
+// read only copy of the state before the operator
+#[derive(Debug, Clone)]
+pub struct HookState {
+    pub page: u32,
+    // 0 for the page, 1 for a form drawn by it and so on
+    pub depth: usize,
+    pub transform: Transform2F,
+    pub fill_color: Fill,
+    pub stroke_color: Fill,
+    pub text_matrix: Transform2F,
+    pub in_text: bool,
+}
+
+// Called before every operator of the page, its forms and annotations.
+// The index is the depth first number of the operator, as in --stop-at-op.
+// To abort, keep the condition in the hook and skip everything after it.
+// Only one page at a time is in the hook, and it must not render with the
+// options it is installed in.
+pub trait OpHook: Send {
+    fn on_op(&mut self, index: usize, op: &Op, state: &HookState) -> HookAction;
+}
+
+// does the operator paint the current path
+pub fn paints_path(op: &Op) -> bool {
+    matches!(op, Op::Stroke | Op::Fill { .. } | Op::FillAndStroke { .. })
+}
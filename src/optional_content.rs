@@ -0,0 +1,328 @@
+use std::collections::HashSet;
+
+use pdf::{
+    object::{PlainRef, Resolve},
+    primitive::{Dictionary, Primitive},
+    PdfError,
+};
+
+// Visibility of the optional content groups, identified by their object reference.
+#[derive(Debug, Clone, Default)]
+pub struct OcState {
+    off: HashSet<PlainRef>,
+}
+
+impl OcState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn set(&mut self, ocg: PlainRef, on: bool) {
+        if on {
+            self.off.remove(&ocg);
+        } else {
+            self.off.insert(ocg);
+        }
+    }
+    pub fn is_on(&self, ocg: PlainRef) -> bool {
+        !self.off.contains(&ocg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisibilityPolicy {
+    AnyOn,
+    AllOn,
+    AnyOff,
+    AllOff,
+}
+
+impl VisibilityPolicy {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "AnyOn" => Some(VisibilityPolicy::AnyOn),
+            "AllOn" => Some(VisibilityPolicy::AllOn),
+            "AnyOff" => Some(VisibilityPolicy::AnyOff),
+            "AllOff" => Some(VisibilityPolicy::AllOff),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VisibilityExpr {
+    Group(PlainRef),
+    And(Vec<VisibilityExpr>),
+    Or(Vec<VisibilityExpr>),
+    Not(Box<VisibilityExpr>),
+}
+
+impl VisibilityExpr {
+    // [/And|/Or|/Not operand...] where operands are OCG references or nested arrays
+    pub fn from_primitive(p: &Primitive) -> Result<Self, PdfError> {
+        match *p {
+            Primitive::Reference(r) => Ok(VisibilityExpr::Group(r)),
+            Primitive::Array(ref parts) => Self::from_parts(parts),
+            ref p => Err(PdfError::Other {
+                msg: format!("invalid visibility expression {:?}", p),
+            }),
+        }
+    }
+    fn from_parts(parts: &[Primitive]) -> Result<Self, PdfError> {
+        let (op, operands) = parts.split_first().ok_or_else(|| PdfError::Other {
+            msg: "empty visibility expression".into(),
+        })?;
+        let operands = operands
+            .iter()
+            .map(Self::from_primitive)
+            .collect::<Result<Vec<_>, _>>()?;
+        match op.as_name()? {
+            "And" => Ok(VisibilityExpr::And(operands)),
+            "Or" => Ok(VisibilityExpr::Or(operands)),
+            "Not" => match <[_; 1]>::try_from(operands) {
+                Ok([operand]) => Ok(VisibilityExpr::Not(Box::new(operand))),
+                Err(operands) => Err(PdfError::Other {
+                    msg: format!("Not expects one operand, got {}", operands.len()),
+                }),
+            },
+            op => Err(PdfError::Other {
+                msg: format!("unknown visibility operator {}", op),
+            }),
+        }
+    }
+    pub fn is_visible(&self, state: &OcState) -> bool {
+        match *self {
+            VisibilityExpr::Group(r) => state.is_on(r),
+            VisibilityExpr::And(ref e) => e.iter().all(|e| e.is_visible(state)),
+            VisibilityExpr::Or(ref e) => e.iter().any(|e| e.is_visible(state)),
+            VisibilityExpr::Not(ref e) => !e.is_visible(state),
+        }
+    }
+}
+
+// optional content membership dictionary (/Type /OCMD)
+#[derive(Debug, Clone)]
+pub struct Membership {
+    pub ocgs: Vec<PlainRef>,
+    pub policy: VisibilityPolicy,
+    pub expr: Option<VisibilityExpr>,
+}
+
+impl Membership {
+    pub fn from_dict(dict: &Dictionary, resolve: &impl Resolve) -> Result<Self, PdfError> {
+        let ocgs = match dict.get("OCGs") {
+            Some(&Primitive::Reference(r)) => match resolve.resolve(r)? {
+                Primitive::Array(ref refs) => refs.iter().filter_map(|p| p.as_reference().ok()).collect(),
+                _ => vec![r],
+            },
+            Some(Primitive::Array(ref refs)) => refs.iter().filter_map(|p| p.as_reference().ok()).collect(),
+            _ => vec![],
+        };
+        let policy = match dict.get("P") {
+            Some(p) => VisibilityPolicy::from_name(p.as_name()?).unwrap_or(VisibilityPolicy::AnyOn),
+            None => VisibilityPolicy::AnyOn,
+        };
+        let expr = match dict.get("VE") {
+            Some(p) => Some(VisibilityExpr::from_primitive(p)?),
+            None => None,
+        };
+        Ok(Membership { ocgs, policy, expr })
+    }
+    // /VE takes precedence over /OCGs and /P
+    pub fn is_visible(&self, state: &OcState) -> bool {
+        if let Some(ref expr) = self.expr {
+            return expr.is_visible(state);
+        }
+        if self.ocgs.is_empty() {
+            return true;
+        }
+        let mut on = self.ocgs.iter().map(|&r| state.is_on(r));
+        match self.policy {
+            VisibilityPolicy::AnyOn => on.any(|on| on),
+            VisibilityPolicy::AllOn => on.all(|on| on),
+            VisibilityPolicy::AnyOff => on.any(|on| !on),
+            VisibilityPolicy::AllOff => on.all(|on| !on),
+        }
+    }
+}
+
+// resolves a possibly indirect object, None for null and missing objects
+fn resolved(p: Option<&Primitive>, resolve: &impl Resolve) -> Result<Option<Primitive>, PdfError> {
+    match p {
+        Some(&Primitive::Reference(r)) => Ok(Some(resolve.resolve(r)?)),
+        Some(Primitive::Null) | None => Ok(None),
+        Some(p) => Ok(Some(p.clone())),
+    }
+}
+
+fn refs(p: Option<&Primitive>, resolve: &impl Resolve) -> Result<Vec<PlainRef>, PdfError> {
+    Ok(match resolved(p, resolve)? {
+        Some(Primitive::Array(ref refs)) => refs.iter().filter_map(|p| p.as_reference().ok()).collect(),
+        _ => vec![],
+    })
+}
+
+// The optional content groups (layers) of a document and which of them are shown.
+#[derive(Debug, Clone, Default)]
+pub struct OptionalContent {
+    groups: Vec<(PlainRef, String)>,
+    state: OcState,
+}
+
+impl OptionalContent {
+    // /OCProperties of the catalog with the visibility of the default configuration /D
+    pub fn from_catalog(catalog: &Dictionary, resolve: &impl Resolve) -> Result<Self, PdfError> {
+        let Some(properties) = resolved(catalog.get("OCProperties"), resolve)? else {
+            return Ok(Self::default());
+        };
+        let properties = properties.into_dictionary()?;
+        let mut groups = vec![];
+        for r in refs(properties.get("OCGs"), resolve)? {
+            let group = resolve.resolve(r)?.into_dictionary()?;
+            let name = match group.get("Name") {
+                Some(name) => name.as_string()?.to_string_lossy(),
+                None => format!("{} {} R", r.id, r.gen),
+            };
+            groups.push((r, name));
+        }
+        let mut state = OcState::new();
+        if let Some(config) = resolved(properties.get("D"), resolve)? {
+            let config = config.into_dictionary()?;
+            if config.get("BaseState").and_then(|b| b.as_name().ok()) == Some("OFF") {
+                for &(r, _) in &groups {
+                    state.set(r, false);
+                }
+            }
+            for r in refs(config.get("ON"), resolve)? {
+                state.set(r, true);
+            }
+            for r in refs(config.get("OFF"), resolve)? {
+                state.set(r, false);
+            }
+        }
+        Ok(OptionalContent { groups, state })
+    }
+    // the layer names and whether they are shown, in the order of /OCGs
+    pub fn layers(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.groups.iter().map(|(r, name)| (name.as_str(), self.state.is_on(*r)))
+    }
+    // Override the default visibility. With `layers` only the named layers are
+    // shown, `exclude` hides layers. Names that match no layer are returned.
+    pub fn select(&mut self, layers: Option<&[String]>, exclude: &[String]) -> Vec<String> {
+        let unknown = layers
+            .into_iter()
+            .flatten()
+            .chain(exclude)
+            .filter(|&name| !self.groups.iter().any(|(_, n)| n == name))
+            .cloned()
+            .collect();
+        for &(r, ref name) in &self.groups {
+            if let Some(layers) = layers {
+                self.state.set(r, layers.contains(name));
+            }
+            if exclude.contains(name) {
+                self.state.set(r, false);
+            }
+        }
+        unknown
+    }
+    // /OC of marked content or an xobject, an OCG or a membership dictionary
+    pub fn is_visible(&self, oc: &Primitive, resolve: &impl Resolve) -> Result<bool, PdfError> {
+        let (group, dict) = match *oc {
+            Primitive::Reference(r) => (Some(r), resolve.resolve(r)?.into_dictionary()?),
+            Primitive::Dictionary(ref dict) => (None, dict.clone()),
+            ref p => {
+                return Err(PdfError::Other {
+                    msg: format!("invalid optional content {:?}", p),
+                })
+            }
+        };
+        match (dict.get("Type").and_then(|t| t.as_name().ok()), group) {
+            (Some("OCMD"), _) => Ok(Membership::from_dict(&dict, resolve)?.is_visible(&self.state)),
+            (_, Some(r)) => Ok(self.state.is_on(r)),
+            // a group that is not an indirect object can not be switched
+            (_, None) => Ok(true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pdf::{
+        object::PlainRef,
+        primitive::Primitive,
+    };
+
+    use super::{Membership, OcState, OptionalContent, VisibilityExpr, VisibilityPolicy};
+
+    fn r(id: u64) -> PlainRef {
+        PlainRef { id, gen: 0 }
+    }
+
+    fn state() -> OcState {
+        let mut state = OcState::new();
+        state.set(r(2), false);
+        state
+    }
+
+    fn membership(policy: VisibilityPolicy, ocgs: &[u64]) -> Membership {
+        Membership {
+            ocgs: ocgs.iter().map(|&id| r(id)).collect(),
+            policy,
+            expr: None,
+        }
+    }
+
+    #[test]
+    fn policies() {
+        let state = state();
+        // group 1 is on, group 2 is off
+        assert!(membership(VisibilityPolicy::AnyOn, &[1, 2]).is_visible(&state));
+        assert!(!membership(VisibilityPolicy::AnyOn, &[2]).is_visible(&state));
+        assert!(!membership(VisibilityPolicy::AllOn, &[1, 2]).is_visible(&state));
+        assert!(membership(VisibilityPolicy::AllOn, &[1]).is_visible(&state));
+        assert!(membership(VisibilityPolicy::AnyOff, &[1, 2]).is_visible(&state));
+        assert!(!membership(VisibilityPolicy::AnyOff, &[1]).is_visible(&state));
+        assert!(membership(VisibilityPolicy::AllOff, &[2]).is_visible(&state));
+        assert!(!membership(VisibilityPolicy::AllOff, &[1, 2]).is_visible(&state));
+    }
+
+    #[test]
+    fn select_layers() {
+        let mut content = OptionalContent {
+            groups: vec![(r(1), "a".into()), (r(2), "b".into()), (r(3), "c".into())],
+            state: state(),
+        };
+        let names = |n: &[&str]| n.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let unknown = content.select(Some(&names(&["b", "c", "d"])), &names(&["c", "e"]));
+        assert_eq!(unknown, ["d", "e"]);
+        let on: Vec<bool> = content.layers().map(|(_, on)| on).collect();
+        assert_eq!(on, [false, true, false]);
+    }
+
+    #[test]
+    fn nested_expression() {
+        let name = |n: &str| Primitive::Name(n.into());
+        // [/And 1 0 R [/Not [/Or 2 0 R 3 0 R]]]
+        let ve = Primitive::Array(vec![
+            name("And"),
+            Primitive::Reference(r(1)),
+            Primitive::Array(vec![
+                name("Not"),
+                Primitive::Array(vec![name("Or"), Primitive::Reference(r(2)), Primitive::Reference(r(3))]),
+            ]),
+        ]);
+        let expr = VisibilityExpr::from_primitive(&ve).unwrap();
+        let mut state = state();
+        state.set(r(3), false);
+        assert!(expr.is_visible(&state));
+        state.set(r(3), true);
+        assert!(!expr.is_visible(&state));
+
+        // /VE wins over the policy
+        let m = Membership {
+            expr: Some(expr),
+            ..membership(VisibilityPolicy::AllOn, &[1])
+        };
+        assert!(!m.is_visible(&state));
+    }
+}
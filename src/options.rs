@@ -0,0 +1,259 @@
+use std::{io::Write, path::PathBuf, sync::Mutex};
+
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F};
+use pdf::content::Op;
+
+use crate::geometry::{Coords, Placement};
+use crate::hpgl::LineOptions;
+use crate::image_cache::{ImageCache, IMAGE_CACHE_BYTES};
+use crate::op_hook::{HookAction, HookState, OpHook};
+use crate::png::{PngFilter, SceneLimits, IMAGE_DOWNSAMPLE};
+use crate::svg::SvgSize;
+
+// report progress only every PROGRESS_INTERVAL operators
+pub const PROGRESS_INTERVAL: usize = 256;
+
+// the first page of another file drawn on top of the page
+#[derive(Debug, Clone)]
+pub struct Stamp {
+    pub file: PathBuf,
+    pub placement: Placement,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub page: u32,
+    pub pages: u32,
+    pub ops: usize,
+    pub total_ops: usize,
+}
+
+pub struct RenderOptions {
+    on_progress: Option<Box<dyn Fn(Progress) + Send + Sync>>,
+    // every visited operator is written here, before it is executed
+    dump_ops: Mutex<Option<Box<dyn Write + Send>>>,
+    // write operators, draw calls, images and a manifest of the conversion here
+    pub debug_dump: Option<PathBuf>,
+    // sees every operator before it is executed and may skip it
+    op_hook: Mutex<Option<Box<dyn OpHook>>>,
+    // write source file, page and settings into the output image
+    pub embed_metadata: bool,
+    // user transform, see geometry::PageGeometry for the order of application
+    pub transform: Transform2F,
+    pub transform_view_box: bool,
+    // stop after this operator, counted depth first over the page and its forms
+    pub stop_at_op: Option<usize>,
+    pub svg_size: SvgSize,
+    pub png_filter: Option<PngFilter>,
+    // write pages without color as gray png
+    pub grayscale_if_monochrome: bool,
+    // png output box filters images with more pixels than this per device pixel, 0 keeps them
+    pub image_downsample: f32,
+    // leave what changes from run to run out of the page metadata: the timings
+    // and the image cache hits. Nothing is randomized, so there is no seed.
+    pub reproducible: bool,
+    // decoded images, kept across the pages converted with these options
+    pub image_cache: ImageCache,
+    // png pages with larger scenes are rendered in tiles
+    pub scene_limits: SceneLimits,
+    // read the structure tree for --info even without the extract permission
+    pub ignore_permissions: bool,
+    // convert cmyk colors so that blending matches a cmyk page group
+    pub group_aware_blending: bool,
+    // put the edges of axis aligned rectangle fills on the device pixel grid
+    pub snap_rects: bool,
+    // strokes thinner than this many device pixels are widened to it
+    pub min_stroke_px: Option<f32>,
+    // device space width of 0 width strokes, None for the default of the plotter
+    pub hairline_width: Option<f32>,
+    // path points are kept within this many view box sizes around the view box
+    pub coord_limit: f32,
+    // forms, patterns and soft masks executed inside each other
+    pub max_depth: usize,
+    // names of the layers to show, None for the default visibility of the document
+    pub layers: Option<Vec<String>>,
+    pub exclude_layers: Vec<String>,
+    pub stamp: Option<Stamp>,
+    // rectangles marked on the page, in the convention of highlight_coords
+    pub highlights: Vec<RectF>,
+    pub highlight_coords: Coords,
+    // curve flattening of the hpgl output
+    pub line: LineOptions,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            on_progress: None,
+            dump_ops: Mutex::new(None),
+            debug_dump: None,
+            op_hook: Mutex::new(None),
+            embed_metadata: true,
+            transform: Transform2F::default(),
+            transform_view_box: false,
+            stop_at_op: None,
+            svg_size: SvgSize::default(),
+            png_filter: None,
+            grayscale_if_monochrome: false,
+            image_downsample: IMAGE_DOWNSAMPLE,
+            reproducible: false,
+            image_cache: ImageCache::new(IMAGE_CACHE_BYTES),
+            scene_limits: SceneLimits::default(),
+            ignore_permissions: false,
+            group_aware_blending: false,
+            snap_rects: false,
+            min_stroke_px: None,
+            hairline_width: None,
+            coord_limit: 100.,
+            max_depth: 32,
+            layers: None,
+            exclude_layers: vec![],
+            stamp: None,
+            highlights: vec![],
+            highlight_coords: Coords::default(),
+            line: LineOptions::default(),
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn embed_metadata(mut self, embed: bool) -> Self {
+        self.embed_metadata = embed;
+        self
+    }
+    pub fn transform(mut self, transform: Transform2F, transform_view_box: bool) -> Self {
+        self.transform = transform;
+        self.transform_view_box = transform_view_box;
+        self
+    }
+    pub fn ignore_permissions(mut self, ignore: bool) -> Self {
+        self.ignore_permissions = ignore;
+        self
+    }
+    pub fn group_aware_blending(mut self, enable: bool) -> Self {
+        self.group_aware_blending = enable;
+        self
+    }
+    pub fn snap_rects(mut self, snap: bool) -> Self {
+        self.snap_rects = snap;
+        self
+    }
+    pub fn min_stroke_px(mut self, px: Option<f32>) -> Self {
+        self.min_stroke_px = px;
+        self
+    }
+    pub fn hairline_width(mut self, width: Option<f32>) -> Self {
+        self.hairline_width = width;
+        self
+    }
+    pub fn coord_limit(mut self, limit: f32) -> Self {
+        self.coord_limit = limit;
+        self
+    }
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+    pub fn layers(mut self, layers: Option<Vec<String>>, exclude: Vec<String>) -> Self {
+        self.layers = layers;
+        self.exclude_layers = exclude;
+        self
+    }
+    pub fn stamp(mut self, file: PathBuf, placement: Placement) -> Self {
+        self.stamp = Some(Stamp { file, placement });
+        self
+    }
+    pub fn highlights(mut self, rects: Vec<RectF>, coords: Coords) -> Self {
+        self.highlights = rects;
+        self.highlight_coords = coords;
+        self
+    }
+    pub fn line_options(mut self, line: LineOptions) -> Self {
+        self.line = line;
+        self
+    }
+    pub fn svg_size(mut self, size: SvgSize) -> Self {
+        self.svg_size = size;
+        self
+    }
+    pub fn png_filter(mut self, filter: Option<PngFilter>) -> Self {
+        self.png_filter = filter;
+        self
+    }
+    pub fn grayscale_if_monochrome(mut self, enable: bool) -> Self {
+        self.grayscale_if_monochrome = enable;
+        self
+    }
+    pub fn reproducible(mut self, enable: bool) -> Self {
+        self.reproducible = enable;
+        self
+    }
+    // 0 decodes every image at each Do
+    pub fn image_cache_bytes(mut self, bytes: usize) -> Self {
+        self.image_cache = ImageCache::new(bytes);
+        self
+    }
+    pub fn image_downsample(mut self, factor: f32) -> Self {
+        self.image_downsample = factor;
+        self
+    }
+    pub fn scene_limits(mut self, limits: SceneLimits) -> Self {
+        self.scene_limits = limits;
+        self
+    }
+    pub fn stop_at_op(mut self, op: usize) -> Self {
+        self.stop_at_op = Some(op);
+        self
+    }
+    pub fn on_progress(mut self, f: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(Box::new(f));
+        self
+    }
+    pub fn dump_ops(self, writer: impl Write + Send + 'static) -> Self {
+        self.set_dump_ops(Some(Box::new(writer)));
+        self
+    }
+    // replaces the writer of a conversion that is under way, the old one is dropped
+    pub fn set_dump_ops(&self, writer: Option<Box<dyn Write + Send>>) {
+        *self.dump_ops.lock().unwrap() = writer;
+    }
+    pub fn debug_dump(mut self, dir: Option<PathBuf>) -> Self {
+        self.debug_dump = dir;
+        self
+    }
+    pub fn op_hook(self, hook: impl OpHook + 'static) -> Self {
+        *self.op_hook.lock().unwrap() = Some(Box::new(hook));
+        self
+    }
+    // The state is only built when there is a hook. The hook runs with its lock
+    // held: one that renders with these options deadlocks, and pages rendered
+    // in parallel take turns. It stays in place so that no page misses it.
+    pub fn hook_op(&self, index: usize, op: &Op, state: impl FnOnce() -> HookState) -> HookAction {
+        match *self.op_hook.lock().unwrap() {
+            Some(ref mut hook) => hook.on_op(index, op, &state()),
+            None => HookAction::Continue,
+        }
+    }
+    pub fn dumps_ops(&self) -> bool {
+        self.dump_ops.lock().unwrap().is_some()
+    }
+    // The operators of one page as a block under a page line, so pages rendered
+    // at the same time do not mix theirs.
+    pub fn dump_page_ops(&self, page: u32, ops: &str) {
+        let mut dump = self.dump_ops.lock().unwrap();
+        if let Some(ref mut writer) = *dump {
+            if let Err(e) = write!(writer, "page {}\n{}", page, ops) {
+                warn!("failed to dump operators: {}", e);
+                *dump = None;
+            }
+        }
+    }
+    pub fn progress(&self, progress: Progress) {
+        if let Some(ref f) = self.on_progress {
+            f(progress);
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::io::Write;
+
+use pathfinder_content::{fill::FillRule, outline::Outline};
+use pathfinder_export::FileFormat;
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F};
+use pdf::PdfError;
+
+use crate::flatten::LineStats;
+use crate::hpgl::HpglPlotter;
+use crate::metadata::OutputMetadata;
+use crate::options::RenderOptions;
+use crate::plotter::{BlendMode, DrawMode, LinearGradient, Plotter, RgbaImage, Tiling};
+use crate::png::PngPlotter;
+use crate::structure::McContext;
+use crate::timings::Timings;
+use crate::vector_plotter::{self, VectorPlotter};
+
+// One call of the renderer to its plotter. Clip paths are numbered in the
+// order they were created.
+pub enum DrawCall {
+    Draw { outline: Outline, mode: DrawMode, fill_rule: FillRule, transform: Transform2F, clip: Option<usize> },
+    ClipPath { outline: Outline, fill_rule: FillRule, parent: Option<usize> },
+    Image { image: RgbaImage, transform: Transform2F, clip: Option<usize>, alpha: f32, smoothing: bool },
+    Layer { image: RgbaImage, transform: Transform2F, mode: BlendMode, clip: Option<usize> },
+    Gradient { outline: Outline, gradient: LinearGradient, clip: Option<usize> },
+    Tiling { outline: Outline, fill_rule: FillRule, tiling: Tiling, clip: Option<usize> },
+    MarkedContent(McContext),
+    BeginOp(usize),
+}
+
+// A rendered page as the draw calls of the renderer, the output formats play
+// it back into their own plotter.
+pub struct DrawStream {
+    pub view_box: RectF,
+    // device units per inch
+    pub dpi: f32,
+    pub metadata: Option<OutputMetadata>,
+    pub calls: Vec<DrawCall>,
+    hairline_width: f32,
+    clips: usize,
+}
+
+impl DrawStream {
+    pub fn new(view_box: RectF, dpi: f32, hairline_width: f32, metadata: Option<OutputMetadata>) -> Self {
+        DrawStream { view_box, dpi, metadata, calls: vec![], hairline_width, clips: 0 }
+    }
+    pub fn replay<P: Plotter>(&self, plotter: &mut P) {
+        let mut clips: Vec<P::ClipPathId> = Vec::with_capacity(self.clips);
+        let clip = |id: &Option<usize>, clips: &[P::ClipPathId]| id.map(|id| clips[id]);
+        for call in &self.calls {
+            match call {
+                DrawCall::Draw { outline, mode, fill_rule, transform, clip: id } => {
+                    plotter.draw(outline, mode, *fill_rule, *transform, clip(id, &clips))
+                }
+                DrawCall::ClipPath { outline, fill_rule, parent } => {
+                    let id = plotter.create_clip_path(outline.clone(), *fill_rule, clip(parent, &clips));
+                    clips.push(id);
+                }
+                DrawCall::Image { image, transform, clip: id, alpha, smoothing } => {
+                    plotter.draw_image(image, *transform, clip(id, &clips), *alpha, *smoothing)
+                }
+                DrawCall::Layer { image, transform, mode, clip: id } => plotter.draw_layer(image, *transform, *mode, clip(id, &clips)),
+                DrawCall::Gradient { outline, gradient, clip: id } => plotter.draw_gradient(outline.clone(), gradient, clip(id, &clips)),
+                DrawCall::Tiling { outline, fill_rule, tiling, clip: id } => {
+                    plotter.draw_tiling(outline.clone(), *fill_rule, tiling, clip(id, &clips))
+                }
+                DrawCall::MarkedContent(context) => plotter.marked_content(*context),
+                DrawCall::BeginOp(nr) => plotter.begin_op(*nr),
+            }
+        }
+    }
+}
+
+impl Plotter for DrawStream {
+    type ClipPathId = usize;
+    fn hairline_width(&self) -> f32 {
+        self.hairline_width
+    }
+    fn draw(&mut self, outline: &Outline, mode: &DrawMode, fill_rule: FillRule, transform: Transform2F, clip: Option<usize>) {
+        self.calls.push(DrawCall::Draw { outline: outline.clone(), mode: mode.clone(), fill_rule, transform, clip });
+    }
+    fn create_clip_path(&mut self, outline: Outline, fill_rule: FillRule, parent: Option<usize>) -> usize {
+        self.calls.push(DrawCall::ClipPath { outline, fill_rule, parent });
+        self.clips += 1;
+        self.clips - 1
+    }
+    fn draw_image(&mut self, image: &RgbaImage, transform: Transform2F, clip: Option<usize>, alpha: f32, smoothing: bool) {
+        self.calls.push(DrawCall::Image { image: image.clone(), transform, clip, alpha, smoothing });
+    }
+    fn draw_layer(&mut self, image: &RgbaImage, transform: Transform2F, mode: BlendMode, clip: Option<usize>) {
+        self.calls.push(DrawCall::Layer { image: image.clone(), transform, mode, clip });
+    }
+    fn draw_gradient(&mut self, outline: Outline, gradient: &LinearGradient, clip: Option<usize>) {
+        self.calls.push(DrawCall::Gradient { outline, gradient: gradient.clone(), clip });
+    }
+    fn draw_tiling(&mut self, outline: Outline, fill_rule: FillRule, tiling: &Tiling, clip: Option<usize>) {
+        self.calls.push(DrawCall::Tiling { outline, fill_rule, tiling: tiling.clone(), clip });
+    }
+    fn marked_content(&mut self, context: McContext) {
+        self.calls.push(DrawCall::MarkedContent(context));
+    }
+    fn begin_op(&mut self, nr: usize) {
+        self.calls.push(DrawCall::BeginOp(nr));
+    }
+}
+
+// what a format reports besides the bytes it wrote
+#[derive(Default)]
+pub struct Written {
+    pub timings: Timings,
+    pub warnings: Vec<String>,
+    // segment counts of line output
+    pub lines: Option<LineStats>,
+}
+
+// A writer for one kind of output file. Formats registered with a Converter
+// are picked by the extension of the output file.
+pub trait OutputFormat {
+    // lower case, without the dot
+    fn extensions(&self) -> &[&str];
+    // the page is rendered with this width for strokes of width 0, see Plotter
+    fn hairline_width(&self) -> f32 {
+        1.0
+    }
+    fn write(&self, page: &DrawStream, out: &mut dyn Write, options: &RenderOptions) -> Result<Written, PdfError>;
+}
+
+pub struct PngFormat;
+
+impl OutputFormat for PngFormat {
+    fn extensions(&self) -> &[&str] {
+        &["png"]
+    }
+    fn write(&self, page: &DrawStream, out: &mut dyn Write, options: &RenderOptions) -> Result<Written, PdfError> {
+        let mut plotter = PngPlotter::new(page.view_box).limits(options.scene_limits).downsample(options.image_downsample);
+        page.replay(&mut plotter);
+        let timings = plotter.write(out, page.metadata.as_ref(), options.png_filter, options.grayscale_if_monochrome)?;
+        Ok(Written { timings, warnings: plotter.degradation().into_iter().collect(), lines: None })
+    }
+}
+
+pub enum VectorFormat {
+    Svg,
+    Pdf,
+    Ps,
+}
+
+impl OutputFormat for VectorFormat {
+    fn extensions(&self) -> &[&str] {
+        match self {
+            VectorFormat::Svg => &["svg"],
+            VectorFormat::Pdf => &["pdf"],
+            VectorFormat::Ps => &["ps"],
+        }
+    }
+    fn hairline_width(&self) -> f32 {
+        vector_plotter::HAIRLINE_WIDTH
+    }
+    fn write(&self, page: &DrawStream, out: &mut dyn Write, options: &RenderOptions) -> Result<Written, PdfError> {
+        let format = match self {
+            VectorFormat::Svg => FileFormat::SVG,
+            VectorFormat::Pdf => FileFormat::PDF,
+            VectorFormat::Ps => FileFormat::PS,
+        };
+        let mut plotter = VectorPlotter::new(page.view_box);
+        page.replay(&mut plotter);
+        let timings = plotter.write(out, format, page.metadata.as_ref(), page.dpi, options.svg_size)?;
+        Ok(Written { timings, ..Written::default() })
+    }
+}
+
+pub struct HpglFormat;
+
+impl OutputFormat for HpglFormat {
+    fn extensions(&self) -> &[&str] {
+        &["hpgl", "plt"]
+    }
+    fn write(&self, page: &DrawStream, out: &mut dyn Write, options: &RenderOptions) -> Result<Written, PdfError> {
+        let mut plotter = HpglPlotter::new(page.view_box);
+        page.replay(&mut plotter);
+        let (timings, stats) = plotter.write(out, page.dpi, options.line)?;
+        Ok(Written { timings, warnings: vec![], lines: Some(stats) })
+    }
+}
+
+// the built-in formats, png is also used for unknown extensions
+pub fn builtin() -> Vec<Box<dyn OutputFormat>> {
+    vec![
+        Box::new(PngFormat),
+        Box::new(VectorFormat::Svg),
+        Box::new(VectorFormat::Pdf),
+        Box::new(VectorFormat::Ps),
+        Box::new(HpglFormat),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use pathfinder_content::{fill::FillRule, outline::Outline};
+    use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::vec2f};
+
+    use super::{DrawCall, DrawStream};
+    use crate::inspect::InspectPlotter;
+    use crate::plotter::{BlendMode, DrawMode, Fill, FillMode, Plotter};
+
+    #[test]
+    fn replay_maps_clip_paths() {
+        let mut stream = DrawStream::new(RectF::new(vec2f(0., 0.), vec2f(100., 100.)), 72., 1.0, None);
+        let rect = |x: f32| Outline::from_rect(RectF::new(vec2f(x, x), vec2f(10., 10.)));
+        let outer = stream.create_clip_path(rect(0.), FillRule::Winding, None);
+        let inner = stream.create_clip_path(rect(5.), FillRule::Winding, Some(outer));
+        assert_eq!((outer, inner), (0, 1));
+        let fill = DrawMode::Fill { fill: FillMode { color: Fill::black(), alpha: 1.0, mode: BlendMode::Normal } };
+        stream.begin_op(3);
+        stream.draw(&rect(0.), &fill, FillRule::Winding, Transform2F::default(), Some(inner));
+        assert!(matches!(stream.calls[3], DrawCall::Draw { clip: Some(1), .. }));
+
+        let mut plotter = InspectPlotter::new();
+        stream.replay(&mut plotter);
+        // only the corner inside both clips is painted
+        assert_eq!(plotter.hit_test(vec2f(7., 7.)).map(|d| d.op), Some(3));
+        assert!(plotter.hit_test(vec2f(2., 2.)).is_none());
+    }
+}
@@ -0,0 +1,115 @@
+use std::{collections::HashMap, hash::Hash};
+
+pub trait Weight {
+    // approximate memory use in bytes
+    fn weight(&self) -> usize;
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+    pub bytes: usize,
+}
+
+// Least recently used pages are dropped when the budget is exceeded, the page
+// that was touched last always stays. Other keys than page numbers work the
+// same, see image_cache.
+pub struct PageCache<V: Weight, K = u32> {
+    budget: usize,
+    entries: HashMap<K, V>,
+    // least recently used first
+    order: Vec<K>,
+    stats: CacheStats,
+}
+
+impl<V: Weight, K: Hash + Eq + Clone> PageCache<V, K> {
+    pub fn new(budget: usize) -> Self {
+        PageCache {
+            budget,
+            entries: HashMap::new(),
+            order: vec![],
+            stats: CacheStats::default(),
+        }
+    }
+    fn touch(&mut self, page: &K) {
+        self.order.retain(|p| p != page);
+        self.order.push(page.clone());
+    }
+    pub fn get(&mut self, page: K) -> Option<&mut V> {
+        if self.entries.contains_key(&page) {
+            self.stats.hits += 1;
+            self.touch(&page);
+            self.entries.get_mut(&page)
+        } else {
+            self.stats.misses += 1;
+            None
+        }
+    }
+    pub fn insert(&mut self, page: K, value: V) {
+        self.touch(&page);
+        self.entries.insert(page, value);
+        self.shrink();
+    }
+    // call after an entry returned by get() grew
+    pub fn shrink(&mut self) {
+        while self.order.len() > 1 && self.bytes() > self.budget {
+            let page = self.order.remove(0);
+            self.entries.remove(&page);
+            self.stats.evictions += 1;
+        }
+    }
+    fn bytes(&self) -> usize {
+        self.entries.values().map(|v| v.weight()).sum()
+    }
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            bytes: self.bytes(),
+            ..self.stats
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PageCache, Weight};
+
+    struct Bytes(usize);
+    impl Weight for Bytes {
+        fn weight(&self) -> usize {
+            self.0
+        }
+    }
+
+    #[test]
+    fn hits_and_evictions() {
+        let mut cache = PageCache::new(100);
+        cache.insert(0, Bytes(40));
+        cache.insert(1, Bytes(40));
+        assert!(cache.get(0).is_some());
+        // page 1 is the least recently used one now
+        cache.insert(2, Bytes(40));
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(0).is_some());
+        assert!(cache.get(2).is_some());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (3, 1, 1));
+        assert_eq!(stats.bytes, 80);
+    }
+
+    #[test]
+    fn growing_entry() {
+        let mut cache = PageCache::new(100);
+        cache.insert(0, Bytes(10));
+        cache.insert(1, Bytes(10));
+        cache.get(1).unwrap().0 = 95;
+        cache.shrink();
+        assert!(cache.get(0).is_none());
+        // a single entry over the budget is kept
+        cache.insert(2, Bytes(200));
+        assert!(cache.get(2).is_some());
+        assert_eq!(cache.stats().evictions, 2);
+    }
+}
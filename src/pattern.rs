@@ -0,0 +1,137 @@
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+use pdf::{
+    primitive::{Dictionary, Primitive},
+    PdfError,
+};
+
+// upper limit of the tile size in pixels, per side
+pub const MAX_TILE_SIZE: u32 = 1024;
+
+fn number(dict: &Dictionary, key: &str) -> Result<f32, PdfError> {
+    dict.get(key)
+        .ok_or_else(|| PdfError::Other {
+            msg: format!("tiling pattern without /{}", key),
+        })?
+        .as_number()
+}
+
+fn numbers<const N: usize>(p: &Primitive) -> Result<[f32; N], PdfError> {
+    let parts = p.as_array()?;
+    if parts.len() != N {
+        return Err(PdfError::Other {
+            msg: format!("expected {} numbers, got {:?}", N, p),
+        });
+    }
+    let mut out = [0.; N];
+    for (o, p) in out.iter_mut().zip(parts) {
+        *o = p.as_number()?;
+    }
+    Ok(out)
+}
+
+pub fn pattern_type(dict: &Dictionary) -> Result<i32, PdfError> {
+    dict.get("PatternType")
+        .ok_or_else(|| PdfError::Other {
+            msg: "pattern without /PatternType".into(),
+        })?
+        .as_integer()
+}
+
+// /PatternType 1, the content stream paints one cell that repeats every step
+#[derive(Debug, Clone)]
+pub struct TilingPattern {
+    // 1: the cell brings its own colors, 2: the cell is a stencil
+    pub paint_type: i32,
+    pub bbox: RectF,
+    pub step: Vector2F,
+    // pattern space to the default page space
+    pub matrix: Transform2F,
+}
+
+impl TilingPattern {
+    pub fn from_dict(dict: &Dictionary) -> Result<Self, PdfError> {
+        let paint_type = dict.get("PaintType").map_or(Ok(1), |p| p.as_integer())?;
+        let [x0, y0, x1, y1] = numbers(dict.get("BBox").ok_or_else(|| PdfError::Other {
+            msg: "tiling pattern without /BBox".into(),
+        })?)?;
+        let bbox = RectF::from_points(Vector2F::new(x0.min(x1), y0.min(y1)), Vector2F::new(x0.max(x1), y0.max(y1)));
+        let step = Vector2F::new(number(dict, "XStep")?, number(dict, "YStep")?);
+        if step.x() == 0. || step.y() == 0. {
+            return Err(PdfError::Other {
+                msg: format!("tiling pattern with a zero step {:?}", step),
+            });
+        }
+        let matrix = match dict.get("Matrix") {
+            Some(p) => {
+                let [a, b, c, d, e, f] = numbers(p)?;
+                Transform2F::row_major(a, c, e, b, d, f)
+            }
+            None => Transform2F::default(),
+        };
+        Ok(TilingPattern { paint_type, bbox, step, matrix })
+    }
+    // The unit square to one cell in pattern space. The cell starts at the
+    // bbox origin and is one step wide and high.
+    pub fn cell(&self) -> Transform2F {
+        Transform2F::from_translation(self.bbox.origin()) * Transform2F::from_scale(self.step.abs())
+    }
+    // pixel size of a cell, for the cell transform in device space
+    pub fn tile_size(cell: Transform2F) -> (u32, u32) {
+        let side = |v: Vector2F| (v.length().ceil() as u32).clamp(1, MAX_TILE_SIZE);
+        (side(cell.matrix * Vector2F::new(1., 0.)), side(cell.matrix * Vector2F::new(0., 1.)))
+    }
+    // Pattern space to the pixels of a tile, y down. Cells are drawn at these
+    // offsets as well, so content that reaches over the cell edges wraps around.
+    pub fn tile_transforms(&self, width: u32, height: u32) -> Vec<Transform2F> {
+        let step = self.step.abs();
+        let (sx, sy) = (width as f32 / step.x(), height as f32 / step.y());
+        let origin = self.bbox.origin();
+        let to_tile = Transform2F::row_major(sx, 0., -origin.x() * sx, 0., -sy, height as f32 + origin.y() * sy);
+        let mut transforms = vec![];
+        for j in -1..=1 {
+            for i in -1..=1 {
+                let offset = Vector2F::new(i as f32 * step.x(), j as f32 * step.y());
+                transforms.push(to_tile * Transform2F::from_translation(offset));
+            }
+        }
+        transforms
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+    use pdf::primitive::{Dictionary, Primitive};
+
+    use super::TilingPattern;
+
+    fn array(values: &[f32]) -> Primitive {
+        Primitive::Array(values.iter().map(|&v| Primitive::Number(v)).collect())
+    }
+
+    #[test]
+    fn cell_geometry() {
+        let mut dict = Dictionary::new();
+        dict.insert("PaintType", Primitive::Integer(1));
+        dict.insert("BBox", array(&[5., 5., 15., 25.]));
+        dict.insert("XStep", Primitive::Number(10.));
+        dict.insert("YStep", Primitive::Number(20.));
+        dict.insert("Matrix", array(&[2., 0., 0., 2., 0., 0.]));
+        let pattern = TilingPattern::from_dict(&dict).unwrap();
+        assert_eq!(pattern.matrix, Transform2F::from_scale(Vector2F::splat(2.)));
+
+        let cell = pattern.matrix * pattern.cell();
+        assert_eq!(cell * Vector2F::new(1., 1.), Vector2F::new(30., 50.));
+        assert_eq!(TilingPattern::tile_size(cell), (20, 40));
+
+        // the cell corners map to the tile corners, y down
+        let to_tile = pattern.tile_transforms(20, 40)[4];
+        assert_eq!(to_tile * Vector2F::new(5., 5.), Vector2F::new(0., 40.));
+        assert_eq!(to_tile * Vector2F::new(15., 25.), Vector2F::new(20., 0.));
+        // the left neighbour
+        assert_eq!(pattern.tile_transforms(20, 40)[3] * Vector2F::new(15., 5.), Vector2F::new(0., 40.));
+
+        dict.insert("XStep", Primitive::Number(0.));
+        assert!(TilingPattern::from_dict(&dict).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt;
+
+use pdf::{crypt::CryptDict, PdfError};
+
+// user access permissions, /P of the encryption dictionary (PDF 32000-1, table 22)
+const PRINT: i32 = 1 << 2;
+const MODIFY: i32 = 1 << 3;
+const EXTRACT: i32 = 1 << 4;
+const ACCESSIBILITY: i32 = 1 << 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions {
+    // None for files that are not encrypted, everything is allowed
+    bits: Option<i32>,
+}
+
+impl Permissions {
+    pub fn from_crypt(crypt: Option<&CryptDict>) -> Self {
+        Permissions { bits: crypt.map(|c| c.p) }
+    }
+    fn allows(&self, bit: i32) -> bool {
+        self.bits.map_or(true, |p| p & bit != 0)
+    }
+    pub fn is_encrypted(&self) -> bool {
+        self.bits.is_some()
+    }
+    pub fn can_print(&self) -> bool {
+        self.allows(PRINT)
+    }
+    pub fn can_modify(&self) -> bool {
+        self.allows(MODIFY)
+    }
+    pub fn can_extract(&self) -> bool {
+        self.allows(EXTRACT)
+    }
+    // Rendering is always allowed. Extraction of structure needs the extract
+    // permission, unless the user takes responsibility with ignore. There is no
+    // text or json extraction yet; it has to go through this check when it comes.
+    pub fn check_extract(&self, ignore: bool) -> Result<(), PermissionDenied> {
+        match self.can_extract() || ignore {
+            true => Ok(()),
+            false => Err(PermissionDenied { action: "extract content" }),
+        }
+    }
+}
+
+impl fmt::Display for Permissions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.is_encrypted() {
+            return write!(f, "not encrypted");
+        }
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        write!(
+            f,
+            "print: {}, modify: {}, extract: {}, accessibility: {}",
+            yes_no(self.can_print()),
+            yes_no(self.can_modify()),
+            yes_no(self.can_extract()),
+            yes_no(self.allows(ACCESSIBILITY))
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionDenied {
+    pub action: &'static str,
+}
+
+impl fmt::Display for PermissionDenied {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the document does not permit to {}, use --ignore-permissions if you are entitled to",
+            self.action
+        )
+    }
+}
+
+impl std::error::Error for PermissionDenied {}
+
+impl From<PermissionDenied> for PdfError {
+    fn from(e: PermissionDenied) -> Self {
+        PdfError::Other { msg: e.to_string() }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PermissionDenied, Permissions};
+
+    #[test]
+    fn extraction() {
+        // all bits set but extract, the reserved high bits are 1 as in real files
+        let locked = Permissions { bits: Some(-1 & !(1 << 4)) };
+        assert!(locked.can_print());
+        assert!(!locked.can_extract());
+        assert_eq!(locked.check_extract(false), Err(PermissionDenied { action: "extract content" }));
+        assert_eq!(locked.check_extract(true), Ok(()));
+
+        let open = Permissions::from_crypt(None);
+        assert!(!open.is_encrypted());
+        assert_eq!(open.check_extract(false), Ok(()));
+        assert_eq!(open.to_string(), "not encrypted");
+        assert!(locked.to_string().contains("extract: no"));
+    }
+}
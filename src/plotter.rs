@@ -1,7 +1,15 @@
-use pathfinder_content::{fill::FillRule, outline::Outline, stroke::StrokeStyle};
-use pathfinder_geometry::transform2d::Transform2F;
+use pathfinder_content::{
+    dash::OutlineDash,
+    fill::FillRule,
+    outline::{Contour, ContourIterFlags, Outline, PushSegmentFlags},
+    stroke::{OutlineStrokeToFill, StrokeStyle},
+};
+use pathfinder_color::ColorU;
+use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
 use pdf::object::{Pattern, Ref};
 
+use crate::structure::McContext;
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Fill {
     Solid(f32, f32, f32),
@@ -13,18 +21,76 @@ impl Fill {
     }
 }
 
+// RGBA pixels, 4 bytes per pixel, row by row
+#[derive(Debug, Clone)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+// axial gradient in device space, stop offsets run from 0 at `from` to 1 at `to`
+#[derive(Debug, Clone)]
+pub struct LinearGradient {
+    pub from: Vector2F,
+    pub to: Vector2F,
+    pub stops: Vec<(f32, ColorU)>,
+}
+
+// one cell of a tiling pattern, repeated in both directions
+#[derive(Debug, Clone)]
+pub struct Tiling {
+    pub image: RgbaImage,
+    // the unit square to one cell in device space, the first image row at y = 1
+    pub transform: Transform2F,
+}
+
+#[derive(Clone)]
 pub struct FillMode {
     pub color: Fill,
     pub alpha: f32,
     pub mode: BlendMode,
 }
 
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+// the separable blend modes of /BM
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, Default)]
 pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
     Overlay,
-    Darken
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+}
+
+impl BlendMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "Normal" | "Compatible" => BlendMode::Normal,
+            "Multiply" => BlendMode::Multiply,
+            "Screen" => BlendMode::Screen,
+            "Overlay" => BlendMode::Overlay,
+            "Darken" => BlendMode::Darken,
+            "Lighten" => BlendMode::Lighten,
+            "ColorDodge" => BlendMode::ColorDodge,
+            "ColorBurn" => BlendMode::ColorBurn,
+            "HardLight" => BlendMode::HardLight,
+            "SoftLight" => BlendMode::SoftLight,
+            "Difference" => BlendMode::Difference,
+            "Exclusion" => BlendMode::Exclusion,
+            _ => return None,
+        })
+    }
 }
 
+#[derive(Clone)]
 pub enum DrawMode {
     Fill { fill: FillMode },
     Stroke { stroke: FillMode, stroke_mode: Stroke },
@@ -37,8 +103,158 @@ pub struct Stroke {
     pub style: StrokeStyle,
 }
 
+impl Stroke {
+    // the area covered by the stroke, as an outline to fill
+    pub fn to_outline(&self, outline: &Outline) -> Outline {
+        let mut stroke = match self.dash_pattern {
+            Some((ref pattern, phase)) => {
+                let dashed = dash(outline, pattern, phase);
+                OutlineStrokeToFill::new(&dashed, self.style)
+            }
+            None => OutlineStrokeToFill::new(outline, self.style),
+        };
+        stroke.offset();
+        stroke.into_outline()
+    }
+}
+
+// dash ends closer than this are the same point
+const DASH_EPSILON: f32 = 1e-4;
+
+fn touches(a: Vector2F, b: Vector2F) -> bool {
+    (a - b).length() < DASH_EPSILON
+}
+
+fn first_point(contour: &Contour) -> Vector2F {
+    contour.position_of(0)
+}
+
+fn last_point(contour: &Contour) -> Vector2F {
+    contour.position_of(contour.len() - 1)
+}
+
+// continue the contour with the segments of next, which starts where it ends
+fn append(contour: &mut Contour, next: &Contour) {
+    for segment in next.iter(ContourIterFlags::IGNORE_CLOSE_SEGMENT) {
+        contour.push_segment(&segment, PushSegmentFlags::UPDATE_BOUNDS);
+    }
+}
+
+// Each subpath is dashed on its own, the pattern starts over with every
+// subpath. The dasher breaks a dash that runs through the start of a closed
+// subpath, or two dashes that touch, into pieces whose ends meet. At a corner
+// these would get two caps and leave a notch, so the pieces are joined again
+// and the corner gets the line join.
+pub fn dash(outline: &Outline, pattern: &[f32], phase: f32) -> Outline {
+    let mut dashed = Outline::new();
+    for contour in outline.contours() {
+        let mut single = Outline::new();
+        single.push_contour(contour.clone());
+        let mut pieces: Vec<Contour> = vec![];
+        for piece in OutlineDash::new(&single, pattern, phase).into_outline().contours() {
+            match pieces.last_mut() {
+                Some(last) if touches(last_point(last), first_point(piece)) => append(last, piece),
+                _ => pieces.push(piece.clone()),
+            }
+        }
+        if contour.is_closed() && !pieces.is_empty() {
+            let start = first_point(contour);
+            let (first, last) = (first_point(&pieces[0]), last_point(&pieces[pieces.len() - 1]));
+            if touches(first, start) && touches(last, start) {
+                match pieces.len() {
+                    // a dash all the way round
+                    1 => pieces[0].close(),
+                    _ => {
+                        let first = pieces.remove(0);
+                        let mut through_start = pieces.pop().unwrap();
+                        append(&mut through_start, &first);
+                        pieces.insert(0, through_start);
+                    }
+                }
+            }
+        }
+        for piece in pieces {
+            dashed.push_contour(piece);
+        }
+    }
+    dashed
+}
+
 pub trait Plotter {
     type ClipPathId: Copy;
 
    fn draw(&mut self, outline: &Outline, mode: &DrawMode, fill_rule: FillRule, transform: Transform2F, clip: Option<Self::ClipPathId>);
+   // outline in device space, the new clip is intersected with the parent clip
+   fn create_clip_path(&mut self, outline: Outline, fill_rule: FillRule, parent: Option<Self::ClipPathId>) -> Self::ClipPathId;
+   // the image covers the unit square of transform, its first row is at the top (y = 1),
+   // with alpha over the alpha of its pixels. Scaled up it shows its pixels, unless
+   // smoothing asks for smooth sampling.
+   fn draw_image(&mut self, image: &RgbaImage, transform: Transform2F, clip: Option<Self::ClipPathId>, alpha: f32, smoothing: bool);
+   // outline in device space, painted with the gradient
+   fn draw_gradient(&mut self, outline: Outline, gradient: &LinearGradient, clip: Option<Self::ClipPathId>);
+   // outline in device space, painted with the repeated pattern cell
+   fn draw_tiling(&mut self, outline: Outline, fill_rule: FillRule, tiling: &Tiling, clip: Option<Self::ClipPathId>);
+   // an offscreen layer, composited with the blend mode
+   fn draw_layer(&mut self, image: &RgbaImage, transform: Transform2F, mode: BlendMode, clip: Option<Self::ClipPathId>) {
+       if mode != BlendMode::Normal {
+           debug!("layer drawn without blend mode {:?}", mode);
+       }
+       self.draw_image(image, transform, clip, 1., false);
+   }
+   // the marked content that the following draw calls belong to
+   fn marked_content(&mut self, _: McContext) {}
+   // the depth first number of the operator the following draw calls come from
+   fn begin_op(&mut self, _: usize) {}
+   // device space width of strokes with a line width of 0, one pixel for raster output
+   fn hairline_width(&self) -> f32 {
+       1.0
+   }
+}
+
+#[cfg(test)]
+mod test {
+    use pathfinder_content::outline::{Contour, Outline};
+    use pathfinder_geometry::{rect::RectF, vector::Vector2F};
+
+    use super::dash;
+
+    fn square() -> Outline {
+        let mut outline = Outline::new();
+        outline.push_contour(Contour::from_rect(RectF::new(Vector2F::zero(), Vector2F::splat(40.))));
+        outline
+    }
+
+    #[test]
+    fn dash_through_the_start_is_joined() {
+        // on 0..5, 15..25, .. 135..145, 155..160: the first and the last piece meet at the corner
+        let dashed = dash(&square(), &[10., 10.], 5.);
+        assert_eq!(dashed.contours().len(), 8);
+        let corner = dashed.contours().iter().find(|c| c.len() == 3).expect("joined dash");
+        assert_eq!(corner.position_of(0), Vector2F::new(0., 5.));
+        assert_eq!(corner.position_of(1), Vector2F::zero());
+        assert_eq!(corner.position_of(2), Vector2F::new(5., 0.));
+    }
+
+    #[test]
+    fn touching_dashes_are_joined() {
+        // the gaps have no length, a single closed contour is left
+        let dashed = dash(&square(), &[10., 0.], 0.);
+        assert_eq!(dashed.contours().len(), 1);
+        assert!(dashed.contours()[0].is_closed());
+    }
+
+    #[test]
+    fn pattern_restarts_with_each_subpath() {
+        let mut outline = Outline::new();
+        for y in [0., 10.] {
+            let mut line = Contour::new();
+            line.push_endpoint(Vector2F::new(0., y));
+            line.push_endpoint(Vector2F::new(15., y));
+            outline.push_contour(line);
+        }
+        let dashed = dash(&outline, &[4., 4.], 0.);
+        let starts: Vec<Vector2F> = dashed.contours().iter().map(|c| c.position_of(0)).collect();
+        let x: Vec<f32> = starts.iter().map(|p| p.x()).collect();
+        assert_eq!(x, [0., 8., 0., 8.]);
+    }
 }
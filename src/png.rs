@@ -1,7 +1,8 @@
+use std::fmt;
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::{self, Path};
-use std::{fs::File, io::BufWriter, path::PathBuf};
+use std::time::Instant;
 
 use gl::types::GLvoid;
 use glutin::api::egl::device::Device;
@@ -11,14 +12,14 @@ use glutin::context::{ContextApi, ContextAttributesBuilder};
 use glutin::prelude::*;
 
 use pathfinder_color::{ColorF, ColorU};
-use pathfinder_content::{dash::OutlineDash, fill::FillRule, outline::Outline, stroke::OutlineStrokeToFill};
+use pathfinder_content::{fill::FillRule, outline::Outline};
 use pathfinder_export::{Export, FileFormat};
 use pathfinder_geometry::{rect::RectF, transform2d::Transform2F};
-use pathfinder_renderer::{paint::{Paint, PaintId}, scene::{ClipPathId, DrawPath, Scene}};
+use pathfinder_renderer::{paint::{Paint, PaintId}, scene::{ClipPath, ClipPathId, DrawPath, Scene}};
 
 use euclid::default::Size2D;
 use pathfinder_canvas::{Canvas, CanvasFontContext, Path2D};
-use pathfinder_geometry::vector::{vec2f, vec2i};
+use pathfinder_geometry::vector::{vec2f, vec2i, Vector2F, Vector2I};
 use pathfinder_gl::{GLDevice, GLVersion};
 use pathfinder_renderer::concurrent::rayon::RayonExecutor;
 use pathfinder_renderer::concurrent::scene_proxy::SceneProxy;
@@ -27,29 +28,117 @@ use pathfinder_renderer::gpu::renderer::Renderer;
 use pathfinder_renderer::options::BuildOptions;
 use pathfinder_resources::embedded::EmbeddedResourceLoader;
 
-use crate::plotter::{BlendMode, DrawMode, Fill, Plotter};
+use crate::color_usage;
+use crate::geometry::pixel_size;
+use crate::images;
+use crate::shading;
+use crate::metadata::OutputMetadata;
+use crate::plotter::{BlendMode, DrawMode, Fill, LinearGradient, Plotter, RgbaImage, Tiling};
+use crate::timings::Timings;
 
 fn blend_mode(mode: BlendMode) -> pathfinder_content::effects::BlendMode {
+    use pathfinder_content::effects::BlendMode as B;
     match mode {
-        BlendMode::Darken => pathfinder_content::effects::BlendMode::Multiply,
-        BlendMode::Overlay => pathfinder_content::effects::BlendMode::Overlay,
+        BlendMode::Normal => B::SrcOver,
+        BlendMode::Multiply => B::Multiply,
+        BlendMode::Screen => B::Screen,
+        BlendMode::Overlay => B::Overlay,
+        BlendMode::Darken => B::Darken,
+        BlendMode::Lighten => B::Lighten,
+        BlendMode::ColorDodge => B::ColorDodge,
+        BlendMode::ColorBurn => B::ColorBurn,
+        BlendMode::HardLight => B::HardLight,
+        BlendMode::SoftLight => B::SoftLight,
+        BlendMode::Difference => B::Difference,
+        BlendMode::Exclusion => B::Exclusion,
     }
 }
 
+// Scenes with more draw paths or segments are rendered in tiles, see
+// rasterize_tiled. The defaults stay well below what breaks the gpu buffers
+// of pathfinder on small devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SceneLimits {
+    pub max_paths: usize,
+    pub max_segments: usize,
+}
+
+impl Default for SceneLimits {
+    fn default() -> Self {
+        SceneLimits { max_paths: 100_000, max_segments: 2_000_000 }
+    }
+}
+
+impl SceneLimits {
+    // tiles per side so that a tile gets a share of the scene within the limits,
+    // if the paths are spread over the page
+    pub fn grid(&self, paths: usize, segments: usize) -> u32 {
+        let shares = paths.div_ceil(self.max_paths.max(1)).max(segments.div_ceil(self.max_segments.max(1)));
+        (shares as f64).sqrt().ceil().max(1.) as u32
+    }
+}
+
+fn segments(outline: &Outline) -> usize {
+    outline.contours().iter().map(|c| c.len() as usize).sum()
+}
+
+// images with more pixels per device pixel than this are box filtered down
+pub const IMAGE_DOWNSAMPLE: f32 = 2.0;
+
 pub struct PngPlotter {
     scene: Scene,
+    limits: SceneLimits,
+    // see images::downsample_to_device
+    downsample: f32,
+    paths: usize,
+    segments: usize,
+    // tiles per side of the last raster
+    grid: u32,
 }
 
 impl PngPlotter {
     pub fn new(view_box: RectF) -> Self {
+        let mut plotter = Self::transparent(view_box);
+        let white = plotter.scene.push_paint(&Paint::from_color(ColorU::white()));
+        plotter.push(DrawPath::new(Outline::from_rect(view_box), white));
+        plotter
+    }
+    // without the white page background, for pattern cells
+    pub fn transparent(view_box: RectF) -> Self {
         let mut scene = Scene::new();
         scene.set_view_box(view_box);
-        let white = scene.push_paint(&Paint::from_color(ColorU::white()));
-        scene.push_draw_path(DrawPath::new(Outline::from_rect(view_box), white));
         Self {
             scene,
+            limits: SceneLimits::default(),
+            downsample: IMAGE_DOWNSAMPLE,
+            paths: 0,
+            segments: 0,
+            grid: 1,
         }
     }
+    pub fn limits(mut self, limits: SceneLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+    pub fn downsample(mut self, factor: f32) -> Self {
+        self.downsample = factor;
+        self
+    }
+    // every draw path goes through here to be counted
+    fn push(&mut self, draw_path: DrawPath) {
+        self.paths += 1;
+        self.segments += segments(draw_path.outline());
+        self.scene.push_draw_path(draw_path);
+    }
+    // the warning for the report when the last raster had to be tiled
+    pub fn degradation(&self) -> Option<String> {
+        (self.grid > 1).then(|| {
+            format!(
+                "scene of {} paths and {} segments is over the gpu limits, rendered in {}x{} tiles",
+                self.paths, self.segments, self.grid, self.grid
+            )
+        })
+    }
     fn paint(&mut self, fill: Fill, alpha: f32) -> PaintId {
         let paint = match fill {
             Fill::Solid(r, g, b) => Paint::from_color(ColorF::new(r, g, b, alpha).to_u8()),
@@ -60,13 +149,117 @@ impl PngPlotter {
         self.scene.push_paint(&paint)
     }
 
-    pub fn write(&mut self, file: PathBuf) {
-        render(&mut self.scene, file);
+    pub fn write(&mut self, out: &mut dyn Write, metadata: Option<&OutputMetadata>, filter: Option<PngFilter>, gray_if_monochrome: bool) -> Result<Timings, PdfError> {
+        let (image, mut timings) = self.raster();
+        let start = Instant::now();
+        let gray = gray_if_monochrome && color_usage::is_monochrome(&image);
+        write_png_filtered(out, image.width, image.height, &image.pixels, metadata, filter, gray)?;
+        timings.encode = start.elapsed();
+        Ok(timings)
+    }
+    // render into memory instead of a file
+    pub fn rasterize(&mut self) -> RgbaImage {
+        self.raster().0
+    }
+    fn raster(&mut self) -> (RgbaImage, Timings) {
+        let (width, height) = pixel_size(self.scene.view_box());
+        let mut pixels: Vec<u8> = vec![0; width as usize * height as usize * 4];
+        let timings = self.rasterize_into(&mut pixels, width as usize * 4).expect("the buffer fits the image");
+        (RgbaImage { width, height, pixels }, timings)
+    }
+    // render into the caller's buffer, see rasterize_into, in tiles when the
+    // scene is over the limits
+    pub fn rasterize_into(&mut self, buf: &mut [u8], stride: usize) -> Result<Timings, BufferError> {
+        let grid = self.limits.grid(self.paths, self.segments);
+        let (timings, grid) = rasterize_tiled(&mut self.scene, buf, stride, grid)?;
+        self.grid = grid;
+        if let Some(warning) = self.degradation() {
+            warn!("{}", warning);
+        }
+        Ok(timings)
+    }
+}
+
+// a caller provided buffer that can not take the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferError {
+    // the stride is shorter than a row of pixels
+    Stride { stride: usize, row: usize },
+    TooSmall { len: usize, needed: usize },
+}
+
+impl fmt::Display for BufferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BufferError::Stride { stride, row } => write!(f, "stride of {} bytes is shorter than a row of {} bytes", stride, row),
+            BufferError::TooSmall { len, needed } => write!(f, "buffer of {} bytes is too small, the image needs {}", len, needed),
+        }
+    }
+}
+
+impl std::error::Error for BufferError {}
+
+impl From<BufferError> for PdfError {
+    fn from(e: BufferError) -> Self {
+        PdfError::Other { msg: e.to_string() }
+    }
+}
+
+// RGBA rows of width pixels, stride bytes apart. The last row needs no padding.
+pub fn check_buffer(width: u32, height: u32, len: usize, stride: usize) -> Result<(), BufferError> {
+    let row = width as usize * 4;
+    if stride < row {
+        return Err(BufferError::Stride { stride, row });
+    }
+    let needed = match height {
+        0 => 0,
+        h => stride * (h as usize - 1) + row,
+    };
+    if len < needed {
+        return Err(BufferError::TooSmall { len, needed });
     }
+    Ok(())
 }
 
 impl Plotter for PngPlotter {
     type ClipPathId = ClipPathId;
+    fn draw_image(&mut self, image: &RgbaImage, transform: Transform2F, clip: Option<Self::ClipPathId>, alpha: f32, smoothing: bool) {
+        // a scan far larger than the output is not uploaded at full size
+        let small = images::downsample_to_device(image, transform, self.downsample);
+        let paint = self.scene.push_paint(&images::image_paint(small.as_ref().unwrap_or(image), transform, alpha, smoothing));
+        let outline = Outline::from_rect(RectF::new(Vector2F::zero(), Vector2F::splat(1.))).transformed(&transform);
+        let mut draw_path = DrawPath::new(outline, paint);
+        draw_path.set_clip_path(clip);
+        self.push(draw_path);
+    }
+    fn draw_layer(&mut self, image: &RgbaImage, transform: Transform2F, mode: BlendMode, clip: Option<Self::ClipPathId>) {
+        let paint = self.scene.push_paint(&images::image_paint(image, transform, 1., false));
+        let outline = Outline::from_rect(RectF::new(Vector2F::zero(), Vector2F::splat(1.))).transformed(&transform);
+        let mut draw_path = DrawPath::new(outline, paint);
+        draw_path.set_clip_path(clip);
+        draw_path.set_blend_mode(blend_mode(mode));
+        self.push(draw_path);
+    }
+    fn draw_gradient(&mut self, outline: Outline, gradient: &LinearGradient, clip: Option<Self::ClipPathId>) {
+        let paint = self.scene.push_paint(&shading::gradient_paint(gradient));
+        let mut draw_path = DrawPath::new(outline, paint);
+        draw_path.set_clip_path(clip);
+        self.push(draw_path);
+    }
+    fn draw_tiling(&mut self, outline: Outline, fill_rule: FillRule, tiling: &Tiling, clip: Option<Self::ClipPathId>) {
+        let paint = self.scene.push_paint(&images::tiling_paint(tiling));
+        let mut draw_path = DrawPath::new(outline, paint);
+        draw_path.set_clip_path(clip);
+        draw_path.set_fill_rule(fill_rule);
+        self.push(draw_path);
+    }
+    fn create_clip_path(&mut self, outline: Outline, fill_rule: FillRule, parent: Option<Self::ClipPathId>) -> Self::ClipPathId {
+        self.segments += segments(&outline);
+        let mut clip_path = ClipPath::new(outline);
+        clip_path.set_fill_rule(fill_rule);
+        clip_path.set_clip_path(parent);
+        self.scene.push_clip_path(clip_path)
+    }
     fn draw(&mut self, outline: &Outline, mode: &DrawMode, fill_rule: FillRule, transform: Transform2F, clip: Option<Self::ClipPathId>) {
         match mode {
             DrawMode::Fill { fill } | DrawMode::FillStroke {fill, .. } => {
@@ -75,49 +268,59 @@ impl Plotter for PngPlotter {
                 draw_path.set_clip_path(clip);
                 draw_path.set_fill_rule(fill_rule);
                 draw_path.set_blend_mode(blend_mode(fill.mode));
-                self.scene.push_draw_path(draw_path);
+                self.push(draw_path);
             }
             _ => {}
         }
         match mode {
             DrawMode::Stroke { stroke, stroke_mode }| DrawMode::FillStroke { stroke, stroke_mode, .. } => {
                 let paint = self.paint(stroke.color, stroke.alpha);
-                let contour = match stroke_mode.dash_pattern {
-                    Some((ref pat, phase)) => {
-                        let dashed = OutlineDash::new(outline, pat, phase).into_outline();
-                        let mut stroke = OutlineStrokeToFill::new(&dashed, stroke_mode.style);
-                        stroke.offset();
-                        stroke.into_outline()
-                    }
-                    None => {
-                        let mut stroke = OutlineStrokeToFill::new(outline, stroke_mode.style);
-                        stroke.offset();
-                        stroke.into_outline()
-                    }
-                };
+                let contour = stroke_mode.to_outline(outline);
                 let mut draw_path = DrawPath::new(contour.transformed(&transform), paint);
                 draw_path.set_clip_path(clip);
-                draw_path.set_fill_rule(fill_rule);
-
-            draw_path.set_blend_mode(blend_mode(stroke.mode));
-                self.scene.push_draw_path(draw_path);
+                // the fill rule of B* is for the fill, the stroke area always uses nonzero
+                draw_path.set_fill_rule(FillRule::Winding);
+                draw_path.set_blend_mode(blend_mode(stroke.mode));
+                self.push(draw_path);
             }
             _ => {}
         }
     }
 }
 
-use png::{BitDepth, ColorType, Encoder};
+use png::{AdaptiveFilterType, BitDepth, ColorType, Compression, Encoder, FilterType};
+use pdf::PdfError;
 use std::mem;
 use std::slice;
 use surfman::{Connection, ContextAttributeFlags, ContextAttributes, GLApi, GLVersion as SurfmanGLVersion};
 use surfman::{SurfaceAccess, SurfaceType};
 
-fn render(scene: &mut Scene, output: PathBuf) {
+pub fn rasterize(scene: &mut Scene) -> (RgbaImage, Timings) {
+    let (width, height) = pixel_size(scene.view_box());
+    let mut pixels: Vec<u8> = vec![0; width as usize * height as usize * 4];
+    let timings = rasterize_into(scene, &mut pixels, width as usize * 4).expect("the buffer fits the image");
+    (RgbaImage { width, height, pixels }, timings)
+}
+
+// Render the scene into buf, the pixel rows from the top, stride bytes apart.
+// The gpu reads back straight into the buffer, the bytes after each row are
+// left as they are.
+pub fn rasterize_into(scene: &mut Scene, buf: &mut [u8], stride: usize) -> Result<Timings, BufferError> {
+    rasterize_tiled(scene, buf, stride, 1).map(|(timings, _)| timings)
+}
 
-    let view_box = dbg!(scene.view_box());
-    let size = view_box.size().ceil().to_i32();
-    let transform = Transform2F::from_translation(-view_box.origin());
+// As rasterize_into, one view box of grid x grid tiles at a time. Pathfinder
+// only builds the tiles inside the view box, so each frame needs smaller gpu
+// buffers. The grid grows when a tile is larger than the driver can render,
+// the grid that was used is returned. Pathfinder computes the exact coverage of
+// each pixel without sample offsets, so tiles match the untiled raster.
+pub fn rasterize_tiled(scene: &mut Scene, buf: &mut [u8], stride: usize, grid: u32) -> Result<(Timings, u32), BufferError> {
+    let mut timings = Timings::default();
+
+    let view_box = scene.view_box();
+    debug!("render view box {:?}", view_box);
+    let (width, height) = pixel_size(view_box);
+    check_buffer(width, height, buf.len(), stride)?;
 
     let connection = Connection::new().unwrap();
     //let native_widget = connection.create_native_widget_from_winit_window(&window).unwrap();
@@ -130,17 +333,29 @@ fn render(scene: &mut Scene, output: PathBuf) {
         flags: ContextAttributeFlags::ALPHA,
     };
     let context_descriptor = device.create_context_descriptor(&context_attributes).unwrap();
+    let mut context = device.create_context(&context_descriptor, None).unwrap();
+
+    // the limits of the driver can be asked once a context is current, on a
+    // surface that is small enough for any of them
+    let probe = SurfaceType::Generic { size: Size2D::new(1, 1) };
+    let surface = device.create_surface(&context, SurfaceAccess::GPUOnly, probe).unwrap();
+    device.bind_surface_to_context(&mut context, surface).unwrap();
+    device.make_context_current(&context).unwrap();
+    gl::load_with(|symbol_name| device.get_proc_address(&context, symbol_name));
+    let max_size = max_surface_size();
+    let mut surface = device.unbind_surface_from_context(&mut context).unwrap().expect("the probe surface is bound");
+    device.destroy_surface(&mut context, &mut surface).unwrap();
+
+    let grid = grid.max(1).max(width.max(height).div_ceil(max_size));
+    let tile = vec2i(width.div_ceil(grid).max(1) as i32, height.div_ceil(grid).max(1) as i32);
+    debug!("{}x{} tiles of {:?}, the gpu renders up to {} pixels", grid, grid, tile, max_size);
 
     // Make the OpenGL context via `surfman`, and load OpenGL functions.
-    let surface_type = SurfaceType::Generic { size: Size2D::new(size.x(), size.y()) };
-    let mut context = device.create_context(&context_descriptor, None).unwrap();
+    let surface_type = SurfaceType::Generic { size: Size2D::new(tile.x(), tile.y()) };
     let surface = device.create_surface(&context, SurfaceAccess::GPUOnly, surface_type)
                         .unwrap();
     device.bind_surface_to_context(&mut context, surface).unwrap();
     device.make_context_current(&context).unwrap();
-    gl::load_with(|symbol_name| device.get_proc_address(&context, symbol_name));
-
-    let framebuffer_size = vec2i(size.x() as i32, size.y() as i32);
 
     // Create a Pathfinder GL device.
     let default_framebuffer = device.context_surface_info(&context)
@@ -152,40 +367,220 @@ fn render(scene: &mut Scene, output: PathBuf) {
     // Create a Pathfinder renderer.
     let mode = RendererMode::default_for_device(&pathfinder_device);
     let options = RendererOptions {
-        dest: DestFramebuffer::full_window(framebuffer_size),
-        background_color: Some(ColorF::white()),
+        dest: DestFramebuffer::full_window(tile),
+        // pages draw their own white background, pattern cells and layers stay transparent
+        background_color: Some(ColorF::transparent_black()),
         ..RendererOptions::default()
     };
     let resource_loader = EmbeddedResourceLoader::new();
     let mut renderer = Renderer::new(pathfinder_device, &resource_loader, mode, options);
 
-    scene.build_and_render(&mut renderer, BuildOptions::default(), RayonExecutor);
-    let mut pixels: Vec<u8> = vec![0; size.x() as usize * size.y() as usize * 4];
+    for ty in 0..grid as i32 {
+        for tx in 0..grid as i32 {
+            let origin = vec2i(tx * tile.x(), ty * tile.y());
+            if origin.x() >= width as i32 || origin.y() >= height as i32 {
+                continue;
+            }
+            scene.set_view_box(RectF::new(view_box.origin() + origin.to_f32(), tile.to_f32()));
+            let start = Instant::now();
+            scene.build_and_render(&mut renderer, BuildOptions::default(), RayonExecutor);
+            timings.build += start.elapsed();
 
-    unsafe {
-        gl::ReadPixels(
-            0,
-            0,
-            size.x(),
-            size.y(),
-            gl::RGBA,
-            gl::UNSIGNED_BYTE,
-            pixels.as_mut_ptr() as *mut GLvoid,
-        );
-    }
-
-    let file = File::create(output).unwrap();
-    let mut encoder = Encoder::new(
-        file,
-        size.x() as u32,
-        size.y() as u32,
-    );
-    encoder.set_color(ColorType::Rgba);
-    encoder.set_depth(BitDepth::Eight);
-    let mut image_writer = encoder.write_header().unwrap();
-    image_writer.write_image_data(&pixels).unwrap();
+            // the read back blocks until the gpu has finished the frame
+            let start = Instant::now();
+            let size = vec2i((width as i32 - origin.x()).min(tile.x()), (height as i32 - origin.y()).min(tile.y()));
+            let offset = origin.y() as usize * stride + origin.x() as usize * 4;
+            read_pixels(&mut buf[offset..], stride, size);
+            timings.raster += start.elapsed();
+        }
+    }
+    scene.set_view_box(view_box);
 
     // Clean up.
     drop(device.destroy_context(&mut context));
+    Ok((timings, grid))
+}
+
+// the largest side of a surface the driver renders to, unlimited if it does not say
+fn max_surface_size() -> u32 {
+    let (mut texture, mut renderbuffer) = (0, 0);
+    unsafe {
+        gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut texture);
+        gl::GetIntegerv(gl::MAX_RENDERBUFFER_SIZE, &mut renderbuffer);
+    }
+    match texture.min(renderbuffer) {
+        n if n > 0 => n as u32,
+        _ => u32::MAX,
+    }
+}
+
+// the top left size pixels of the framebuffer into buf, stride bytes a row
+fn read_pixels(buf: &mut [u8], stride: usize, size: Vector2I) {
+    let row = size.x() as usize * 4;
+    unsafe {
+        if stride == row {
+            gl::ReadPixels(0, 0, size.x(), size.y(), gl::RGBA, gl::UNSIGNED_BYTE, buf.as_mut_ptr() as *mut GLvoid);
+        } else {
+            // one row at a time, the padding and the other tiles are not ours to write
+            for y in 0..size.y() {
+                let offset = y as usize * stride;
+                let dest = &mut buf[offset..offset + row];
+                gl::ReadPixels(0, y, size.x(), 1, gl::RGBA, gl::UNSIGNED_BYTE, dest.as_mut_ptr() as *mut GLvoid);
+            }
+        }
+    }
+}
+
+// Row filter of the written png. None with fast compression is the quickest to
+// encode, the default leaves the choice to the encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PngFilter {
+    None,
+    Paeth,
+    Adaptive,
+}
+
+// rows handed to the encoder at a time
+const ROWS_PER_CHUNK: usize = 64;
+
+// Encodes the image as it arrives, a chunk of rows at a time, so only the rows
+// not yet written have to be kept.
+pub struct PngStream<W: Write> {
+    writer: png::StreamWriter<'static, W>,
+    // the rgba rows are written as one gray channel
+    gray: bool,
+}
+
+impl<W: Write> PngStream<W> {
+    pub fn new(writer: W, width: u32, height: u32, metadata: Option<&OutputMetadata>, filter: Option<PngFilter>, gray: bool) -> Result<Self, PdfError> {
+        let mut encoder = Encoder::new(writer, width, height);
+        encoder.set_color(if gray { ColorType::Grayscale } else { ColorType::Rgba });
+        encoder.set_depth(BitDepth::Eight);
+        match filter {
+            Some(PngFilter::None) => {
+                encoder.set_filter(FilterType::NoFilter);
+                encoder.set_compression(Compression::Fast);
+            }
+            Some(PngFilter::Paeth) => encoder.set_filter(FilterType::Paeth),
+            Some(PngFilter::Adaptive) => encoder.set_adaptive_filter(AdaptiveFilterType::Adaptive),
+            None => {}
+        }
+        if let Some(metadata) = metadata {
+            for (key, value) in metadata.entries() {
+                encoder.add_text_chunk(key.to_string(), value).map_err(png_error)?;
+            }
+        }
+        let writer = encoder.write_header().map_err(png_error)?;
+        Ok(PngStream {
+            writer: writer.into_stream_writer().map_err(png_error)?,
+            gray,
+        })
+    }
+    // whole rows of rgba pixels, top to bottom
+    pub fn write_rows(&mut self, rows: &[u8]) -> Result<(), PdfError> {
+        let gray: Vec<u8>;
+        let rows = match self.gray {
+            true => {
+                gray = rows.chunks_exact(4).map(|p| ((p[0] as u16 + p[1] as u16 + p[2] as u16) / 3) as u8).collect();
+                &gray
+            }
+            false => rows,
+        };
+        self.writer.write_all(rows).map_err(|e| PdfError::Other {
+            msg: format!("can not write png rows: {}", e),
+        })
+    }
+    pub fn finish(self) -> Result<(), PdfError> {
+        self.writer.finish().map_err(png_error)
+    }
+}
+
+fn png_error(e: png::EncodingError) -> PdfError {
+    PdfError::Other {
+        msg: format!("png encoding failed: {}", e),
+    }
+}
+
+pub fn write_png<W: Write>(writer: W, width: u32, height: u32, pixels: &[u8], metadata: Option<&OutputMetadata>) {
+    write_png_filtered(writer, width, height, pixels, metadata, None, false).unwrap();
 }
 
+pub fn write_png_filtered<W: Write>(
+    writer: W,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+    metadata: Option<&OutputMetadata>,
+    filter: Option<PngFilter>,
+    gray: bool,
+) -> Result<(), PdfError> {
+    let mut stream = PngStream::new(writer, width, height, metadata, filter, gray)?;
+    for rows in pixels.chunks(width as usize * 4 * ROWS_PER_CHUNK) {
+        stream.write_rows(rows)?;
+    }
+    stream.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_buffer, BufferError, SceneLimits};
+    use crate::metadata::OutputMetadata;
+
+    #[test]
+    fn tile_grid() {
+        let limits = SceneLimits { max_paths: 100, max_segments: 1000 };
+        assert_eq!(limits.grid(0, 0), 1);
+        assert_eq!(limits.grid(100, 1000), 1);
+        assert_eq!(limits.grid(101, 0), 2);
+        // 17 shares need 5x5 tiles
+        assert_eq!(limits.grid(1601, 0), 5);
+        assert_eq!(limits.grid(10, 9000), 3);
+    }
+
+    #[test]
+    fn buffer_checks() {
+        assert_eq!(check_buffer(10, 5, 200, 40), Ok(()));
+        assert_eq!(check_buffer(10, 5, 4 * 48 + 40, 48), Ok(()));
+        assert_eq!(check_buffer(10, 5, 1000, 39), Err(BufferError::Stride { stride: 39, row: 40 }));
+        assert_eq!(check_buffer(10, 5, 199, 40), Err(BufferError::TooSmall { len: 199, needed: 200 }));
+        assert_eq!(check_buffer(10, 0, 0, 40), Ok(()));
+    }
+
+    #[test]
+    fn png_text_chunks() {
+        let metadata = OutputMetadata {
+            source: "rack.pdf".into(),
+            page: 3,
+            dpi: 300.,
+            options: String::new(),
+        };
+        let mut data = vec![];
+        super::write_png(&mut data, 2, 2, &[255; 16], Some(&metadata));
+
+        let decoder = png::Decoder::new(data.as_slice());
+        let reader = decoder.read_info().unwrap();
+        let chunks = &reader.info().uncompressed_latin1_text;
+        let get = |key: &str| chunks.iter().find(|c| c.keyword == key).map(|c| c.text.clone());
+        assert_eq!(get("Page").as_deref(), Some("3"));
+        assert_eq!(get("DPI").as_deref(), Some("300"));
+        assert_eq!(get("Source").as_deref(), Some("rack.pdf"));
+    }
+
+    #[test]
+    fn filters_keep_pixels() {
+        use super::{write_png_filtered, PngFilter};
+
+        // taller than one chunk, so the rows are streamed in several parts
+        let (width, height) = (37, 150);
+        let pixels: Vec<u8> = (0..width * height * 4).map(|i| (i * 7 % 251) as u8).collect();
+        for filter in [None, Some(PngFilter::None), Some(PngFilter::Paeth), Some(PngFilter::Adaptive)] {
+            let mut data = vec![];
+            write_png_filtered(&mut data, width, height, &pixels, None, filter, false).unwrap();
+
+            let mut reader = png::Decoder::new(data.as_slice()).read_info().unwrap();
+            let mut decoded = vec![0; reader.output_buffer_size()];
+            reader.next_frame(&mut decoded).unwrap();
+            assert_eq!(decoded, pixels, "{:?}", filter);
+        }
+    }
+}
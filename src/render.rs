@@ -1,18 +1,51 @@
+use std::{borrow::Cow, path::Path, sync::Arc, time::Instant};
+
+use pathfinder_color::ColorF;
 use pathfinder_content::{
     fill::FillRule,
     outline::{Contour, Outline},
-    stroke::StrokeStyle,
+    stroke::{LineCap, LineJoin},
 };
 use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
 use pdf::{
-    content::{Cmyk, Color, Matrix, Op, Point, Rect, Rgb, Winding},
-    object::{ColorSpace, Page, Resolve, Resources},
+    content::{parse_ops, Cmyk, Color, Matrix, Op, Point, Rect, Rgb, Winding},
+    enc::{CCITTFaxDecodeParams, StreamFilter},
+    object::{
+        AppearanceStreamEntry, ColorSpace, FormXObject, ImageXObject, MaybeRef, Object, Page, Pattern, PlainRef, Ref, Resolve, Resources, Stream, XObject,
+    },
+    primitive::{Dictionary, Name, Primitive},
     t, PdfError,
 };
+use serde::Serialize;
 
+#[cfg(feature = "jbig2")]
+use pdf::enc::JBIG2DecodeParams;
+#[cfg(feature = "jbig2")]
+use crate::jbig2;
 use crate::{
+    annotations::{self, annotation_style, appearance_transform, is_hidden},
+    color::CmykToRgb,
+    color_usage::{self, ColorUsage},
+    content,
+    context::PageContext,
+    fax,
+    geometry,
+    image_cache::{ImageCache, ImageKey},
+    images,
+    optional_content::OptionalContent,
+    options::{Progress, RenderOptions, PROGRESS_INTERVAL},
+    timings::Timings,
+    transparency::{self, page_group_color_space, GroupColorSpace, TransparencyGroup},
     graphics_state::GraphicsState,
-    plotter::{BlendMode, DrawMode, Fill, FillMode, Plotter},
+    plotter::{BlendMode, DrawMode, Fill, FillMode, LinearGradient, Plotter, RgbaImage, Stroke, Tiling},
+    mesh::{self, MeshFormat},
+    op_hook::{self, HookAction, HookState},
+    pattern::{self, TilingPattern},
+    png::PngPlotter,
+    xobject::{self, XObjectKind},
+    shading::{self, AxialShading, GRADIENT_STOPS},
+    soft_mask::{self, MaskKind, SoftMask, MAX_LAYER_DEPTH},
+    structure::{McContext, McEntry},
     text_state::{Span, TextSpan, TextState},
 };
 
@@ -74,58 +107,105 @@ impl Cvt for Cmyk {
     }
 }
 
-fn convert_color<'a>(
-    cs: &mut &'a ColorSpace,
+fn convert_color(
+    cs: &ColorSpace,
     color: &Color,
     resources: &Resources,
     resolve: &impl Resolve,
     mode: BlendMode,
+    group_blending: bool,
+    warnings: &mut Vec<String>,
 ) -> Result<Fill, PdfError> {
-    match convert_color2(cs, color, resources, mode) {
+    match convert_color2(cs, color, resources, mode, group_blending) {
         Ok(color) => Ok(color),
         Err(e) if resolve.options().allow_error_in_option => {
-            println!("failed to convert color: {:?}", e);
+            warn!("failed to convert color: {:?}", e);
+            warnings.push(format!("failed to convert color: {:?}", e));
             Ok(Fill::Solid(0.0, 0.0, 0.0))
         }
         Err(e) => Err(e),
     }
 }
 
+fn device_space(components: usize) -> Option<&'static ColorSpace> {
+    match components {
+        1 => Some(&ColorSpace::DeviceGray),
+        3 => Some(&ColorSpace::DeviceRGB),
+        4 => Some(&ColorSpace::DeviceCMYK),
+        _ => None,
+    }
+}
+
+// The color space an ICC profile is drawn in: its alternate, else the device
+// space with the /N components of the stream dictionary. /N is there even when
+// the profile itself can not be read.
+fn icc_device_space(alternate: Option<&ColorSpace>, components: u32) -> Option<&ColorSpace> {
+    alternate.or_else(|| device_space(components as usize))
+}
+
+// the device spaces by name, with the abbreviations of inline images
+fn named_device_space(name: &str) -> Option<&'static ColorSpace> {
+    match name {
+        "G" | "DeviceGray" => Some(&ColorSpace::DeviceGray),
+        "RGB" | "DeviceRGB" => Some(&ColorSpace::DeviceRGB),
+        "CMYK" | "DeviceCMYK" => Some(&ColorSpace::DeviceCMYK),
+        _ => None,
+    }
+}
+
+// The number of components of a palette entry, for fills and images alike. The
+// base is a device space or an ICC profile drawn in one, else it is None.
+fn palette_components(base: &ColorSpace, resources: &Resources) -> Option<usize> {
+    let base = match *base {
+        ColorSpace::Named(ref name) => match named_device_space(name.as_str()) {
+            Some(space) => space,
+            None => resources.color_spaces.get(name.as_str())?,
+        },
+        ref base => base,
+    };
+    let base = match *base {
+        ColorSpace::Icc(ref icc) => icc_device_space(icc.info.alternate.as_deref(), icc.info.components)?,
+        ref base => base,
+    };
+    images::components(base)
+}
+
+// g, rg and k set the device color space along with the color
+fn set_device_space(cs: &mut Arc<ColorSpace>, color: &Color) {
+    let device = match *color {
+        Color::Gray(_) => ColorSpace::DeviceGray,
+        Color::Rgb(_) => ColorSpace::DeviceRGB,
+        Color::Cmyk(_) => ColorSpace::DeviceCMYK,
+        Color::Other(_) => return,
+    };
+    if std::mem::discriminant(&**cs) != std::mem::discriminant(&device) {
+        *cs = Arc::new(device);
+    }
+}
+
 #[allow(unused_variables)]
-fn convert_color2<'a>(
-    cs: &mut &'a ColorSpace,
+fn convert_color2(
+    cs: &ColorSpace,
     color: &Color,
     resources: &Resources,
     mode: BlendMode,
+    group_blending: bool,
 ) -> Result<Fill, PdfError> {
     match *color {
-        Color::Gray(g) => {
-            *cs = &ColorSpace::DeviceGray;
-            Ok(gray2rgb(g))
-        }
+        Color::Gray(g) => Ok(gray2rgb(g)),
         Color::Rgb(rgb) => {
-            *cs = &ColorSpace::DeviceRGB;
             let (r, g, b) = rgb.cvt();
             Ok(Fill::Solid(r, g, b))
         }
-        Color::Cmyk(cmyk) => {
-            *cs = &ColorSpace::DeviceCMYK;
-            Ok(cmyk2rgb(cmyk.cvt(), mode))
-        }
+        Color::Cmyk(cmyk) => Ok(cmyk2rgb(cmyk.cvt(), mode, group_blending)),
         Color::Other(ref args) => {
-            let cs = match **cs {
-                ColorSpace::Icc(ref icc) => match icc.info.alternate {
-                    Some(ref alt) => alt,
-                    None => match args.len() {
-                        3 => &ColorSpace::DeviceRGB,
-                        4 => &ColorSpace::DeviceCMYK,
-                        _ => {
-                            return Err(PdfError::Other {
-                                msg: format!("ICC profile without alternate color space"),
-                            })
-                        }
-                    },
-                },
+            let cs = match *cs {
+                // the number of arguments is the last resort
+                ColorSpace::Icc(ref icc) => icc_device_space(icc.info.alternate.as_deref(), icc.info.components)
+                    .or_else(|| device_space(args.len()))
+                    .ok_or_else(|| PdfError::Other {
+                        msg: format!("ICC profile without alternate color space"),
+                    })?,
                 ColorSpace::Named(ref name) => {
                     resources
                         .color_spaces
@@ -134,7 +214,7 @@ fn convert_color2<'a>(
                             msg: format!("named color space {} not found", name),
                         })?
                 }
-                _ => &**cs,
+                _ => cs,
             };
 
             match *cs {
@@ -173,7 +253,7 @@ fn convert_color2<'a>(
                     let m = args[1].as_number()?;
                     let y = args[2].as_number()?;
                     let k = args[3].as_number()?;
-                    Ok(cmyk2rgb((c, m, y, k), mode))
+                    Ok(cmyk2rgb((c, m, y, k), mode, group_blending))
                 }
                 ColorSpace::DeviceN {
                     ref names,
@@ -181,7 +261,11 @@ fn convert_color2<'a>(
                     ref tint,
                     ref attr,
                 } => {
-                    assert_eq!(args.len(), tint.input_dim());
+                    if args.len() != tint.input_dim() {
+                        return Err(PdfError::Other {
+                            msg: format!("expected {} color arguments, got {:?}", tint.input_dim(), args),
+                        });
+                    }
                     let mut input = vec![0.; args.len()];
                     for (i, a) in input.iter_mut().zip(args.iter()) {
                         *i = a.as_number()?;
@@ -190,20 +274,22 @@ fn convert_color2<'a>(
                     tint.apply(&input, &mut out)?;
 
                     let alt = match **alt {
-                        ColorSpace::Icc(ref icc) => icc.info.alternate.as_ref().map(|b| &**b),
+                        ColorSpace::Icc(ref icc) => icc_device_space(icc.info.alternate.as_deref(), icc.info.components),
                         ref a => Some(a),
                     };
                     match alt {
                         Some(ColorSpace::DeviceGray) => Ok(Fill::Solid(out[0], out[0], out[0])),
                         Some(ColorSpace::DeviceRGB) => Ok(Fill::Solid(out[0], out[1], out[2])),
                         Some(ColorSpace::DeviceCMYK) => {
-                            Ok(cmyk2rgb((out[0], out[1], out[2], out[3]), mode))
+                            Ok(cmyk2rgb((out[0], out[1], out[2], out[3]), mode, group_blending))
                         }
-                        _ => unimplemented!("DeviceN colorspace"),
+                        _ => Err(PdfError::Other {
+                            msg: format!("DeviceN color space {:?} with alternate {:?}", names, alt),
+                        }),
                     }
                 }
                 ColorSpace::Separation(ref name, ref alt, ref f) => {
-                    println!("Separation(name={}, alt={:?}, f={:?}", name, alt, f);
+                    debug!("Separation(name={}, alt={:?}, f={:?})", name, alt, f);
                     if args.len() != 1 {
                         return Err(PdfError::Other {
                             msg: format!("expected 1 color arguments, got {:?}", args),
@@ -211,11 +297,9 @@ fn convert_color2<'a>(
                     }
                     let x = args[0].as_number()?;
                     let cs = match **alt {
-                        ColorSpace::Icc(ref info) => {
-                            &**info.alternate.as_ref().ok_or(PdfError::Other {
-                                msg: format!("no alternate color space in ICC profile {:?}", info),
-                            })?
-                        }
+                        ColorSpace::Icc(ref info) => icc_device_space(info.alternate.as_deref(), info.components).ok_or(PdfError::Other {
+                            msg: format!("no alternate color space in ICC profile {:?}", info),
+                        })?,
                         _ => alt,
                     };
                     match cs {
@@ -224,7 +308,7 @@ fn convert_color2<'a>(
                             f.apply(&[x], &mut cmyk)?;
                             let [c, m, y, k] = cmyk;
                             //debug!("c={c}, m={m}, y={y}, k={k}");
-                            Ok(cmyk2rgb((c, m, y, k), mode))
+                            Ok(cmyk2rgb((c, m, y, k), mode, group_blending))
                         }
                         &ColorSpace::DeviceRGB => {
                             let mut rgb = [0.0, 0.0, 0.0];
@@ -240,40 +324,49 @@ fn convert_color2<'a>(
                             //debug!("gray={gray}");
                             Ok(Fill::Solid(gray, gray, gray))
                         }
-                        c => unimplemented!("Separation(alt={:?})", c),
+                        c => Err(PdfError::Other {
+                            msg: format!("Separation color space {} with alternate {:?}", name, c),
+                        }),
                     }
                 }
-                ColorSpace::Indexed(ref cs, hival, ref lut) => {
+                ColorSpace::Indexed(ref base, hival, ref lut) => {
                     if args.len() != 1 {
                         return Err(PdfError::Other {
                             msg: format!("expected 1 color arguments, got {:?}", args),
                         });
                     }
-                    let i = args[0].as_integer()?;
-                    match **cs {
-                        ColorSpace::DeviceRGB => {
-                            let c = &lut[3 * i as usize..];
-                            let cvt = |b: u8| b as f32;
-                            Ok(Fill::Solid(cvt(c[0]), cvt(c[1]), cvt(c[2])))
-                        }
-                        ColorSpace::DeviceCMYK => {
-                            let c = &lut[4 * i as usize..];
-                            let cvt = |b: u8| b as f32;
-                            Ok(cmyk2rgb((cvt(c[0]), cvt(c[1]), cvt(c[2]), cvt(c[3])), mode))
-                        }
-                        ref base => unimplemented!("Indexed colorspace with base {:?}", base),
+                    let components = palette_components(base, resources).ok_or_else(|| PdfError::Other {
+                        msg: format!("Indexed color space with base {:?}", base),
+                    })?;
+                    let index = args[0].as_number()?.round().max(0.) as usize;
+                    let entry = images::palette_entry(lut, components, hival as usize, index).ok_or_else(|| PdfError::Other {
+                        msg: format!("Indexed color space with base {:?} and a palette of {} bytes", base, lut.len()),
+                    })?;
+                    let c: Vec<f32> = entry.iter().map(|&b| b as f32 / 255.).collect();
+                    match *c {
+                        [g] => Ok(gray2rgb(g)),
+                        [r, g, b] => Ok(Fill::Solid(r, g, b)),
+                        [c, m, y, k] => Ok(cmyk2rgb((c, m, y, k), mode, group_blending)),
+                        _ => unreachable!("{} components", c.len()),
                     }
                 }
                 ColorSpace::Pattern => {
-                    let name = args[0].as_name()?;
-                    if let Some(&pat) = resources.pattern.get(name) {
-                        Ok(Fill::Pattern(pat))
-                    } else {
-                        unimplemented!("Pattern {} not found", name)
-                    }
+                    let name = args
+                        .last()
+                        .ok_or_else(|| PdfError::Other {
+                            msg: format!("expected a pattern name, got {:?}", args),
+                        })?
+                        .as_name()?;
+                    resources.pattern.get(name).map(|&pat| Fill::Pattern(pat)).ok_or_else(|| PdfError::Other {
+                        msg: format!("pattern {} not found", name),
+                    })
                 }
-                ColorSpace::Other(ref p) => unimplemented!("Other Color space {:?}", p),
-                ColorSpace::Named(ref p) => unimplemented!("nested Named {:?}", p),
+                ColorSpace::Other(ref p) => Err(PdfError::Other {
+                    msg: format!("unknown color space {:?}", p),
+                }),
+                ColorSpace::Named(ref p) => Err(PdfError::Other {
+                    msg: format!("nested named color space {}", p),
+                }),
             }
         }
     }
@@ -283,9 +376,9 @@ fn gray2rgb(g: f32) -> Fill {
     Fill::Solid(g, g, g)
 }
 
-fn cmyk2rgb((c, m, y, k): (f32, f32, f32, f32), mode: BlendMode) -> Fill {
-    let clamp = |f| if f > 1.0 { 1.0 } else { f };
-    Fill::Solid(1.0 - clamp(c + k), 1.0 - clamp(m + k), 1.0 - clamp(y + k))
+fn cmyk2rgb((c, m, y, k): (f32, f32, f32, f32), mode: BlendMode, group_blending: bool) -> Fill {
+    let [r, g, b] = CmykToRgb::new(group_blending).rgb([c, m, y, k]);
+    Fill::Solid(r, g, b)
 }
 
 enum PathTokens {
@@ -293,8 +386,31 @@ enum PathTokens {
     LineTo { x: f32, y: f32 },
 }
 
+#[derive(Serialize, Default, Debug, Clone)]
+pub struct RenderStats {
+    pub ops: usize,
+    pub forms: usize,
+    pub forms_culled: usize,
+    // forms drawn as transparency groups into a layer
+    pub groups: usize,
+    pub images: usize,
+    // images taken from the cache instead of being decoded again
+    pub images_cached: usize,
+    // PostScript and unknown xobjects that were not drawn
+    pub xobjects_skipped: usize,
+    // the page content ended inside a text object, marked content or a path
+    pub truncated: bool,
+    // device area painted in color, in pixels
+    pub colored_area: f32,
+    pub color: ColorUsage,
+    // path and cm operators with NaN or infinite numbers, they are dropped
+    pub bad_numbers: usize,
+    // path points far outside the view box, moved to the edge of --coord-limit
+    pub clamped_points: usize,
+}
+
 pub struct RenderState<'a, R: Resolve, P: Plotter> {
-    graphics_state: GraphicsState<'a, P>,
+    graphics_state: GraphicsState<P>,
     text_state: TextState,
     //text_state: TextState,
     plotter: &'a mut P,
@@ -303,46 +419,61 @@ pub struct RenderState<'a, R: Resolve, P: Plotter> {
     resolve: &'a R,
     resources: &'a Resources,
     transform: Transform2F,
+    // default space of the content stream, the page or the form around it;
+    // pattern matrices map to it
+    pattern_base: Transform2F,
+    view_box: RectF,
     //stack: Vec<(GraphicsState<'a, B>, TextState)>,
     //data: Vec<Command>,
     path: Vec<PathTokens>,
-    stack: Vec<(GraphicsState<'a, P>, TextState)>,
+    // fill rule of a W / W* that waits for the next painting operator
+    pending_clip: Option<FillRule>,
+    stack: Vec<(GraphicsState<P>, TextState)>,
+    ctx: PageContext,
+    timings: Timings,
+    options: &'a RenderOptions,
+    page_nr: u32,
+    pages: u32,
+    op_nr: usize,
+    // nesting level of the executed content stream, 0 is the page
+    depth: usize,
+    // color space of the page transparency group
+    group_color_space: Option<GroupColorSpace>,
+    // between BT and ET
+    in_text: bool,
+    // open BMC / BDC sequences
+    marked_content: Vec<McEntry>,
+    // marked content around the Do of a form
+    enclosing_marked_content: McContext,
+    // layers of the document, without them everything is shown
+    optional_content: Option<&'a OptionalContent>,
+    // set by gs, the following operators are drawn through the mask
+    pending_soft_mask: Option<SoftMask>,
+    // decoded images and the document they are keyed with
+    image_cache: Option<(&'a ImageCache, &'a Path)>,
 }
 
 impl<'a, R: Resolve, P: Plotter> RenderState<'a, R, P> {
     pub fn new(
         plotter: &'a mut P,
-        resolve: &'a mut R,
+        resolve: &'a R,
         resources: &'a Resources,
         transform: Transform2F,
+        view_box: RectF,
+        options: &'a RenderOptions,
+        page_nr: u32,
+        pages: u32,
     ) -> Self {
         Self {
-            graphics_state: GraphicsState {
-                transform,
-                stroke_style: StrokeStyle::default(),
-                fill_color: Fill::black(),
-                fill_color_alpha: 1.0,
-                fill_paint: None,
-                stroke_color: Fill::black(),
-                stroke_color_alpha: 1.0,
-                stroke_paint: None,
-                clip_path_id: None,
-                //clip_path: None,
-                //clip_path_rect: None,
-                fill_color_space: &ColorSpace::DeviceRGB,
-                stroke_color_space: &ColorSpace::DeviceRGB,
-                dash_pattern: None,
-                stroke_alpha: 1.0,
-                fill_alpha: 1.0,
-                overprint_fill: false,
-                overprint_stroke: false,
-                overprint_mode: 0,
-            },
+            graphics_state: GraphicsState::new(transform),
             plotter,
             resolve,
             resources,
             transform,
+            pattern_base: transform,
+            view_box,
             path: vec![],
+            pending_clip: None,
             text_state: TextState::new(),
             //resolve,
             //resources,
@@ -350,6 +481,71 @@ impl<'a, R: Resolve, P: Plotter> RenderState<'a, R, P> {
             //data: vec![],
             current_outline: Outline::new(),
             current_contour: Contour::new(),
+            ctx: PageContext {
+                dump_ops: options.dumps_ops().then(String::new),
+                ..PageContext::new(page_nr)
+            },
+            timings: Timings::default(),
+            options,
+            page_nr,
+            pages,
+            op_nr: 0,
+            depth: 0,
+            group_color_space: None,
+            in_text: false,
+            marked_content: vec![],
+            enclosing_marked_content: McContext::default(),
+            optional_content: None,
+            pending_soft_mask: None,
+            image_cache: None,
+        }
+    }
+
+    pub fn context(&self) -> &PageContext {
+        &self.ctx
+    }
+    pub fn set_optional_content(&mut self, content: &'a OptionalContent) {
+        self.optional_content = Some(content);
+    }
+    pub fn set_image_cache(&mut self, cache: &'a ImageCache, document: &'a Path) {
+        self.image_cache = Some((cache, document));
+    }
+    // keep the decoded images in the page context
+    pub fn collect_images(&mut self) {
+        self.ctx.images = Some(vec![]);
+    }
+    pub fn into_context(self) -> PageContext {
+        self.ctx
+    }
+    // decode and render times of the page
+    pub fn timings(&self) -> Timings {
+        self.timings
+    }
+    fn warn(&mut self, msg: String) {
+        warn!("page {} op {}: {}", self.page_nr, self.op_nr, msg);
+        self.ctx.warnings.push(msg);
+    }
+    // None if one of the points is not finite, the operator is dropped then.
+    // Points far outside the view box are moved towards it, a huge outline
+    // only costs memory and time when it is rasterized.
+    fn sane_points<const N: usize>(&mut self, points: [Vector2F; N]) -> Option<[Vector2F; N]> {
+        if !points.iter().all(|p| p.x().is_finite() && p.y().is_finite()) {
+            self.ctx.stats.bad_numbers += 1;
+            return None;
+        }
+        let limit = self.view_box.dilate(self.view_box.size() * self.options.coord_limit);
+        Some(points.map(|p| match geometry::clamp_point(p, self.graphics_state.transform, limit) {
+            Some(clamped) => {
+                self.ctx.stats.clamped_points += 1;
+                clamped
+            }
+            None => p,
+        }))
+    }
+    // report each unsupported operator only once per page
+    fn unsupported(&mut self, op: &'static str) {
+        if self.ctx.unsupported.insert(op) {
+            self.warn(format!("unsupported operator: {}", op));
         }
     }
 
@@ -369,46 +565,274 @@ impl<'a, R: Resolve, P: Plotter> RenderState<'a, R, P> {
             self.current_contour.clear();
         }
     }
-    fn color_space(&self, name: &str) -> Result<&'a ColorSpace, PdfError> {
-        match name {
-            "DeviceGray" => return Ok(&ColorSpace::DeviceGray),
-            "DeviceRGB" => return Ok(&ColorSpace::DeviceRGB),
-            "DeviceCMYK" => return Ok(&ColorSpace::DeviceCMYK),
-            "Pattern" => return Ok(&ColorSpace::Pattern),
-            _ => {}
-        }
-        match self.resources.color_spaces.get(name) {
-            Some(cs) => Ok(cs),
-            None => Err(PdfError::Other {
-                msg: format!("color space {:?} not present", name),
-            }),
-        }
+    fn color_space(&self, name: &str) -> Result<Arc<ColorSpace>, PdfError> {
+        let cs = match name {
+            "DeviceGray" => ColorSpace::DeviceGray,
+            "DeviceRGB" => ColorSpace::DeviceRGB,
+            "DeviceCMYK" => ColorSpace::DeviceCMYK,
+            "Pattern" => ColorSpace::Pattern,
+            _ => match self.resources.color_spaces.get(name) {
+                Some(cs) => cs.clone(),
+                None => {
+                    return Err(PdfError::Other {
+                        msg: format!("color space {:?} not present", name),
+                    })
+                }
+            },
+        };
+        Ok(Arc::new(cs))
+    }
+    // cmyk colors painted with a blend mode inside a cmyk page group
+    fn group_blending(&self) -> bool {
+        self.options.group_aware_blending
+            && self.graphics_state.blend_mode.is_some()
+            && self.group_color_space.as_ref().map_or(false, |cs| cs.is_cmyk())
     }
     fn blend_mode_stroke(&self) -> BlendMode {
-        if self.graphics_state.overprint_stroke {
-            BlendMode::Darken
+        if let Some(mode) = self.graphics_state.blend_mode {
+            mode
+        } else if self.graphics_state.overprint_stroke {
+            // overprinting ink darkens what is below
+            BlendMode::Multiply
         } else {
-            BlendMode::Overlay
+            BlendMode::Normal
         }
     }
     fn blend_mode_fill(&self) -> BlendMode {
-        if self.graphics_state.overprint_fill {
-            BlendMode::Darken
+        if let Some(mode) = self.graphics_state.blend_mode {
+            mode
+        } else if self.graphics_state.overprint_fill {
+            // overprinting ink darkens what is below
+            BlendMode::Multiply
         } else {
-            BlendMode::Overlay
+            BlendMode::Normal
         }
     }
     fn draw(&mut self, mode: &DrawMode, fill_rule: FillRule) {
         self.flush();
-        self.plotter.draw(
-            &self.current_outline,
-            mode,
-            fill_rule,
-            self.graphics_state.transform,
-            self.graphics_state.clip_path_id,
-        );
+        // hidden paths still clip
+        if self.hidden() {
+            self.apply_clip();
+            self.current_outline.clear();
+            return;
+        }
+        let widened = match *mode {
+            DrawMode::Fill { .. } => None,
+            DrawMode::Stroke { ref stroke, ref stroke_mode } => {
+                self.widen_stroke(stroke_mode).map(|stroke_mode| DrawMode::Stroke { stroke: stroke.clone(), stroke_mode })
+            }
+            DrawMode::FillStroke { ref fill, ref stroke, ref stroke_mode } => self
+                .widen_stroke(stroke_mode)
+                .map(|stroke_mode| DrawMode::FillStroke { fill: fill.clone(), stroke: stroke.clone(), stroke_mode }),
+        };
+        let mode = widened.as_ref().unwrap_or(mode);
+        self.count_draw_color(mode);
+        let (transform, clip) = (self.graphics_state.transform, self.graphics_state.clip_path_id);
+        let (fill, stroke) = match mode {
+            DrawMode::Fill { fill } => (Some(fill), None),
+            DrawMode::Stroke { stroke, stroke_mode } => (None, Some((stroke, stroke_mode))),
+            DrawMode::FillStroke { fill, stroke, stroke_mode } => (Some(fill), Some((stroke, stroke_mode))),
+        };
+        // painted after the path, in the stroke color
+        let dots = stroke.and_then(|(_, s)| geometry::zero_length_dots(&self.current_outline, s.style.line_cap, s.style.line_width));
+        let is_pattern = |mode: Option<&FillMode>| matches!(mode, Some(FillMode { color: Fill::Pattern(_), .. }));
+        if !is_pattern(fill) && !is_pattern(stroke.map(|s| s.0)) {
+            let snapped = match mode {
+                DrawMode::Fill { .. } if self.options.snap_rects => geometry::snap_rects(&self.current_outline, transform, self.view_box.origin()),
+                _ => None,
+            };
+            match snapped {
+                // already in device space
+                Some(ref outline) => self.plotter.draw(outline, mode, fill_rule, Transform2F::default(), clip),
+                None => self.plotter.draw(&self.current_outline, mode, fill_rule, transform, clip),
+            }
+        } else {
+            // pattern fills and strokes are painted on their own, in the same order
+            let outline = std::mem::take(&mut self.current_outline);
+            if let Some(fill) = fill {
+                match fill.color {
+                    Fill::Pattern(pattern) => self.fill_pattern(pattern, outline.clone().transformed(&transform), fill_rule),
+                    _ => self.plotter.draw(&outline, &DrawMode::Fill { fill: fill.clone() }, fill_rule, transform, clip),
+                }
+            }
+            if let Some((stroke, stroke_mode)) = stroke {
+                match stroke.color {
+                    Fill::Pattern(pattern) => {
+                        let area = stroke_mode.to_outline(&outline).transformed(&transform);
+                        self.fill_pattern(pattern, area, FillRule::Winding)
+                    }
+                    _ => {
+                        let mode = DrawMode::Stroke { stroke: stroke.clone(), stroke_mode: stroke_mode.clone() };
+                        self.plotter.draw(&outline, &mode, FillRule::Winding, transform, clip)
+                    }
+                }
+            }
+            self.current_outline = outline;
+        }
+        if let (Some(dots), Some((stroke, _))) = (dots, stroke) {
+            match stroke.color {
+                Fill::Pattern(pattern) => self.fill_pattern(pattern, dots.transformed(&transform), FillRule::Winding),
+                _ => self.plotter.draw(&dots, &DrawMode::Fill { fill: stroke.clone() }, FillRule::Winding, transform, clip),
+            }
+        }
+        self.apply_clip();
         self.current_outline.clear();
     }
+    // Add the device space bounds, cut down to the clip and the view box, to
+    // the colored area. fraction is the part of the bounds that is colored.
+    fn count_color(&mut self, bounds: RectF, fraction: f32) {
+        if !(fraction > 0.) {
+            return;
+        }
+        let bounds = match self.graphics_state.clip_bounds {
+            Some(clip) => bounds.intersection(clip).and_then(|b| b.intersection(self.view_box)),
+            None => bounds.intersection(self.view_box),
+        };
+        if let Some(bounds) = bounds {
+            self.ctx.stats.colored_area += fraction * bounds.width() * bounds.height();
+        }
+    }
+    fn count_draw_color(&mut self, mode: &DrawMode) {
+        let transform = self.graphics_state.transform;
+        let bounds = transform * self.current_outline.bounds();
+        let colored = |mode: &FillMode| mode.alpha > 0. && color_usage::is_colored(&mode.color);
+        let (fill, stroke) = match mode {
+            DrawMode::Fill { fill } => (Some(fill), None),
+            DrawMode::Stroke { stroke, stroke_mode } => (None, Some((stroke, stroke_mode))),
+            DrawMode::FillStroke { fill, stroke, stroke_mode } => (Some(fill), Some((stroke, stroke_mode))),
+        };
+        if fill.is_some_and(colored) {
+            self.count_color(bounds, 1.);
+        }
+        if let Some((stroke, stroke_mode)) = stroke.filter(|&(stroke, _)| colored(stroke)) {
+            let width = stroke_mode.style.line_width * transform.matrix.det().abs().sqrt();
+            self.count_color(bounds.dilate(Vector2F::splat(width * 0.5)), 1.);
+        }
+    }
+    // With --min-stroke-px a stroke that is thinner in device space, in its
+    // thinnest direction, is widened to the minimum. Wider strokes are kept.
+    fn widen_stroke(&self, stroke: &Stroke) -> Option<Stroke> {
+        // a width of 0 is the thinnest line the device can show
+        let min = match stroke.style.line_width {
+            width if width == 0. => {
+                let hairline = self.options.hairline_width.unwrap_or_else(|| self.plotter.hairline_width());
+                hairline.max(self.options.min_stroke_px.unwrap_or(0.))
+            }
+            _ => self.options.min_stroke_px?,
+        };
+        let scale = geometry::min_scale(self.graphics_state.transform);
+        if !(scale > 0.) || stroke.style.line_width * scale >= min {
+            return None;
+        }
+        let mut stroke = stroke.clone();
+        stroke.style.line_width = min / scale;
+        Some(stroke)
+    }
+    // Paint the device space outline with a pattern. Patterns that can not be
+    // rendered are painted black.
+    fn fill_pattern(&mut self, pattern: Ref<Pattern>, outline: Outline, fill_rule: FillRule) {
+        let clip = self.graphics_state.clip_path_id;
+        match self.tiling(pattern) {
+            Ok(Some(tiling)) => {
+                self.count_color(outline.bounds(), color_usage::colored_fraction(&tiling.image));
+                self.plotter.draw_tiling(outline, fill_rule, &tiling, clip)
+            }
+            result => {
+                if let Err(e) = result {
+                    self.warn(format!("failed to render pattern: {:?}", e));
+                }
+                let mode = DrawMode::Fill {
+                    fill: FillMode { color: Fill::black(), alpha: 1.0, mode: self.blend_mode_fill() },
+                };
+                self.plotter.draw(&outline, &mode, fill_rule, Transform2F::default(), clip);
+            }
+        }
+    }
+    // the cell of each pattern is rendered once per page and pattern space
+    fn tiling(&mut self, pattern: Ref<Pattern>) -> Result<Option<Arc<Tiling>>, PdfError> {
+        let key = pattern.get_inner();
+        let cache_key = (key, geometry::transform_bits(self.pattern_base));
+        if let Some(tiling) = self.ctx.tilings.get(&cache_key) {
+            return Ok(tiling.clone());
+        }
+        let tiling = self.render_tiling(key)?;
+        self.ctx.tilings.insert(cache_key, tiling.clone());
+        Ok(tiling)
+    }
+    // Render one cell of a colored tiling pattern into an image at device resolution.
+    fn render_tiling(&mut self, key: PlainRef) -> Result<Option<Arc<Tiling>>, PdfError> {
+        if self.depth >= self.options.max_depth {
+            self.warn(format!("content nested deeper than {}, pattern skipped", self.options.max_depth));
+            return Ok(None);
+        }
+        let stream = match self.resolve.resolve(key)? {
+            p @ Primitive::Stream(_) => Stream::<Dictionary>::from_primitive(p, self.resolve)?,
+            _ => {
+                self.unsupported("shading pattern");
+                return Ok(None);
+            }
+        };
+        let dict = &stream.info.info;
+        if pattern::pattern_type(dict)? != 1 {
+            self.unsupported("shading pattern");
+            return Ok(None);
+        }
+        let pattern = TilingPattern::from_dict(dict)?;
+        if pattern.paint_type != 1 {
+            self.unsupported("uncolored tiling pattern");
+            return Ok(None);
+        }
+        let own_resources = match dict.get("Resources") {
+            Some(p) => Some(Resources::from_primitive(p.clone(), self.resolve)?),
+            None => None,
+        };
+        let resources = own_resources.as_ref().unwrap_or(self.resources);
+        let ops = parse_ops(&stream.data(self.resolve)?, self.resolve)?;
+
+        let Some(matrix) = self.checked_matrix(pattern.matrix, "pattern") else {
+            return Ok(None);
+        };
+        // the pattern matrix maps to the default space of the content stream that
+        // uses the pattern, for a form that is the form space where it is drawn
+        let cell = self.pattern_base * matrix * pattern.cell();
+        let (width, height) = TilingPattern::tile_size(cell);
+        debug!("page {} op {}: tiling pattern cell of {}x{} pixels", self.page_nr, self.op_nr, width, height);
+        let view_box = RectF::new(Vector2F::zero(), Vector2F::new(width as f32, height as f32));
+        let mut plotter = PngPlotter::transparent(view_box);
+        for transform in pattern.tile_transforms(width, height) {
+            let mut inner = RenderState::new(&mut plotter, self.resolve, resources, transform, view_box, self.options, self.page_nr, self.pages);
+            inner.depth = self.depth + 1;
+            inner.optional_content = self.optional_content;
+            inner.image_cache = self.image_cache;
+            inner.ctx = std::mem::take(&mut self.ctx);
+            let result = inner.render_ops(&ops);
+            self.ctx = inner.ctx;
+            result?;
+        }
+        Ok(Some(Arc::new(Tiling { image: plotter.rasterize(), transform: cell })))
+    }
+    // Intersect the clip with the current path, after it has been painted. Q
+    // restores the previous clip with the graphics state.
+    // clip to a rectangle in user space, as re W n would
+    fn clip_rect(&mut self, rect: RectF) {
+        let path = std::mem::replace(&mut self.current_outline, Outline::from_rect(rect));
+        self.pending_clip = Some(FillRule::Winding);
+        self.apply_clip();
+        self.current_outline = path;
+    }
+    fn apply_clip(&mut self) {
+        let Some(fill_rule) = self.pending_clip.take() else {
+            return;
+        };
+        let outline = self.current_outline.clone().transformed(&self.graphics_state.transform);
+        let bounds = outline.bounds();
+        let parent = self.graphics_state.clip_path_id;
+        self.graphics_state.clip_path_id = Some(self.plotter.create_clip_path(outline, fill_rule, parent));
+        self.graphics_state.clip_bounds = Some(match self.graphics_state.clip_bounds {
+            Some(clip) => clip.intersection(bounds).unwrap_or_default(),
+            None => bounds,
+        });
+    }
    fn text(&mut self, inner: impl FnOnce(&mut P, &mut TextState, &mut GraphicsState<P>, &mut Span), op_nr: usize) {
         let mut span = Span::default();
         let tm = self.text_state.text_matrix;
@@ -421,7 +845,7 @@ impl<'a, R: Resolve, P: Plotter> RenderState<'a, R, P> {
         let p2 = (tm * Transform2F::from_translation(Vector2F::new(span.width, self.text_state.font_size))).translation();
         let clip = self.graphics_state.clip_path_id;
 
-        println!("text {}", span.text);
+        debug!("text {}", span.text);
         //self.plotter.add_text(TextSpan {
         //    rect: self.graphics_state.transform * RectF::from_points(p1.min(p2), p1.max(p2)),
         //    width: span.width,
@@ -431,254 +855,1994 @@ impl<'a, R: Resolve, P: Plotter> RenderState<'a, R, P> {
         //    font: self.text_state.font_entry.clone(),
         //    font_size: self.text_state.font_size,
         //    color: self.graphics_state.fill_color,
-        //    alpha: self.graphics_state.fill_color_alpha,
+        //    alpha: self.graphics_state.effective_fill_alpha(),
         //    mode: self.text_state.mode,
         //    transform,
-        //    op_nr
+        //    op_nr,
+        //    marked_content: self.marked_content_context(),
         //}, clip);
     }
+    fn form_matrix(&self, form: &FormXObject) -> Result<Transform2F, PdfError> {
+        match form.dict().matrix {
+            Some(ref m) => Ok(Matrix::from_primitive(m.clone(), self.resolve)?.cvt()),
+            None => Ok(Transform2F::default()),
+        }
+    }
+    // the /BBox in form space, None when it has NaN or infinite corners
+    fn form_bbox(form: &FormXObject) -> Option<RectF> {
+        let pdf::object::Rect { left, bottom, right, top } = form.dict().bbox;
+        if ![left, bottom, right, top].iter().all(|v| v.is_finite()) {
+            return None;
+        }
+        Some(RectF::from_points(
+            Vector2F::new(left.min(right), bottom.min(top)),
+            Vector2F::new(left.max(right), bottom.max(top)),
+        ))
+    }
+    // A form can only be skipped when its bbox is usable and, transformed to device
+    // space, misses the view box or the current clip.
+    fn form_is_invisible(&self, form: &FormXObject, matrix: Transform2F) -> bool {
+        let Some(bbox) = Self::form_bbox(form) else {
+            return false;
+        };
+        let device_bbox = self.graphics_state.transform * matrix * bbox;
+        if !device_bbox.intersects(self.view_box) {
+            return true;
+        }
+        match self.graphics_state.clip_bounds {
+            Some(clip) => !device_bbox.intersects(clip),
+            None => false,
+        }
+    }
+    // A matrix that squashes everything to a line or a point. Strict parsing
+    // skips what it applies to, lenient parsing uses the identity instead.
+    fn checked_matrix(&mut self, matrix: Transform2F, what: &str) -> Option<Transform2F> {
+        if !geometry::is_singular(matrix) {
+            return Some(matrix);
+        }
+        let lenient = self.resolve.options().allow_error_in_option;
+        let action = if lenient { "replaced by the identity" } else { "skipped" };
+        self.warn(format!("singular {} matrix {:?}, {}", what, matrix, action));
+        lenient.then(Transform2F::default)
+    }
+    fn draw_form(&mut self, form: &FormXObject, group: Option<TransparencyGroup>) -> Result<(), PdfError> {
+        self.flush();
+        if self.depth >= self.options.max_depth {
+            self.warn(format!("content nested deeper than {}, form skipped", self.options.max_depth));
+            return Ok(());
+        }
+        let Some(matrix) = self.checked_matrix(self.form_matrix(form)?, "form") else {
+            return Ok(());
+        };
+        self.ctx.stats.forms += 1;
+        if self.form_is_invisible(form, matrix) {
+            debug!("page {} op {}: skip form outside of the visible area", self.page_nr, self.op_nr);
+            self.ctx.stats.forms_culled += 1;
+            return Ok(());
+        }
+        let ops = form.operations(self.resolve)?;
+        let resources = match form.dict().resources {
+            Some(ref r) => &**r,
+            None => self.resources,
+        };
+        let alpha = self.graphics_state.effective_fill_alpha();
+        let mode = self.blend_mode_fill();
+        if let Some(ref group) = group {
+            // an opaque group looks the same when its objects are drawn directly
+            if (alpha < 1. || mode != BlendMode::Normal) && self.depth < MAX_LAYER_DEPTH {
+                return self.draw_group(&ops, resources, matrix, Self::form_bbox(form), group, alpha, mode);
+            }
+        }
+        let mut graphics_state = self.graphics_state.clone();
+        graphics_state.transform = graphics_state.transform * matrix;
+        let pattern_base = graphics_state.transform;
+
+        let mut inner = RenderState {
+            graphics_state,
+            text_state: self.text_state.clone(),
+            plotter: &mut *self.plotter,
+            current_outline: Outline::new(),
+            current_contour: Contour::new(),
+            resolve: self.resolve,
+            resources,
+            transform: self.transform,
+            pattern_base,
+            view_box: self.view_box,
+            path: vec![],
+            pending_clip: None,
+            stack: vec![],
+            ctx: std::mem::take(&mut self.ctx),
+            timings: Timings::default(),
+            options: self.options,
+            page_nr: self.page_nr,
+            pages: self.pages,
+            op_nr: self.op_nr,
+            depth: self.depth + 1,
+            group_color_space: group.and_then(|g| g.color_space).or_else(|| self.group_color_space.clone()),
+            in_text: false,
+            marked_content: vec![],
+            enclosing_marked_content: self.marked_content_context(),
+            optional_content: self.optional_content,
+            pending_soft_mask: None,
+            image_cache: self.image_cache,
+        };
+        if let Some(bbox) = Self::form_bbox(form) {
+            inner.clip_rect(bbox);
+        }
+        let result = inner.render_ops(&ops);
+        self.ctx = inner.ctx;
+        result
+    }
+    // Draw the group into its own layer, the constant alpha and blend mode apply
+    // to the result. Inside the group they start over.
+    fn draw_group(
+        &mut self,
+        ops: &[Op],
+        resources: &Resources,
+        matrix: Transform2F,
+        bbox: Option<RectF>,
+        group: &TransparencyGroup,
+        alpha: f32,
+        mode: BlendMode,
+    ) -> Result<(), PdfError> {
+        if group.knockout && self.ctx.unsupported.insert("knockout group") {
+            self.warn("knockout group drawn like a normal group".into());
+        }
+        // The layer starts transparent, as for an isolated group. A group that is
+        // not isolated differs only where its objects use blend modes themselves.
+        let mut layer = PngPlotter::transparent(self.view_box);
+        let mut inner = self.begin_layer(&mut layer, resources);
+        inner.graphics_state.copy_from(&self.graphics_state);
+        inner.graphics_state.transform = self.graphics_state.transform * matrix;
+        inner.pattern_base = inner.graphics_state.transform;
+        inner.graphics_state.fill_alpha = 1.0;
+        inner.graphics_state.stroke_alpha = 1.0;
+        inner.graphics_state.group_fill_alpha = 1.0;
+        inner.graphics_state.group_stroke_alpha = 1.0;
+        inner.graphics_state.blend_mode = None;
+        inner.text_state = self.text_state.clone();
+        if let Some(ref cs) = group.color_space {
+            inner.group_color_space = Some(cs.clone());
+        }
+        if let Some(bbox) = bbox {
+            inner.clip_rect(bbox);
+        }
+        let result = inner.render_ops(ops);
+        self.end_layer(&mut inner);
+        drop(inner);
+        result?;
+        self.ctx.stats.groups += 1;
+        self.composite_layer(layer.rasterize(), None, alpha, mode);
+        Ok(())
+    }
+    // Inline images abbreviate the device spaces and /Indexed, other names are
+    // looked up in the resources. A name that is not there is guessed from the
+    // number of samples, with a warning.
+    fn image_color_space<'c>(&mut self, image: &'c ImageXObject, data: &[u8]) -> Result<Option<Cow<'c, ColorSpace>>, PdfError>
+    where
+        'a: 'c,
+    {
+        let Some(ref color_space) = image.color_space else {
+            return Ok(None);
+        };
+        let name = match *color_space {
+            ColorSpace::Named(ref name) => name.as_str(),
+            ColorSpace::Other(ref parts) if matches!(parts.first(), Some(Primitive::Name(name)) if name.as_str() == "I") => {
+                let mut parts = parts.clone();
+                parts[0] = Primitive::Name("Indexed".into());
+                return Ok(Some(Cow::Owned(ColorSpace::from_primitive(Primitive::Array(parts), self.resolve)?)));
+            }
+            ref cs => return Ok(Some(Cow::Borrowed(cs))),
+        };
+        let resources = self.resources;
+        if let Some(space) = named_device_space(name).or_else(|| resources.color_spaces.get(name)) {
+            return Ok(Some(Cow::Borrowed(space)));
+        }
+        let bits = image.bits_per_component.unwrap_or(8) as usize;
+        let (width, height) = (image.width as usize, image.height as usize);
+        // inline data may keep the end of line in front of EI
+        let fits = |n: usize| matches!(data.len().checked_sub((width * n * bits + 7) / 8 * height), Some(0 | 1));
+        let guess = (1..=4).find(|&n| height > 0 && fits(n)).and_then(device_space);
+        match guess {
+            Some(space) => self.warn(format!("image color space {} not found, drawn as {:?}", name, space)),
+            None => self.warn(format!("image color space {} not found", name)),
+        }
+        Ok(guess.map(Cow::Borrowed))
+    }
+    // r is the reference of an image xobject, inline images have none
+    fn draw_image(&mut self, image: &ImageXObject, r: Option<PlainRef>) -> Result<(), PdfError> {
+        if self.hidden() {
+            return Ok(());
+        }
+        self.ctx.stats.images += 1;
+        // stencil masks take the fill color and are decoded at every Do
+        let key = match (self.image_cache, r) {
+            (Some((cache, document)), Some(r)) if !image.image_mask => {
+                Some((cache, ImageKey { document: document.into(), image: r, cmyk: CmykToRgb::new(self.group_blending()) }))
+            }
+            _ => None,
+        };
+        let rgba = match key.as_ref().and_then(|(cache, key)| cache.get(key)) {
+            Some(rgba) => {
+                self.ctx.stats.images_cached += 1;
+                rgba
+            }
+            None => {
+                let Some(rgba) = self.decode(image)? else {
+                    return Ok(());
+                };
+                let rgba = Arc::new(rgba);
+                if let Some((cache, key)) = key {
+                    cache.insert(key, rgba.clone());
+                }
+                rgba
+            }
+        };
+        self.count_image_color(&rgba);
+        if let Some(images) = self.ctx.images.as_mut() {
+            images.push((r, rgba.clone()));
+        }
+        // stencil masks have the fill alpha from their fill color already
+        let alpha = match image.image_mask {
+            true => 1.,
+            false => self.graphics_state.effective_fill_alpha(),
+        };
+        let (transform, clip) = (self.graphics_state.transform, self.graphics_state.clip_path_id);
+        self.plotter.draw_image(&rgba, transform, clip, alpha, image.interpolate);
+        Ok(())
+    }
+    // the image as it is drawn, with its soft mask applied, None when it is skipped
+    pub fn decode(&mut self, image: &ImageXObject) -> Result<Option<RgbaImage>, PdfError> {
+        let Some(mut rgba) = self.decode_image(image)? else {
+            return Ok(None);
+        };
+        if let (Some(smask), false) = (image.smask, image.image_mask) {
+            self.apply_image_soft_mask(smask.get_inner(), &mut rgba)?;
+        }
+        Ok(Some(rgba))
+    }
+    // The samples of the image as rgba, None when it is skipped. Stencil masks
+    // come out in the fill color.
+    fn decode_image(&mut self, image: &ImageXObject) -> Result<Option<RgbaImage>, PdfError> {
+        let (data, filter) = image.raw_image_data(self.resolve)?;
+        let jpeg = matches!(filter, Some(StreamFilter::DCTDecode(_)));
+        let data = match filter {
+            Some(StreamFilter::CCITTFaxDecode(params)) => self.fax_rows(&data, params, image)?.into(),
+            #[cfg(feature = "jbig2")]
+            Some(StreamFilter::JBIG2Decode(params)) => match self.jbig2_rows(&data, params, image) {
+                Ok(rows) => rows.into(),
+                Err(e) => {
+                    self.warn(format!("jbig2 image skipped: {}", e));
+                    return Ok(None);
+                }
+            },
+            #[cfg(not(feature = "jbig2"))]
+            Some(StreamFilter::JBIG2Decode(_)) => {
+                self.unsupported("jbig2 images");
+                return Ok(None);
+            }
+            Some(StreamFilter::DCTDecode(_)) | None => data,
+            Some(_) => image.image_data(self.resolve)?,
+        };
+        if image.image_mask {
+            // the fill color and alpha at the Do, the mask only decides where they go
+            let (r, g, b) = match self.graphics_state.fill_color {
+                Fill::Solid(r, g, b) => (r, g, b),
+                Fill::Pattern(_) => {
+                    self.unsupported("image mask filled with a pattern");
+                    (0., 0., 0.)
+                }
+            };
+            let alpha = self.graphics_state.effective_fill_alpha();
+            let color = [r, g, b, alpha].map(|c| (c.clamp(0., 1.) * 255.).round() as u8);
+            let invert = images::is_inverted(image.decode.as_deref());
+            return images::decode_stencil(&data, image.width, image.height, invert, color).map(Some);
+        }
+        let color_space = self.image_color_space(image, &data)?;
+        let palette = match color_space.as_deref() {
+            Some(&ColorSpace::Indexed(ref base, hival, ref lut)) => {
+                Some((palette_components(base, self.resources), hival as usize, lut.to_vec()))
+            }
+            _ => None,
+        };
+        if let (Some((base, hival, lut)), false) = (palette, jpeg) {
+            let Some(components) = base else {
+                self.unsupported("indexed image base color space");
+                return Ok(None);
+            };
+            return self.decode_indexed(image, &data, components, hival, &lut).map(Some);
+        }
+        let (mut samples, components, width, height) = match jpeg {
+            // the jpeg knows its own layout, also when the color space is an icc profile
+            true => images::decode_jpeg(&data)?,
+            false => {
+                let Some(components) = color_space.as_deref().and_then(images::components) else {
+                    self.unsupported("image color space");
+                    return Ok(None);
+                };
+                let samples = match image.bits_per_component {
+                    Some(8) => data.to_vec(),
+                    // rows of fewer bits start at a byte, 16 bit samples are rounded to 8 bit
+                    Some(bits @ (1 | 2 | 4 | 16)) => images::expand_bits(&data, image.width, image.height, components, bits as u32)?,
+                    _ => {
+                        self.unsupported("image bits per component");
+                        return Ok(None);
+                    }
+                };
+                (samples, components, image.width, image.height)
+            }
+        };
+        // the color key compares the samples before /Decode
+        let keyed = match image.mask {
+            Some(Primitive::Array(ref ranges)) => {
+                let bits = if jpeg { 8 } else { image.bits_per_component.unwrap_or(8) };
+                self.color_key_ranges(ranges, components, bits)?.map(|ranges| images::color_key(&samples, components, &ranges))
+            }
+            _ => None,
+        };
+        if let Some(decode) = image.decode.as_deref() {
+            images::apply_decode(&mut samples, components, decode);
+        }
+        let mut rgba = images::decode_samples(&samples, width, height, components, CmykToRgb::new(self.group_blending()))?;
+        if let Some(keyed) = keyed {
+            images::clear_keyed(&mut rgba, &keyed);
+        }
+        Ok(Some(rgba))
+    }
+    // The samples of an indexed image are palette indices, the color key and
+    // /Decode apply to the indices before the lookup.
+    fn decode_indexed(&mut self, image: &ImageXObject, data: &[u8], components: usize, hival: usize, palette: &[u8]) -> Result<RgbaImage, PdfError> {
+        let bits = image.bits_per_component.unwrap_or(8) as u32;
+        let mut indices = images::unpack_samples(data, image.width, image.height, 1, bits)?;
+        let keyed = match image.mask {
+            Some(Primitive::Array(ref ranges)) => self.color_key_ranges(ranges, 1, 8)?.map(|ranges| images::color_key(&indices, 1, &ranges)),
+            _ => None,
+        };
+        if let Some(decode) = image.decode.as_deref() {
+            images::decode_indices(&mut indices, bits, hival, decode);
+        }
+        let samples = images::lookup_palette(&indices, components, hival, palette)?;
+        let mut rgba = images::decode_samples(&samples, image.width, image.height, components, CmykToRgb::new(self.group_blending()))?;
+        if let Some(keyed) = keyed {
+            images::clear_keyed(&mut rgba, &keyed);
+        }
+        Ok(rgba)
+    }
+    // The /Mask [min0 max0 min1 max1 ...] ranges of the samples, scaled from
+    // bits per component to the 8 bit samples they are compared with.
+    fn color_key_ranges(&mut self, ranges: &[Primitive], components: usize, bits: i32) -> Result<Option<Vec<(u8, u8)>>, PdfError> {
+        if ranges.len() != 2 * components {
+            self.warn(format!("color key mask with {} values for {} components ignored", ranges.len(), components));
+            return Ok(None);
+        }
+        let max = (1i64 << bits.clamp(1, 16)) - 1;
+        let scale = |v: &Primitive| -> Result<u8, PdfError> { Ok(((v.as_integer()? as i64).clamp(0, max) * 255 / max) as u8) };
+        let values = ranges.iter().map(scale).collect::<Result<Vec<u8>, _>>()?;
+        Ok(Some(values.chunks_exact(2).map(|r| (r[0], r[1])).collect()))
+    }
+    // Take the alpha of the image from its /SMask, a gray image that may have
+    // its own size and /Decode.
+    fn apply_image_soft_mask(&mut self, smask: PlainRef, rgba: &mut RgbaImage) -> Result<(), PdfError> {
+        let mut stream = match self.resolve.resolve(smask)? {
+            Primitive::Stream(stream) => stream,
+            p => {
+                return Err(PdfError::Other {
+                    msg: format!("image /SMask is not a stream: {:?}", p),
+                })
+            }
+        };
+        // soft masks often leave out the subtype
+        if stream.info.get("Subtype").is_none() {
+            stream.info.insert("Subtype", Primitive::Name("Image".into()));
+        }
+        let XObject::Image(mask) = XObject::from_primitive(Primitive::Stream(stream), self.resolve)? else {
+            return Err(PdfError::Other {
+                msg: "image /SMask is not an image".into(),
+            });
+        };
+        let Some(alpha) = self.decode_image(&mask)? else {
+            self.warn("image soft mask skipped".into());
+            return Ok(());
+        };
+        // the color the image was premultiplied with, in its own color space
+        let matte = match mask.other.get("Matte") {
+            Some(matte) => {
+                let values = matte
+                    .as_array()?
+                    .iter()
+                    .map(|v| v.as_number().map(|v| (v.clamp(0., 1.) * 255.).round() as u8))
+                    .collect::<Result<Vec<u8>, _>>()?;
+                match values.len() {
+                    1 | 3 | 4 => Some(images::decode_samples(&values, 1, 1, values.len(), CmykToRgb::new(self.group_blending()))?.pixels),
+                    _ => None,
+                }
+            }
+            None => None,
+        };
+        images::apply_soft_mask(rgba, &alpha, matte.as_deref());
+        Ok(())
+    }
+    // fax data as 1 bit rows, the rows missing at the end of the data stay white
+    fn fax_rows(&mut self, data: &[u8], params: &CCITTFaxDecodeParams, image: &ImageXObject) -> Result<Vec<u8>, PdfError> {
+        let (rows, decoded) = fax::decode(data, params, image.width, image.height)?;
+        if decoded < image.height {
+            self.warn(format!("fax image ends after {} of {} rows", decoded, image.height));
+        }
+        Ok(rows)
+    }
+    // jbig2 data as 1 bit rows, the segments of the globals stream come first
+    #[cfg(feature = "jbig2")]
+    fn jbig2_rows(&mut self, data: &[u8], params: &JBIG2DecodeParams, image: &ImageXObject) -> Result<Vec<u8>, PdfError> {
+        let globals = params.globals.as_ref().map(|globals| globals.data(self.resolve)).transpose()?;
+        jbig2::decode(data, globals.as_deref(), image.width, image.height)
+    }
+    // images cover the unit square of the transform
+    fn count_image_color(&mut self, rgba: &RgbaImage) {
+        let bounds = self.graphics_state.transform * RectF::new(Vector2F::zero(), Vector2F::splat(1.));
+        self.count_color(bounds, color_usage::colored_fraction(rgba));
+    }
+    fn draw_xobject(&mut self, name: &Name) -> Result<(), PdfError> {
+        if self.hidden() {
+            return Ok(());
+        }
+        let Some(&r) = self.resources.xobjects.get(name) else {
+            self.warn(format!("missing xobject {}", name));
+            return Ok(());
+        };
+        let mut stream = match self.resolve.resolve(r.get_inner())? {
+            Primitive::Stream(stream) => stream,
+            p => {
+                return Err(PdfError::Other {
+                    msg: format!("xobject {} is not a stream: {:?}", name, p),
+                })
+            }
+        };
+        if let Some(oc) = stream.info.get("OC") {
+            if !self.oc_visible(oc) {
+                return Ok(());
+            }
+        }
+        let (subtype, inferred) = match xobject::classify(&stream.info) {
+            (XObjectKind::Form, inferred) => ("Form", inferred),
+            (XObjectKind::Image, inferred) => ("Image", inferred),
+            (XObjectKind::PostScript, _) => {
+                self.ctx.stats.xobjects_skipped += 1;
+                self.warn(format!("skipped PostScript xobject {}", name));
+                return Ok(());
+            }
+            (XObjectKind::Unknown(subtype), _) => {
+                let msg = format!("xobject {} has unknown subtype {}", name, subtype);
+                if !self.resolve.options().allow_error_in_option {
+                    return Err(PdfError::Other { msg });
+                }
+                self.ctx.stats.xobjects_skipped += 1;
+                self.warn(msg);
+                return Ok(());
+            }
+        };
+        let group = match subtype {
+            "Form" => transparency::form_group(&stream.info, self.resolve)?,
+            _ => None,
+        };
+        let cached;
+        let parsed;
+        let xobject: &XObject = if inferred {
+            self.warn(format!("xobject {} without /Subtype, drawn as {}", name, subtype));
+            stream.info.insert("Subtype", Primitive::Name(subtype.into()));
+            parsed = XObject::from_primitive(Primitive::Stream(stream), self.resolve)?;
+            &parsed
+        } else {
+            cached = self.resolve.get(r)?;
+            &cached
+        };
+        match *xobject {
+            XObject::Form(ref form) => {
+                // a form that draws itself, directly or through other forms
+                if self.ctx.forms.contains(&r.get_inner()) {
+                    self.warn(format!("form xobject {} draws itself, skipped", name));
+                    return Ok(());
+                }
+                self.ctx.forms.push(r.get_inner());
+                let result = self.draw_form(form, group);
+                self.ctx.forms.pop();
+                result
+            }
+            XObject::Image(ref image) => self.draw_image(image, Some(r.get_inner())),
+            XObject::Postscript(_) => Ok(()),
+        }
+    }
+    // paint the current clip region with the shading
+    fn shade(&mut self, name: &Name) -> Result<(), PdfError> {
+        if self.hidden() {
+            return Ok(());
+        }
+        let Some(shading) = self.resources.shadings.get(name) else {
+            self.warn(format!("shading {} not found", name));
+            return Ok(());
+        };
+        // mesh shadings are streams, the others dictionaries
+        let (dict, data) = match self.resolve.resolve(shading.get_inner())? {
+            p @ Primitive::Stream(_) => {
+                let stream = Stream::<Dictionary>::from_primitive(p, self.resolve)?;
+                let data = stream.data(self.resolve)?;
+                (stream.info.info.clone(), Some(data))
+            }
+            p => (p.into_dictionary()?, None),
+        };
+        match (shading::shading_type(&dict)?, data) {
+            (2, _) => self.shade_axial(&dict),
+            (shading_type @ 4..=7, Some(data)) => self.shade_mesh(shading_type, &dict, &data),
+            _ => {
+                self.unsupported("sh");
+                Ok(())
+            }
+        }
+    }
+    fn shading_color(&mut self, color_space: &ColorSpace, values: Vec<f32>) -> Result<Fill, PdfError> {
+        let mode = self.blend_mode_fill();
+        let group_blending = self.group_blending();
+        convert_color(
+            color_space,
+            &Color::Other(values.into_iter().map(Primitive::Number).collect()),
+            &self.resources,
+            self.resolve,
+            mode,
+            group_blending,
+            &mut self.ctx.warnings,
+        )
+    }
+    fn shade_axial(&mut self, dict: &Dictionary) -> Result<(), PdfError> {
+        let shading = AxialShading::from_dict(dict, self.resolve)?;
+        let [x0, y0, x1, y1] = shading.coords;
+        let transform = self.graphics_state.transform;
+        let (from, to) = (transform * Vector2F::new(x0, y0), transform * Vector2F::new(x1, y1));
+        if from == to {
+            return Ok(());
+        }
+        let mut stops = Vec::with_capacity(GRADIENT_STOPS + 1);
+        for i in 0..=GRADIENT_STOPS {
+            let t = i as f32 / GRADIENT_STOPS as f32;
+            if let Fill::Solid(r, g, b) = self.shading_color(&shading.color_space, shading.eval(t)?)? {
+                stops.push((t, ColorF::new(r, g, b, self.graphics_state.effective_fill_alpha()).to_u8()));
+            }
+        }
+        let gradient = LinearGradient { from, to, stops };
+        let outline = shading::axial_outline(&gradient, shading.extend, self.view_box);
+        let colored = gradient.stops.iter().filter(|(_, c)| !color_usage::is_gray(c.r, c.g, c.b)).count();
+        self.count_color(outline.bounds(), colored as f32 / gradient.stops.len().max(1) as f32);
+        self.plotter.draw_gradient(outline, &gradient, self.graphics_state.clip_path_id);
+        Ok(())
+    }
+    // Mesh shadings are cut into flat shaded triangles and patch cells.
+    fn shade_mesh(&mut self, shading_type: i32, dict: &Dictionary, data: &[u8]) -> Result<(), PdfError> {
+        let color_space = match dict.get("ColorSpace") {
+            Some(cs) => ColorSpace::from_primitive(cs.clone(), self.resolve)?,
+            None => {
+                return Err(PdfError::Other {
+                    msg: "mesh shading without /ColorSpace".into(),
+                })
+            }
+        };
+        let functions = shading::read_functions(dict, self.resolve)?;
+        let format = MeshFormat::from_dict(dict)?;
+        let mut pieces = vec![];
+        match shading_type {
+            4 => {
+                for triangle in mesh::free_form_triangles(data, &format) {
+                    mesh::tessellate_triangle(&triangle, mesh::COLOR_THRESHOLD, &mut pieces);
+                }
+            }
+            5 => {
+                let vertices_per_row = dict
+                    .get("VerticesPerRow")
+                    .ok_or_else(|| PdfError::Other {
+                        msg: "lattice shading without /VerticesPerRow".into(),
+                    })?
+                    .as_integer()?;
+                for triangle in mesh::lattice_triangles(data, &format, vertices_per_row as usize)? {
+                    mesh::tessellate_triangle(&triangle, mesh::COLOR_THRESHOLD, &mut pieces);
+                }
+            }
+            _ => {
+                for patch in mesh::patches(data, &format, shading_type == 7) {
+                    mesh::tessellate_patch(&patch, mesh::COLOR_THRESHOLD, &mut pieces);
+                }
+            }
+        }
+        debug!("mesh shading type {}: {} pieces", shading_type, pieces.len());
+        for piece in pieces {
+            let values = match functions.is_empty() {
+                true => piece.color,
+                false => shading::apply_functions(&functions, piece.color[0])?,
+            };
+            let color = self.shading_color(&color_space, values)?;
+            let mut contour = Contour::new();
+            for p in piece.points {
+                contour.push_endpoint(p);
+            }
+            contour.close();
+            let mut outline = Outline::new();
+            outline.push_contour(contour);
+            if color_usage::is_colored(&color) {
+                self.count_color(self.graphics_state.transform * outline.bounds(), 1.);
+            }
+            let mode = DrawMode::Fill {
+                fill: FillMode {
+                    color,
+                    alpha: self.graphics_state.effective_fill_alpha(),
+                    mode: self.blend_mode_fill(),
+                },
+            };
+            self.plotter.draw(&outline, &mode, FillRule::Winding, self.graphics_state.transform, self.graphics_state.clip_path_id);
+        }
+        Ok(())
+    }
     pub fn render(&mut self, page: &Page) -> Result<(), PdfError> {
         let contents = pdf::try_opt!(page.contents.as_ref());
-        let ops = contents.operations(self.resolve)?;
+        self.group_color_space = page_group_color_space(page, self.resolve)?;
+        if let Some(ref cs) = self.group_color_space {
+            debug!("page {}: transparency group in {}", self.page_nr, cs);
+        }
+        let start = Instant::now();
+        let ops = content::operations(contents, self.resolve)?;
+        self.timings.decode = start.elapsed();
+        debug!("page {}: {} operators", self.page_nr, ops.len());
 
+        let start = Instant::now();
+        let result = self.render_content(&ops).and_then(|_| self.render_annotations(page));
+        let (bad, clamped) = (self.ctx.stats.bad_numbers, self.ctx.stats.clamped_points);
+        if bad > 0 || clamped > 0 {
+            self.warn(format!("dropped {} operators with NaN or infinite numbers, clamped {} points far outside the page", bad, clamped));
+        }
+        let page_area = self.view_box.width() * self.view_box.height();
+        self.ctx.stats.color = ColorUsage::new(self.ctx.stats.colored_area, page_area);
+        self.timings.render = start.elapsed();
+        // a failed page is dumped up to the operator that failed
+        if let Some(ops) = self.ctx.dump_ops.take() {
+            self.options.dump_page_ops(self.page_nr, &ops);
+        }
+        result
+    }
+    fn render_content(&mut self, ops: &[Op]) -> Result<(), PdfError> {
+        self.render_ops(ops)?;
+        self.finish_content();
+        Ok(())
+    }
+    // Close what a truncated content stream left open, so the annotations start
+    // from a clean state. A pending path is dropped, it has no painting operator.
+    fn finish_content(&mut self) {
+        let mut pending = vec![];
+        if self.in_text {
+            self.in_text = false;
+            pending.push("text object".to_string());
+        }
+        if !self.marked_content.is_empty() {
+            pending.push(format!("{} marked content sequence(s)", self.marked_content.len()));
+            self.marked_content.clear();
+            self.plotter.marked_content(self.enclosing_marked_content);
+        }
+        self.flush();
+        if !self.current_outline.is_empty() || self.pending_clip.is_some() {
+            self.current_outline.clear();
+            self.pending_clip = None;
+            pending.push("path".to_string());
+        }
+        // a missing Q is common and harmless, it is no sign of truncation
+        if !self.stack.is_empty() {
+            debug!("page {}: {} unbalanced q at the end of the content", self.page_nr, self.stack.len());
+            if let Some((g, t)) = self.stack.drain(..).next() {
+                self.graphics_state = g;
+                self.text_state.restore(t);
+            }
+        }
+        // stopping early leaves everything open on purpose
+        if pending.is_empty() || self.options.stop_at_op.is_some() {
+            return;
+        }
+        self.ctx.stats.truncated = true;
+        self.warn(format!("content ends inside a {}, closed", pending.join(", ")));
+    }
+    // draw the normal appearance of the visible annotations on top of the page
+    fn render_annotations(&mut self, page: &Page) -> Result<(), PdfError> {
+        let annotations = page.annotations.load(self.resolve)?;
+        for annot in annotations.iter() {
+            if is_hidden(annot.annot_flags) {
+                continue;
+            }
+            let (Some(ref ap), Some(rect)) = (&annot.appearance_streams, annot.rect) else {
+                continue;
+            };
+            let form = match *self.resolve.get(ap.normal)? {
+                AppearanceStreamEntry::Single(ref form) => form.clone(),
+                AppearanceStreamEntry::Dict(ref states) => {
+                    match annot.appearance_state.as_ref().and_then(|state| states.get(state)) {
+                        Some(AppearanceStreamEntry::Single(form)) => form.clone(),
+                        _ => continue,
+                    }
+                }
+            };
+            let style = annotation_style(annot.subtype.as_str(), &annot.other);
+            let pdf::object::Rect { left, bottom, right, top } = form.dict().bbox;
+            let bbox = RectF::from_points(
+                Vector2F::new(left.min(right), bottom.min(top)),
+                Vector2F::new(left.max(right), bottom.max(top)),
+            );
+            let rect = RectF::from_points(
+                Vector2F::new(rect.left.min(rect.right), rect.bottom.min(rect.top)),
+                Vector2F::new(rect.left.max(rect.right), rect.bottom.max(rect.top)),
+            );
+            let Some(matrix) = self.checked_matrix(self.form_matrix(&form)?, "appearance") else {
+                continue;
+            };
+            let transform = appearance_transform(matrix * bbox, rect);
+
+            // no group transparency, the alpha is multiplied into the draw calls instead
+            let saved = self.graphics_state.clone();
+            self.graphics_state.transform = self.transform * transform;
+            self.graphics_state.clip_path_id = None;
+            self.graphics_state.clip_bounds = None;
+            self.graphics_state.fill_alpha = 1.0;
+            self.graphics_state.stroke_alpha = 1.0;
+            self.graphics_state.group_fill_alpha = style.fill_alpha;
+            self.graphics_state.group_stroke_alpha = style.stroke_alpha;
+            self.graphics_state.blend_mode = style.blend_mode;
+            let result = self.draw_form(&form, None);
+            self.graphics_state = saved;
+            result?;
+        }
+        Ok(())
+    }
+    fn progress(&self, ops: usize, total_ops: usize) {
+        self.options.progress(Progress {
+            page: self.page_nr,
+            pages: self.pages,
+            ops,
+            total_ops,
+        });
+    }
+    fn render_ops(&mut self, ops: &[Op]) -> Result<(), PdfError> {
+        let mut masked = 0..0;
+        for (i, op) in ops.iter().enumerate() {
+            if masked.contains(&i) {
+                continue;
+            }
+            // depth first number of the operator, shared with the nested forms
+            let nr = self.ctx.stats.ops;
+            if self.options.stop_at_op.is_some_and(|stop| nr > stop) {
+                debug!("page {}: stop before op {}", self.page_nr, nr);
+                self.flush();
+                break;
+            }
+            self.ctx.stats.ops += 1;
+            trace!("page {} op {}: {:?}", self.page_nr, nr, op);
+            // nested content streams are indented by their depth
+            if let Some(ref mut dump) = self.ctx.dump_ops {
+                dump.push_str(&format!("{:indent$}{}: {:?}\n", "", nr, op, indent = 2 * self.depth));
+            }
+            self.op_nr = nr;
+            self.plotter.begin_op(nr);
+            if self.options.hook_op(nr, op, || self.hook_state()) == HookAction::Skip {
+                if op_hook::paints_path(op) {
+                    self.draw_op(&Op::EndPath)?;
+                }
+                continue;
+            }
+            if let Err(e) = self.draw_op(op) {
+                error!("page {} op {} ({:?}) failed: {:?}", self.page_nr, nr, op, e);
+                return Err(e);
+            }
+            if let Some(mask) = self.pending_soft_mask.take() {
+                let end = i + 1 + self.masked_len(&ops[i + 1..]);
+                self.render_masked(&ops[i + 1..end], &mask)?;
+                masked = i + 1..end;
+            }
+            if self.depth == 0 && i % PROGRESS_INTERVAL == 0 {
+                self.progress(i, ops.len());
+            }
+        }
+        if self.depth == 0 {
+            self.progress(ops.len(), ops.len());
+        }
+
+        Ok(())
+    }
+    fn hook_state(&self) -> HookState {
+        HookState {
+            page: self.page_nr,
+            depth: self.depth,
+            transform: self.graphics_state.transform,
+            fill_color: self.graphics_state.fill_color,
+            stroke_color: self.graphics_state.stroke_color,
+            text_matrix: self.text_state.text_matrix,
+            in_text: self.in_text,
+        }
+    }
+    // A soft mask applies until the graphics state it was set in is restored, or
+    // the next gs with an /SMask entry. The number of operators up to there.
+    fn masked_len(&self, ops: &[Op]) -> usize {
+        let mut level = 0;
         for (i, op) in ops.iter().enumerate() {
-            //println!("op {}: {:?}", i, op);
-            match op {
-                Op::BeginMarkedContent { tag, properties } => {}
-                Op::EndMarkedContent => {}
-                Op::MarkedContentPoint { tag, properties } => {}
-                Op::Close => {
-                    self.current_contour.close();
-                }
-                Op::MoveTo { p } => {
+            match *op {
+                Op::Save => level += 1,
+                Op::Restore if level == 0 => return i,
+                Op::Restore => level -= 1,
+                Op::GraphicsState { ref name } if level == 0 => {
+                    if self.resources.graphics_states.get(name).is_some_and(|gs| gs.smask.is_some()) {
+                        return i;
+                    }
+                }
+                _ => {}
+            }
+        }
+        ops.len()
+    }
+    // Draw the operators into a transparent layer, take the mask as its alpha
+    // and draw the layer as an image.
+    fn render_masked(&mut self, ops: &[Op], mask: &SoftMask) -> Result<(), PdfError> {
+        self.flush();
+        if self.depth >= MAX_LAYER_DEPTH {
+            self.warn(format!("soft masks nested deeper than {}", MAX_LAYER_DEPTH));
+            return self.render_ops(ops);
+        }
+        let values = match self.render_mask(mask)? {
+            Some(values) => values,
+            None => return self.render_ops(ops),
+        };
+        let mut layer = PngPlotter::transparent(self.view_box);
+        let mut inner = self.begin_layer(&mut layer, self.resources);
+        inner.graphics_state.copy_from(&self.graphics_state);
+        inner.text_state = self.text_state.clone();
+        inner.in_text = self.in_text;
+        inner.marked_content = self.marked_content.clone();
+        inner.enclosing_marked_content = self.enclosing_marked_content;
+        let result = inner.render_ops(ops);
+        self.end_layer(&mut inner);
+        // A gs ends the masked operators without Q, the changed state carries on.
+        // Clips set in between are lost.
+        self.graphics_state.copy_from(&inner.graphics_state);
+        self.text_state = inner.text_state.clone();
+        self.in_text = inner.in_text;
+        self.marked_content = std::mem::take(&mut inner.marked_content);
+        drop(inner);
+        result?;
+        self.composite_layer(layer.rasterize(), Some(&values), 1.0, BlendMode::Normal);
+        Ok(())
+    }
+    // A state that draws into an offscreen layer over the view box. The page
+    // context moves to the layer until end_layer.
+    fn begin_layer<'b>(&mut self, plotter: &'b mut PngPlotter, resources: &'b Resources) -> RenderState<'b, R, PngPlotter>
+    where
+        'a: 'b,
+    {
+        let mut inner = RenderState::new(plotter, self.resolve, resources, self.transform, self.view_box, self.options, self.page_nr, self.pages);
+        inner.depth = self.depth + 1;
+        inner.pattern_base = self.pattern_base;
+        inner.group_color_space = self.group_color_space.clone();
+        inner.op_nr = self.op_nr;
+        inner.optional_content = self.optional_content;
+        inner.image_cache = self.image_cache;
+        inner.enclosing_marked_content = self.marked_content_context();
+        inner.ctx = std::mem::take(&mut self.ctx);
+        inner
+    }
+    fn end_layer(&mut self, inner: &mut RenderState<R, PngPlotter>) {
+        inner.flush();
+        self.ctx = std::mem::take(&mut inner.ctx);
+    }
+    // Draw a rendered layer over the view box, through the mask and with the
+    // constant alpha.
+    fn composite_layer(&mut self, mut image: RgbaImage, mask: Option<&[f32]>, alpha: f32, mode: BlendMode) {
+        soft_mask::apply_mask(&mut image, mask, alpha);
+        let (origin, size) = (self.view_box.origin(), self.view_box.size());
+        // the first row of the image at the top
+        let transform = Transform2F::row_major(size.x(), 0., origin.x(), 0., -size.y(), origin.y() + size.y());
+        self.plotter.draw_layer(&image, transform, mode, self.graphics_state.clip_path_id);
+    }
+    // the mask group rendered over the view box, one value per pixel
+    fn render_mask(&mut self, mask: &SoftMask) -> Result<Option<Vec<f32>>, PdfError> {
+        let form = match XObject::from_primitive(self.resolve.resolve(mask.group)?, self.resolve)? {
+            XObject::Form(form) => form,
+            _ => {
+                self.warn("soft mask group is not a form".into());
+                return Ok(None);
+            }
+        };
+        let matrix = self.form_matrix(&form)?;
+        let ops = form.operations(self.resolve)?;
+        let resources = match form.dict().resources {
+            Some(ref r) => &**r,
+            None => self.resources,
+        };
+        let mut plotter = PngPlotter::transparent(self.view_box);
+        if mask.kind == MaskKind::Luminosity {
+            let fill = FillMode { color: mask.backdrop, alpha: 1.0, mode: BlendMode::Normal };
+            let background = Outline::from_rect(self.view_box);
+            plotter.draw(&background, &DrawMode::Fill { fill }, FillRule::Winding, Transform2F::default(), None);
+        }
+        let mut inner = self.begin_layer(&mut plotter, resources);
+        inner.graphics_state.transform = mask.transform * matrix;
+        inner.pattern_base = inner.graphics_state.transform;
+        let result = inner.render_ops(&ops);
+        self.end_layer(&mut inner);
+        drop(inner);
+        result?;
+        Ok(Some(soft_mask::mask_values(&plotter.rasterize(), mask.kind)))
+    }
+    // apply the parameters of an ExtGState dictionary
+    fn set_graphics_state(&mut self, name: &Name) -> Result<(), PdfError> {
+        let Some(gs) = self.resources.graphics_states.get(name) else {
+            self.warn(format!("graphics state {} not found", name));
+            return Ok(());
+        };
+        if let Some(width) = gs.line_width {
+            self.graphics_state.stroke_style.line_width = width;
+        }
+        if let Some(limit) = gs.miter_limit {
+            self.graphics_state.miter_limit = limit;
+        }
+        if let Some(ref join) = gs.line_join {
+            self.graphics_state.stroke_style.line_join = match join {
+                pdf::content::LineJoin::Miter => LineJoin::Miter(self.graphics_state.miter_limit),
+                pdf::content::LineJoin::Round => LineJoin::Round,
+                pdf::content::LineJoin::Bevel => LineJoin::Bevel,
+            };
+        } else if let LineJoin::Miter(_) = self.graphics_state.stroke_style.line_join {
+            self.graphics_state.stroke_style.line_join = LineJoin::Miter(self.graphics_state.miter_limit);
+        }
+        if let Some(ref cap) = gs.line_cap {
+            self.graphics_state.stroke_style.line_cap = match cap {
+                pdf::content::LineCap::Butt => LineCap::Butt,
+                pdf::content::LineCap::Round => LineCap::Round,
+                pdf::content::LineCap::Square => LineCap::Square,
+            };
+        }
+        if let Some(alpha) = gs.fill_alpha {
+            self.graphics_state.set_fill_alpha(alpha);
+        }
+        if let Some(alpha) = gs.stroke_alpha {
+            self.graphics_state.set_stroke_alpha(alpha);
+        }
+        // /OP sets both, /op only the fill
+        if let Some(overprint) = gs.overprint {
+            self.graphics_state.overprint_stroke = overprint;
+            self.graphics_state.overprint_fill = gs.overprint_fill.unwrap_or(overprint);
+        } else if let Some(overprint) = gs.overprint_fill {
+            self.graphics_state.overprint_fill = overprint;
+        }
+        if let Some(mode) = gs.overprint_mode {
+            self.graphics_state.overprint_mode = mode;
+        }
+        if let Some((font, size)) = gs.font {
+            // the font is not one of the named font resources
+            debug!("page {} op {}: font {:?} at {} from {}", self.page_nr, self.op_nr, font.get_inner(), size, name);
+            self.text_state.font = None;
+            self.text_state.font_size = size;
+        }
+        if let Some(ref mode) = gs.blend_mode {
+            match annotations::blend_mode(mode) {
+                Some(mode) => self.graphics_state.blend_mode = mode,
+                None => debug!("page {} op {}: blend mode {:?} ignored", self.page_nr, self.op_nr, mode),
+            }
+        }
+        let smask = match gs.smask {
+            Some(Primitive::Name(ref none)) if none.as_str() == "None" => Ok(None),
+            Some(Primitive::Reference(r)) => self.resolve.resolve(r).map(Some),
+            Some(ref p) => Ok(Some(p.clone())),
+            None => Ok(None),
+        };
+        if let Some(ref dash) = gs.dash_pattern {
+            // [[lengths] phase]
+            let parsed = match dash.as_slice() {
+                [Primitive::Array(lengths), phase] => lengths
+                    .iter()
+                    .map(|l| l.as_number())
+                    .collect::<Result<Vec<f32>, PdfError>>()
+                    .and_then(|lengths| Ok((lengths, phase.as_number()?))),
+                _ => Err(PdfError::Other { msg: format!("invalid /D {:?}", dash) }),
+            };
+            match parsed {
+                Ok((lengths, phase)) => self.graphics_state.set_dash(&lengths, phase),
+                Err(e) => self.warn(format!("dash pattern in {}: {}", name, e)),
+            }
+        }
+        let transform = self.graphics_state.transform;
+        let smask = smask.and_then(|smask| match smask {
+            Some(p) => SoftMask::from_dict(&p.into_dictionary()?, transform).map(Some),
+            None => Ok(None),
+        });
+        match smask {
+            Ok(mask) => self.pending_soft_mask = mask,
+            Err(e) => self.warn(format!("soft mask in {}: {}", name, e)),
+        }
+        Ok(())
+    }
+    // inside marked content of a layer that is off
+    fn hidden(&self) -> bool {
+        self.marked_content.iter().any(|entry| !entry.visible)
+    }
+    fn marked_content_context(&self) -> McContext {
+        self.enclosing_marked_content.enter(&self.marked_content)
+    }
+    // BDC names an entry of /Properties or has the dictionary inline
+    fn marked_content_properties(&mut self, properties: &Primitive) -> Option<Primitive> {
+        match *properties {
+            Primitive::Name(ref name) => match self.resources.properties.get(name) {
+                Some(MaybeRef::Indirect(ref r)) => Some(Primitive::Reference(r.get_ref().get_inner())),
+                Some(MaybeRef::Direct(ref dict)) => Some(Primitive::Dictionary((**dict).clone())),
+                None => {
+                    self.warn(format!("missing marked content properties {}", name));
+                    None
+                }
+            },
+            ref p => Some(p.clone()),
+        }
+    }
+    fn begin_marked_content(&mut self, tag: &Name, properties: Option<&Primitive>) {
+        let properties = properties.and_then(|p| self.marked_content_properties(p));
+        let visible = match (tag.as_str(), &properties) {
+            ("OC", Some(oc)) => self.oc_visible(oc),
+            _ => true,
+        };
+        let properties = match properties {
+            Some(Primitive::Reference(r)) => self.resolve.resolve(r).and_then(|p| p.into_dictionary()).ok(),
+            Some(Primitive::Dictionary(dict)) => Some(dict),
+            _ => None,
+        };
+        let mcid = properties.as_ref().and_then(|p| p.get("MCID")).and_then(|mcid| mcid.as_integer().ok());
+        self.marked_content.push(McEntry { tag: tag.clone(), properties, mcid, visible });
+        self.plotter.marked_content(self.marked_content_context());
+    }
+    // /OC of marked content or of an xobject. Optional content that can not be read is shown.
+    fn oc_visible(&mut self, oc: &Primitive) -> bool {
+        let Some(content) = self.optional_content else {
+            return true;
+        };
+        match content.is_visible(oc, self.resolve) {
+            Ok(visible) => visible,
+            Err(e) => {
+                self.warn(format!("optional content: {}", e));
+                true
+            }
+        }
+    }
+    fn draw_op(&mut self, op: &Op) -> Result<(), PdfError> {
+        match op {
+            Op::BeginMarkedContent { tag, properties } => self.begin_marked_content(tag, properties.as_ref()),
+            Op::EndMarkedContent => match self.marked_content.pop() {
+                Some(_) => self.plotter.marked_content(self.marked_content_context()),
+                None => self.warn("EMC without BMC or BDC".into()),
+            },
+            Op::MarkedContentPoint { tag, properties } => {}
+            Op::Close => {
+                self.current_contour.close();
+            }
+            Op::MoveTo { p } => {
+                if let Some([p]) = self.sane_points([p.cvt()]) {
                     self.flush();
-                    self.current_contour.push_endpoint(p.cvt());
+                    self.current_contour.push_endpoint(p);
                 }
-                Op::LineTo { p } => {
-                    self.current_contour.push_endpoint(p.cvt());
+            }
+            Op::LineTo { p } => {
+                if let Some([p]) = self.sane_points([p.cvt()]) {
+                    self.current_contour.push_endpoint(p);
                 }
-                Op::CurveTo { c1, c2, p } => {
-                    self.current_contour.push_cubic(c1.cvt(), c2.cvt(), p.cvt());
+            }
+            Op::CurveTo { c1, c2, p } => {
+                if let Some([c1, c2, p]) = self.sane_points([c1.cvt(), c2.cvt(), p.cvt()]) {
+                    self.current_contour.push_cubic(c1, c2, p);
                 }
-                Op::Rect { rect } => {
+            }
+            Op::Rect { rect } => {
+                let rect = rect.cvt();
+                if let Some([min, max]) = self.sane_points([rect.origin(), rect.lower_right()]) {
                     self.flush();
                     self.current_outline
-                        .push_contour(Contour::from_rect(rect.cvt()));
-                }
-                Op::EndPath => {
-                    self.current_contour.clear();
-                    self.current_outline.clear();
-                }
-
-                Op::Stroke => {
-                    self.draw(
-                        &DrawMode::Stroke {
-                            stroke: FillMode {
-                                color: self.graphics_state.stroke_color,
-                                alpha: self.graphics_state.stroke_color_alpha,
-                                mode: self.blend_mode_stroke(),
-                            },
-                            stroke_mode: self.graphics_state.stroke(),
+                        .push_contour(Contour::from_rect(RectF::new(min, max - min)));
+                }
+            }
+            Op::EndPath => {
+                self.flush();
+                self.apply_clip();
+                self.current_outline.clear();
+            }
+
+            Op::Stroke => {
+                self.draw(
+                    &DrawMode::Stroke {
+                        stroke: FillMode {
+                            color: self.graphics_state.stroke_color,
+                            alpha: self.graphics_state.effective_stroke_alpha(),
+                            mode: self.blend_mode_stroke(),
                         },
-                        FillRule::Winding,
-                    );
-                }
-                Op::FillAndStroke { winding } => {
-                    self.draw(
-                        &DrawMode::FillStroke {
-                            fill: FillMode {
-                                color: self.graphics_state.fill_color,
-                                alpha: self.graphics_state.fill_color_alpha,
-                                mode: self.blend_mode_fill(),
-                            },
-                            stroke: FillMode {
-                                color: self.graphics_state.stroke_color,
-                                alpha: self.graphics_state.stroke_color_alpha,
-                                mode: self.blend_mode_stroke(),
-                            },
-                            stroke_mode: self.graphics_state.stroke(),
+                        stroke_mode: self.graphics_state.stroke(),
+                    },
+                    FillRule::Winding,
+                );
+            }
+            Op::FillAndStroke { winding } => {
+                self.draw(
+                    &DrawMode::FillStroke {
+                        fill: FillMode {
+                            color: self.graphics_state.fill_color,
+                            alpha: self.graphics_state.effective_fill_alpha(),
+                            mode: self.blend_mode_fill(),
                         },
-                        winding.cvt(),
-                    );
-                }
-                Op::Fill { winding } => {
-                    self.draw(
-                        &DrawMode::Fill {
-                            fill: FillMode {
-                                color: self.graphics_state.fill_color,
-                                alpha: self.graphics_state.fill_color_alpha,
-                                mode: self.blend_mode_fill(),
-                            },
+                        stroke: FillMode {
+                            color: self.graphics_state.stroke_color,
+                            alpha: self.graphics_state.effective_stroke_alpha(),
+                            mode: self.blend_mode_stroke(),
                         },
-                        winding.cvt(),
-                    );
-                }
-                Op::Shade { name } => {}
-                Op::Clip { winding } => {
-                    //self.flush();
-                    //let mut path = self.current_outline.clone().transformed(&self.graphics_state.transform);
-                    //let clip_path_rect = to_rect(&path);
-                    //
-                    //let (path, r, parent) = match (self.graphics_state.clip_path_rect, clip_path_rect, self.graphics_state.clip_path_id) {
-                    //    (Some(r1), Some(r2), Some(p)) => {
-                    //        let r = r1.intersection(r2).unwrap_or_default();
-                    //        (Outline::from_rect(r), Some(r), None)
-                    //    }
-                    //    (Some(r), None, Some(p)) => {
-                    //        path.clip_against_polygon(&[r.origin(), r.upper_right(), r.lower_right(), r.lower_left()]);
-                    //        (path, None, None)
-                    //    }
-                    //    (None, Some(r), Some(p)) => {
-                    //        let mut path = self.graphics_state.clip_path.as_ref().unwrap().outline.clone();
-                    //        path.clip_against_polygon(&[r.origin(), r.upper_right(), r.lower_right(), r.lower_left()]);
-                    //        (path, None, None)
-                    //    }
-                    //    (None, Some(r), None) => {
-                    //        (path, Some(r), None)
-                    //    }
-                    //    (None, None, Some(p)) => (path, None, Some(p)),
-                    //    (None, None, None) => (path, None, None),
-                    //    _ => unreachable!()
-                    //};
-                    //
-                    //let id = self.backend.create_clip_path(path.clone(), winding.cvt(), parent);
-                    //self.graphics_state.clip_path_id = Some(id);
-                    //let mut clip = ClipPath::new(path);
-                    //clip.set_fill_rule(winding.cvt());
-                    //self.graphics_state.clip_path = Some(clip);
-                    //self.graphics_state.clip_path_rect = r;
-                }
-                Op::Save => {
-                    self.stack
-                        .push((self.graphics_state.clone(), self.text_state.clone()));
-                }
-                pdf::content::Op::Restore => {
-                    let (g, t) = self
-                        .stack
-                        .pop()
-                        .ok_or_else(|| pdf::error::PdfError::Other {
-                            msg: "graphcs stack is empty".into(),
-                        })?;
+                        stroke_mode: self.graphics_state.stroke(),
+                    },
+                    winding.cvt(),
+                );
+            }
+            Op::Fill { winding } => {
+                self.draw(
+                    &DrawMode::Fill {
+                        fill: FillMode {
+                            color: self.graphics_state.fill_color,
+                            alpha: self.graphics_state.effective_fill_alpha(),
+                            mode: self.blend_mode_fill(),
+                        },
+                    },
+                    winding.cvt(),
+                );
+            }
+            Op::Shade { name } => self.shade(name)?,
+            // the clip applies after the next painting operator, see apply_clip
+            Op::Clip { winding } => self.pending_clip = Some(winding.cvt()),
+            Op::Save => {
+                self.stack
+                    .push((self.graphics_state.clone(), self.text_state.clone()));
+            }
+            pdf::content::Op::Restore => match self.stack.pop() {
+                Some((g, t)) => {
                     self.graphics_state = g;
-                    self.text_state = t;
+                    self.text_state.restore(t);
+                }
+                // unbalanced Q are common, lenient parsing ignores them
+                None if self.resolve.options().allow_error_in_option => self.warn("Q without q".into()),
+                None => {
+                    return Err(PdfError::Other {
+                        msg: "graphics stack is empty".into(),
+                    })
                 }
-                pdf::content::Op::Transform { matrix } => {
-                    let Matrix { a, b, c, d, e, f } = matrix;
-                    let matrix = Transform2F::row_major(*a, *c, *e, *b, *d, *f);
+            },
+            pdf::content::Op::Transform { matrix } => {
+                let Matrix { a, b, c, d, e, f } = matrix;
+                let matrix = Transform2F::row_major(*a, *c, *e, *b, *d, *f);
+                let transform = self.graphics_state.transform * matrix;
+                let m = transform.matrix;
+                if ![m.m11(), m.m12(), m.m21(), m.m22(), transform.vector.x(), transform.vector.y()].iter().all(|v| v.is_finite()) {
+                    self.ctx.stats.bad_numbers += 1;
+                } else if let Some(matrix) = self.checked_matrix(matrix, "cm") {
                     self.graphics_state.transform = self.graphics_state.transform * matrix;
                 }
-                pdf::content::Op::LineWidth { width } => {
-                    self.graphics_state.stroke_style.line_width = *width
-                }
-                pdf::content::Op::Dash { ref pattern, phase } => {} //self.graphics_state.dash_pattern = Some(&*pattern, *phase)),
-                pdf::content::Op::LineJoin { join } => {}
-                pdf::content::Op::LineCap { cap } => {}
-                pdf::content::Op::MiterLimit { limit } => {}
-                pdf::content::Op::Flatness { tolerance } => {}
-                pdf::content::Op::GraphicsState { name } => {
-                    //                    let gs = try_opt!(self.resources.graphics_states.get(name));
-                    //println!("GS: {gs:?}");
-                    //if let Some(lw) = gs.line_width {
-                    //    self.graphics_state.stroke_style.line_width = lw;
-                    //}
-                    //self.graphics_state.set_fill_alpha(gs.fill_alpha.unwrap_or(1.0));
-                    //self.graphics_state.set_stroke_alpha(gs.stroke_alpha.unwrap_or(1.0));
-                    //
-                    //if let Some((font_ref, size)) = gs.font {
-                    //    let font = self.resolve.get(font_ref)?;
-                    //    if let Some(e) = self.backend.get_font(&MaybeRef::Indirect(font), self.resolve)? {
-                    //        debug!("new font: {} at size {}", e.name, size);
-                    //        self.text_state.font_entry = Some(e);
-                    //        self.text_state.font_size = size;
-                    //    } else {
-                    //        self.text_state.font_entry = None;
-                    //    }
-                    //}
-                    //if let Some(op) = gs.overprint {
-                    //    self.graphics_state.overprint_fill = op;
-                    //    self.graphics_state.overprint_stroke = op;
-                    //}
-                    //if let Some(op) = gs.overprint_fill {
-                    //    self.graphics_state.overprint_fill = op;
-                    //}
-                    //if let Some(m) = gs.overprint_mode {
-                    //    self.graphics_state.overprint_mode = m;
-                    //}
-                }
-                pdf::content::Op::StrokeColor { color } => {
-                    let mode = self.blend_mode_stroke();
-                    let color = t!(convert_color(
-                        &mut self.graphics_state.stroke_color_space,
-                        color,
-                        &self.resources,
-                        self.resolve,
-                        mode
-                    ));
-                    self.graphics_state.set_stroke_color(color);
-                }
-                pdf::content::Op::FillColor { color } => {
-                    let mode = self.blend_mode_fill();
-                    let color = t!(convert_color(
-                        &mut self.graphics_state.fill_color_space,
-                        color,
-                        &self.resources,
-                        self.resolve,
-                        mode
-                    ));
-                    self.graphics_state.set_fill_color(color);
-                }
-                pdf::content::Op::FillColorSpace { name } => {
-                    self.graphics_state.fill_color_space = self.color_space(name)?;
-                    self.graphics_state.set_fill_color(Fill::black());
-                }
-                pdf::content::Op::StrokeColorSpace { name } => {
-                    self.graphics_state.stroke_color_space = self.color_space(name)?;
-                    self.graphics_state.set_stroke_color(Fill::black());
-                }
-                pdf::content::Op::RenderingIntent { intent } => {}
-                pdf::content::Op::BeginText => self.text_state.reset_matrix(),
-                pdf::content::Op::EndText => {}
-                pdf::content::Op::CharSpacing { char_space } => self.text_state.char_space = *char_space,
-                pdf::content::Op::WordSpacing { word_space } => self.text_state.word_space = *word_space,
-                pdf::content::Op::TextScaling { horiz_scale } => self.text_state.horiz_scale = 0.01 * horiz_scale,
-                pdf::content::Op::Leading { leading } => self.text_state.leading = *leading,
-                pdf::content::Op::TextFont { name, size } => {
-                    //let font = match self.resources.fonts.get(name) {
-                    //    Some(font_ref) => {
-                    //        self.backend.get_font(font_ref, self.resolve)?
-                    //    },
-                    //    None => None
-                    //};
-                    //if let Some(e) = font {
-                    //    println!("new font: {} (is_cid={:?})", e.name, e.is_cid);
-                    //    //self.text_state.font_entry = Some(e);
-                    //    self.text_state.font_size = *size;
-                    //} else {
-                    //    println!("no font {}", name);
-                    //    //self.text_state.font_entry = None;
-                    //}
-                },
-                pdf::content::Op::TextRenderMode { mode } => self.text_state.mode = *mode,
-                pdf::content::Op::TextRise { rise } => self.text_state.rise = *rise,
-                pdf::content::Op::MoveTextPosition { translation } => self.text_state.translate(translation.cvt()),
-                pdf::content::Op::SetTextMatrix { matrix } => self.text_state.set_matrix(matrix.cvt()),
-                pdf::content::Op::TextNewline => self.text_state.next_line(),
-                pdf::content::Op::TextDraw { text } => {
-                    //let fill_mode = self.blend_mode_fill();
-                    //let stroke_mode = self.blend_mode_stroke();
-                    //self.text(|backend, text_state, graphics_state, span| {
-                    //    text_state.draw_text(backend, graphics_state, &text.data, span, fill_mode, stroke_mode);
-                    //}, op_nr);
-                },
-                pdf::content::Op::TextDrawAdjusted { array } => {}
-                pdf::content::Op::XObject { name } => {}
-                pdf::content::Op::InlineImage { image } => {}
             }
-            //if let Some(path) = renderstate.draw_op(op, i)? {
-            //    document = document.add(path);
-            //}
+            pdf::content::Op::LineWidth { width } => {
+                self.graphics_state.stroke_style.line_width = *width
+            }
+            pdf::content::Op::Dash { ref pattern, phase } => self.graphics_state.set_dash(pattern, *phase),
+            pdf::content::Op::LineJoin { join } => {
+                self.graphics_state.stroke_style.line_join = match join {
+                    pdf::content::LineJoin::Miter => LineJoin::Miter(self.graphics_state.miter_limit),
+                    pdf::content::LineJoin::Round => LineJoin::Round,
+                    pdf::content::LineJoin::Bevel => LineJoin::Bevel,
+                }
+            }
+            pdf::content::Op::LineCap { cap } => {
+                self.graphics_state.stroke_style.line_cap = match cap {
+                    pdf::content::LineCap::Butt => LineCap::Butt,
+                    pdf::content::LineCap::Round => LineCap::Round,
+                    pdf::content::LineCap::Square => LineCap::Square,
+                }
+            }
+            pdf::content::Op::MiterLimit { limit } => {
+                self.graphics_state.miter_limit = *limit;
+                if let LineJoin::Miter(_) = self.graphics_state.stroke_style.line_join {
+                    self.graphics_state.stroke_style.line_join = LineJoin::Miter(*limit);
+                }
+            }
+            pdf::content::Op::Flatness { tolerance } => {}
+            pdf::content::Op::GraphicsState { name } => self.set_graphics_state(name)?,
+            pdf::content::Op::StrokeColor { color } => {
+                let mode = self.blend_mode_stroke();
+                set_device_space(&mut self.graphics_state.stroke_color_space, color);
+                let color = t!(convert_color(
+                    &self.graphics_state.stroke_color_space,
+                    color,
+                    &self.resources,
+                    self.resolve,
+                    mode,
+                    self.group_blending(),
+                    &mut self.ctx.warnings
+                ));
+                self.graphics_state.set_stroke_color(color);
+            }
+            pdf::content::Op::FillColor { color } => {
+                let mode = self.blend_mode_fill();
+                set_device_space(&mut self.graphics_state.fill_color_space, color);
+                let color = t!(convert_color(
+                    &self.graphics_state.fill_color_space,
+                    color,
+                    &self.resources,
+                    self.resolve,
+                    mode,
+                    self.group_blending(),
+                    &mut self.ctx.warnings
+                ));
+                self.graphics_state.set_fill_color(color);
+            }
+            pdf::content::Op::FillColorSpace { name } => {
+                self.graphics_state.fill_color_space = self.color_space(name)?;
+                self.graphics_state.set_fill_color(Fill::black());
+            }
+            pdf::content::Op::StrokeColorSpace { name } => {
+                self.graphics_state.stroke_color_space = self.color_space(name)?;
+                self.graphics_state.set_stroke_color(Fill::black());
+            }
+            pdf::content::Op::RenderingIntent { intent } => {}
+            pdf::content::Op::BeginText => {
+                if self.in_text {
+                    self.warn("BT inside a text object".into());
+                }
+                self.in_text = true;
+                self.text_state.reset_matrix();
+            }
+            pdf::content::Op::EndText => self.in_text = false,
+            pdf::content::Op::CharSpacing { char_space } => self.text_state.char_space = *char_space,
+            pdf::content::Op::WordSpacing { word_space } => self.text_state.word_space = *word_space,
+            pdf::content::Op::TextScaling { horiz_scale } => self.text_state.horiz_scale = 0.01 * horiz_scale,
+            pdf::content::Op::Leading { leading } => self.text_state.leading = *leading,
+            pdf::content::Op::TextFont { name, size } => {
+                if !self.resources.fonts.contains_key(name) {
+                    self.warn(format!("missing font {}", name));
+                }
+                self.text_state.font = Some(name.clone());
+                self.text_state.font_size = *size;
+                //let font = match self.resources.fonts.get(name) {
+                //    Some(font_ref) => {
+                //        self.backend.get_font(font_ref, self.resolve)?
+                //    },
+                //    None => None
+                //};
+                //if let Some(e) = font {
+                //    println!("new font: {} (is_cid={:?})", e.name, e.is_cid);
+                //    //self.text_state.font_entry = Some(e);
+                //    self.text_state.font_size = *size;
+                //} else {
+                //    println!("no font {}", name);
+                //    //self.text_state.font_entry = None;
+                //}
+            },
+            pdf::content::Op::TextRenderMode { mode } => self.text_state.mode = *mode,
+            pdf::content::Op::TextRise { rise } => self.text_state.rise = *rise,
+            pdf::content::Op::MoveTextPosition { translation } => self.text_state.translate(translation.cvt()),
+            pdf::content::Op::SetTextMatrix { matrix } => self.text_state.set_matrix(matrix.cvt()),
+            pdf::content::Op::TextNewline => self.text_state.next_line(),
+            pdf::content::Op::TextDraw { text } => {
+                self.unsupported("Tj");
+                //let fill_mode = self.blend_mode_fill();
+                //let stroke_mode = self.blend_mode_stroke();
+                //self.text(|backend, text_state, graphics_state, span| {
+                //    text_state.draw_text(backend, graphics_state, &text.data, span, fill_mode, stroke_mode);
+                //}, op_nr);
+            },
+            pdf::content::Op::TextDrawAdjusted { array } => self.unsupported("TJ"),
+            pdf::content::Op::XObject { name } => self.draw_xobject(name)?,
+            pdf::content::Op::InlineImage { image } => self.draw_image(image, None)?,
         }
-
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use pathfinder_content::{fill::FillRule, outline::Outline, stroke::StrokeStyle};
+    use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+    use pdf::{
+        content::{parse_ops, Color, Matrix, Op, Point, Rect, Rgb, TextMode, Winding},
+        object::{NoResolve, Resources},
+        primitive::Name,
+    };
+
+    use super::{cmyk2rgb, convert_color2, icc_device_space, named_device_space, palette_components, RenderState};
+    use crate::{
+        bounds_plotter::BoundsPlotter,
+        options::RenderOptions,
+        plotter::{BlendMode, DrawMode, Fill, LinearGradient, Plotter, RgbaImage, Tiling},
+        structure::McContext,
+    };
+
+    #[test]
+    fn icc_without_alternate() {
+        use pdf::object::ColorSpace;
+        assert!(matches!(icc_device_space(None, 1), Some(ColorSpace::DeviceGray)));
+        assert!(matches!(icc_device_space(None, 3), Some(ColorSpace::DeviceRGB)));
+        assert!(matches!(icc_device_space(None, 4), Some(ColorSpace::DeviceCMYK)));
+        assert!(icc_device_space(None, 2).is_none());
+        // the alternate wins over /N
+        assert!(matches!(icc_device_space(Some(&ColorSpace::DeviceRGB), 4), Some(ColorSpace::DeviceRGB)));
+    }
+
+    #[test]
+    fn abbreviated_spaces() {
+        use pdf::object::ColorSpace;
+        assert!(matches!(named_device_space("G"), Some(ColorSpace::DeviceGray)));
+        assert!(matches!(named_device_space("DeviceGray"), Some(ColorSpace::DeviceGray)));
+        assert!(matches!(named_device_space("RGB"), Some(ColorSpace::DeviceRGB)));
+        assert!(matches!(named_device_space("CMYK"), Some(ColorSpace::DeviceCMYK)));
+        // /I is Indexed, which needs its arguments, and names are case sensitive
+        assert!(named_device_space("I").is_none());
+        assert!(named_device_space("rgb").is_none());
+        // a palette base named in the resources or abbreviated
+        let mut resources = Resources::default();
+        resources.color_spaces.insert(Name::from("CS0"), ColorSpace::DeviceCMYK);
+        assert_eq!(palette_components(&ColorSpace::Named(Name::from("CS0")), &resources), Some(4));
+        assert_eq!(palette_components(&ColorSpace::Named(Name::from("G")), &resources), Some(1));
+        assert_eq!(palette_components(&ColorSpace::Named(Name::from("CS1")), &resources), None);
+    }
+
+    #[test]
+    fn bad_colors_are_errors() {
+        use pdf::{object::ColorSpace, primitive::Primitive};
+        let resources = Resources::default();
+        let convert = |cs: &ColorSpace, args: Vec<Primitive>| {
+            convert_color2(cs, &Color::Other(args), &resources, BlendMode::Normal, false)
+        };
+        // a missing pattern, a pattern without a name and a nested named space
+        assert!(convert(&ColorSpace::Pattern, vec![Primitive::Name("P0".into())]).is_err());
+        assert!(convert(&ColorSpace::Pattern, vec![]).is_err());
+        let mut resources = Resources::default();
+        resources.color_spaces.insert(Name::from("CS0"), ColorSpace::Named(Name::from("CS1")));
+        let named = ColorSpace::Named(Name::from("CS0"));
+        assert!(convert_color2(&named, &Color::Other(vec![Primitive::Integer(0)]), &resources, BlendMode::Normal, false).is_err());
+    }
+
+    // one call to the recording plotter, images, gradients and tilings without outline
+    struct Call {
+        kind: &'static str,
+        outline: Outline,
+        mode: Option<DrawMode>,
+        transform: Transform2F,
+        clip: Option<usize>,
+        marked: McContext,
+    }
+
+    // records every call, clip paths are numbered in the order they are created
+    #[derive(Default)]
+    struct RecordingPlotter {
+        calls: Vec<Call>,
+        // the parent of every clip path
+        parents: Vec<Option<usize>>,
+        current: McContext,
+    }
+    impl RecordingPlotter {
+        fn record(&mut self, kind: &'static str, outline: Outline, mode: Option<DrawMode>, transform: Transform2F, clip: Option<usize>) {
+            self.calls.push(Call { kind, outline, mode, transform, clip, marked: self.current });
+        }
+        fn kinds(&self) -> Vec<&'static str> {
+            self.calls.iter().map(|call| call.kind).collect()
+        }
+        fn clips(&self) -> Vec<Option<usize>> {
+            self.calls.iter().map(|call| call.clip).collect()
+        }
+    }
+    impl Plotter for RecordingPlotter {
+        type ClipPathId = usize;
+        fn draw(&mut self, outline: &Outline, mode: &DrawMode, _: FillRule, transform: Transform2F, clip: Option<usize>) {
+            let kind = match mode {
+                DrawMode::Fill { .. } => "fill",
+                DrawMode::Stroke { .. } => "stroke",
+                DrawMode::FillStroke { .. } => "fill stroke",
+            };
+            self.record(kind, outline.clone(), Some(mode.clone()), transform, clip);
+        }
+        fn create_clip_path(&mut self, _: Outline, _: FillRule, parent: Option<usize>) -> usize {
+            self.parents.push(parent);
+            self.parents.len() - 1
+        }
+        fn draw_image(&mut self, _: &RgbaImage, transform: Transform2F, clip: Option<usize>, _: f32, _: bool) {
+            self.record("image", Outline::new(), None, transform, clip);
+        }
+        fn draw_gradient(&mut self, outline: Outline, _: &LinearGradient, clip: Option<usize>) {
+            self.record("gradient", outline, None, Transform2F::default(), clip);
+        }
+        fn draw_tiling(&mut self, outline: Outline, _: FillRule, _: &Tiling, clip: Option<usize>) {
+            self.record("tiling", outline, None, Transform2F::default(), clip);
+        }
+        fn marked_content(&mut self, context: McContext) {
+            self.current = context;
+        }
+    }
+
+    #[test]
+    fn hook_strips_fills() {
+        use crate::op_hook::{HookAction, HookState, OpHook};
+        struct NoFills;
+        impl OpHook for NoFills {
+            fn on_op(&mut self, _: usize, op: &Op, _: &HookState) -> HookAction {
+                match op {
+                    Op::Fill { .. } => HookAction::Skip,
+                    _ => HookAction::Continue,
+                }
+            }
+        }
+        let (mut plotter, resolve) = (RecordingPlotter::default(), NoResolve);
+        let resources = Resources::default();
+        let options = RenderOptions::new().op_hook(NoFills);
+        let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+        let mut render = RenderState::new(&mut plotter, &resolve, &resources, Transform2F::default(), view_box, &options, 0, 1);
+        let ops = parse_ops(b"0 0 10 10 re f 1 0 0 rg 20 20 10 10 re f 40 40 m 60 60 l S 70 70 10 10 re f", &NoResolve).unwrap();
+        render.render_content(&ops).unwrap();
+        assert_eq!(render.context().stats.ops, ops.len());
+        drop(render);
+        assert_eq!(plotter.kinds(), ["stroke"]);
+    }
+
+    #[test]
+    fn hook_sees_state() {
+        use crate::op_hook::{HookAction, HookState, OpHook};
+        use std::sync::{Arc, Mutex};
+        // the fill color and translation at every f
+        struct Fills(Arc<Mutex<Vec<(Fill, Vector2F)>>>);
+        impl OpHook for Fills {
+            fn on_op(&mut self, _: usize, op: &Op, state: &HookState) -> HookAction {
+                if let Op::Fill { .. } = op {
+                    self.0.lock().unwrap().push((state.fill_color, state.transform.translation()));
+                }
+                HookAction::Continue
+            }
+        }
+        let seen = Arc::new(Mutex::new(vec![]));
+        let (mut plotter, resolve) = (RecordingPlotter::default(), NoResolve);
+        let resources = Resources::default();
+        let options = RenderOptions::new().op_hook(Fills(seen.clone()));
+        let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+        let mut render = RenderState::new(&mut plotter, &resolve, &resources, Transform2F::default(), view_box, &options, 0, 1);
+        render.render_content(&parse_ops(b"0 0 1 1 re f 1 0 0 rg 1 0 0 1 5 7 cm 0 0 1 1 re f", &NoResolve).unwrap()).unwrap();
+        drop(render);
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0], (Fill::black(), Vector2F::zero()));
+        assert_eq!(seen[1], (Fill::Solid(1., 0., 0.), Vector2F::new(5., 7.)));
+    }
+
+    #[test]
+    fn marked_content_stack() {
+        let data = b"0 0 1 1 re f /P <</MCID 3>> BDC 0 0 1 1 re f /Artifact BMC 0 0 1 1 re f EMC \
+            /Span BMC 0 0 1 1 re f EMC EMC 0 0 1 1 re f /Artifact BMC";
+        let (mut plotter, resolve) = (RecordingPlotter::default(), NoResolve);
+        let resources = Resources::default();
+        let options = RenderOptions::new();
+        let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+        let mut render = RenderState::new(&mut plotter, &resolve, &resources, Transform2F::default(), view_box, &options, 0, 1);
+        render.render_content(&parse_ops(data, &NoResolve).unwrap()).unwrap();
+        drop(render);
+        let (none, mcid) = (McContext::default(), McContext { mcid: Some(3), artifact: false });
+        let artifact = McContext { artifact: true, ..mcid };
+        let marked: Vec<McContext> = plotter.calls.iter().map(|call| call.marked).collect();
+        assert_eq!(marked, [none, mcid, artifact, mcid, none]);
+        // the sequence left open is closed at the end of the page
+        assert_eq!(plotter.current, none);
+    }
+
+    fn font(name: &str) -> Op {
+        Op::TextFont { name: Name::from(name), size: 12. }
+    }
+
+    // run the ops and return the font after each of them
+    fn fonts(ops: &[Op]) -> Vec<Option<String>> {
+        let (mut plotter, mut resolve) = (RecordingPlotter::default(), NoResolve);
+        let resources = Resources::default();
+        let options = RenderOptions::new();
+        let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+        let mut render = RenderState::new(
+            &mut plotter, &mut resolve, &resources, Transform2F::default(), view_box, &options, 0, 1,
+        );
+        ops.iter()
+            .map(|op| {
+                render.render_ops(std::slice::from_ref(op)).unwrap();
+                render.text_state.font.as_ref().map(|f| f.as_str().to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn font_set_before_bt() {
+        let f = fonts(&[font("F1"), Op::BeginText, Op::EndText]);
+        assert_eq!(f[2].as_deref(), Some("F1"));
+    }
+
+    #[test]
+    fn font_persists_across_text_objects() {
+        let f = fonts(&[Op::BeginText, font("F1"), Op::EndText, Op::BeginText, Op::EndText]);
+        assert_eq!(f[4].as_deref(), Some("F1"));
+    }
+
+    #[test]
+    fn save_restore_around_font_change() {
+        let f = fonts(&[
+            font("F1"),
+            Op::Save,
+            Op::BeginText,
+            font("F2"),
+            Op::EndText,
+            Op::Restore,
+            Op::BeginText,
+        ]);
+        assert_eq!(f[4].as_deref(), Some("F2"));
+        assert_eq!(f[6].as_deref(), Some("F1"));
+    }
+
+    // the state q saves and Q restores, without the text matrix
+    #[derive(Debug, PartialEq)]
+    struct Snapshot {
+        transform: Transform2F,
+        stroke_style: StrokeStyle,
+        miter_limit: f32,
+        fill_color: Fill,
+        stroke_color: Fill,
+        fill_alpha: f32,
+        stroke_alpha: f32,
+        dash_pattern: Option<(Vec<f32>, f32)>,
+        clip_path_id: Option<usize>,
+        clip_bounds: Option<RectF>,
+        blend_mode: Option<BlendMode>,
+        font: Option<Name>,
+        font_size: f32,
+        char_space: f32,
+        word_space: f32,
+        horiz_scale: f32,
+        leading: f32,
+        rise: f32,
+        mode: String,
+    }
+
+    impl Snapshot {
+        fn of<R: pdf::object::Resolve>(render: &RenderState<R, RecordingPlotter>) -> Self {
+            let (gs, ts) = (&render.graphics_state, &render.text_state);
+            Snapshot {
+                transform: gs.transform,
+                stroke_style: gs.stroke_style,
+                miter_limit: gs.miter_limit,
+                fill_color: gs.fill_color,
+                stroke_color: gs.stroke_color,
+                fill_alpha: gs.effective_fill_alpha(),
+                stroke_alpha: gs.effective_stroke_alpha(),
+                dash_pattern: gs.dash_pattern.clone(),
+                clip_path_id: gs.clip_path_id,
+                clip_bounds: gs.clip_bounds,
+                blend_mode: gs.blend_mode,
+                font: ts.font.clone(),
+                font_size: ts.font_size,
+                char_space: ts.char_space,
+                word_space: ts.word_space,
+                horiz_scale: ts.horiz_scale,
+                leading: ts.leading,
+                rise: ts.rise,
+                mode: format!("{:?}", ts.mode),
+            }
+        }
+    }
+
+    // every parameter set between q and Q
+    fn change_everything(round: f32) -> Vec<Op> {
+        vec![
+            Op::Transform { matrix: Matrix { a: round, b: 0., c: 0., d: round, e: 5., f: 5. } },
+            Op::LineWidth { width: round },
+            Op::LineCap { cap: pdf::content::LineCap::Round },
+            Op::LineJoin { join: pdf::content::LineJoin::Bevel },
+            Op::MiterLimit { limit: round },
+            Op::Dash { pattern: vec![round, 1.], phase: 0.5 },
+            Op::FillColor { color: Color::Rgb(Rgb { red: 1., green: 0., blue: 0. }) },
+            Op::StrokeColor { color: Color::Rgb(Rgb { red: 0., green: 0., blue: round / 10. }) },
+            rect(0., 0., 10. * round, 10. * round),
+            Op::Clip { winding: Winding::NonZero },
+            Op::EndPath,
+            font("F1"),
+            Op::CharSpacing { char_space: round },
+            Op::WordSpacing { word_space: round },
+            Op::TextScaling { horiz_scale: 50. * round },
+            Op::Leading { leading: round },
+            Op::TextRise { rise: round },
+            Op::TextRenderMode { mode: TextMode::Stroke },
+        ]
+    }
+
+    #[test]
+    fn save_restore_round_trip() {
+        let (mut plotter, mut resolve) = (RecordingPlotter::default(), NoResolve);
+        let resources = Resources::default();
+        let options = RenderOptions::new();
+        let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+        let mut render = RenderState::new(
+            &mut plotter, &mut resolve, &resources, Transform2F::default(), view_box, &options, 0, 1,
+        );
+        render.render_ops(&change_everything(2.)).unwrap();
+        let before = Snapshot::of(&render);
+        render.render_ops(&[Op::Save]).unwrap();
+        render.render_ops(&change_everything(3.)).unwrap();
+        render.graphics_state.blend_mode = Some(BlendMode::Multiply);
+        render.graphics_state.set_fill_alpha(0.5);
+        let changed = Snapshot::of(&render);
+        render.render_ops(&[Op::Restore]).unwrap();
+        assert_eq!(Snapshot::of(&render), before);
+        // the changes took effect before Q
+        assert_ne!(changed.clip_path_id, before.clip_path_id);
+        assert_ne!(changed.dash_pattern, before.dash_pattern);
+        assert_ne!(changed.transform, before.transform);
+
+        // strict parsing rejects a Q without q
+        assert!(render.render_ops(&[Op::Restore]).is_err());
+    }
+
+    #[test]
+    fn restore_keeps_text_position() {
+        let (mut plotter, mut resolve) = (RecordingPlotter::default(), NoResolve);
+        let resources = Resources::default();
+        let options = RenderOptions::new();
+        let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+        let mut render = RenderState::new(
+            &mut plotter, &mut resolve, &resources, Transform2F::default(), view_box, &options, 0, 1,
+        );
+        let ops = [
+            Op::BeginText,
+            Op::Save,
+            Op::MoveTextPosition { translation: Point { x: 10., y: 20. } },
+            Op::Restore,
+        ];
+        render.render_ops(&ops).unwrap();
+        assert_eq!(render.text_state.text_matrix.translation(), Vector2F::new(10., 20.));
+    }
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Op {
+        Op::Rect { rect: pdf::content::Rect { x, y, width, height } }
+    }
+
+    #[test]
+    fn nested_clips() {
+        let (mut plotter, mut resolve) = (RecordingPlotter::default(), NoResolve);
+        let resources = Resources::default();
+        let options = RenderOptions::new();
+        let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+        let mut render = RenderState::new(
+            &mut plotter, &mut resolve, &resources, Transform2F::default(), view_box, &options, 0, 1,
+        );
+        let ops = [
+            rect(0., 0., 50., 50.),
+            Op::Clip { winding: Winding::NonZero },
+            // the clip does not apply to the path that defines it
+            Op::Fill { winding: Winding::NonZero },
+            Op::Save,
+            rect(25., 25., 50., 50.),
+            Op::Clip { winding: Winding::NonZero },
+            Op::EndPath,
+            rect(0., 0., 100., 100.),
+            Op::Fill { winding: Winding::NonZero },
+        ];
+        render.render_ops(&ops).unwrap();
+        let clip = render.graphics_state.clip_bounds.unwrap();
+        assert_eq!(clip, RectF::new(Vector2F::splat(25.), Vector2F::splat(25.)));
+
+        render.render_ops(&[Op::Restore, rect(0., 0., 10., 10.), Op::Fill { winding: Winding::NonZero }]).unwrap();
+        assert_eq!(render.graphics_state.clip_bounds.unwrap().size(), Vector2F::splat(50.));
+        drop(render);
+
+        // the inner clip is nested in the outer one
+        assert_eq!(plotter.parents, vec![None, Some(0)]);
+        assert_eq!(plotter.clips(), vec![None, Some(1), Some(0)]);
+    }
+
+    #[test]
+    fn consecutive_clips() {
+        let (mut plotter, mut resolve) = (RecordingPlotter::default(), NoResolve);
+        let resources = Resources::default();
+        let options = RenderOptions::new();
+        let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+        let mut render = RenderState::new(
+            &mut plotter, &mut resolve, &resources, Transform2F::default(), view_box, &options, 0, 1,
+        );
+        let ops = [
+            Op::Save,
+            rect(0., 0., 60., 60.),
+            Op::Clip { winding: Winding::EvenOdd },
+            Op::EndPath,
+            rect(40., 40., 60., 60.),
+            Op::Clip { winding: Winding::NonZero },
+            Op::EndPath,
+        ];
+        render.render_ops(&ops).unwrap();
+        // W n paints nothing, the second clip narrows the first
+        assert_eq!(render.graphics_state.clip_bounds.unwrap(), RectF::new(Vector2F::splat(40.), Vector2F::splat(20.)));
+        assert!(render.pending_clip.is_none() && render.current_outline.is_empty());
+        render.render_ops(&[rect(0., 0., 100., 100.), Op::Fill { winding: Winding::NonZero }, Op::Restore]).unwrap();
+        assert!(render.graphics_state.clip_bounds.is_none());
+        render.render_ops(&[rect(0., 0., 10., 10.), Op::Fill { winding: Winding::NonZero }]).unwrap();
+        drop(render);
+
+        assert_eq!(plotter.parents, vec![None, Some(0)]);
+        assert_eq!(plotter.clips(), vec![Some(1), None]);
+    }
+
+    #[test]
+    fn stroke_bounds() {
+        let (mut plotter, mut resolve) = (BoundsPlotter::new(), NoResolve);
+        let resources = Resources::default();
+        let options = RenderOptions::new();
+        let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+        let mut render = RenderState::new(
+            &mut plotter, &mut resolve, &resources, Transform2F::default(), view_box, &options, 0, 1,
+        );
+        let ops = [
+            Op::LineWidth { width: 10. },
+            Op::LineCap { cap: pdf::content::LineCap::Square },
+            Op::MoveTo { p: Point { x: 10., y: 50. } },
+            Op::LineTo { p: Point { x: 90., y: 50. } },
+            Op::Stroke,
+        ];
+        render.render_ops(&ops).unwrap();
+        drop(render);
+        // the centerline is 80 x 0, the stroke adds half the width on every side
+        let bounds = plotter.bounds().unwrap();
+        assert!((bounds.origin() - Vector2F::new(5., 45.)).length() < 1e-3, "{:?}", bounds);
+        assert!((bounds.size() - Vector2F::new(90., 10.)).length() < 1e-3, "{:?}", bounds);
+    }
+
+    #[test]
+    fn width_scales_with_ctm() {
+        // the width is in user space, the outline is stroked before it is transformed
+        let stroke = |matrix: Matrix| {
+            let (mut plotter, resolve) = (BoundsPlotter::new(), NoResolve);
+            let resources = Resources::default();
+            let options = RenderOptions::new();
+            let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+            let mut render = RenderState::new(&mut plotter, &resolve, &resources, Transform2F::default(), view_box, &options, 0, 1);
+            let ops = [
+                Op::Transform { matrix },
+                Op::LineWidth { width: 1. },
+                Op::MoveTo { p: Point { x: 10., y: 10. } },
+                Op::LineTo { p: Point { x: 90., y: 10. } },
+                Op::Stroke,
+            ];
+            render.render_ops(&ops).unwrap();
+            drop(render);
+            plotter.bounds().unwrap()
+        };
+        let half = stroke(Matrix { a: 0.5, b: 0., c: 0., d: 0.5, e: 0., f: 0. });
+        assert!((half.height() - 0.5).abs() < 1e-3, "{:?}", half);
+        assert!((half.width() - 40.).abs() < 1e-3, "{:?}", half);
+        // anisotropic: the horizontal line gets the vertical scale
+        let wide = stroke(Matrix { a: 3., b: 0., c: 0., d: 0.1, e: 0., f: 0. });
+        assert!((wide.height() - 0.1).abs() < 1e-3, "{:?}", wide);
+    }
+
+    #[test]
+    fn singular_cm() {
+        let (mut plotter, resolve) = (BoundsPlotter::new(), NoResolve);
+        let resources = Resources::default();
+        let options = RenderOptions::new();
+        let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+        let mut render = RenderState::new(&mut plotter, &resolve, &resources, Transform2F::default(), view_box, &options, 0, 1);
+        let ops = parse_ops(b"2 0 0 2 0 0 cm 0 0 0 0 0 0 cm 1 2 2 4 0 0 cm 10 10 10 10 re f", &NoResolve).unwrap();
+        render.render_content(&ops).unwrap();
+        assert_eq!(render.context().warnings.len(), 2, "{:?}", render.context().warnings);
+        drop(render);
+        // both singular matrices were skipped, the first one still applies
+        assert_eq!(plotter.bounds(), Some(RectF::new(Vector2F::splat(20.), Vector2F::splat(20.))));
+    }
+
+    #[test]
+    fn garbage_coordinates() {
+        // from a corrupt file: NaN in cm, 1e38 coordinates, an infinite curve point
+        let nan = Matrix { a: f32::NAN, b: 0., c: 0., d: 1., e: 0., f: 0. };
+        let huge = Matrix { a: 1e38, b: 0., c: 0., d: 1e38, e: 0., f: 0. };
+        let ops = [
+            Op::Save,
+            Op::Transform { matrix: nan },
+            Op::Rect { rect: Rect { x: 10., y: 10., width: 10., height: 10. } },
+            Op::Fill { winding: Winding::NonZero },
+            Op::MoveTo { p: Point { x: 0., y: 0. } },
+            Op::LineTo { p: Point { x: 1e38, y: -1e38 } },
+            Op::CurveTo { c1: Point { x: 1., y: 1. }, c2: Point { x: f32::INFINITY, y: 1. }, p: Point { x: 2., y: 2. } },
+            Op::Stroke,
+            Op::Transform { matrix: huge },
+            Op::Transform { matrix: huge },
+            Op::Rect { rect: Rect { x: 0., y: 0., width: 1., height: 1. } },
+            Op::Fill { winding: Winding::NonZero },
+            Op::Restore,
+        ];
+        let (mut plotter, resolve) = (BoundsPlotter::new(), NoResolve);
+        let resources = Resources::default();
+        let options = RenderOptions::new().coord_limit(1.);
+        let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+        let mut render = RenderState::new(&mut plotter, &resolve, &resources, Transform2F::default(), view_box, &options, 0, 1);
+        render.render_content(&ops).unwrap();
+        let stats = render.context().stats.clone();
+        drop(render);
+        // the NaN cm, the infinite curve and the second huge cm, whose product overflows
+        assert_eq!(stats.bad_numbers, 3);
+        // the far line end and the corner of the huge rectangle
+        assert_eq!(stats.clamped_points, 2);
+        let bounds = plotter.bounds().unwrap();
+        let limit = RectF::new(Vector2F::splat(-100.), Vector2F::splat(300.));
+        assert!(limit.dilate(Vector2F::splat(1.)).contains_rect(bounds), "{:?}", bounds);
+    }
+
+    #[test]
+    fn hairlines() {
+        let height = |dpi: f32, options: &RenderOptions| {
+            let (mut plotter, resolve) = (BoundsPlotter::new(), NoResolve);
+            let resources = Resources::default();
+            let root = Transform2F::from_scale(dpi / 72.);
+            let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100. * dpi / 72.));
+            let mut render = RenderState::new(&mut plotter, &resolve, &resources, root, view_box, options, 0, 1);
+            render.render_content(&parse_ops(b"0 w 10 10 m 90 10 l S", &NoResolve).unwrap()).unwrap();
+            drop(render);
+            plotter.bounds().unwrap().height()
+        };
+        // one device pixel, whatever the resolution
+        let options = RenderOptions::new();
+        assert!((height(72., &options) - 1.).abs() < 1e-3);
+        assert!((height(300., &options) - 1.).abs() < 1e-3);
+        let options = RenderOptions::new().hairline_width(Some(0.25));
+        assert!((height(300., &options) - 0.25).abs() < 1e-3);
+        // --min-stroke-px applies to hairlines as well
+        let options = RenderOptions::new().hairline_width(Some(0.25)).min_stroke_px(Some(2.));
+        assert!((height(300., &options) - 2.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn dashes_scale_with_ctm() {
+        let (mut plotter, resolve) = (RecordingPlotter::default(), NoResolve);
+        let resources = Resources::default();
+        let options = RenderOptions::new();
+        let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+        let mut render = RenderState::new(&mut plotter, &resolve, &resources, Transform2F::default(), view_box, &options, 0, 1);
+        render.render_content(&parse_ops(b"2 0 0 2 0 0 cm [10 10] 5 d 0 10 m 40 10 l S", &NoResolve).unwrap()).unwrap();
+        drop(render);
+        // the phase skips half a dash: 5 on, 10 off, 10 on, 10 off, 5 on, twice as long on the page
+        let call = &plotter.calls[0];
+        let Some(DrawMode::Stroke { stroke_mode, .. }) = &call.mode else { panic!("{}", call.kind) };
+        let stroke = stroke_mode.to_outline(&call.outline).transformed(&call.transform);
+        let dashes: Vec<(f32, f32)> = stroke.contours().iter().map(|c| (c.bounds().min_x(), c.bounds().width())).collect();
+        let expected = [(0., 10.), (30., 20.), (70., 10.)];
+        assert_eq!(dashes.len(), expected.len(), "{:?}", dashes);
+        for ((x, width), (ex, ewidth)) in dashes.into_iter().zip(expected) {
+            assert!((x - ex).abs() < 1e-3 && (width - ewidth).abs() < 1e-3, "{} {}", x, width);
+        }
+    }
+
+    #[test]
+    fn zero_length_dots() {
+        let dots = |cap: u8| {
+            // a 3 x 3 grid of drill holes, the middle row as m h
+            let mut data = format!("{} J 2 w ", cap);
+            for y in [10, 20, 30] {
+                for x in [10, 20, 30] {
+                    match y {
+                        20 => data += &format!("{} {} m h ", x, y),
+                        _ => data += &format!("{} {} m {} {} l ", x, y, x, y),
+                    }
+                }
+            }
+            // a lone moveto is not painted
+            data += "50 50 m S";
+            let (mut plotter, resolve) = (RecordingPlotter::default(), NoResolve);
+            let resources = Resources::default();
+            let options = RenderOptions::new();
+            let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+            let mut render = RenderState::new(&mut plotter, &resolve, &resources, Transform2F::default(), view_box, &options, 0, 1);
+            render.render_content(&parse_ops(data.as_bytes(), &NoResolve).unwrap()).unwrap();
+            drop(render);
+            // the device space bounds of every filled contour
+            let fills = plotter.calls.iter().filter(|call| call.kind == "fill");
+            fills.flat_map(|call| call.outline.contours().iter().map(|c| call.transform * c.bounds())).collect::<Vec<_>>()
+        };
+        assert!(dots(0).is_empty());
+        for cap in [1, 2] {
+            let dots = dots(cap);
+            assert_eq!(dots.len(), 9, "{:?}", dots);
+            for (i, dot) in dots.iter().enumerate() {
+                let center = Vector2F::new(10. * (i % 3 + 1) as f32, 10. * (i / 3 + 1) as f32);
+                assert!((dot.center() - center).length() < 1e-3, "{:?}", dot);
+                assert!((dot.width() - 2.).abs() < 1e-2 && (dot.height() - 2.).abs() < 1e-2, "{:?}", dot);
+            }
+        }
+    }
+
+    #[test]
+    fn caps_and_joins() {
+        use pdf::content::{LineCap, LineJoin};
+        let stroke = |ops: &[Op]| {
+            let (mut plotter, mut resolve) = (BoundsPlotter::new(), NoResolve);
+            let resources = Resources::default();
+            let options = RenderOptions::new();
+            let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+            let mut render = RenderState::new(
+                &mut plotter, &mut resolve, &resources, Transform2F::default(), view_box, &options, 0, 1,
+            );
+            render.render_ops(ops).unwrap();
+            // a 45 degree corner at 80,20
+            let path = [
+                Op::LineWidth { width: 10. },
+                Op::MoveTo { p: Point { x: 20., y: 20. } },
+                Op::LineTo { p: Point { x: 80., y: 20. } },
+                Op::LineTo { p: Point { x: 20., y: 80. } },
+                Op::Stroke,
+            ];
+            render.render_ops(&path).unwrap();
+            drop(render);
+            plotter.bounds().unwrap()
+        };
+        // the caps reach left, the corner reaches right
+        let caps = [(LineCap::Butt, 16.46), (LineCap::Round, 15.), (LineCap::Square, 12.93)];
+        let joins = [(LineJoin::Miter, 92.07), (LineJoin::Round, 85.), (LineJoin::Bevel, 83.54)];
+        for (cap, min_x) in caps {
+            for (join, max_x) in joins {
+                let bounds = stroke(&[Op::LineCap { cap }, Op::LineJoin { join }]);
+                assert!((bounds.min_x() - min_x).abs() < 0.05, "{:?} {:?}: {:?}", cap, join, bounds);
+                assert!((bounds.max_x() - max_x).abs() < 0.05, "{:?} {:?}: {:?}", cap, join, bounds);
+            }
+        }
+        // the miter is cut off above the limit
+        let bounds = stroke(&[Op::MiterLimit { limit: 2. }]);
+        assert!((bounds.max_x() - 83.54).abs() < 0.05, "{:?}", bounds);
+        // and the style is restored with the graphics state
+        let bounds = stroke(&[
+            Op::Save,
+            Op::LineCap { cap: LineCap::Square },
+            Op::LineJoin { join: LineJoin::Round },
+            Op::MiterLimit { limit: 2. },
+            Op::Restore,
+        ]);
+        assert!((bounds.min_x() - 16.46).abs() < 0.05 && (bounds.max_x() - 92.07).abs() < 0.05, "{:?}", bounds);
+    }
+
+    #[test]
+    fn cmyk_group_multiply() {
+        let rgb = |cmyk| match cmyk2rgb(cmyk, BlendMode::Multiply, true) {
+            Fill::Solid(r, g, b) => [r, g, b],
+            _ => unreachable!(),
+        };
+        let (a, b) = ((0.2, 0.5, 0.0, 0.3), (0.6, 0.1, 0.4, 0.5));
+        // multiply in a cmyk group is 1 - (1 - a) * (1 - b) on every component
+        let mix = |a: f32, b: f32| 1. - (1. - a) * (1. - b);
+        let blended = rgb((mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2), mix(a.3, b.3)));
+        let (ra, rb) = (rgb(a), rgb(b));
+        for i in 0..3 {
+            assert!((ra[i] * rb[i] - blended[i]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn truncated_content() {
+        let data = b"q /P BMC 1 0 0 rg BT 10 20 Td ET 0 0 m 10 0 l 10 10 l h f EMC Q";
+        let cut_at = |after: &str| {
+            let pos = data.windows(after.len()).position(|w| w == after.as_bytes()).unwrap();
+            pos + after.len()
+        };
+        let render = |data: &[u8]| {
+            let (mut plotter, resolve) = (RecordingPlotter::default(), NoResolve);
+            let resources = Resources::default();
+            let options = RenderOptions::new();
+            let view_box = RectF::new(Vector2F::zero(), Vector2F::splat(100.));
+            let mut render = RenderState::new(
+                &mut plotter, &resolve, &resources, Transform2F::default(), view_box, &options, 0, 1,
+            );
+            render.render_content(&parse_ops(data, &NoResolve).unwrap()).unwrap();
+            assert!(!render.in_text);
+            assert!(render.marked_content.is_empty());
+            assert!(render.stack.is_empty());
+            render.into_context()
+        };
+        for after in ["BMC ", "rg ", "BT ", "Td ", "10 0 l ", "h "] {
+            let ctx = render(&data[..cut_at(after)]);
+            assert!(ctx.stats.truncated, "cut after {:?}", after);
+            assert_eq!(ctx.warnings.len(), 1);
+        }
+        // only the Q is missing
+        assert!(!render(&data[..cut_at("EMC ")]).stats.truncated);
+        assert!(!render(data).stats.truncated);
+    }
+}
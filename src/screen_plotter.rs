@@ -22,11 +22,9 @@ use glutin_winit::{self, DisplayBuilder, GlWindow};
 
 use pathfinder_canvas::{Canvas, CanvasFontContext, FillRule, Path2D, Transform2F};
 use pathfinder_color::{ColorF, ColorU};
-use pathfinder_content::dash::OutlineDash;
 use pathfinder_content::outline::Outline;
-use pathfinder_content::stroke::OutlineStrokeToFill;
 use pathfinder_geometry::rect::RectF;
-use pathfinder_geometry::vector::{vec2f, vec2i};
+use pathfinder_geometry::vector::{vec2f, vec2i, Vector2F};
 use pathfinder_gl::{GLDevice, GLVersion};
 use pathfinder_renderer::concurrent::rayon::RayonExecutor;
 use pathfinder_renderer::concurrent::scene_proxy::SceneProxy;
@@ -34,17 +32,30 @@ use pathfinder_renderer::gpu::options::{DestFramebuffer, RendererMode, RendererO
 //use pathfinder_renderer::gpu::renderer::Renderer;
 use pathfinder_renderer::options::BuildOptions;
 use pathfinder_renderer::paint::{Paint, PaintId};
-use pathfinder_renderer::scene::{ClipPathId, DrawPath, Scene};
+use pathfinder_renderer::scene::{ClipPath, ClipPathId, DrawPath, Scene};
 use pathfinder_resources::embedded::EmbeddedResourceLoader;
 
 use gl::types::GLfloat;
 
-use crate::plotter::{BlendMode, DrawMode, Fill, Plotter};
+use crate::images;
+use crate::shading;
+use crate::plotter::{BlendMode, DrawMode, Fill, LinearGradient, Plotter, RgbaImage, Tiling};
 
 fn blend_mode(mode: BlendMode) -> pathfinder_content::effects::BlendMode {
+    use pathfinder_content::effects::BlendMode as B;
     match mode {
-        BlendMode::Darken => pathfinder_content::effects::BlendMode::Multiply,
-        BlendMode::Overlay => pathfinder_content::effects::BlendMode::Overlay,
+        BlendMode::Normal => B::SrcOver,
+        BlendMode::Multiply => B::Multiply,
+        BlendMode::Screen => B::Screen,
+        BlendMode::Overlay => B::Overlay,
+        BlendMode::Darken => B::Darken,
+        BlendMode::Lighten => B::Lighten,
+        BlendMode::ColorDodge => B::ColorDodge,
+        BlendMode::ColorBurn => B::ColorBurn,
+        BlendMode::HardLight => B::HardLight,
+        BlendMode::SoftLight => B::SoftLight,
+        BlendMode::Difference => B::Difference,
+        BlendMode::Exclusion => B::Exclusion,
     }
 }
 
@@ -76,6 +87,32 @@ impl ScreenPlotter {
 
 impl Plotter for ScreenPlotter {
     type ClipPathId = ClipPathId;
+    fn draw_image(&mut self, image: &RgbaImage, transform: Transform2F, clip: Option<Self::ClipPathId>, alpha: f32, smoothing: bool) {
+        let paint = self.scene.push_paint(&images::image_paint(image, transform, alpha, smoothing));
+        let outline = Outline::from_rect(RectF::new(Vector2F::zero(), Vector2F::splat(1.))).transformed(&transform);
+        let mut draw_path = DrawPath::new(outline, paint);
+        draw_path.set_clip_path(clip);
+        self.scene.push_draw_path(draw_path);
+    }
+    fn draw_gradient(&mut self, outline: Outline, gradient: &LinearGradient, clip: Option<Self::ClipPathId>) {
+        let paint = self.scene.push_paint(&shading::gradient_paint(gradient));
+        let mut draw_path = DrawPath::new(outline, paint);
+        draw_path.set_clip_path(clip);
+        self.scene.push_draw_path(draw_path);
+    }
+    fn draw_tiling(&mut self, outline: Outline, fill_rule: FillRule, tiling: &Tiling, clip: Option<Self::ClipPathId>) {
+        let paint = self.scene.push_paint(&images::tiling_paint(tiling));
+        let mut draw_path = DrawPath::new(outline, paint);
+        draw_path.set_clip_path(clip);
+        draw_path.set_fill_rule(fill_rule);
+        self.scene.push_draw_path(draw_path);
+    }
+    fn create_clip_path(&mut self, outline: Outline, fill_rule: FillRule, parent: Option<Self::ClipPathId>) -> Self::ClipPathId {
+        let mut clip_path = ClipPath::new(outline);
+        clip_path.set_fill_rule(fill_rule);
+        clip_path.set_clip_path(parent);
+        self.scene.push_clip_path(clip_path)
+    }
     fn draw(
         &mut self,
         outline: &Outline,
@@ -106,23 +143,11 @@ impl Plotter for ScreenPlotter {
                 ..
             } => {
                 let paint = self.paint(stroke.color, stroke.alpha);
-                let contour = match stroke_mode.dash_pattern {
-                    Some((ref pat, phase)) => {
-                        let dashed = OutlineDash::new(outline, pat, phase).into_outline();
-                        let mut stroke = OutlineStrokeToFill::new(&dashed, stroke_mode.style);
-                        stroke.offset();
-                        stroke.into_outline()
-                    }
-                    None => {
-                        let mut stroke = OutlineStrokeToFill::new(outline, stroke_mode.style);
-                        stroke.offset();
-                        stroke.into_outline()
-                    }
-                };
+                let contour = stroke_mode.to_outline(outline);
                 let mut draw_path = DrawPath::new(contour.transformed(&transform), paint);
                 draw_path.set_clip_path(clip);
-                draw_path.set_fill_rule(fill_rule);
-
+                // the fill rule of B* is for the fill, the stroke area always uses nonzero
+                draw_path.set_fill_rule(FillRule::Winding);
                 draw_path.set_blend_mode(blend_mode(stroke.mode));
                 self.scene.push_draw_path(draw_path);
             }
@@ -165,7 +190,7 @@ pub fn main(event_loop: winit::event_loop::EventLoop<()>) -> Result<(), Box<dyn
 
     let (mut window, gl_config) = display_builder.build(&event_loop, template, gl_config_picker)?;
 
-    println!("Picked a config with {} samples", gl_config.num_samples());
+    info!("Picked a config with {} samples", gl_config.num_samples());
 
     let raw_window_handle = window.as_ref().map(|window| window.raw_window_handle());
 
@@ -206,7 +231,7 @@ pub fn main(event_loop: winit::event_loop::EventLoop<()>) -> Result<(), Box<dyn
         match event {
             Event::Resumed => {
                 #[cfg(android_platform)]
-                println!("Android window available");
+                info!("Android window available");
 
                 let window = window.take().unwrap_or_else(|| {
                     let window_builder = WindowBuilder::new()
@@ -234,7 +259,7 @@ pub fn main(event_loop: winit::event_loop::EventLoop<()>) -> Result<(), Box<dyn
                 if let Err(res) = gl_surface
                     .set_swap_interval(&gl_context, SwapInterval::Wait(NonZeroU32::new(1).unwrap()))
                 {
-                    eprintln!("Error setting vsync: {res:?}");
+                    warn!("Error setting vsync: {res:?}");
                 }
 
                 assert!(state.replace((gl_context, gl_surface, window)).is_none());
@@ -242,7 +267,7 @@ pub fn main(event_loop: winit::event_loop::EventLoop<()>) -> Result<(), Box<dyn
             Event::Suspended => {
                 // This event is only raised on Android, where the backing NativeWindow for a GL
                 // Surface can appear and disappear at any moment.
-                println!("Android window removed");
+                info!("Android window removed");
 
                 // Destroy the GL Surface and un-current the GL Context before ndk-glue releases
                 // the window back to the system.
@@ -325,14 +350,14 @@ impl GlutinRenderer {
             });
 
             if let Some(renderer) = get_gl_string(&gl, gl::RENDERER) {
-                println!("Running on {}", renderer.to_string_lossy());
+                info!("Running on {}", renderer.to_string_lossy());
             }
             if let Some(version) = get_gl_string(&gl, gl::VERSION) {
-                println!("OpenGL Version {}", version.to_string_lossy());
+                info!("OpenGL Version {}", version.to_string_lossy());
             }
 
             if let Some(shaders_version) = get_gl_string(&gl, gl::SHADING_LANGUAGE_VERSION) {
-                println!("Shaders version on {}", shaders_version.to_string_lossy());
+                info!("Shaders version on {}", shaders_version.to_string_lossy());
             }
 
             let vertex_shader = create_shader(&gl, gl::VERTEX_SHADER, VERTEX_SHADER_SOURCE);
@@ -0,0 +1,186 @@
+use pathfinder_content::{
+    gradient::Gradient,
+    outline::{Contour, Outline},
+};
+use pathfinder_geometry::{line_segment::LineSegment2F, rect::RectF, vector::Vector2F};
+use pathfinder_renderer::paint::Paint;
+use pdf::{
+    function::Function,
+    object::{ColorSpace, Object, Resolve},
+    primitive::{Dictionary, Primitive},
+    PdfError,
+};
+
+use crate::plotter::LinearGradient;
+
+// the shading function is sampled into this many gradient stops
+pub const GRADIENT_STOPS: usize = 32;
+
+fn get(dict: &Dictionary, key: &str, resolve: &impl Resolve) -> Result<Option<Primitive>, PdfError> {
+    match dict.get(key) {
+        Some(&Primitive::Reference(r)) => Ok(Some(resolve.resolve(r)?)),
+        Some(p) => Ok(Some(p.clone())),
+        None => Ok(None),
+    }
+}
+
+fn numbers<const N: usize>(p: &Primitive) -> Result<[f32; N], PdfError> {
+    let parts = p.as_array()?;
+    if parts.len() != N {
+        return Err(PdfError::Other {
+            msg: format!("expected {} numbers, got {:?}", N, p),
+        });
+    }
+    let mut out = [0.; N];
+    for (o, p) in out.iter_mut().zip(parts) {
+        *o = p.as_number()?;
+    }
+    Ok(out)
+}
+
+pub fn shading_type(dict: &Dictionary) -> Result<i32, PdfError> {
+    dict.get("ShadingType")
+        .ok_or_else(|| PdfError::Other {
+            msg: "shading without /ShadingType".into(),
+        })?
+        .as_integer()
+}
+
+// /ShadingType 2, the color varies along the axis from (x0, y0) to (x1, y1)
+pub struct AxialShading {
+    pub color_space: ColorSpace,
+    pub coords: [f32; 4],
+    pub domain: [f32; 2],
+    pub extend: [bool; 2],
+    // a single function, or one function per color component
+    functions: Vec<Function>,
+}
+
+impl AxialShading {
+    pub fn from_dict(dict: &Dictionary, resolve: &impl Resolve) -> Result<Self, PdfError> {
+        let required = |key: &str| {
+            get(dict, key, resolve)?.ok_or_else(|| PdfError::Other {
+                msg: format!("axial shading without /{}", key),
+            })
+        };
+        let color_space = ColorSpace::from_primitive(required("ColorSpace")?, resolve)?;
+        let coords = numbers(&required("Coords")?)?;
+        let domain = match get(dict, "Domain", resolve)? {
+            Some(p) => numbers(&p)?,
+            None => [0., 1.],
+        };
+        let extend = match get(dict, "Extend", resolve)? {
+            Some(p) => match *p.as_array()? {
+                [ref a, ref b] => [a.as_bool()?, b.as_bool()?],
+                _ => return Err(PdfError::Other { msg: format!("invalid /Extend {:?}", p) }),
+            },
+            None => [false, false],
+        };
+        let functions = read_functions(dict, resolve)?;
+        if functions.is_empty() {
+            return Err(PdfError::Other {
+                msg: "axial shading without /Function".into(),
+            });
+        }
+        Ok(AxialShading { color_space, coords, domain, extend, functions })
+    }
+    // color components at t, 0 is the start and 1 the end of the axis
+    pub fn eval(&self, t: f32) -> Result<Vec<f32>, PdfError> {
+        let [t0, t1] = self.domain;
+        apply_functions(&self.functions, t0 + t * (t1 - t0))
+    }
+}
+
+// /Function of a shading: none, a single function or one per color component
+pub fn read_functions(dict: &Dictionary, resolve: &impl Resolve) -> Result<Vec<Function>, PdfError> {
+    match get(dict, "Function", resolve)? {
+        Some(Primitive::Array(parts)) => parts
+            .into_iter()
+            .map(|f| Function::from_primitive(f, resolve))
+            .collect(),
+        Some(f) => Ok(vec![Function::from_primitive(f, resolve)?]),
+        None => Ok(vec![]),
+    }
+}
+
+pub fn apply_functions(functions: &[Function], x: f32) -> Result<Vec<f32>, PdfError> {
+    let mut out = vec![];
+    for f in functions {
+        let mut values = vec![0.; f.output_dim()];
+        f.apply(&[x], &mut values)?;
+        out.extend(values);
+    }
+    Ok(out)
+}
+
+// The device space area painted by the gradient: a band across the axis that
+// reaches past the view box on the extended ends.
+pub fn axial_outline(gradient: &LinearGradient, extend: [bool; 2], view_box: RectF) -> Outline {
+    let axis = gradient.to - gradient.from;
+    let along = axis.normalize();
+    let across = along.yx() * Vector2F::new(-1., 1.);
+    let reach = view_box.size().length()
+        + (gradient.from - view_box.center()).length()
+        + axis.length();
+    let start = if extend[0] { gradient.from - along * reach } else { gradient.from };
+    let end = if extend[1] { gradient.to + along * reach } else { gradient.to };
+
+    let mut contour = Contour::new();
+    for p in [start - across * reach, end - across * reach, end + across * reach, start + across * reach] {
+        contour.push_endpoint(p);
+    }
+    contour.close();
+    let mut outline = Outline::new();
+    outline.push_contour(contour);
+    outline
+}
+
+pub fn gradient_paint(gradient: &LinearGradient) -> Paint {
+    let mut g = Gradient::linear(LineSegment2F::new(gradient.from, gradient.to));
+    for &(offset, color) in &gradient.stops {
+        g.add_color_stop(color, offset);
+    }
+    Paint::from_gradient(g)
+}
+
+#[cfg(test)]
+mod test {
+    use pathfinder_geometry::{rect::RectF, vector::vec2f};
+    use pdf::{file::FileOptions, primitive::Primitive};
+
+    use super::{axial_outline, shading_type, AxialShading};
+    use crate::plotter::LinearGradient;
+
+    #[test]
+    fn two_color_axial_shade() {
+        let file = FileOptions::cached().open("resources/axial_shading.pdf").unwrap();
+        let resolve = file.resolver();
+        let page = file.get_page(0).unwrap();
+        let resources = page.resources().unwrap();
+        let shading = resources.shadings.get("Sh1").unwrap();
+        let dict = match resolve.resolve(shading.get_inner()).unwrap() {
+            Primitive::Dictionary(dict) => dict,
+            p => panic!("unexpected shading {:?}", p),
+        };
+        assert_eq!(shading_type(&dict).unwrap(), 2);
+        let shading = AxialShading::from_dict(&dict, &resolve).unwrap();
+        assert_eq!(shading.coords, [50., 0., 150., 0.]);
+        assert_eq!(shading.extend, [true, true]);
+        assert_eq!(shading.eval(0.).unwrap(), vec![1., 0., 0.]);
+        assert_eq!(shading.eval(0.5).unwrap(), vec![0.5, 0., 0.5]);
+        assert_eq!(shading.eval(1.).unwrap(), vec![0., 0., 1.]);
+    }
+
+    #[test]
+    fn extend() {
+        let gradient = LinearGradient { from: vec2f(50., 0.), to: vec2f(150., 0.), stops: vec![] };
+        let view_box = RectF::new(vec2f(0., 0.), vec2f(200., 100.));
+        let bounds = axial_outline(&gradient, [false, false], view_box).bounds();
+        assert_eq!((bounds.min_x(), bounds.max_x()), (50., 150.));
+        assert!(bounds.min_y() < 0. && bounds.max_y() > 100.);
+
+        let bounds = axial_outline(&gradient, [true, false], view_box).bounds();
+        assert!(bounds.min_x() < 0.);
+        assert_eq!(bounds.max_x(), 150.);
+    }
+}
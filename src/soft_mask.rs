@@ -0,0 +1,139 @@
+use pathfinder_geometry::transform2d::Transform2F;
+use pdf::{
+    object::PlainRef,
+    primitive::{Dictionary, Primitive},
+    PdfError,
+};
+
+use crate::plotter::{Fill, RgbaImage};
+
+// offscreen layers (groups, soft masks) drawn inside of each other
+pub const MAX_LAYER_DEPTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaskKind {
+    // the luminosity of the group over the backdrop
+    Luminosity,
+    // the alpha of the group
+    Alpha,
+}
+
+// /SMask dictionary of an ExtGState
+#[derive(Debug, Clone)]
+pub struct SoftMask {
+    pub kind: MaskKind,
+    // the transparency group xobject
+    pub group: PlainRef,
+    // /BC, the mask outside of the group, black if missing
+    pub backdrop: Fill,
+    // the current transformation when the mask was set, the group is drawn in it
+    pub transform: Transform2F,
+}
+
+impl SoftMask {
+    pub fn from_dict(dict: &Dictionary, transform: Transform2F) -> Result<Self, PdfError> {
+        let kind = match dict.get("S").map(|s| s.as_name()).transpose()? {
+            Some("Luminosity") => MaskKind::Luminosity,
+            Some("Alpha") => MaskKind::Alpha,
+            s => {
+                return Err(PdfError::Other {
+                    msg: format!("soft mask of type {:?}", s),
+                })
+            }
+        };
+        let group = match dict.get("G") {
+            Some(&Primitive::Reference(r)) => r,
+            g => {
+                return Err(PdfError::Other {
+                    msg: format!("soft mask group is not a reference: {:?}", g),
+                })
+            }
+        };
+        let backdrop = match dict.get("BC") {
+            Some(bc) => {
+                let values = bc
+                    .as_array()?
+                    .iter()
+                    .map(|v| v.as_number())
+                    .collect::<Result<Vec<f32>, PdfError>>()?;
+                backdrop(&values)?
+            }
+            None => Fill::Solid(0., 0., 0.),
+        };
+        if dict.get("TR").is_some_and(|tr| tr.as_name().ok() != Some("Identity")) {
+            debug!("soft mask transfer function ignored");
+        }
+        Ok(SoftMask { kind, group, backdrop, transform })
+    }
+}
+
+// /BC has the components of the group color space
+fn backdrop(values: &[f32]) -> Result<Fill, PdfError> {
+    match *values {
+        [g] => Ok(Fill::Solid(g, g, g)),
+        [r, g, b] => Ok(Fill::Solid(r, g, b)),
+        [c, m, y, k] => Ok(Fill::Solid((1. - c) * (1. - k), (1. - m) * (1. - k), (1. - y) * (1. - k))),
+        _ => Err(PdfError::Other {
+            msg: format!("soft mask backdrop {:?}", values),
+        }),
+    }
+}
+
+// One value per pixel from the rendered mask group. Luminosity masks are rendered
+// over their opaque backdrop.
+pub fn mask_values(mask: &RgbaImage, kind: MaskKind) -> Vec<f32> {
+    mask.pixels
+        .chunks_exact(4)
+        .map(|p| match kind {
+            MaskKind::Luminosity => (0.30 * p[0] as f32 + 0.59 * p[1] as f32 + 0.11 * p[2] as f32) / 255.,
+            MaskKind::Alpha => p[3] as f32 / 255.,
+        })
+        .collect()
+}
+
+// Multiply the alpha of the layer with the mask, if any, and the constant alpha.
+// The read back pixels are premultiplied, the result is plain rgba like a
+// decoded image.
+pub fn apply_mask(layer: &mut RgbaImage, mask: Option<&[f32]>, alpha: f32) {
+    for (i, p) in layer.pixels.chunks_exact_mut(4).enumerate() {
+        let a = p[3] as f32;
+        if a == 0. {
+            continue;
+        }
+        for c in &mut p[..3] {
+            *c = (*c as f32 * 255. / a).round().min(255.) as u8;
+        }
+        let m = mask.and_then(|mask| mask.get(i)).copied().unwrap_or(1.);
+        p[3] = (a * m.clamp(0., 1.) * alpha.clamp(0., 1.)).round() as u8;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{apply_mask, backdrop, mask_values, MaskKind};
+    use crate::plotter::{Fill, RgbaImage};
+
+// offscreen layers (groups, soft masks) drawn inside of each other
+pub const MAX_LAYER_DEPTH: usize = 4;
+
+    #[test]
+    fn luminosity() {
+        let mask = RgbaImage { width: 3, height: 1, pixels: vec![0, 0, 0, 255, 255, 255, 255, 255, 0, 255, 0, 255] };
+        let values = mask_values(&mask, MaskKind::Luminosity);
+        assert_eq!(values[0], 0.);
+        assert!((values[1] - 1.).abs() < 1e-6 && (values[2] - 0.59).abs() < 1e-6);
+        assert_eq!(mask_values(&mask, MaskKind::Alpha), [1., 1., 1.]);
+
+        // half transparent premultiplied red, the mask halves the alpha again
+        let mut layer = RgbaImage { width: 2, height: 1, pixels: vec![128, 0, 0, 128, 0, 0, 0, 0] };
+        apply_mask(&mut layer, Some(&[0.5, 0.5]), 1.0);
+        assert_eq!(layer.pixels, [255, 0, 0, 64, 0, 0, 0, 0]);
+        // a group with a constant alpha and no mask
+        let mut layer = RgbaImage { width: 1, height: 1, pixels: vec![0, 0, 255, 255] };
+        apply_mask(&mut layer, None, 0.5);
+        assert_eq!(layer.pixels, [0, 0, 255, 128]);
+
+        assert!(matches!(backdrop(&[0., 0., 0., 1.]).unwrap(), Fill::Solid(r, g, b) if r + g + b == 0.));
+        assert!(backdrop(&[0., 0.]).is_err());
+    }
+}
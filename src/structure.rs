@@ -0,0 +1,153 @@
+use std::{
+    collections::HashSet,
+    fmt,
+};
+
+use pdf::{
+    object::{PlainRef, Resolve},
+    primitive::{Dictionary, Name, Primitive},
+    PdfError,
+};
+
+// Content of a page referenced by the structure tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentItem {
+    // marked content with this /MCID
+    Mcid(i32),
+    // an annotation, through an object reference
+    Annotation(PlainRef),
+}
+
+impl fmt::Display for ContentItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ContentItem::Mcid(mcid) => write!(f, "mcid {}", mcid),
+            ContentItem::Annotation(r) => write!(f, "annotation {} {} R", r.id, r.gen),
+        }
+    }
+}
+
+// an open BMC / BDC sequence of a content stream
+#[derive(Debug, Clone)]
+pub struct McEntry {
+    pub tag: Name,
+    // inline or from /Properties of the resources
+    pub properties: Option<Dictionary>,
+    pub mcid: Option<i32>,
+    // false inside optional content that is off
+    pub visible: bool,
+}
+
+// What drawn content belongs to: the innermost /MCID, and whether it is an
+// /Artifact like running heads and page numbers, which is not part of the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct McContext {
+    pub mcid: Option<i32>,
+    pub artifact: bool,
+}
+
+impl McContext {
+    // the context inside the open sequences of a content stream drawn in self
+    pub fn enter(self, stack: &[McEntry]) -> Self {
+        stack.iter().fold(self, |outer, entry| McContext {
+            mcid: entry.mcid.or(outer.mcid),
+            artifact: outer.artifact || entry.tag.as_str() == "Artifact",
+        })
+    }
+}
+
+fn page_of(dict: &Dictionary) -> Option<PlainRef> {
+    match dict.get("Pg") {
+        Some(&Primitive::Reference(r)) => Some(r),
+        _ => None,
+    }
+}
+
+struct Walk<'a, R: Resolve> {
+    resolve: &'a R,
+    page: PlainRef,
+    visited: HashSet<PlainRef>,
+    items: Vec<ContentItem>,
+}
+
+impl<'a, R: Resolve> Walk<'a, R> {
+    // pg: the page inherited from the parent element
+    fn kid(&mut self, kid: &Primitive, pg: Option<PlainRef>) -> Result<(), PdfError> {
+        match *kid {
+            Primitive::Reference(r) => {
+                // the tree is a tree only if the file says so
+                if self.visited.insert(r) {
+                    let kid = self.resolve.resolve(r)?;
+                    self.kid(&kid, pg)?;
+                }
+            }
+            Primitive::Integer(mcid) if pg == Some(self.page) => self.items.push(ContentItem::Mcid(mcid)),
+            Primitive::Array(ref kids) => {
+                for kid in kids {
+                    self.kid(kid, pg)?;
+                }
+            }
+            Primitive::Dictionary(ref dict) => {
+                let pg = page_of(dict).or(pg);
+                match dict.get("Type").and_then(|t| t.as_name().ok()) {
+                    // marked content in a form xobject (/Stm) is not in the page content
+                    Some("MCR") if pg == Some(self.page) && dict.get("Stm").is_none() => {
+                        if let Some(mcid) = dict.get("MCID") {
+                            self.items.push(ContentItem::Mcid(mcid.as_integer()?));
+                        }
+                    }
+                    Some("MCR") => {}
+                    Some("OBJR") => {
+                        if let (true, Some(&Primitive::Reference(obj))) = (pg == Some(self.page), dict.get("Obj")) {
+                            self.items.push(ContentItem::Annotation(obj));
+                        }
+                    }
+                    _ => {
+                        if let Some(kids) = dict.get("K") {
+                            self.kid(kids, pg)?;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+// The content of the page in the depth first order of the structure tree, None
+// if the document is not tagged.
+pub fn reading_order(catalog: &Dictionary, page: PlainRef, resolve: &impl Resolve) -> Result<Option<Vec<ContentItem>>, PdfError> {
+    let root = match catalog.get("StructTreeRoot") {
+        Some(&Primitive::Reference(r)) => resolve.resolve(r)?,
+        Some(root) => root.clone(),
+        None => return Ok(None),
+    };
+    let root = root.into_dictionary()?;
+    let mut walk = Walk { resolve, page, visited: HashSet::new(), items: vec![] };
+    if let Some(kids) = root.get("K") {
+        walk.kid(kids, None)?;
+    }
+    Ok(Some(walk.items))
+}
+
+#[cfg(test)]
+mod test {
+    use pdf::{file::FileOptions, object::PlainRef};
+
+    use super::{reading_order, ContentItem};
+
+    #[test]
+    fn two_columns() {
+        let file = FileOptions::cached().open("resources/tagged_columns.pdf").unwrap();
+        let resolve = file.resolver();
+        let catalog = resolve.resolve(PlainRef { id: 1, gen: 0 }).unwrap().into_dictionary().unwrap();
+        let order = reading_order(&catalog, PlainRef { id: 3, gen: 0 }, &resolve).unwrap().unwrap();
+        let mcids = [0, 2, 1, 3].map(ContentItem::Mcid);
+        assert_eq!(order, mcids);
+
+        // another page has no content in the tree
+        let other = reading_order(&catalog, PlainRef { id: 4, gen: 0 }, &resolve).unwrap().unwrap();
+        assert!(other.is_empty());
+    }
+}
@@ -0,0 +1,382 @@
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F};
+
+use crate::metadata::OutputMetadata;
+use crate::plotter::{LinearGradient, RgbaImage, Tiling};
+use crate::png::write_png;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SvgUnits {
+    #[default]
+    Pt,
+    // css pixels at 96 dpi
+    Px,
+    Mm,
+}
+
+impl SvgUnits {
+    fn per_inch(self) -> f32 {
+        match self {
+            SvgUnits::Pt => 72.,
+            SvgUnits::Px => 96.,
+            SvgUnits::Mm => 25.4,
+        }
+    }
+    fn suffix(self) -> &'static str {
+        match self {
+            SvgUnits::Pt => "pt",
+            SvgUnits::Px => "px",
+            SvgUnits::Mm => "mm",
+        }
+    }
+}
+
+// Size attributes of the root element. A responsive svg has no width and height
+// and scales to its container.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SvgSize {
+    pub units: SvgUnits,
+    pub responsive: bool,
+}
+
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// byte range of the root <svg ...> start tag
+fn root_tag(svg: &str) -> Option<(usize, usize)> {
+    let start = svg.find("<svg")?;
+    let end = start + svg[start..].find('>')?;
+    Some((start, end + 1))
+}
+
+// at most three decimals, without trailing zeros
+fn number(v: f32) -> String {
+    let s = format!("{:.3}", v);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+// name="value" pairs of the root element
+pub fn root_attributes(svg: &str) -> Vec<(String, String)> {
+    let Some((start, end)) = root_tag(svg) else {
+        return vec![];
+    };
+    let mut rest = svg[start + "<svg".len()..end - 1].trim_end_matches('/');
+    let mut attributes = vec![];
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq].trim();
+        let value = rest[eq + 1..].trim_start();
+        let Some(quote) = value.chars().next().filter(|&c| c == '"' || c == '\'') else {
+            break;
+        };
+        let Some(len) = value[1..].find(quote) else {
+            break;
+        };
+        attributes.push((name.to_string(), value[1..1 + len].to_string()));
+        rest = &value[len + 2..];
+    }
+    attributes
+}
+
+// Replace the size attributes of the root element. view_box is in output units,
+// dpi the number of output units per inch.
+pub fn set_size(svg: &str, view_box: RectF, dpi: f32, size: SvgSize) -> String {
+    let Some((start, end)) = root_tag(svg) else {
+        return svg.to_string();
+    };
+    let mut attributes: Vec<_> = root_attributes(svg)
+        .into_iter()
+        .filter(|(name, _)| !matches!(name.as_str(), "width" | "height" | "viewBox" | "preserveAspectRatio"))
+        // values are still escaped as they were read from the document
+        .map(|(name, value)| format!("{}=\"{}\"", name, value))
+        .collect();
+    attributes.push(format!(
+        "viewBox=\"{} {} {} {}\"",
+        number(view_box.min_x()),
+        number(view_box.min_y()),
+        number(view_box.width()),
+        number(view_box.height())
+    ));
+    if !size.responsive {
+        let scale = size.units.per_inch() / dpi;
+        let suffix = size.units.suffix();
+        attributes.push(format!("width=\"{}{}\"", number(view_box.width() * scale), suffix));
+        attributes.push(format!("height=\"{}{}\"", number(view_box.height() * scale), suffix));
+    }
+    attributes.push("preserveAspectRatio=\"xMidYMid meet\"".into());
+    format!("{}<svg {}>{}", &svg[..start], attributes.join(" "), &svg[end..])
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
+
+// An <image> on the unit square of transform, its first row at the top. The
+// viewer smooths it only for /Interpolate.
+fn image_element(image: &RgbaImage, transform: Transform2F, alpha: f32, interpolate: bool) -> String {
+    let mut png = vec![];
+    write_png(&mut png, image.width, image.height, &image.pixels, None);
+    // the svg image is y down, so flip the unit square
+    let t = transform * Transform2F::row_major(1., 0., 0., 0., -1., 1.);
+    let opacity = match alpha < 1. {
+        true => format!(" opacity=\"{}\"", alpha.max(0.)),
+        false => String::new(),
+    };
+    format!(
+        "<image width=\"1\" height=\"1\" preserveAspectRatio=\"none\"{} image-rendering=\"{}\" transform=\"matrix({} {} {} {} {} {})\" href=\"data:image/png;base64,{}\"/>",
+        opacity,
+        match interpolate {
+            true => "optimizeQuality",
+            false => "optimizeSpeed",
+        },
+        t.m11(),
+        t.m21(),
+        t.m12(),
+        t.m22(),
+        t.m13(),
+        t.m23(),
+        base64(&png)
+    )
+}
+
+// Replace the placeholder paths named image{index} with the images.
+pub fn insert_images(svg: &str, images: &[(RgbaImage, Transform2F, f32, bool)]) -> String {
+    let mut out = svg.to_string();
+    for (i, &(ref image, transform, alpha, interpolate)) in images.iter().enumerate() {
+        let id = format!("id=\"image{}\"", i);
+        let Some(pos) = out.find(&id) else {
+            continue;
+        };
+        let (Some(start), Some(len)) = (out[..pos].rfind("<path"), out[pos..].find("/>")) else {
+            continue;
+        };
+        out.replace_range(start..pos + len + 2, &image_element(image, transform, alpha, interpolate));
+    }
+    out
+}
+
+// the d attribute of a <path> element
+fn path_data(element: &str) -> Option<&str> {
+    let start = element.find(" d=\"")? + " d=\"".len();
+    let len = element[start..].find('"')?;
+    Some(&element[start..start + len])
+}
+
+fn gradient_element(i: usize, gradient: &LinearGradient, d: &str) -> String {
+    let stops: String = gradient
+        .stops
+        .iter()
+        .map(|&(offset, c)| {
+            format!(
+                "<stop offset=\"{}\" stop-color=\"rgb({},{},{})\" stop-opacity=\"{}\"/>",
+                offset,
+                c.r,
+                c.g,
+                c.b,
+                c.a as f32 / 255.
+            )
+        })
+        .collect();
+    format!(
+        "<defs><linearGradient id=\"gradient{i}\" gradientUnits=\"userSpaceOnUse\" x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\">{}</linearGradient></defs><path d=\"{}\" fill=\"url(#gradient{i})\"/>",
+        gradient.from.x(),
+        gradient.from.y(),
+        gradient.to.x(),
+        gradient.to.y(),
+        stops,
+        d,
+    )
+}
+
+// Replace the placeholder paths named gradient{index} with a path that is
+// filled with the gradient.
+pub fn insert_gradients(svg: &str, gradients: &[LinearGradient]) -> String {
+    let mut out = svg.to_string();
+    for (i, gradient) in gradients.iter().enumerate() {
+        let id = format!("id=\"gradient{}\"", i);
+        let Some(pos) = out.find(&id) else {
+            continue;
+        };
+        let (Some(start), Some(len)) = (out[..pos].rfind("<path"), out[pos..].find("/>")) else {
+            continue;
+        };
+        let end = pos + len + 2;
+        let d = path_data(&out[start..end]).unwrap_or_default().to_string();
+        out.replace_range(start..end, &gradient_element(i, gradient, &d));
+    }
+    out
+}
+
+fn tiling_element(i: usize, tiling: &Tiling, d: &str, fill_rule: &str) -> String {
+    let t = tiling.transform;
+    format!(
+        "<defs><pattern id=\"tiling{i}\" patternUnits=\"userSpaceOnUse\" width=\"1\" height=\"1\" patternTransform=\"matrix({} {} {} {} {} {})\">{}</pattern></defs><path d=\"{}\" fill=\"url(#tiling{i})\"{}/>",
+        t.m11(),
+        t.m21(),
+        t.m12(),
+        t.m22(),
+        t.m13(),
+        t.m23(),
+        image_element(&tiling.image, Transform2F::default(), false),
+        d,
+        fill_rule,
+    )
+}
+
+// Replace the placeholder paths named tiling{index} with a path that is filled
+// with the repeated pattern cell.
+pub fn insert_tilings(svg: &str, tilings: &[Tiling]) -> String {
+    let mut out = svg.to_string();
+    for (i, tiling) in tilings.iter().enumerate() {
+        let id = format!("id=\"tiling{}\"", i);
+        let Some(pos) = out.find(&id) else {
+            continue;
+        };
+        let (Some(start), Some(len)) = (out[..pos].rfind("<path"), out[pos..].find("/>")) else {
+            continue;
+        };
+        let end = pos + len + 2;
+        let element = &out[start..end];
+        let d = path_data(element).unwrap_or_default().to_string();
+        let fill_rule = match element.contains("fill-rule=\"evenodd\"") {
+            true => " fill-rule=\"evenodd\"",
+            false => "",
+        };
+        out.replace_range(start..end, &tiling_element(i, tiling, &d, fill_rule));
+    }
+    out
+}
+
+// Add a <metadata> element as the first child of the root element.
+pub fn insert_metadata(svg: &str, metadata: &OutputMetadata) -> String {
+    let Some((_, end)) = root_tag(svg) else {
+        return svg.to_string();
+    };
+    let attributes: String = metadata
+        .entries()
+        .iter()
+        .map(|(key, value)| format!(" {}=\"{}\"", key.to_lowercase(), escape(value)))
+        .collect();
+    format!(
+        "{}\n    <metadata><pdf2svg:info xmlns:pdf2svg=\"https://github.com/spielhuus/pdf_convert\"{}/></metadata>{}",
+        &svg[..end],
+        attributes,
+        &svg[end..]
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use pathfinder_color::ColorU;
+    use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::vec2f};
+
+    use super::{base64, set_size, SvgSize, SvgUnits};
+    use crate::metadata::OutputMetadata;
+    use crate::plotter::{LinearGradient, RgbaImage, Tiling};
+
+    #[test]
+    fn root_size() {
+        let svg = "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"1\" height=\"1\" viewBox=\"0 0 1 1\">\n</svg>\n";
+        let view_box = RectF::new(Default::default(), pathfinder_geometry::vector::vec2f(600., 300.));
+        let out = set_size(svg, view_box, 300., SvgSize { units: SvgUnits::Mm, responsive: false });
+        assert!(out.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 600 300\" width=\"50.8mm\" height=\"25.4mm\""));
+
+        let out = set_size(svg, view_box, 300., SvgSize { units: SvgUnits::Pt, responsive: true });
+        assert!(!out.contains("width="));
+        assert!(out.contains("preserveAspectRatio=\"xMidYMid meet\""));
+    }
+
+    #[test]
+    fn metadata_element() {
+        let svg = "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 612 792\">\n</svg>\n";
+        let metadata = OutputMetadata {
+            source: "a&b.pdf".into(),
+            page: 3,
+            dpi: 72.,
+            options: String::new(),
+        };
+        let out = super::insert_metadata(svg, &metadata);
+        assert!(out.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 612 792\">\n    <metadata>"));
+        assert!(out.contains(" page=\"3\""));
+        assert!(out.contains(" dpi=\"72\""));
+        assert!(out.contains(" source=\"a&amp;b.pdf\""));
+        assert!(out.ends_with("</metadata>\n</svg>\n"));
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+        assert_eq!(base64(b""), "");
+    }
+
+    #[test]
+    fn image_placeholder() {
+        let svg = "<svg>\n    <path id=\"image0\" fill=\"#000000\" d=\"M 0 0 L 1 0 L 1 1 Z\" />\n</svg>\n";
+        let image = RgbaImage { width: 1, height: 1, pixels: vec![255, 0, 0, 255] };
+        let transform = Transform2F::row_major(100., 0., 10., 0., 50., 20.);
+        let out = super::insert_images(svg, &[(image.clone(), transform, 1., false)]);
+        assert!(out.starts_with("<svg>\n    <image width=\"1\" height=\"1\""));
+        assert!(out.contains("image-rendering=\"optimizeSpeed\""));
+        assert!(out.contains("transform=\"matrix(100 0 0 -50 10 70)\""));
+        assert!(out.contains("href=\"data:image/png;base64,"));
+        assert!(!out.contains("<path"));
+        assert!(out.ends_with("/>\n</svg>\n"));
+        // /Interpolate true lets the viewer smooth the pixels
+        let out = super::insert_images(svg, &[(image.clone(), transform, 1., true)]);
+        assert!(out.contains("image-rendering=\"optimizeQuality\""));
+        assert!(!out.contains("opacity"));
+        // the fill alpha of the graphics state
+        let out = super::insert_images(svg, &[(image, transform, 0.5, false)]);
+        assert!(out.contains("opacity=\"0.5\""));
+    }
+
+    #[test]
+    fn gradient_placeholder() {
+        let svg = "<svg>\n    <path id=\"gradient0\" fill=\"#000000\" d=\"M 0 0 L 1 0 L 1 1 Z\" />\n</svg>\n";
+        let gradient = LinearGradient {
+            from: vec2f(0., 0.),
+            to: vec2f(10., 0.),
+            stops: vec![(0., ColorU::new(255, 0, 0, 255)), (1., ColorU::new(0, 0, 255, 255))],
+        };
+        let out = super::insert_gradients(svg, &[gradient]);
+        assert!(out.contains("<linearGradient id=\"gradient0\" gradientUnits=\"userSpaceOnUse\" x1=\"0\" y1=\"0\" x2=\"10\" y2=\"0\">"));
+        assert!(out.contains("<stop offset=\"1\" stop-color=\"rgb(0,0,255)\" stop-opacity=\"1\"/>"));
+        assert!(out.contains("<path d=\"M 0 0 L 1 0 L 1 1 Z\" fill=\"url(#gradient0)\"/>"));
+        assert!(!out.contains("#000000"));
+    }
+
+    #[test]
+    fn tiling_placeholder() {
+        let svg = "<svg>\n    <path id=\"tiling0\" fill=\"#000000\" fill-rule=\"evenodd\" d=\"M 0 0 L 1 0 L 1 1 Z\" />\n</svg>\n";
+        let tiling = Tiling {
+            image: RgbaImage { width: 1, height: 1, pixels: vec![255, 0, 0, 255] },
+            transform: Transform2F::row_major(10., 0., 5., 0., -10., 95.),
+        };
+        let out = super::insert_tilings(svg, &[tiling]);
+        assert!(out.contains("<pattern id=\"tiling0\" patternUnits=\"userSpaceOnUse\" width=\"1\" height=\"1\" patternTransform=\"matrix(10 0 0 -10 5 95)\"><image "));
+        assert!(out.contains("<path d=\"M 0 0 L 1 0 L 1 1 Z\" fill=\"url(#tiling0)\" fill-rule=\"evenodd\"/>"));
+        assert!(!out.contains("#000000"));
+    }
+}
@@ -4,9 +4,13 @@ use itertools::Itertools;
 
 use pathfinder_canvas::{RectF, Transform2F, Vector2F};
 use pdf::content::{Matrix, TextMode};
+use pdf::primitive::Name;
 
 use crate::plotter::Fill;
+use crate::structure::McContext;
 
+// Everything but the two matrices is part of the graphics state: it survives BT/ET
+// and is saved and restored with q/Q.
 #[derive(Clone, Debug)]
 pub struct TextState {
     pub text_matrix: Transform2F, // tracks current glyph
@@ -16,6 +20,7 @@ pub struct TextState {
     pub horiz_scale: f32, // Horizontal scaling
     pub leading: f32, // Leading
     //pub font_entry: Option<Arc<FontEntry>>, // Text font
+    pub font: Option<Name>, // name of the font resource
     pub font_size: f32, // Text font size
     pub mode: TextMode, // Text rendering mode
     pub rise: f32, // Text rise
@@ -32,12 +37,21 @@ impl TextState {
             horiz_scale: 1.,
             leading: 0.,
             //font_entry: None,
+            font: None,
             font_size: 0.,
             mode: TextMode::Fill,
             rise: 0.,
             knockout: 0.
         }
     }
+    // Q restores the parameters, the text position is not part of the graphics state
+    pub fn restore(&mut self, saved: TextState) {
+        *self = TextState {
+            text_matrix: self.text_matrix,
+            line_matrix: self.line_matrix,
+            ..saved
+        };
+    }
     pub fn reset_matrix(&mut self) {
         self.set_matrix(Transform2F::default());
     }
@@ -54,7 +68,6 @@ impl TextState {
     pub fn next_line(&mut self) {
         self.translate(Vector2F::new(0., -self.leading));
     }
-
 }
 
 #[derive(Copy, Clone, Default)]
@@ -120,6 +133,8 @@ pub struct TextSpan {
     pub transform: Transform2F,
     pub mode: TextMode,
     pub op_nr: usize,
+    // the mcid for the structure tree, artifacts are not body text
+    pub marked_content: McContext,
 }
 impl TextSpan {
     pub fn parts(&self) -> impl Iterator<Item=Part> + '_ {
@@ -0,0 +1,61 @@
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+use pdf::{
+    object::{ImageXObject, Object, PlainRef, Resolve, XObject},
+    primitive::Primitive,
+    PdfError,
+};
+
+use crate::{
+    plotter::{Plotter, RgbaImage},
+    png::PngPlotter,
+};
+
+// The /Thumb image of the page object, None when there is none. Thumbnails are
+// image streams that usually leave out /Subtype.
+pub fn page_thumb(page: PlainRef, resolve: &impl Resolve) -> Result<Option<ImageXObject>, PdfError> {
+    let dict = resolve.resolve(page)?.into_dictionary()?;
+    let Some(&Primitive::Reference(r)) = dict.get("Thumb") else {
+        return Ok(None);
+    };
+    let mut stream = match resolve.resolve(r)? {
+        Primitive::Stream(stream) => stream,
+        p => {
+            return Err(PdfError::Other {
+                msg: format!("thumbnail is not a stream: {:?}", p),
+            })
+        }
+    };
+    stream.info.insert("Subtype", Primitive::Name("Image".into()));
+    match XObject::from_primitive(Primitive::Stream(stream), resolve)? {
+        XObject::Image(image) => Ok(Some(image)),
+        _ => Ok(None),
+    }
+}
+
+// the size that fits into size x size with the aspect ratio of width x height
+pub fn fit(width: f32, height: f32, size: u32) -> (u32, u32) {
+    let scale = size as f32 / width.max(height);
+    ((width * scale).round().max(1.) as u32, (height * scale).round().max(1.) as u32)
+}
+
+// Draw the image over width x height pixels, sampled bilinearly when it is
+// enlarged and box filtered when it is reduced a lot.
+pub fn scale(image: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let (w, h) = (width as f32, height as f32);
+    let mut plotter = PngPlotter::new(RectF::new(Vector2F::zero(), Vector2F::new(w, h)));
+    // the unit square of the image, its first row at the top
+    plotter.draw_image(image, Transform2F::row_major(w, 0., 0., 0., -h, h), None, 1., true);
+    plotter.rasterize()
+}
+
+#[cfg(test)]
+mod test {
+    use super::fit;
+
+    #[test]
+    fn aspect_ratio() {
+        assert_eq!(fit(106., 137., 128), (99, 128));
+        assert_eq!(fit(300., 100., 60), (60, 20));
+        assert_eq!(fit(1000., 1., 10), (10, 1));
+    }
+}
@@ -0,0 +1,27 @@
+use std::{fmt, time::Duration};
+
+use serde::Serialize;
+
+// Wall clock time of the conversion stages. In the png backend `build` covers the
+// scene build and command submission, `raster` waits for the gpu in the read back.
+#[derive(Serialize, Default, Debug, Clone, Copy)]
+pub struct Timings {
+    pub open: Duration,
+    pub decode: Duration,
+    pub render: Duration,
+    pub build: Duration,
+    pub raster: Duration,
+    pub encode: Duration,
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.;
+        writeln!(f, "open:   {:10.3} ms", ms(self.open))?;
+        writeln!(f, "decode: {:10.3} ms", ms(self.decode))?;
+        writeln!(f, "render: {:10.3} ms", ms(self.render))?;
+        writeln!(f, "build:  {:10.3} ms", ms(self.build))?;
+        writeln!(f, "raster: {:10.3} ms", ms(self.raster))?;
+        write!(f, "encode: {:10.3} ms", ms(self.encode))
+    }
+}
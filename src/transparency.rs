@@ -0,0 +1,162 @@
+use std::fmt;
+
+use pdf::{
+    object::{Page, Resolve},
+    primitive::{Dictionary, Primitive},
+    PdfError,
+};
+
+// Color space of the page transparency group, the space blending happens in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    Other(String),
+}
+
+impl GroupColorSpace {
+    fn from_components(n: i32) -> Option<Self> {
+        match n {
+            1 => Some(GroupColorSpace::Gray),
+            3 => Some(GroupColorSpace::Rgb),
+            4 => Some(GroupColorSpace::Cmyk),
+            _ => None,
+        }
+    }
+    pub fn from_primitive(cs: &Primitive, resolve: &impl Resolve) -> Result<Self, PdfError> {
+        let cs = match *cs {
+            Primitive::Reference(r) => resolve.resolve(r)?,
+            ref cs => cs.clone(),
+        };
+        let (name, args) = match cs {
+            Primitive::Name(ref name) => (name.as_str(), &[][..]),
+            Primitive::Array(ref parts) => match parts.split_first() {
+                Some((name, args)) => (name.as_name()?, args),
+                None => return Ok(GroupColorSpace::Other("[]".into())),
+            },
+            ref p => return Ok(GroupColorSpace::Other(format!("{:?}", p))),
+        };
+        Ok(match name {
+            "DeviceGray" | "CalGray" => GroupColorSpace::Gray,
+            "DeviceRGB" | "CalRGB" => GroupColorSpace::Rgb,
+            "DeviceCMYK" => GroupColorSpace::Cmyk,
+            "ICCBased" => {
+                let n = match args.first() {
+                    Some(&Primitive::Reference(r)) => match resolve.resolve(r)? {
+                        Primitive::Stream(ref s) => s.info.get("N").and_then(|n| n.as_integer().ok()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                n.and_then(Self::from_components).unwrap_or_else(|| GroupColorSpace::Other(name.into()))
+            }
+            name => GroupColorSpace::Other(name.into()),
+        })
+    }
+    pub fn is_cmyk(&self) -> bool {
+        *self == GroupColorSpace::Cmyk
+    }
+}
+
+impl fmt::Display for GroupColorSpace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GroupColorSpace::Gray => write!(f, "Gray"),
+            GroupColorSpace::Rgb => write!(f, "RGB"),
+            GroupColorSpace::Cmyk => write!(f, "CMYK"),
+            GroupColorSpace::Other(ref name) => write!(f, "{}", name),
+        }
+    }
+}
+
+// /Group << /S /Transparency >> of a form xobject
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransparencyGroup {
+    pub color_space: Option<GroupColorSpace>,
+    // /I, the group is composited over a transparent backdrop
+    pub isolated: bool,
+    // /K, the objects of the group do not composite with each other
+    pub knockout: bool,
+}
+
+impl TransparencyGroup {
+    // None for groups of other types
+    pub fn from_dict(dict: &Dictionary, resolve: &impl Resolve) -> Result<Option<Self>, PdfError> {
+        if dict.get("S").and_then(|s| s.as_name().ok()) != Some("Transparency") {
+            return Ok(None);
+        }
+        let flag = |key| dict.get(key).and_then(|f| f.as_bool().ok()).unwrap_or(false);
+        let color_space = match dict.get("CS") {
+            Some(cs) => Some(GroupColorSpace::from_primitive(cs, resolve)?),
+            None => None,
+        };
+        Ok(Some(TransparencyGroup { color_space, isolated: flag("I"), knockout: flag("K") }))
+    }
+}
+
+// the transparency group of a form xobject stream
+pub fn form_group(info: &Dictionary, resolve: &impl Resolve) -> Result<Option<TransparencyGroup>, PdfError> {
+    let group = match info.get("Group") {
+        Some(&Primitive::Reference(r)) => resolve.resolve(r)?,
+        Some(group) => group.clone(),
+        None => return Ok(None),
+    };
+    TransparencyGroup::from_dict(&group.into_dictionary()?, resolve)
+}
+
+fn group_dict(page: &Page, resolve: &impl Resolve) -> Result<Option<Dictionary>, PdfError> {
+    let group = match page.other.get("Group") {
+        Some(&Primitive::Reference(r)) => resolve.resolve(r)?,
+        Some(group) => group.clone(),
+        None => return Ok(None),
+    };
+    Ok(Some(group.into_dictionary()?))
+}
+
+// /Group << /S /Transparency /CS ... >> of the page
+pub fn page_group_color_space(page: &Page, resolve: &impl Resolve) -> Result<Option<GroupColorSpace>, PdfError> {
+    let Some(group) = group_dict(page, resolve)? else {
+        return Ok(None);
+    };
+    match group.get("CS") {
+        Some(cs) => Ok(Some(GroupColorSpace::from_primitive(cs, resolve)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pdf::{
+        object::NoResolve,
+        primitive::{Dictionary, Primitive},
+    };
+
+    use super::{form_group, GroupColorSpace, TransparencyGroup};
+
+    #[test]
+    fn color_spaces() {
+        let name = |n: &str| Primitive::Name(n.into());
+        let cs = |p: Primitive| GroupColorSpace::from_primitive(&p, &NoResolve).unwrap();
+        assert_eq!(cs(name("DeviceCMYK")), GroupColorSpace::Cmyk);
+        assert_eq!(cs(Primitive::Array(vec![name("CalRGB"), Primitive::Null])), GroupColorSpace::Rgb);
+        assert_eq!(cs(name("Lab")), GroupColorSpace::Other("Lab".into()));
+        assert!(cs(name("DeviceCMYK")).is_cmyk());
+    }
+
+    #[test]
+    fn form_groups() {
+        let mut group = Dictionary::new();
+        group.insert("S", Primitive::Name("Transparency".into()));
+        group.insert("I", Primitive::Boolean(true));
+        group.insert("CS", Primitive::Name("DeviceGray".into()));
+        let mut info = Dictionary::new();
+        info.insert("Group", Primitive::Dictionary(group.clone()));
+        let expected = TransparencyGroup { color_space: Some(GroupColorSpace::Gray), isolated: true, knockout: false };
+        assert_eq!(form_group(&info, &NoResolve).unwrap(), Some(expected));
+
+        group.insert("S", Primitive::Name("Other".into()));
+        assert_eq!(TransparencyGroup::from_dict(&group, &NoResolve).unwrap(), None);
+        assert_eq!(form_group(&Dictionary::new(), &NoResolve).unwrap(), None);
+    }
+}
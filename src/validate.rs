@@ -0,0 +1,303 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use pdf::{
+    content::{Color, Op},
+    object::{ColorSpace, Resolve, Resources},
+    primitive::{Dictionary, Primitive},
+    PdfError,
+};
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    // an operator names a resource the dictionary does not have
+    MissingResource,
+    // a resource no operator of the page uses
+    UnusedResource,
+    // text shown without a font set by Tf
+    FontBeforeTf,
+    // a color with more or less components than its color space
+    ColorComponents,
+    // an image and its /Mask or /SMask differ in size
+    MaskSize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub page: u32,
+    // index of the operator, numbered like in --dump-ops
+    pub op: Option<usize>,
+    pub kind: FindingKind,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.op {
+            Some(op) => write!(f, "page {} op {}: {}", self.page, op, self.message),
+            None => write!(f, "page {}: {}", self.page, self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Category {
+    Font,
+    XObject,
+    ExtGState,
+    ColorSpace,
+    Pattern,
+    Shading,
+}
+
+impl Category {
+    fn name(self) -> &'static str {
+        match self {
+            Category::Font => "font",
+            Category::XObject => "xobject",
+            Category::ExtGState => "graphics state",
+            Category::ColorSpace => "color space",
+            Category::Pattern => "pattern",
+            Category::Shading => "shading",
+        }
+    }
+}
+
+fn declared(resources: &Resources, category: Category, name: &str) -> bool {
+    match category {
+        Category::Font => resources.fonts.contains_key(name),
+        Category::XObject => resources.xobjects.contains_key(name),
+        Category::ExtGState => resources.graphics_states.contains_key(name),
+        Category::ColorSpace => resources.color_spaces.contains_key(name),
+        Category::Pattern => resources.pattern.contains_key(name),
+        Category::Shading => resources.shadings.contains_key(name),
+    }
+}
+
+fn all_declared(resources: &Resources) -> Vec<(Category, String)> {
+    let mut all = vec![];
+    all.extend(resources.fonts.keys().map(|n| (Category::Font, n.as_str().to_owned())));
+    all.extend(resources.xobjects.keys().map(|n| (Category::XObject, n.as_str().to_owned())));
+    all.extend(resources.graphics_states.keys().map(|n| (Category::ExtGState, n.as_str().to_owned())));
+    all.extend(resources.color_spaces.keys().map(|n| (Category::ColorSpace, n.as_str().to_owned())));
+    all.extend(resources.pattern.keys().map(|n| (Category::Pattern, n.as_str().to_owned())));
+    all.extend(resources.shadings.keys().map(|n| (Category::Shading, n.as_str().to_owned())));
+    all.sort_by(|a, b| a.1.cmp(&b.1));
+    all
+}
+
+// Number of color components, None where it is not known from the color space alone.
+fn components(cs: &ColorSpace, resources: &Resources) -> Option<usize> {
+    match *cs {
+        ColorSpace::DeviceGray | ColorSpace::CalGray(_) => Some(1),
+        ColorSpace::DeviceRGB | ColorSpace::CalRGB(_) => Some(3),
+        ColorSpace::DeviceCMYK | ColorSpace::CalCMYK(_) => Some(4),
+        ColorSpace::Indexed(..) | ColorSpace::Separation(..) => Some(1),
+        ColorSpace::DeviceN { ref names, .. } => Some(names.len()),
+        ColorSpace::Icc(ref icc) => Some(icc.info.components as usize),
+        ColorSpace::Named(ref name) => resources.color_spaces.get(name).and_then(|cs| components(cs, resources)),
+        _ => None,
+    }
+}
+
+fn size(dict: &Dictionary) -> Option<(i32, i32)> {
+    let get = |key| dict.get(key).and_then(|p| p.as_integer().ok());
+    Some((get("Width")?, get("Height")?))
+}
+
+struct Check<'a, R: Resolve> {
+    page: u32,
+    resources: &'a Resources,
+    resolve: &'a R,
+    used: HashSet<(Category, String)>,
+    // color spaces are looked up in the resources, unless they are device spaces
+    fill_space: Option<ColorSpace>,
+    stroke_space: Option<ColorSpace>,
+    font_set: bool,
+    stack: Vec<(Option<ColorSpace>, Option<ColorSpace>, bool)>,
+    checked_images: HashSet<String>,
+    findings: Vec<Finding>,
+}
+
+impl<'a, R: Resolve> Check<'a, R> {
+    fn finding(&mut self, op: Option<usize>, kind: FindingKind, message: String) {
+        self.findings.push(Finding { page: self.page, op, kind, message });
+    }
+    fn use_resource(&mut self, nr: usize, category: Category, name: &str) -> bool {
+        self.used.insert((category, name.to_owned()));
+        if declared(self.resources, category, name) {
+            return true;
+        }
+        self.finding(Some(nr), FindingKind::MissingResource, format!("{} {} not in the resources", category.name(), name));
+        false
+    }
+    fn color_space(&mut self, nr: usize, name: &str) -> Option<ColorSpace> {
+        match name {
+            "DeviceGray" => Some(ColorSpace::DeviceGray),
+            "DeviceRGB" => Some(ColorSpace::DeviceRGB),
+            "DeviceCMYK" => Some(ColorSpace::DeviceCMYK),
+            "Pattern" => Some(ColorSpace::Pattern),
+            _ if self.use_resource(nr, Category::ColorSpace, name) => self.resources.color_spaces.get(name).cloned(),
+            _ => None,
+        }
+    }
+    fn color(&mut self, nr: usize, color: &Color, stroke: bool) {
+        let Color::Other(ref args) = *color else {
+            // g, rg and k select their device space
+            let space = match *color {
+                Color::Gray(_) => ColorSpace::DeviceGray,
+                Color::Rgb(_) => ColorSpace::DeviceRGB,
+                _ => ColorSpace::DeviceCMYK,
+            };
+            match stroke {
+                true => self.stroke_space = Some(space),
+                false => self.fill_space = Some(space),
+            }
+            return;
+        };
+        let space = match stroke {
+            true => self.stroke_space.clone(),
+            false => self.fill_space.clone(),
+        };
+        let space = space.unwrap_or(ColorSpace::DeviceGray);
+        if let ColorSpace::Pattern = space {
+            match args.last() {
+                Some(Primitive::Name(name)) => {
+                    self.use_resource(nr, Category::Pattern, name);
+                }
+                _ => self.finding(Some(nr), FindingKind::ColorComponents, format!("pattern color without a name: {:?}", args)),
+            }
+            return;
+        }
+        if let Some(n) = components(&space, self.resources) {
+            if n != args.len() {
+                let msg = format!("{} color components for a color space with {}", args.len(), n);
+                self.finding(Some(nr), FindingKind::ColorComponents, msg);
+            }
+        }
+    }
+    fn image_masks(&mut self, nr: usize, name: &str) -> Result<(), PdfError> {
+        if !self.checked_images.insert(name.to_owned()) {
+            return Ok(());
+        }
+        let Some(&r) = self.resources.xobjects.get(name) else { return Ok(()) };
+        let Primitive::Stream(stream) = self.resolve.resolve(r.get_inner())? else { return Ok(()) };
+        let Some(image) = size(&stream.info) else { return Ok(()) };
+        for key in ["Mask", "SMask"] {
+            // a /Mask array is a color key mask without a size
+            let Some(&Primitive::Reference(mask)) = stream.info.get(key) else { continue };
+            let Primitive::Stream(mask) = self.resolve.resolve(mask)? else { continue };
+            if let Some(mask) = size(&mask.info).filter(|&mask| mask != image) {
+                let msg = format!("image {} is {}x{}, its /{} {}x{}", name, image.0, image.1, key, mask.0, mask.1);
+                self.finding(Some(nr), FindingKind::MaskSize, msg);
+            }
+        }
+        Ok(())
+    }
+    fn op(&mut self, nr: usize, op: &Op) -> Result<(), PdfError> {
+        match *op {
+            Op::Save => self.stack.push((self.fill_space.clone(), self.stroke_space.clone(), self.font_set)),
+            Op::Restore => {
+                if let Some((fill, stroke, font)) = self.stack.pop() {
+                    (self.fill_space, self.stroke_space, self.font_set) = (fill, stroke, font);
+                }
+            }
+            Op::TextFont { ref name, .. } => self.font_set = self.use_resource(nr, Category::Font, name),
+            Op::TextDraw { .. } | Op::TextDrawAdjusted { .. } if !self.font_set => {
+                self.finding(Some(nr), FindingKind::FontBeforeTf, "text shown without a font".into());
+                // reported once
+                self.font_set = true;
+            }
+            Op::XObject { ref name } => {
+                if self.use_resource(nr, Category::XObject, name) {
+                    self.image_masks(nr, name)?;
+                }
+            }
+            Op::GraphicsState { ref name } => {
+                self.use_resource(nr, Category::ExtGState, name);
+            }
+            Op::Shade { ref name } => {
+                self.use_resource(nr, Category::Shading, name);
+            }
+            Op::FillColorSpace { ref name } => self.fill_space = self.color_space(nr, name),
+            Op::StrokeColorSpace { ref name } => self.stroke_space = self.color_space(nr, name),
+            Op::FillColor { ref color } => self.color(nr, color, false),
+            Op::StrokeColor { ref color } => self.color(nr, color, true),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+// Compare the resources of a page with the operators of its content.
+pub fn check_page(page: u32, ops: &[Op], resources: &Resources, resolve: &impl Resolve) -> Result<Vec<Finding>, PdfError> {
+    let mut check = Check {
+        page,
+        resources,
+        resolve,
+        used: HashSet::new(),
+        fill_space: None,
+        stroke_space: None,
+        font_set: false,
+        stack: vec![],
+        checked_images: HashSet::new(),
+        findings: vec![],
+    };
+    for (nr, op) in ops.iter().enumerate() {
+        check.op(nr, op)?;
+    }
+    for (category, name) in all_declared(resources) {
+        if !check.used.contains(&(category, name.clone())) {
+            check.finding(None, FindingKind::UnusedResource, format!("{} {} is never used", category.name(), name));
+        }
+    }
+    Ok(check.findings)
+}
+
+pub fn write_report(findings: &[Finding], file: &Path) -> Result<(), PdfError> {
+    let writer = BufWriter::new(File::create(file).map_err(|e| PdfError::Other {
+        msg: format!("can not create report {:?}: {}", file, e),
+    })?);
+    serde_json::to_writer_pretty(writer, findings).map_err(|e| PdfError::Other {
+        msg: format!("can not write report {:?}: {}", file, e),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use pdf::file::FileOptions;
+
+    use super::{check_page, FindingKind};
+    use crate::content;
+
+    #[test]
+    fn sloppy_page() {
+        let file = FileOptions::cached().open("resources/sloppy_resources.pdf").unwrap();
+        let resolve = file.resolver();
+        let page = file.get_page(0).unwrap();
+        let ops = content::operations(page.contents.as_ref().unwrap(), &resolve).unwrap();
+        let findings = check_page(0, &ops, page.resources().unwrap(), &resolve).unwrap();
+        let mut kinds: Vec<_> = findings.iter().map(|f| f.kind).collect();
+        kinds.sort_by_key(|&k| k as u8);
+        assert_eq!(
+            kinds,
+            [
+                FindingKind::MissingResource,
+                FindingKind::UnusedResource,
+                FindingKind::FontBeforeTf,
+                FindingKind::ColorComponents,
+                FindingKind::MaskSize,
+            ],
+            "{:#?}",
+            findings
+        );
+        let missing = findings.iter().find(|f| f.kind == FindingKind::MissingResource).unwrap();
+        assert_eq!(missing.message, "graphics state GS9 not in the resources");
+        assert!(missing.op.is_some());
+    }
+}
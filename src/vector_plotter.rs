@@ -1,22 +1,44 @@
-use std::{fs::File, io::BufWriter, path::PathBuf};
+use std::{io::Write, time::Instant};
 
 use pathfinder_color::{ColorF, ColorU};
-use pathfinder_content::{dash::OutlineDash, fill::FillRule, outline::Outline, stroke::OutlineStrokeToFill};
+use pathfinder_content::{fill::FillRule, outline::Outline};
 use pathfinder_export::{Export, FileFormat};
-use pathfinder_geometry::{rect::RectF, transform2d::Transform2F};
-use pathfinder_renderer::{paint::{Paint, PaintId}, scene::{ClipPathId, DrawPath, Scene}};
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+use pathfinder_renderer::{paint::{Paint, PaintId}, scene::{ClipPath, ClipPathId, DrawPath, Scene}};
+use pdf::PdfError;
 
-use crate::plotter::{BlendMode, DrawMode, Fill, Plotter};
+use crate::images;
+use crate::shading;
+use crate::metadata::OutputMetadata;
+use crate::png;
+use crate::plotter::{BlendMode, DrawMode, Fill, LinearGradient, Plotter, RgbaImage, Tiling};
+use crate::svg::{self, SvgSize};
+use crate::timings::Timings;
 
 fn blend_mode(mode: BlendMode) -> pathfinder_content::effects::BlendMode {
+    use pathfinder_content::effects::BlendMode as B;
     match mode {
-        BlendMode::Darken => pathfinder_content::effects::BlendMode::Multiply,
-        BlendMode::Overlay => pathfinder_content::effects::BlendMode::Overlay,
+        BlendMode::Normal => B::SrcOver,
+        BlendMode::Multiply => B::Multiply,
+        BlendMode::Screen => B::Screen,
+        BlendMode::Overlay => B::Overlay,
+        BlendMode::Darken => B::Darken,
+        BlendMode::Lighten => B::Lighten,
+        BlendMode::ColorDodge => B::ColorDodge,
+        BlendMode::ColorBurn => B::ColorBurn,
+        BlendMode::HardLight => B::HardLight,
+        BlendMode::SoftLight => B::SoftLight,
+        BlendMode::Difference => B::Difference,
+        BlendMode::Exclusion => B::Exclusion,
     }
 }
 
 pub struct VectorPlotter {
     scene: Scene,
+    // svg export writes these as <image> in place of the paths named image{index}
+    images: Vec<(RgbaImage, Transform2F, f32, bool)>,
+    gradients: Vec<LinearGradient>,
+    tilings: Vec<Tiling>,
 }
 
 impl VectorPlotter {
@@ -27,6 +49,9 @@ impl VectorPlotter {
         scene.push_draw_path(DrawPath::new(Outline::from_rect(view_box), white));
         Self {
             scene,
+            images: vec![],
+            gradients: vec![],
+            tilings: vec![],
         }
     }
     fn paint(&mut self, fill: Fill, alpha: f32) -> PaintId {
@@ -38,20 +63,88 @@ impl VectorPlotter {
         };
         self.scene.push_paint(&paint)
     }
-    pub fn write(&mut self, file: PathBuf) {
-        let mut writer = BufWriter::new(File::create(&file).unwrap());
-        let format = match file.extension().and_then(|s| s.to_str()) {
-            Some("pdf") => FileFormat::PDF,
-            Some("ps") => FileFormat::PS,
-            Some("svg") => FileFormat::SVG,
-            _ => panic!("output filename must have .ps or .pdf extension")
+    // rasterize the scene that would be exported, to compare it with the png backend
+    pub fn rasterize(&mut self) -> RgbaImage {
+        png::rasterize(&mut self.scene).0
+    }
+    // dpi: output units per inch, used for the svg size
+    pub fn write(&mut self, mut out: &mut dyn Write, format: FileFormat, metadata: Option<&OutputMetadata>, dpi: f32, size: SvgSize) -> Result<Timings, PdfError> {
+        let start = Instant::now();
+        let io_error = |e: std::io::Error| PdfError::Other {
+            msg: format!("can not write the vector output: {}", e),
         };
-       self.scene.export(&mut writer, format).unwrap();
+        match format {
+            FileFormat::SVG => {
+                let mut data = vec![];
+                self.scene.export(&mut data, format).map_err(io_error)?;
+                let mut svg = svg::set_size(&String::from_utf8_lossy(&data), self.scene.view_box(), dpi, size);
+                svg = svg::insert_images(&svg, &self.images);
+                svg = svg::insert_gradients(&svg, &self.gradients);
+                svg = svg::insert_tilings(&svg, &self.tilings);
+                if let Some(metadata) = metadata {
+                    svg = svg::insert_metadata(&svg, metadata);
+                }
+                out.write_all(svg.as_bytes()).map_err(io_error)?;
+            }
+            _ => self.scene.export(&mut out, format).map_err(io_error)?,
+        }
+        Ok(Timings {
+            encode: start.elapsed(),
+            ..Timings::default()
+        })
     }
 }
 
+// a pixel depends on the zoom of the viewer, hairlines only have to stay visible
+pub const HAIRLINE_WIDTH: f32 = 0.1;
+
 impl Plotter for VectorPlotter {
     type ClipPathId = ClipPathId;
+    fn hairline_width(&self) -> f32 {
+        HAIRLINE_WIDTH
+    }
+    fn draw_image(&mut self, image: &RgbaImage, transform: Transform2F, clip: Option<Self::ClipPathId>, alpha: f32, smoothing: bool) {
+        let paint = self.scene.push_paint(&images::image_paint(image, transform, alpha, smoothing));
+        let outline = Outline::from_rect(RectF::new(Vector2F::zero(), Vector2F::splat(1.))).transformed(&transform);
+        let mut draw_path = DrawPath::new(outline, paint);
+        draw_path.set_clip_path(clip);
+        draw_path.set_name(format!("image{}", self.images.len()));
+        self.scene.push_draw_path(draw_path);
+        self.images.push((image.clone(), transform, alpha, smoothing));
+    }
+    fn draw_layer(&mut self, image: &RgbaImage, transform: Transform2F, mode: BlendMode, clip: Option<Self::ClipPathId>) {
+        let paint = self.scene.push_paint(&images::image_paint(image, transform, 1., false));
+        let outline = Outline::from_rect(RectF::new(Vector2F::zero(), Vector2F::splat(1.))).transformed(&transform);
+        let mut draw_path = DrawPath::new(outline, paint);
+        draw_path.set_clip_path(clip);
+        draw_path.set_blend_mode(blend_mode(mode));
+        draw_path.set_name(format!("image{}", self.images.len()));
+        self.scene.push_draw_path(draw_path);
+        self.images.push((image.clone(), transform, 1., false));
+    }
+    fn draw_gradient(&mut self, outline: Outline, gradient: &LinearGradient, clip: Option<Self::ClipPathId>) {
+        let paint = self.scene.push_paint(&shading::gradient_paint(gradient));
+        let mut draw_path = DrawPath::new(outline, paint);
+        draw_path.set_clip_path(clip);
+        draw_path.set_name(format!("gradient{}", self.gradients.len()));
+        self.scene.push_draw_path(draw_path);
+        self.gradients.push(gradient.clone());
+    }
+    fn draw_tiling(&mut self, outline: Outline, fill_rule: FillRule, tiling: &Tiling, clip: Option<Self::ClipPathId>) {
+        let paint = self.scene.push_paint(&images::tiling_paint(tiling));
+        let mut draw_path = DrawPath::new(outline, paint);
+        draw_path.set_clip_path(clip);
+        draw_path.set_fill_rule(fill_rule);
+        draw_path.set_name(format!("tiling{}", self.tilings.len()));
+        self.scene.push_draw_path(draw_path);
+        self.tilings.push(tiling.clone());
+    }
+    fn create_clip_path(&mut self, outline: Outline, fill_rule: FillRule, parent: Option<Self::ClipPathId>) -> Self::ClipPathId {
+        let mut clip_path = ClipPath::new(outline);
+        clip_path.set_fill_rule(fill_rule);
+        clip_path.set_clip_path(parent);
+        self.scene.push_clip_path(clip_path)
+    }
     fn draw(&mut self, outline: &Outline, mode: &DrawMode, fill_rule: FillRule, transform: Transform2F, clip: Option<Self::ClipPathId>) {
         match mode {
             DrawMode::Fill { fill } | DrawMode::FillStroke {fill, .. } => {
@@ -67,24 +160,12 @@ impl Plotter for VectorPlotter {
         match mode {
             DrawMode::Stroke { stroke, stroke_mode }| DrawMode::FillStroke { stroke, stroke_mode, .. } => {
                 let paint = self.paint(stroke.color, stroke.alpha);
-                let contour = match stroke_mode.dash_pattern {
-                    Some((ref pat, phase)) => {
-                        let dashed = OutlineDash::new(outline, pat, phase).into_outline();
-                        let mut stroke = OutlineStrokeToFill::new(&dashed, stroke_mode.style);
-                        stroke.offset();
-                        stroke.into_outline()
-                    }
-                    None => {
-                        let mut stroke = OutlineStrokeToFill::new(outline, stroke_mode.style);
-                        stroke.offset();
-                        stroke.into_outline()
-                    }
-                };
+                let contour = stroke_mode.to_outline(outline);
                 let mut draw_path = DrawPath::new(contour.transformed(&transform), paint);
                 draw_path.set_clip_path(clip);
-                draw_path.set_fill_rule(fill_rule);
-
-            draw_path.set_blend_mode(blend_mode(stroke.mode));
+                // the fill rule of B* is for the fill, the stroke area always uses nonzero
+                draw_path.set_fill_rule(FillRule::Winding);
+                draw_path.set_blend_mode(blend_mode(stroke.mode));
                 self.scene.push_draw_path(draw_path);
             }
             _ => {}
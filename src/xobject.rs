@@ -0,0 +1,54 @@
+use pdf::primitive::{Dictionary, Primitive};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XObjectKind {
+    Form,
+    Image,
+    // /Subtype /PS, viewers may ignore these
+    PostScript,
+    Unknown(String),
+}
+
+// The kind of an XObject stream and whether it was inferred, because /Subtype
+// is missing. Only forms have a /BBox.
+pub fn classify(dict: &Dictionary) -> (XObjectKind, bool) {
+    let kind = match dict.get("Subtype") {
+        Some(Primitive::Name(ref name)) => match name.as_str() {
+            "Form" => XObjectKind::Form,
+            "Image" => XObjectKind::Image,
+            "PS" => XObjectKind::PostScript,
+            name => XObjectKind::Unknown(name.into()),
+        },
+        Some(p) => XObjectKind::Unknown(format!("{:?}", p)),
+        None if dict.get("BBox").is_some() => return (XObjectKind::Form, true),
+        None => return (XObjectKind::Image, true),
+    };
+    (kind, false)
+}
+
+#[cfg(test)]
+mod test {
+    use pdf::primitive::{Dictionary, Primitive};
+
+    use super::{classify, XObjectKind};
+
+    fn dict(entries: &[(&str, Primitive)]) -> Dictionary {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(*key, value.clone());
+        }
+        dict
+    }
+
+    #[test]
+    fn subtypes() {
+        let name = |n: &str| Primitive::Name(n.into());
+        assert_eq!(classify(&dict(&[("Subtype", name("Form"))])), (XObjectKind::Form, false));
+        assert_eq!(classify(&dict(&[("Subtype", name("PS"))])), (XObjectKind::PostScript, false));
+        assert_eq!(classify(&dict(&[("Subtype", name("Vendor"))])), (XObjectKind::Unknown("Vendor".into()), false));
+        // without /Subtype a /BBox makes it a form
+        let bbox = Primitive::Array(vec![Primitive::Integer(0); 4]);
+        assert_eq!(classify(&dict(&[("BBox", bbox)])), (XObjectKind::Form, true));
+        assert_eq!(classify(&dict(&[("Width", Primitive::Integer(2))])), (XObjectKind::Image, true));
+    }
+}
@@ -0,0 +1,39 @@
+use std::io::Write;
+use std::path::Path;
+
+use pdf::PdfError;
+use pdf2svg::output::{DrawCall, DrawStream, Written};
+use pdf2svg::{OutputFormat, RenderOptions};
+
+// writes how many paths and clip paths the page draws
+struct Counts;
+
+impl OutputFormat for Counts {
+    fn extensions(&self) -> &[&str] {
+        &["dummy", "svg"]
+    }
+    fn write(&self, page: &DrawStream, out: &mut dyn Write, _: &RenderOptions) -> Result<Written, PdfError> {
+        let count = |f: fn(&DrawCall) -> bool| page.calls.iter().filter(|c| f(c)).count();
+        let draws = count(|c| matches!(c, DrawCall::Draw { .. }));
+        let clips = count(|c| matches!(c, DrawCall::ClipPath { .. }));
+        writeln!(out, "draws {} clips {}", draws, clips).unwrap();
+        Ok(Written::default())
+    }
+}
+
+#[test]
+fn registered_formats() {
+    let dir = std::env::temp_dir().join(format!("pdf2svg-formats-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut converter = pdf2svg::Converter::new();
+    converter.register(Box::new(Counts));
+    let options = RenderOptions::new();
+    // the registered format also takes over svg from the built-in one
+    for name in ["page.dummy", "page.SVG"] {
+        converter.convert("resources/inspect.pdf".into(), dir.join(name), 0, &options).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join(name)).unwrap(), "draws 3 clips 1\n");
+    }
+    assert_eq!(converter.format(Path::new("page.plt")).extensions(), ["hpgl", "plt"]);
+    assert_eq!(converter.format(Path::new("page.bmp")).extensions(), ["png"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}